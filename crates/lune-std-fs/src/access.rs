@@ -0,0 +1,127 @@
+use std::io::{ErrorKind, Result as IoResult};
+use std::path::Path;
+
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+use crate::options::FsAccessOptions;
+
+/**
+    Checks if the current process may access the given path in all of the given
+    ways, using its effective privileges the same way the operating system would
+    when actually opening the path, instead of just looking at permission bits.
+
+    Returns `false` if the path does not exist.
+*/
+pub async fn check_access(path: impl AsRef<Path>, options: FsAccessOptions) -> LuaResult<bool> {
+    let path = path.as_ref().to_path_buf();
+    let checked = path.clone();
+    spawn_blocking(move || platform::check_access(&checked, options))
+        .await
+        .into_lua_err()?
+        .map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to check access for '{}'\n{e}",
+                path.display()
+            ))
+        })
+}
+
+/**
+    Errors that mean that access is denied, rather than that access could not be checked.
+*/
+fn is_denied(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::PermissionDenied
+            | ErrorKind::NotFound
+            | ErrorKind::NotADirectory
+            | ErrorKind::ReadOnlyFilesystem
+            | ErrorKind::ExecutableFileBusy
+    )
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    use super::{is_denied, FsAccessOptions, IoResult, Path};
+
+    pub fn check_access(path: &Path, options: FsAccessOptions) -> IoResult<bool> {
+        let mut mode = 0;
+        if options.read {
+            mode |= libc::R_OK;
+        }
+        if options.write {
+            mode |= libc::W_OK;
+        }
+        if options.execute {
+            mode |= libc::X_OK;
+        }
+        if mode == 0 {
+            mode = libc::F_OK;
+        }
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        // NOTE: AT_EACCESS checks using the effective user and group ids, the ones
+        // that are used when opening files, instead of the real ids used by access
+        // SAFETY: The path is a valid null-terminated string for the duration of the call
+        let res = unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), mode, libc::AT_EACCESS) };
+        if res == 0 {
+            return Ok(true);
+        }
+        let err = std::io::Error::last_os_error();
+        if is_denied(err.kind()) {
+            Ok(false)
+        } else {
+            Err(err)
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+
+    use super::{is_denied, FsAccessOptions, IoResult, Path};
+
+    const GENERIC_READ: u32 = 0x8000_0000;
+    const GENERIC_WRITE: u32 = 0x4000_0000;
+    const GENERIC_EXECUTE: u32 = 0x2000_0000;
+    const FILE_READ_ATTRIBUTES: u32 = 0x0000_0080;
+    const FILE_SHARE_ALL: u32 = 0x0000_0007;
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    /**
+        Opens the path with the requested access rights, which makes Windows evaluate
+        the access control list of the path against the token of the current process.
+    */
+    pub fn check_access(path: &Path, options: FsAccessOptions) -> IoResult<bool> {
+        let mut access = 0;
+        if options.read {
+            access |= GENERIC_READ;
+        }
+        if options.write {
+            access |= GENERIC_WRITE;
+        }
+        if options.execute {
+            access |= GENERIC_EXECUTE;
+        }
+        if access == 0 {
+            access = FILE_READ_ATTRIBUTES;
+        }
+        let res = OpenOptions::new()
+            .access_mode(access)
+            .share_mode(FILE_SHARE_ALL)
+            // Needed to be able to open directories
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path);
+        match res {
+            Ok(_) => Ok(true),
+            Err(e) if is_denied(e.kind()) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
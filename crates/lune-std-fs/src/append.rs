@@ -0,0 +1,31 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+/**
+    Appends a single line to the file at the given path, creating the file if it does not exist.
+
+    The file is opened in append mode and the line, including its newline, is written using
+    a single write, so that lines appended by concurrent writers are never interleaved.
+*/
+pub async fn append_line(path: impl Into<PathBuf>, line: impl Into<Vec<u8>>) -> LuaResult<()> {
+    let path = path.into();
+    let mut line = line.into();
+    if line.contains(&b'\n') {
+        return Err(LuaError::RuntimeError(String::from(
+            "Appended lines must not contain newlines",
+        )));
+    }
+    line.push(b'\n');
+    spawn_blocking(move || {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&line)
+    })
+    .await
+    .into_lua_err()?
+    .into_lua_err()
+}
@@ -0,0 +1,98 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use mlua::prelude::*;
+use tokio::fs;
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/**
+    Creates a unique path for a temporary file next to the given path, so that it is
+    on the same filesystem as the path, and can be renamed into place atomically.
+*/
+pub fn sibling_temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{name}.tmp-{}-{id}", std::process::id()))
+}
+
+/**
+    Writes the given contents to a file by writing them to a temporary file
+    first and then renaming it into place, so that readers only ever see
+    either the previous or the new contents of the file, and never a mix.
+
+    The permissions of any previous file at the path are kept.
+*/
+pub async fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> LuaResult<()> {
+    let path = path.as_ref();
+    let temp = sibling_temp_path(path);
+    let res = async {
+        fs::write(&temp, contents).await?;
+        rename_into_place(&temp, path).await
+    }
+    .await;
+    if res.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    Ok(res?)
+}
+
+/**
+    Renames a temporary file over the given path, giving it the
+    permissions of any previous file at the path beforehand.
+*/
+pub async fn rename_into_place(temp: &Path, path: &Path) -> std::io::Result<()> {
+    match fs::metadata(path).await {
+        Ok(meta) => fs::set_permissions(temp, meta.permissions()).await?,
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    fs::rename(temp, path).await
+}
+
+/**
+    Keeps the current contents of a file in a hidden file next to it, returning
+    its path, or `None` if there is no file to keep.
+
+    Hard links are used where possible, so that nothing needs
+    to be copied, and the original file keeps its metadata.
+*/
+pub async fn backup_file(path: &Path) -> std::io::Result<Option<PathBuf>> {
+    let backup = sibling_temp_path(path);
+    match fs::hard_link(path, &backup).await {
+        Ok(()) => Ok(Some(backup)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(_) => match fs::copy(path, &backup).await {
+            Ok(_) => Ok(Some(backup)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                let _ = fs::remove_file(&backup).await;
+                Err(e)
+            }
+        },
+    }
+}
+
+/**
+    Undoes changes to files that were already made, given as their paths and backups
+    from [`backup_file`], in reverse order, moving backups back into place and
+    removing files that did not exist before.
+*/
+pub async fn restore_backups(changed: Vec<(PathBuf, Option<PathBuf>)>) {
+    for (path, backup) in changed.into_iter().rev() {
+        let _ = match backup {
+            Some(backup) => fs::rename(backup, path).await,
+            None => fs::remove_file(path).await,
+        };
+    }
+}
+
+/**
+    Removes the backups from [`backup_file`] once all of the changes they were kept for succeeded.
+*/
+pub async fn remove_backups(changed: Vec<(PathBuf, Option<PathBuf>)>) {
+    for backup in changed.into_iter().filter_map(|(_, backup)| backup) {
+        let _ = fs::remove_file(backup).await;
+    }
+}
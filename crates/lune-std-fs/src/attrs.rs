@@ -0,0 +1,363 @@
+use std::collections::HashSet;
+use std::fs::{File, FileTimes};
+use std::io::{ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::{fs, task::JoinSet};
+
+use mlua::prelude::*;
+
+use lune_std_datetime::DateTime;
+
+use crate::link::metadata_with;
+use crate::options::FsAttributeOptions;
+use crate::perms::FsMode;
+
+/**
+    The maximum number of entries whose attributes are changed at
+    once, when changing the attributes of a directory recursively.
+*/
+const MAX_CONCURRENT_CHANGES: usize = 16;
+
+/**
+    A change to the attributes of a single file or directory.
+*/
+#[derive(Debug, Clone, Copy)]
+pub enum AttributeChange {
+    /// Sets the permission mode. On Windows, only the write bits are
+    /// used, and the entry is made read-only if none of them are set.
+    /// Symlinks that are not followed are skipped, since they have no modes on most platforms.
+    /// Presets are expanded separately for every entry, depending on if it is a directory.
+    Mode(FsMode),
+    /// Sets the owning user and group, keeping those that are `None`. Only supported on Unix.
+    Owner { uid: Option<u32>, gid: Option<u32> },
+    /// Sets the access and modification times, keeping those that are `None`.
+    Times {
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    },
+}
+
+impl AttributeChange {
+    fn apply(self, path: &Path, follow_symlinks: bool) -> IoResult<()> {
+        let is_link = !follow_symlinks && std::fs::symlink_metadata(path)?.is_symlink();
+        match self {
+            Self::Mode(_) if is_link => Ok(()),
+            Self::Mode(FsMode::Octal(mode)) => set_mode_blocking(path, mode),
+            Self::Mode(mode) => {
+                set_mode_blocking(path, mode.for_entry(std::fs::metadata(path)?.is_dir()))
+            }
+            Self::Owner { uid, gid } => set_owner_blocking(path, uid, gid, is_link),
+            Self::Times { accessed, modified } if is_link => {
+                set_link_times_blocking(path, accessed, modified)
+            }
+            Self::Times { accessed, modified } => {
+                open_for_times(path)?.set_times(file_times(accessed, modified))
+            }
+        }
+    }
+}
+
+/**
+    Applies a change to the attributes of the given path, and if the
+    `recursive` option is set, to all entries inside of it as well.
+
+    By default, the given path is followed if it is a symlink, but symlinks
+    inside of the directory are skipped, so that recursive changes never affect
+    anything outside of the directory. If the options say to follow symlinks,
+    every symlink is followed, and if they say not to, symlinks are changed themselves.
+*/
+pub async fn change_attributes(
+    path: impl AsRef<Path>,
+    change: AttributeChange,
+    options: FsAttributeOptions,
+) -> LuaResult<()> {
+    let path = path.as_ref().to_path_buf();
+    let follow = options.follow_symlinks.unwrap_or(true);
+    if !options.recursive {
+        return tokio::task::spawn_blocking(move || change.apply(&path, follow))
+            .await
+            .into_lua_err()?
+            .map_err(LuaError::from);
+    }
+
+    // NOTE: Entries are changed one depth at a time, starting with the deepest,
+    // so that removing permissions from a directory does not prevent us from
+    // changing the entries inside of it, which may still be in progress
+    for level in collect_levels(path, options.follow_symlinks)
+        .await?
+        .into_iter()
+        .rev()
+    {
+        let mut tasks = JoinSet::new();
+        let mut paths = level.into_iter();
+        loop {
+            while tasks.len() < MAX_CONCURRENT_CHANGES {
+                let Some(path) = paths.next() else {
+                    break;
+                };
+                tasks.spawn_blocking(move || change.apply(&path, follow).map_err(|e| (path, e)));
+            }
+            let Some(res) = tasks.join_next().await else {
+                break;
+            };
+            if let Err((path, e)) = res.into_lua_err()? {
+                return Err(LuaError::RuntimeError(format!(
+                    "Failed to change attributes of '{}'\n{e}",
+                    path.display()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+    Collects the given path and all entries inside of it, grouped by their depth.
+
+    Symlinks inside of the directory are skipped if `follow_symlinks` is not given,
+    and only their targets are descended into if it is `true`. Directories that
+    are reached more than once through symlinks are only descended into once.
+*/
+async fn collect_levels(
+    root: PathBuf,
+    follow_symlinks: Option<bool>,
+) -> LuaResult<Vec<Vec<PathBuf>>> {
+    let follow = follow_symlinks.unwrap_or(true);
+    let is_dir = metadata_with(&root, follow).await?.is_dir();
+    let mut levels = vec![vec![root.clone()]];
+    if !is_dir {
+        return Ok(levels);
+    }
+    let mut visited = HashSet::from([fs::canonicalize(&root).await?]);
+    let mut dirs = vec![root];
+    while !dirs.is_empty() {
+        let mut level = Vec::new();
+        let mut next_dirs = Vec::new();
+        for dir in &dirs {
+            let mut entries = fs::read_dir(dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let mut file_type = entry.file_type().await?;
+                if file_type.is_symlink() {
+                    match follow_symlinks {
+                        None => continue,
+                        Some(false) => {}
+                        Some(true) => match fs::metadata(entry.path()).await {
+                            Ok(meta) => file_type = meta.file_type(),
+                            // Broken symlinks have nothing to follow
+                            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                            Err(e) => return Err(e.into()),
+                        },
+                    }
+                }
+                if file_type.is_dir() && visited.insert(fs::canonicalize(entry.path()).await?) {
+                    next_dirs.push(entry.path());
+                }
+                level.push(entry.path());
+            }
+        }
+        if !level.is_empty() {
+            levels.push(level);
+        }
+        dirs = next_dirs;
+    }
+    Ok(levels)
+}
+
+/**
+    Gives `target` the same owning user and group as `source`, which
+    usually requires the current process to be running as root.
+
+    If `source` is a symlink that is not followed, the owner of the symlink
+    at `target` is changed, instead of the owner of whatever it points to.
+*/
+pub async fn copy_owner(
+    source: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    follow_symlinks: bool,
+) -> LuaResult<()> {
+    let source = source.as_ref().to_path_buf();
+    let target = target.as_ref().to_path_buf();
+    let changed = target.clone();
+    tokio::task::spawn_blocking(move || {
+        let meta = if follow_symlinks {
+            std::fs::metadata(&source)?
+        } else {
+            std::fs::symlink_metadata(&source)?
+        };
+        let (uid, gid) = owner_of(&meta)?;
+        set_owner_blocking(&changed, Some(uid), Some(gid), meta.is_symlink())
+    })
+    .await
+    .into_lua_err()?
+    .map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to preserve owner of '{}'\n{e}",
+            target.display()
+        ))
+    })
+}
+
+/**
+    Parses a time from Lua, which may be given either as a
+    `DateTime`, or as a number of seconds since the unix epoch.
+*/
+pub fn parse_time(value: LuaValue) -> LuaResult<Option<SystemTime>> {
+    let secs = match value {
+        LuaValue::Nil => return Ok(None),
+        LuaValue::Integer(i) => i as f64,
+        LuaValue::Number(n) => n,
+        LuaValue::UserData(ud) if ud.is::<DateTime>() => {
+            ud.get::<_, i64>("unixTimestampMillis")? as f64 / 1000.0
+        }
+        other => {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid time - expected DateTime or number, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    if secs.is_finite() && secs >= 0.0 {
+        Ok(Some(UNIX_EPOCH + Duration::from_secs_f64(secs)))
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid time - expected a time after the unix epoch, got {secs}"
+        )))
+    }
+}
+
+fn file_times(accessed: Option<SystemTime>, modified: Option<SystemTime>) -> FileTimes {
+    let mut times = FileTimes::new();
+    if let Some(accessed) = accessed {
+        times = times.set_accessed(accessed);
+    }
+    if let Some(modified) = modified {
+        times = times.set_modified(modified);
+    }
+    times
+}
+
+#[cfg(unix)]
+fn set_mode_blocking(path: &Path, mode: u32) -> IoResult<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode_blocking(path: &Path, mode: u32) -> IoResult<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn owner_of(meta: &std::fs::Metadata) -> IoResult<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    Ok((meta.uid(), meta.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner_of(_: &std::fs::Metadata) -> IoResult<(u32, u32)> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Preserving owners is only supported on Unix",
+    ))
+}
+
+#[cfg(unix)]
+fn set_owner_blocking(
+    path: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    is_link: bool,
+) -> IoResult<()> {
+    if is_link {
+        std::os::unix::fs::lchown(path, uid, gid)
+    } else {
+        std::os::unix::fs::chown(path, uid, gid)
+    }
+}
+
+#[cfg(not(unix))]
+fn set_owner_blocking(_: &Path, _: Option<u32>, _: Option<u32>, _: bool) -> IoResult<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Changing owners is only supported on Unix",
+    ))
+}
+
+#[cfg(unix)]
+fn set_link_times_blocking(
+    path: &Path,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> IoResult<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let to_timespec = |time: Option<SystemTime>| match time {
+        Some(time) => {
+            let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            libc::timespec {
+                tv_sec: since_epoch.as_secs() as libc::time_t,
+                tv_nsec: since_epoch.subsec_nanos() as _,
+            }
+        }
+        None => libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        },
+    };
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let times = [to_timespec(accessed), to_timespec(modified)];
+    // SAFETY: The path is a valid null-terminated string, and times
+    // is an array of two timespecs, both valid for the duration of the call
+    let res = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn set_link_times_blocking(
+    path: &Path,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> IoResult<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    // NOTE: Opening a reparse point opens the symlink itself instead of its target
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+    File::options()
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+        .open(path)?
+        .set_times(file_times(accessed, modified))
+}
+
+#[cfg(unix)]
+fn open_for_times(path: &Path) -> IoResult<File> {
+    File::open(path)
+}
+
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> IoResult<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    // NOTE: Directories can only be opened using backup semantics
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    File::options()
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
@@ -0,0 +1,268 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::future::Future;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use tokio::fs;
+use tokio::task::spawn_blocking;
+
+use crate::contents::write_contents;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use self::uring::IoUringBackend;
+
+/**
+    A future returned by the methods of an [`FsBackend`].
+*/
+pub type FsFuture<'a, T> = Pin<Box<dyn Future<Output = IoResult<T>> + Send + 'a>>;
+
+/**
+    The kind of an entry in the filesystem.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsEntryKind {
+    File,
+    Dir,
+    /// Only returned by [`FsBackend::stat_many`], which does not follow symlinks.
+    Symlink,
+    /// Anything that is not a file, directory or symlink, such as a socket.
+    Other,
+}
+
+impl From<std::fs::FileType> for FsEntryKind {
+    fn from(file_type: std::fs::FileType) -> Self {
+        if file_type.is_file() {
+            Self::File
+        } else if file_type.is_dir() {
+            Self::Dir
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/**
+    The metadata of an entry that listings need, as returned by [`FsBackend::stat_many`].
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsEntryStat {
+    pub kind: FsEntryKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl From<std::fs::Metadata> for FsEntryStat {
+    fn from(meta: std::fs::Metadata) -> Self {
+        Self {
+            kind: meta.file_type().into(),
+            size: meta.len(),
+            modified: meta.modified().ok(),
+        }
+    }
+}
+
+/**
+    The backend that the core operations of the `fs` standard library perform their I/O with.
+
+    The default backend uses the filesystem through tokio. Embedders may register
+    another one using [`FsConfig::with_backend`], to use a different runtime for I/O,
+    such as `io_uring`, or to serve files from a virtual filesystem instead.
+    An `io_uring` backend is included on Linux, behind the `io-uring` feature.
+
+    These operations go through the backend:
+
+    - `fs.readFile`, `fs.writeFile`, `fs.isFile` and `fs.isDir`
+    - `fs.readDir` and `fs.readDirWithMetadata`
+    - `fs.writeDir`, `fs.removeFile`, `fs.removeFileIfExists` and `fs.removeDir`
+    - `fs.move`, and `fs.copy` for each file that is copied
+
+    Everything else uses the filesystem directly, such as file handles, streams, watchers,
+    `fs.metadata`, and walking the source of `fs.copy` when copying a directory. So do
+    the extras of the operations above, such as backups, verification, following symlinks,
+    and applying permissions, which means that a backend for a virtual filesystem should
+    be used by scripts that stick to the operations above, without any of their extras.
+
+    All methods should fail with the same [`std::io::ErrorKind`]s as the filesystem
+    would, such as `NotFound` for missing paths, since scripts and the library
+    itself rely on those to tell different failures apart.
+
+    [`FsConfig::with_backend`]: crate::FsConfig::with_backend
+*/
+pub trait FsBackend: Send + Sync + 'static {
+    /**
+        Reads the entire contents of the file at the given path.
+    */
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>>;
+
+    /**
+        Writes the given contents to the file at the given path,
+        creating it if it does not exist, and replacing it if it does.
+    */
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()>;
+
+    /**
+        Reads the names of all of the entries in the directory at the given path.
+    */
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>>;
+
+    /**
+        Gets the kind of the entry at the given path, following symlinks.
+    */
+    fn kind<'a>(&'a self, path: &'a Path) -> FsFuture<'a, FsEntryKind>;
+
+    /**
+        Gets the metadata of all of the given paths, without following symlinks, returning
+        the result for each path in the same order. Backends should fetch them concurrently.
+    */
+    fn stat_many<'a>(&'a self, paths: &'a [PathBuf]) -> FsFuture<'a, Vec<IoResult<FsEntryStat>>>;
+
+    /**
+        Creates a directory at the given path, failing if its parent does not exist.
+    */
+    fn create_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()>;
+
+    /**
+        Removes the file, or symlink, at the given path.
+    */
+    fn remove_file<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()>;
+
+    /**
+        Removes the directory at the given path, along with all of its contents,
+        without following any symlinks inside of it.
+    */
+    fn remove_dir_all<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()>;
+
+    /**
+        Moves the entry at one path to another, replacing any file at the target.
+    */
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> FsFuture<'a, ()>;
+
+    /**
+        Copies the contents of the file at one path to another, creating the
+        target if it does not exist, returning the number of bytes copied.
+    */
+    fn copy<'a>(&'a self, from: &'a Path, to: &'a Path) -> FsFuture<'a, u64>;
+}
+
+/**
+    The default backend, which uses the filesystem through tokio.
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TokioBackend;
+
+impl FsBackend for TokioBackend {
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+        Box::pin(fs::read(path))
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+        Box::pin(write_contents(path, contents))
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+        Box::pin(read_dir_names(path))
+    }
+
+    fn kind<'a>(&'a self, path: &'a Path) -> FsFuture<'a, FsEntryKind> {
+        Box::pin(async move { Ok(fs::metadata(path).await?.file_type().into()) })
+    }
+
+    fn stat_many<'a>(&'a self, paths: &'a [PathBuf]) -> FsFuture<'a, Vec<IoResult<FsEntryStat>>> {
+        let paths = paths.to_vec();
+        Box::pin(async move {
+            spawn_blocking(move || {
+                paths
+                    .iter()
+                    .map(|path| std::fs::symlink_metadata(path).map(FsEntryStat::from))
+                    .collect()
+            })
+            .await
+            .map_err(std::io::Error::other)
+        })
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()> {
+        Box::pin(fs::create_dir(path))
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()> {
+        Box::pin(fs::remove_file(path))
+    }
+
+    fn remove_dir_all<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()> {
+        Box::pin(fs::remove_dir_all(path))
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> FsFuture<'a, ()> {
+        Box::pin(fs::rename(from, to))
+    }
+
+    fn copy<'a>(&'a self, from: &'a Path, to: &'a Path) -> FsFuture<'a, u64> {
+        Box::pin(fs::copy(from, to))
+    }
+}
+
+async fn read_dir_names(path: &Path) -> IoResult<Vec<OsString>> {
+    let mut names = Vec::new();
+    let mut dir = fs::read_dir(path).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        names.push(entry.file_name());
+    }
+    Ok(names)
+}
+
+/**
+    The backend registered by the embedder, or the default backend.
+*/
+#[derive(Clone)]
+pub(crate) struct SharedBackend {
+    backend: Arc<dyn FsBackend>,
+    is_default: bool,
+}
+
+impl SharedBackend {
+    pub fn new(backend: impl FsBackend) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            is_default: false,
+        }
+    }
+
+    pub fn get(&self) -> &dyn FsBackend {
+        self.backend.as_ref()
+    }
+
+    /**
+        Checks if this is the default backend, which uses the filesystem
+        directly, so that the filesystem may also be used without it.
+    */
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+}
+
+impl Default for SharedBackend {
+    fn default() -> Self {
+        Self {
+            is_default: true,
+            ..Self::new(TokioBackend)
+        }
+    }
+}
+
+impl fmt::Debug for SharedBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedBackend")
+            .field("is_default", &self.is_default)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsString};
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use io_uring::{opcode, squeue, types, IoUring, Probe};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::{FsBackend, FsEntryKind, FsEntryStat, FsFuture, TokioBackend};
+
+/*
+    Operations are performed using the `io-uring` crate:
+
+    - Submissions are pushed by whichever task performs the operation, one
+      at a time while holding a lock, and submitted to the kernel right away.
+    - Completions are reaped by a single driver thread, which wakes up the
+      task waiting for each of them, so that many operations can be in flight
+      at once without each of them occupying a blocking thread.
+    - At most as many operations as fit in the submission queue are in flight at
+      once, so that pushing never finds it full, and completions never overflow.
+    - Buffers and paths are borrowed by operations, instead of being copied, which
+      is made safe by operations waiting for the kernel when dropped, see [`Op`].
+*/
+
+const RING_ENTRIES: u32 = 256;
+const MIN_READ_CHUNK: usize = 64 * 1024;
+const COPY_CHUNK: usize = 1024 * 1024;
+
+// Completions for this id wake up the driver when the backend is dropped
+const SHUTDOWN_ID: u64 = u64::MAX;
+
+/**
+    Where the driver leaves the result of an operation, once the kernel has completed it.
+*/
+#[derive(Debug, Default)]
+struct Completion {
+    state: Mutex<CompletionState>,
+    done: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct CompletionState {
+    result: Option<i32>,
+    waker: Option<Waker>,
+}
+
+impl Completion {
+    fn complete(&self, result: i32) {
+        let mut state = self.state.lock().expect("Completion lock was poisoned");
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.done.notify_all();
+    }
+}
+
+/**
+    An operation that has been submitted to the kernel, resolving to its result.
+
+    The kernel may read from or write to the memory that an operation was given until it
+    completes, so dropping an operation before it completes blocks until it does, which keeps
+    borrowed buffers and paths alive for long enough. Operations on regular files complete
+    quickly, and this only happens when the task waiting for one stops waiting early.
+*/
+#[derive(Debug)]
+#[must_use]
+struct Op {
+    completion: Arc<Completion>,
+    finished: bool,
+    // Opened files need closing if nobody is waiting for them anymore
+    opens_fd: bool,
+}
+
+impl Future for Op {
+    type Output = IoResult<u32>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = {
+            let mut state = self
+                .completion
+                .state
+                .lock()
+                .expect("Completion lock was poisoned");
+            let Some(res) = state.result else {
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+            res
+        };
+        self.finished = true;
+        Poll::Ready(u32::try_from(res).map_err(|_| IoError::from_raw_os_error(-res)))
+    }
+}
+
+impl Drop for Op {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let state = self
+            .completion
+            .state
+            .lock()
+            .expect("Completion lock was poisoned");
+        let state = self
+            .completion
+            .done
+            .wait_while(state, |state| state.result.is_none())
+            .expect("Completion lock was poisoned");
+        if let Some(fd) = state.result.filter(|fd| self.opens_fd && *fd >= 0) {
+            // SAFETY: Nobody is waiting for the opened file, so we own it
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    completion: Arc<Completion>,
+    // Released once the kernel is done with the operation, not when nobody waits for it
+    _permit: OwnedSemaphorePermit,
+}
+
+/**
+    Operations that are only supported by newer kernels, which are
+    performed using tokio instead when they are not supported.
+*/
+#[derive(Debug, Clone, Copy)]
+struct Supported {
+    mkdirat: bool,
+    unlinkat: bool,
+    renameat: bool,
+}
+
+struct Ring {
+    uring: IoUring,
+    sq: Mutex<()>,
+    pending: Mutex<HashMap<u64, Pending>>,
+    next_id: AtomicU64,
+    permits: Arc<Semaphore>,
+    supported: Supported,
+}
+
+impl Ring {
+    fn new() -> IoResult<Self> {
+        let uring = IoUring::new(RING_ENTRIES)?;
+        let mut probe = Probe::new();
+        uring.submitter().register_probe(&mut probe)?;
+        let required = [
+            opcode::OpenAt::CODE,
+            opcode::Statx::CODE,
+            opcode::Read::CODE,
+            opcode::Write::CODE,
+        ];
+        if !required.into_iter().all(|code| probe.is_supported(code)) {
+            return Err(IoError::new(
+                ErrorKind::Unsupported,
+                "The kernel does not support the io_uring operations for reading and writing files",
+            ));
+        }
+        let supported = Supported {
+            mkdirat: probe.is_supported(opcode::MkDirAt::CODE),
+            unlinkat: probe.is_supported(opcode::UnlinkAt::CODE),
+            renameat: probe.is_supported(opcode::RenameAt::CODE),
+        };
+        let permits = uring.params().sq_entries() as usize;
+        Ok(Self {
+            uring,
+            sq: Mutex::new(()),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            permits: Arc::new(Semaphore::new(permits)),
+            supported,
+        })
+    }
+
+    /**
+        Pushes an entry and submits it to the kernel right away,
+        only failing if the submission queue is full.
+
+        # Safety
+
+        Everything that the entry points to must stay valid until it has completed.
+    */
+    unsafe fn push(&self, entry: &squeue::Entry) -> IoResult<()> {
+        {
+            let _sq = self.sq.lock().expect("Submission queue lock was poisoned");
+            // SAFETY: The submission queue is only ever accessed while holding its
+            // lock, and the caller guarantees that the entry stays valid
+            unsafe { self.uring.submission_shared().push(entry) }.map_err(|_| {
+                IoError::new(
+                    ErrorKind::WouldBlock,
+                    "The io_uring submission queue is full",
+                )
+            })?;
+        }
+        // NOTE: If submitting fails, such as when the kernel is busy, the entry stays
+        // queued, and is submitted along with the next one, or by the driver once it
+        // is done waiting, which cancels everything if the ring is no longer usable
+        loop {
+            match self.uring.submit() {
+                Err(e) if e.raw_os_error() == Some(libc::EINTR) => {}
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /**
+        Submits an operation, waiting for one of the operations
+        in flight to complete first if there are too many of them.
+
+        # Safety
+
+        Everything that the entry points to must stay valid and unmoved
+        until the returned operation has completed, or has been dropped.
+    */
+    async unsafe fn submit(&self, entry: squeue::Entry, opens_fd: bool) -> IoResult<Op> {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| IoError::other("The io_uring backend was shut down"))?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let completion = Arc::new(Completion::default());
+        // NOTE: The operation must be pending before it is submitted, so that
+        // the driver finds it even if it completes before we get to wait for it
+        self.pending
+            .lock()
+            .expect("Pending operations lock was poisoned")
+            .insert(
+                id,
+                Pending {
+                    completion: Arc::clone(&completion),
+                    _permit: permit,
+                },
+            );
+        let mut op = Op {
+            completion,
+            finished: false,
+            opens_fd,
+        };
+        // SAFETY: The caller guarantees that the entry stays valid for as long as the operation
+        if let Err(e) = unsafe { self.push(&entry.user_data(id)) } {
+            // NOTE: Pushing only fails if the entry never made it into the queue
+            self.pending
+                .lock()
+                .expect("Pending operations lock was poisoned")
+                .remove(&id);
+            op.finished = true;
+            return Err(e);
+        }
+        Ok(op)
+    }
+
+    async fn open(&self, path: &Path, flags: i32, mode: u32) -> IoResult<OwnedFd> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let entry = opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), path.as_ptr())
+            .flags(flags | libc::O_CLOEXEC)
+            .mode(mode)
+            .build();
+        // SAFETY: The path outlives the operation
+        let op = unsafe { self.submit(entry, true) }.await?;
+        let fd = RawFd::try_from(op.await?).map_err(IoError::other)?;
+        // SAFETY: The kernel just opened this file descriptor for us
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    async fn statx(&self, fd: RawFd, path: &CStr, flags: i32, mask: u32) -> IoResult<libc::statx> {
+        let mut statx = MaybeUninit::<libc::statx>::zeroed();
+        let entry = opcode::Statx::new(types::Fd(fd), path.as_ptr(), statx.as_mut_ptr().cast())
+            .flags(flags)
+            .mask(mask)
+            .build();
+        // SAFETY: The path and the statx struct outlive the operation
+        let op = unsafe { self.submit(entry, false) }.await?;
+        op.await?;
+        // SAFETY: The struct was zeroed, which is valid for it, and then filled in by the kernel
+        Ok(unsafe { statx.assume_init() })
+    }
+
+    /**
+        Reads into the spare capacity of the given buffer, at the offset
+        of its length, returning the number of bytes that were read.
+    */
+    async fn read_into(&self, fd: &OwnedFd, data: &mut Vec<u8>) -> IoResult<usize> {
+        let len = data.len();
+        let spare = data.spare_capacity_mut();
+        let spare_len = u32::try_from(spare.len()).unwrap_or(u32::MAX);
+        let entry = opcode::Read::new(
+            types::Fd(fd.as_raw_fd()),
+            spare.as_mut_ptr().cast(),
+            spare_len,
+        )
+        .offset(len as u64)
+        .build();
+        // SAFETY: The buffer is borrowed for longer than the operation
+        let op = unsafe { self.submit(entry, false) }.await?;
+        let read = op.await? as usize;
+        // SAFETY: The kernel initialized this many bytes of the spare capacity
+        unsafe { data.set_len(len + read) };
+        Ok(read)
+    }
+
+    async fn read_at(&self, fd: &OwnedFd, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+        let buf_len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        let entry = opcode::Read::new(types::Fd(fd.as_raw_fd()), buf.as_mut_ptr(), buf_len)
+            .offset(offset)
+            .build();
+        // SAFETY: The buffer is borrowed for longer than the operation
+        let op = unsafe { self.submit(entry, false) }.await?;
+        Ok(op.await? as usize)
+    }
+
+    async fn write_all_at(&self, fd: &OwnedFd, mut buf: &[u8], mut offset: u64) -> IoResult<()> {
+        while !buf.is_empty() {
+            let buf_len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+            let entry = opcode::Write::new(types::Fd(fd.as_raw_fd()), buf.as_ptr(), buf_len)
+                .offset(offset)
+                .build();
+            // SAFETY: The buffer is borrowed for longer than the operation
+            let op = unsafe { self.submit(entry, false) }.await?;
+            let written = op.await? as usize;
+            if written == 0 {
+                return Err(IoError::from(ErrorKind::WriteZero));
+            }
+            buf = &buf[written..];
+            offset += written as u64;
+        }
+        Ok(())
+    }
+
+    async fn path_op(
+        &self,
+        path: &Path,
+        build: impl FnOnce(*const libc::c_char) -> squeue::Entry,
+    ) -> IoResult<()> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: The path outlives the operation
+        let op = unsafe { self.submit(build(path.as_ptr()), false) }.await?;
+        op.await?;
+        Ok(())
+    }
+}
+
+/**
+    Reaps completions and hands them to the tasks waiting for them, until
+    the backend has been dropped and every pending operation has completed.
+*/
+fn drive(ring: &Ring) {
+    let mut shutting_down = false;
+    loop {
+        // SAFETY: The completion queue is only ever accessed by this thread
+        let completed = unsafe { ring.uring.completion_shared() }
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect::<Vec<_>>();
+        for (id, res) in completed {
+            if id == SHUTDOWN_ID {
+                shutting_down = true;
+                continue;
+            }
+            let pending = ring
+                .pending
+                .lock()
+                .expect("Pending operations lock was poisoned")
+                .remove(&id);
+            if let Some(pending) = pending {
+                pending.completion.complete(res);
+            }
+        }
+        let idle = ring
+            .pending
+            .lock()
+            .expect("Pending operations lock was poisoned")
+            .is_empty();
+        if shutting_down && idle {
+            return;
+        }
+        match ring.uring.submit_and_wait(1) {
+            // NOTE: The kernel is busy when completions overflow, which we reap right away
+            Err(e) if !matches!(e.raw_os_error(), Some(libc::EINTR | libc::EBUSY)) => break,
+            _ => {}
+        }
+    }
+    // NOTE: Nothing completes once the ring is unusable, so anything still waiting is cancelled
+    let pending = std::mem::take(
+        &mut *ring
+            .pending
+            .lock()
+            .expect("Pending operations lock was poisoned"),
+    );
+    for pending in pending.into_values() {
+        pending.completion.complete(-libc::ECANCELED);
+    }
+}
+
+fn entry_kind(mode: u16) -> FsEntryKind {
+    match u32::from(mode) & libc::S_IFMT {
+        libc::S_IFREG => FsEntryKind::File,
+        libc::S_IFDIR => FsEntryKind::Dir,
+        libc::S_IFLNK => FsEntryKind::Symlink,
+        _ => FsEntryKind::Other,
+    }
+}
+
+fn entry_stat(statx: &libc::statx) -> FsEntryStat {
+    let modified = (statx.stx_mask & libc::STATX_MTIME != 0).then_some(statx.stx_mtime);
+    FsEntryStat {
+        kind: entry_kind(statx.stx_mode),
+        size: statx.stx_size,
+        modified: modified.and_then(|time| {
+            let secs = Duration::from_secs(time.tv_sec.unsigned_abs());
+            let base = if time.tv_sec >= 0 {
+                SystemTime::UNIX_EPOCH.checked_add(secs)
+            } else {
+                SystemTime::UNIX_EPOCH.checked_sub(secs)
+            };
+            base?.checked_add(Duration::from_nanos(u64::from(time.tv_nsec)))
+        }),
+    }
+}
+
+/**
+    A backend that performs I/O using `io_uring`, available on Linux
+    when the `io-uring` feature is enabled.
+
+    Reading, writing and copying files, getting the metadata of many entries at once, and
+    checking what kind of entry a path is, are done using `io_uring`, so many small operations
+    can be in flight at once without occupying a blocking thread each. Creating directories,
+    removing files and moving entries are too, on kernels that support it.
+
+    Reading and removing directories is done using tokio, since `io_uring` has no operation
+    for reading directories, and so is anything that the kernel does not support.
+
+    Register it using [`FsConfig::with_backend`], falling back to the default
+    backend if it can not be created, since `io_uring` may be unavailable
+    on older kernels or disabled in sandboxed environments.
+
+    [`FsConfig::with_backend`]: crate::FsConfig::with_backend
+*/
+pub struct IoUringBackend {
+    ring: Arc<Ring>,
+}
+
+impl IoUringBackend {
+    /**
+        Creates a new `io_uring` instance, along with a thread that reaps its completions.
+
+        # Errors
+
+        Errors if the kernel does not support `io_uring`, or if it is not permitted.
+    */
+    pub fn new() -> IoResult<Self> {
+        let ring = Arc::new(Ring::new()?);
+        let driven = Arc::clone(&ring);
+        thread::Builder::new()
+            .name("lune-fs-io-uring".to_string())
+            .spawn(move || drive(&driven))?;
+        Ok(Self { ring })
+    }
+}
+
+impl std::fmt::Debug for IoUringBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IoUringBackend")
+            .field("supported", &self.ring.supported)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for IoUringBackend {
+    fn drop(&mut self) {
+        let entry = opcode::Nop::new().build().user_data(SHUTDOWN_ID);
+        // SAFETY: The entry does not point to anything
+        let _ = unsafe { self.ring.push(&entry) };
+    }
+}
+
+impl FsBackend for IoUringBackend {
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            let fd = self.ring.open(path, libc::O_RDONLY, 0).await?;
+            let size = self
+                .ring
+                .statx(
+                    fd.as_raw_fd(),
+                    &CString::default(),
+                    libc::AT_EMPTY_PATH,
+                    libc::STATX_SIZE,
+                )
+                .await?
+                .stx_size;
+            // NOTE: Files may grow while being read, or report no size at all, such as
+            // for files in procfs, so we keep reading until the end of the file anyway
+            let mut data = Vec::with_capacity(usize::try_from(size).unwrap_or(0) + 1);
+            loop {
+                if data.len() == data.capacity() {
+                    data.reserve(MIN_READ_CHUNK);
+                }
+                if self.ring.read_into(&fd, &mut data).await? == 0 {
+                    return Ok(data);
+                }
+            }
+        })
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+            let fd = self.ring.open(path, flags, 0o666).await?;
+            self.ring.write_all_at(&fd, contents, 0).await
+        })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+        TokioBackend.read_dir(path)
+    }
+
+    fn kind<'a>(&'a self, path: &'a Path) -> FsFuture<'a, FsEntryKind> {
+        Box::pin(async move {
+            let path = CString::new(path.as_os_str().as_bytes())?;
+            let statx = self
+                .ring
+                .statx(libc::AT_FDCWD, &path, 0, libc::STATX_TYPE)
+                .await?;
+            Ok(entry_kind(statx.stx_mode))
+        })
+    }
+
+    fn stat_many<'a>(&'a self, paths: &'a [PathBuf]) -> FsFuture<'a, Vec<IoResult<FsEntryStat>>> {
+        Box::pin(async move {
+            let paths = paths
+                .iter()
+                .map(|path| CString::new(path.as_os_str().as_bytes()).ok())
+                .collect::<Vec<_>>();
+            let mut stats = vec![MaybeUninit::<libc::statx>::zeroed(); paths.len()];
+            let mask = libc::STATX_TYPE | libc::STATX_SIZE | libc::STATX_MTIME;
+
+            // NOTE: Everything is submitted before waiting for anything, so that all
+            // of the paths are stat-ed concurrently, up to the size of the ring
+            let mut ops = Vec::with_capacity(paths.len());
+            for (path, statx) in paths.iter().zip(stats.iter_mut()) {
+                let Some(path) = path else {
+                    ops.push(None);
+                    continue;
+                };
+                let entry = opcode::Statx::new(
+                    types::Fd(libc::AT_FDCWD),
+                    path.as_ptr(),
+                    statx.as_mut_ptr().cast(),
+                )
+                .flags(libc::AT_SYMLINK_NOFOLLOW)
+                .mask(mask)
+                .build();
+                // SAFETY: The paths and statx structs outlive the operations, since they
+                // are declared before them, and are never moved, since they are on the heap
+                ops.push(Some(unsafe { self.ring.submit(entry, false) }.await?));
+            }
+
+            let mut results = Vec::with_capacity(ops.len());
+            for (op, statx) in ops.into_iter().zip(&stats) {
+                results.push(match op {
+                    None => Err(IoError::new(
+                        ErrorKind::InvalidInput,
+                        "Paths must not contain null bytes",
+                    )),
+                    // SAFETY: The struct was zeroed, which is valid for it, and then filled in by the kernel
+                    Some(op) => op
+                        .await
+                        .map(|_| entry_stat(unsafe { statx.assume_init_ref() })),
+                });
+            }
+            Ok(results)
+        })
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()> {
+        if !self.ring.supported.mkdirat {
+            return TokioBackend.create_dir(path);
+        }
+        Box::pin(self.ring.path_op(path, |path| {
+            opcode::MkDirAt::new(types::Fd(libc::AT_FDCWD), path)
+                .mode(0o777)
+                .build()
+        }))
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()> {
+        if !self.ring.supported.unlinkat {
+            return TokioBackend.remove_file(path);
+        }
+        Box::pin(self.ring.path_op(path, |path| {
+            opcode::UnlinkAt::new(types::Fd(libc::AT_FDCWD), path).build()
+        }))
+    }
+
+    fn remove_dir_all<'a>(&'a self, path: &'a Path) -> FsFuture<'a, ()> {
+        TokioBackend.remove_dir_all(path)
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> FsFuture<'a, ()> {
+        if !self.ring.supported.renameat {
+            return TokioBackend.rename(from, to);
+        }
+        Box::pin(async move {
+            let from = CString::new(from.as_os_str().as_bytes())?;
+            let to = CString::new(to.as_os_str().as_bytes())?;
+            let entry = opcode::RenameAt::new(
+                types::Fd(libc::AT_FDCWD),
+                from.as_ptr(),
+                types::Fd(libc::AT_FDCWD),
+                to.as_ptr(),
+            )
+            .build();
+            // SAFETY: Both paths outlive the operation
+            let op = unsafe { self.ring.submit(entry, false) }.await?;
+            op.await?;
+            Ok(())
+        })
+    }
+
+    fn copy<'a>(&'a self, from: &'a Path, to: &'a Path) -> FsFuture<'a, u64> {
+        Box::pin(async move {
+            let source = self.ring.open(from, libc::O_RDONLY, 0).await?;
+            let mode = self
+                .ring
+                .statx(
+                    source.as_raw_fd(),
+                    &CString::default(),
+                    libc::AT_EMPTY_PATH,
+                    libc::STATX_MODE,
+                )
+                .await?
+                .stx_mode;
+            let mode = u32::from(mode) & 0o7777;
+            let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+            let target = self.ring.open(to, flags, mode).await?;
+
+            let mut buf = vec![0; COPY_CHUNK];
+            let mut offset = 0;
+            loop {
+                let read = self.ring.read_at(&source, &mut buf, offset).await?;
+                if read == 0 {
+                    break;
+                }
+                self.ring
+                    .write_all_at(&target, &buf[..read], offset)
+                    .await?;
+                offset += read as u64;
+            }
+
+            // NOTE: Like the default backend, the target gets the permissions
+            // of the source, even if it existed before and had others
+            // SAFETY: The descriptor is open for the duration of the call
+            if unsafe { libc::fchmod(target.as_raw_fd(), mode) } != 0 {
+                return Err(IoError::last_os_error());
+            }
+            Ok(offset)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn performs_file_operations() {
+        // NOTE: Tests for this feature are only ever run when it is enabled explicitly,
+        // so a missing io_uring is an error here, instead of a reason to skip the test
+        let backend =
+            IoUringBackend::new().expect("io_uring must be available to test the io-uring feature");
+        let dir = std::env::temp_dir().join(format!("lune-fs-uring-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.bin");
+        let copied = dir.join("copied.bin");
+
+        let contents = (0..3_000_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        backend.write(&path, &contents).await.unwrap();
+        assert_eq!(backend.read(&path).await.unwrap(), contents);
+        assert_eq!(backend.kind(&path).await.unwrap(), FsEntryKind::File);
+        assert_eq!(backend.kind(&dir).await.unwrap(), FsEntryKind::Dir);
+
+        let bytes = backend.copy(&path, &copied).await.unwrap();
+        assert_eq!(bytes, contents.len() as u64);
+        assert_eq!(std::fs::read(&copied).unwrap(), contents);
+
+        let missing = dir.join("missing.bin");
+        let stats = backend
+            .stat_many(&[path.clone(), dir.clone(), missing.clone()])
+            .await
+            .unwrap();
+        let file_stat = stats[0].as_ref().unwrap();
+        assert_eq!(file_stat.kind, FsEntryKind::File);
+        assert_eq!(file_stat.size, contents.len() as u64);
+        assert!(file_stat.modified.is_some());
+        assert_eq!(stats[1].as_ref().unwrap().kind, FsEntryKind::Dir);
+        assert_eq!(stats[2].as_ref().unwrap_err().kind(), ErrorKind::NotFound);
+
+        let err = backend.read(&missing).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let nested = dir.join("nested");
+        backend.create_dir(&nested).await.unwrap();
+        backend
+            .rename(&copied, &nested.join("moved.bin"))
+            .await
+            .unwrap();
+        backend
+            .remove_file(&nested.join("moved.bin"))
+            .await
+            .unwrap();
+        assert_eq!(backend.read_dir(&nested).await.unwrap().len(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::*;
+use tokio::fs;
+
+pub const DEFAULT_BACKUP_SUFFIX: &str = ".bak";
+
+/**
+    Reads the backup options from an options table, returning the
+    suffix to use for backups, or `None` if backups are disabled.
+
+    Setting a suffix implies that backups should be made.
+*/
+pub fn parse_backup_suffix(table: &LuaTable) -> LuaResult<Option<String>> {
+    let backup: Option<bool> = table.get("backup")?;
+    let suffix: Option<String> = table.get("backupSuffix")?;
+    match suffix {
+        Some(suffix) if suffix.is_empty() => Err(LuaError::RuntimeError(
+            "Backup suffix must not be empty".to_string(),
+        )),
+        Some(suffix) => Ok(Some(suffix)),
+        None if backup.unwrap_or(false) => Ok(Some(DEFAULT_BACKUP_SUFFIX.to_string())),
+        None => Ok(None),
+    }
+}
+
+/**
+    Gets the path that a backup of the given path is stored at.
+*/
+pub fn backup_path(path: impl AsRef<Path>, suffix: &str) -> PathBuf {
+    let mut backup = path.as_ref().as_os_str().to_os_string();
+    backup.push(suffix);
+    PathBuf::from(backup)
+}
+
+/**
+    Copies the file at the given path to its backup path, if the file exists.
+
+    The original file is left in place, so that it stays intact if writing to it fails.
+*/
+pub async fn backup_file(path: impl AsRef<Path>, suffix: &str) -> LuaResult<bool> {
+    let path = path.as_ref();
+    match fs::metadata(path).await {
+        Ok(meta) if meta.is_file() => {
+            fs::copy(path, backup_path(path, suffix)).await?;
+            Ok(true)
+        }
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/**
+    Moves the file or directory at the given path to its backup
+    path, if it exists, replacing any previous backup.
+*/
+pub async fn backup_entry(path: impl AsRef<Path>, suffix: &str) -> LuaResult<bool> {
+    let path = path.as_ref();
+    if !entry_exists(path).await? {
+        return Ok(false);
+    }
+    let backup = backup_path(path, suffix);
+    remove_entry(&backup).await?;
+    fs::rename(path, backup).await?;
+    Ok(true)
+}
+
+/**
+    Restores the backup of the given path, replacing whatever is currently at the path.
+*/
+pub async fn restore_backup(path: impl AsRef<Path>, suffix: &str) -> LuaResult<()> {
+    let path = path.as_ref();
+    let backup = backup_path(path, suffix);
+    if !entry_exists(&backup).await? {
+        return Err(LuaError::RuntimeError(format!(
+            "No backup exists at the path '{}'",
+            backup.display()
+        )));
+    }
+    remove_entry(path).await?;
+    fs::rename(backup, path).await?;
+    Ok(())
+}
+
+async fn entry_exists(path: &Path) -> LuaResult<bool> {
+    match fs::symlink_metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/**
+    Removes whatever is at the given path, without following symlinks.
+*/
+pub async fn remove_entry(path: &Path) -> LuaResult<()> {
+    match fs::symlink_metadata(path).await {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path).await?,
+        Ok(_) => fs::remove_file(path).await?,
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
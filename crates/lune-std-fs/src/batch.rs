@@ -0,0 +1,399 @@
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use tokio::task::{spawn_blocking, JoinSet};
+
+use mlua::prelude::*;
+
+use crate::atomic::{
+    backup_file, remove_backups, rename_into_place, restore_backups, sibling_temp_path,
+};
+use crate::codes::map_path_error;
+use crate::contents::{write_contents, FileContents};
+use crate::limit::DescriptorPermit;
+use crate::perms::DefaultModes;
+use crate::trash::move_to_trash;
+
+const DEFAULT_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WriteFilesOptions {
+    pub(crate) concurrency: usize,
+    pub(crate) atomic: bool,
+}
+
+impl Default for WriteFilesOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            atomic: false,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for WriteFilesOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let defaults = Self::default();
+        Ok(match value {
+            LuaValue::Nil => defaults,
+            LuaValue::Table(t) => Self {
+                concurrency: parse_concurrency(&t, "write files")?.unwrap_or(defaults.concurrency),
+                atomic: t
+                    .get::<_, Option<bool>>("atomic")?
+                    .unwrap_or(defaults.atomic),
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "WriteFilesOptions",
+                    message: Some(format!(
+                        "Invalid write files options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RemovePathsOptions {
+    pub(crate) concurrency: usize,
+    pub(crate) continue_on_error: bool,
+    pub(crate) trash: bool,
+}
+
+impl Default for RemovePathsOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            continue_on_error: false,
+            trash: false,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for RemovePathsOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let defaults = Self::default();
+        Ok(match value {
+            LuaValue::Nil => defaults,
+            LuaValue::Table(t) => Self {
+                concurrency: parse_concurrency(&t, "remove paths")?.unwrap_or(defaults.concurrency),
+                continue_on_error: t
+                    .get::<_, Option<bool>>("continueOnError")?
+                    .unwrap_or(defaults.continue_on_error),
+                trash: t.get::<_, Option<bool>>("trash")?.unwrap_or(defaults.trash),
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "RemovePathsOptions",
+                    message: Some(format!(
+                        "Invalid remove paths options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    The result of removing a single path using `fs.removePaths`.
+*/
+#[derive(Debug, Clone)]
+pub struct RemovePathResult {
+    path: String,
+    removed: bool,
+    error: Option<String>,
+}
+
+impl<'lua> IntoLua<'lua> for RemovePathResult {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 3)?;
+        tab.set("path", self.path)?;
+        tab.set("removed", self.removed)?;
+        tab.set("error", self.error)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+fn parse_concurrency(t: &LuaTable, name: &str) -> LuaResult<Option<usize>> {
+    let concurrency: Option<usize> = t.get("concurrency")?;
+    if concurrency == Some(0) {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid {name} options - concurrency must be greater than zero"
+        )));
+    }
+    Ok(concurrency)
+}
+
+/**
+    Writes many files at once, given as a table of paths to contents.
+
+    When writing atomically, every file is first staged to a temporary file next to
+    it, and only once all of them have been staged are they renamed into place, so
+    that failing to write any one file leaves all of the others untouched as well.
+    Files are renamed into place in the order of their paths, keeping the previous
+    contents of each one as a backup, so that they can all be restored if renaming
+    any one of them fails, and the backups are removed once all of them succeeded.
+*/
+pub async fn write_files(
+    lua: &Lua,
+    files: LuaTable<'_>,
+    options: WriteFilesOptions,
+) -> LuaResult<()> {
+    let mut files = files
+        .pairs::<String, FileContents>()
+        .map(|pair| {
+            pair.map(|(path, contents)| (PathBuf::from(path), contents.as_bytes().to_vec()))
+        })
+        .collect::<LuaResult<Vec<_>>>()?;
+    files.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let paths = files
+        .iter()
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+    let modes = DefaultModes::get(lua);
+
+    if !options.atomic {
+        let results = run_concurrently(
+            lua,
+            files,
+            options.concurrency,
+            true,
+            |(path, contents)| async move {
+                let created = !fs::try_exists(&path).await?;
+                write_contents(&path, &contents).await?;
+                if created {
+                    modes.apply_file(&path).await?;
+                }
+                Ok(())
+            },
+        )
+        .await?;
+        return match partition(results) {
+            Ok(_) => Ok(()),
+            Err((index, e, _)) => Err(batch_error("write", &paths[index], e)),
+        };
+    }
+
+    let results = run_concurrently(
+        lua,
+        files,
+        options.concurrency,
+        true,
+        |(path, contents)| async move {
+            let created = !fs::try_exists(&path).await?;
+            let temp = sibling_temp_path(&path);
+            if let Err(e) = fs::write(&temp, contents).await {
+                let _ = fs::remove_file(&temp).await;
+                return Err(e);
+            }
+            Ok((path, temp, created))
+        },
+    )
+    .await?;
+    let staged = match partition(results) {
+        Ok(staged) => staged,
+        Err((index, e, staged)) => {
+            remove_staged(staged).await;
+            return Err(batch_error("stage a write to", &paths[index], e));
+        }
+    };
+
+    let mut staged = staged.into_iter();
+    let mut committed = Vec::new();
+    while let Some((path, temp, created)) = staged.next() {
+        if let Err(e) = commit_staged(&path, &temp, created, modes, &mut committed).await {
+            let _ = fs::remove_file(&temp).await;
+            remove_staged(staged).await;
+            restore_backups(committed).await;
+            return Err(batch_error("commit a write to", &path, e));
+        }
+    }
+    remove_backups(committed).await;
+    Ok(())
+}
+
+/**
+    Renames a single staged file into place, adding it along with the
+    backup of its previous contents to `committed` once it was renamed.
+*/
+async fn commit_staged(
+    path: &Path,
+    temp: &Path,
+    created: bool,
+    modes: DefaultModes,
+    committed: &mut Vec<(PathBuf, Option<PathBuf>)>,
+) -> IoResult<()> {
+    let backup = backup_file(path).await?;
+    if let Err(e) = rename_into_place(temp, path).await {
+        if let Some(backup) = backup {
+            let _ = fs::remove_file(backup).await;
+        }
+        return Err(e);
+    }
+    committed.push((path.to_path_buf(), backup));
+    if created {
+        modes.apply_file(path).await?;
+    }
+    Ok(())
+}
+
+/**
+    Removes many files and directories at once, returning the result for every path.
+
+    Unlike `fs.removeDir`, paths may be either files or directories, and paths that do not
+    exist are not errors, but reported as not removed. Symlinks are removed themselves.
+*/
+pub async fn remove_paths(
+    lua: &Lua,
+    paths: Vec<String>,
+    options: RemovePathsOptions,
+) -> LuaResult<Vec<RemovePathResult>> {
+    let stop_on_error = !options.continue_on_error;
+    let results = run_concurrently(
+        lua,
+        paths.iter().map(PathBuf::from).collect(),
+        options.concurrency,
+        stop_on_error,
+        |path| async move {
+            match remove_path(path, options.trash).await {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+                res => res.map(|()| true),
+            }
+        },
+    )
+    .await?;
+
+    let mut removed = Vec::with_capacity(paths.len());
+    for (path, res) in paths.into_iter().zip(results) {
+        let (was_removed, error) = match res {
+            Some(Ok(was_removed)) => (was_removed, None),
+            Some(Err(e)) => {
+                let e = batch_error("remove", Path::new(&path), e);
+                if stop_on_error {
+                    return Err(e);
+                }
+                (false, Some(e.to_string()))
+            }
+            None => (false, None),
+        };
+        removed.push(RemovePathResult {
+            path,
+            removed: was_removed,
+            error,
+        });
+    }
+    Ok(removed)
+}
+
+async fn remove_path(path: PathBuf, trash: bool) -> IoResult<()> {
+    if trash {
+        return spawn_blocking(move || move_to_trash(&path))
+            .await
+            .map_err(IoError::other)?;
+    }
+    let file_type = fs::symlink_metadata(&path).await?.file_type();
+    if file_type.is_dir() {
+        fs::remove_dir_all(&path).await
+    } else if file_type.is_symlink() && cfg!(windows) && is_dir_link(&path).await {
+        // NOTE: Directory symlinks on Windows are removed as directories
+        fs::remove_dir(&path).await
+    } else {
+        fs::remove_file(&path).await
+    }
+}
+
+async fn is_dir_link(path: &Path) -> bool {
+    fs::metadata(path).await.is_ok_and(|meta| meta.is_dir())
+}
+
+async fn remove_staged(staged: impl IntoIterator<Item = (PathBuf, PathBuf, bool)>) {
+    for (_, temp, _) in staged {
+        let _ = fs::remove_file(temp).await;
+    }
+}
+
+/**
+    Runs a task for every item, with at most `concurrency` tasks running at once,
+    returning the results in the same order as the items.
+
+    When stopping on errors, items that have not been started once any task
+    failed are never started, and have no result, while running tasks finish.
+*/
+async fn run_concurrently<T, R, F, Fut>(
+    lua: &Lua,
+    items: Vec<T>,
+    concurrency: usize,
+    stop_on_error: bool,
+    task: F,
+) -> LuaResult<Vec<Option<IoResult<R>>>>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = IoResult<R>> + Send + 'static,
+    R: Send + 'static,
+{
+    let mut results = Vec::new();
+    results.resize_with(items.len(), || None);
+    let mut running = JoinSet::new();
+    let mut failed = false;
+    for (index, item) in items.into_iter().enumerate() {
+        while running.len() >= concurrency {
+            if let Some(res) = running.join_next().await {
+                let (index, res): (usize, IoResult<R>) = res.into_lua_err()?;
+                failed |= res.is_err();
+                results[index] = Some(res);
+            }
+        }
+        if failed && stop_on_error {
+            break;
+        }
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let fut = task(item);
+        running.spawn(async move {
+            let _permit = permit;
+            (index, fut.await)
+        });
+    }
+    while let Some(res) = running.join_next().await {
+        let (index, res) = res.into_lua_err()?;
+        results[index] = Some(res);
+    }
+    Ok(results)
+}
+
+/**
+    Splits the results of tasks into their values if all of them succeeded, or the index
+    and error of the first task that failed, along with the values of those that succeeded.
+*/
+#[allow(clippy::type_complexity)]
+fn partition<R>(results: Vec<Option<IoResult<R>>>) -> Result<Vec<R>, (usize, IoError, Vec<R>)> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut first_error = None;
+    for (index, res) in results.into_iter().enumerate() {
+        match res {
+            Some(Ok(value)) => values.push(value),
+            Some(Err(e)) if first_error.is_none() => first_error = Some((index, e)),
+            _ => {}
+        }
+    }
+    match first_error {
+        None => Ok(values),
+        Some((index, e)) => Err((index, e, values)),
+    }
+}
+
+fn batch_error(action: &str, path: &Path, err: IoError) -> LuaError {
+    match map_path_error(err, path) {
+        LuaError::ExternalError(e) => {
+            LuaError::RuntimeError(format!("Failed to {action} '{}'\n{e}", path.display()))
+        }
+        err => err,
+    }
+}
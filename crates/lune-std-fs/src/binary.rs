@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+use mlua::prelude::*;
+
+use crate::config::FsConfig;
+
+/**
+    The kind of Lua value that APIs reading the contents of files return.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsBinaryMode {
+    /// Contents are returned as Lua strings.
+    #[default]
+    String,
+    /// Contents are returned as Luau buffers.
+    Buffer,
+}
+
+impl FsBinaryMode {
+    pub(crate) fn get(lua: &Lua) -> Self {
+        FsConfig::get(lua).binary_mode
+    }
+
+    /**
+        Creates the Lua value for the given contents, as a string or a buffer.
+    */
+    pub(crate) fn create(self, lua: &Lua, bytes: impl AsRef<[u8]>) -> LuaResult<LuaValue> {
+        match self {
+            Self::String => lua.create_string(bytes).map(LuaValue::String),
+            Self::Buffer => lua.create_buffer(bytes).map(LuaValue::UserData),
+        }
+    }
+}
+
+impl FromStr for FsBinaryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(Self::String),
+            "buffer" => Ok(Self::Buffer),
+            _ => Err(format!(
+                "Invalid binary mode '{s}' - expected 'string' or 'buffer'"
+            )),
+        }
+    }
+}
+
+/**
+    Options that scripts can change for the whole module using `fs.configure`.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ConfigureOptions {
+    pub(crate) binary: Option<FsBinaryMode>,
+}
+
+impl<'lua> FromLua<'lua> for ConfigureOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Table(t) => Ok(Self {
+                binary: match t.get::<_, Option<String>>("binary")? {
+                    Some(mode) => Some(mode.parse().map_err(LuaError::RuntimeError)?),
+                    None => None,
+                },
+            }),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ConfigureOptions",
+                message: Some(format!(
+                    "Invalid configure options - expected table, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/**
+    A flag that blocking work checks to find out if the operation it is part of was cancelled.
+
+    Futures of async functions are dropped when the Lua thread awaiting them is closed
+    or cancelled, but dropping a future does not stop work that was handed off to a
+    blocking thread, which would otherwise run to completion in the background.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    /**
+        Creates a new cancellation, along with a guard that cancels it once dropped.
+
+        The guard should be held by the future of the operation, so
+        that dropping the future also cancels any of its blocking work.
+    */
+    pub fn new() -> (CancelGuard, Self) {
+        let cancellation = Self::default();
+        (CancelGuard(cancellation.clone()), cancellation)
+    }
+
+    /**
+        Returns an error if the operation was cancelled, to stop blocking work early.
+    */
+    pub fn check(&self) -> IoResult<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(IoError::new(
+                ErrorKind::Interrupted,
+                "The operation was cancelled",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/**
+    Cancels the [`Cancellation`] it was created with once dropped.
+*/
+#[derive(Debug)]
+pub struct CancelGuard(Cancellation);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropping_guard_cancels() {
+        let (guard, cancellation) = Cancellation::new();
+        assert!(cancellation.check().is_ok());
+        drop(guard);
+        let err = cancellation.check().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bstr::{BString, ByteSlice};
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncReadExt};
+
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+use crate::binary::FsBinaryMode;
+use crate::limit::DescriptorPermit;
+
+/*
+    Objects in the store are named by the hex-encoded SHA-256 hash of
+    their contents, and stored in a directory named by its first two
+    characters, to keep the number of entries in any directory small:
+
+    <root>/objects/ab/cdef0123...
+*/
+
+const OBJECTS_DIR: &str = "objects";
+const HASH_LEN: usize = 64;
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/**
+    Creates the `fs.cas` table, containing functions for
+    using directories as content-addressed blob stores.
+*/
+pub fn create_cas(lua: &Lua) -> LuaResult<LuaTable> {
+    TableBuilder::new(lua)?
+        .with_async_function("put", cas_put)?
+        .with_async_function("putFile", cas_put_file)?
+        .with_async_function("get", cas_get)?
+        .with_async_function("has", cas_has)?
+        .with_async_function("link", cas_link)?
+        .build_readonly()
+}
+
+async fn cas_put(lua: &Lua, (root, contents): (String, BString)) -> LuaResult<String> {
+    let hash = hash_hex(Sha256::digest(contents.as_bytes()));
+    let object = object_path(&root, &hash)?;
+    if !fs::try_exists(&object).await? {
+        let _permit = DescriptorPermit::acquire(lua, 1).await;
+        let temp = temp_path(&root).await?;
+        if let Err(e) = fs::write(&temp, contents.as_bytes()).await {
+            let _ = fs::remove_file(&temp).await;
+            return Err(e.into());
+        }
+        commit_object(&temp, &object).await?;
+    }
+    Ok(hash)
+}
+
+async fn cas_put_file(lua: &Lua, (root, path): (String, String)) -> LuaResult<String> {
+    // NOTE: The file is copied into the store before hashing it, so
+    // that the hash always matches the stored contents, even if the
+    // source file is modified while we are reading it
+    let _permit = DescriptorPermit::acquire(lua, 2).await;
+    let temp = temp_path(&root).await?;
+    if let Err(e) = fs::copy(&path, &temp).await {
+        let _ = fs::remove_file(&temp).await;
+        return Err(e.into());
+    }
+    let hash = match hash_file(&temp).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            let _ = fs::remove_file(&temp).await;
+            return Err(e);
+        }
+    };
+    commit_object(&temp, &object_path(&root, &hash)?).await?;
+    Ok(hash)
+}
+
+async fn cas_get(lua: &Lua, (root, hash): (String, String)) -> LuaResult<Option<LuaValue>> {
+    let object = object_path(&root, &hash)?;
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    match fs::read(&object).await {
+        Ok(bytes) => Ok(Some(FsBinaryMode::get(lua).create(lua, bytes)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn cas_has(_: &Lua, (root, hash): (String, String)) -> LuaResult<bool> {
+    Ok(fs::try_exists(object_path(&root, &hash)?).await?)
+}
+
+async fn cas_link(lua: &Lua, (root, hash, dest): (String, String, String)) -> LuaResult<()> {
+    let object = object_path(&root, &hash)?;
+    if !fs::try_exists(&object).await? {
+        return Err(LuaError::RuntimeError(format!(
+            "No object with the hash '{hash}' exists in the store at '{root}'"
+        )));
+    }
+    // NOTE: Hard links can not cross filesystems, in which
+    // case we fall back to copying the object contents instead
+    match fs::hard_link(&object, &dest).await {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let _permit = DescriptorPermit::acquire(lua, 2).await;
+            fs::copy(&object, &dest).await?;
+            Ok(())
+        }
+        res => Ok(res?),
+    }
+}
+
+fn object_path(root: impl AsRef<Path>, hash: &str) -> LuaResult<PathBuf> {
+    let is_valid = hash.len() == HASH_LEN
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if !is_valid {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid hash '{hash}' - expected {HASH_LEN} lowercase hexadecimal characters"
+        )));
+    }
+    Ok(root
+        .as_ref()
+        .join(OBJECTS_DIR)
+        .join(&hash[..2])
+        .join(&hash[2..]))
+}
+
+/**
+    Creates a unique path for a temporary file inside the store, which is
+    on the same filesystem as the objects, so that it can be renamed into place.
+*/
+async fn temp_path(root: impl AsRef<Path>) -> LuaResult<PathBuf> {
+    let dir = root.as_ref().join(OBJECTS_DIR);
+    fs::create_dir_all(&dir).await?;
+    let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(dir.join(format!(".tmp-{}-{id}", std::process::id())))
+}
+
+/**
+    Moves a temporary file into place as an object, or removes
+    it if an object with the same contents is already stored.
+
+    Objects are made read-only, since they are shared by every hard
+    link to them, and writing to any of those would corrupt the object.
+    The temporary file is removed if it could not be moved into place.
+*/
+async fn commit_object(temp: &Path, object: &Path) -> LuaResult<()> {
+    let res = async {
+        if fs::try_exists(object).await? {
+            return fs::remove_file(temp).await;
+        }
+        let mut permissions = fs::metadata(temp).await?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(temp, permissions).await?;
+        if let Some(parent) = object.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(temp, object).await
+    }
+    .await;
+    if res.is_err() {
+        let _ = fs::remove_file(temp).await;
+    }
+    Ok(res?)
+}
+
+pub async fn hash_file(path: &Path) -> LuaResult<String> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hash_hex(hasher.finalize()))
+}
+
+pub(crate) fn hash_hex(digest: impl AsRef<[u8]>) -> String {
+    use std::fmt::Write;
+    digest.as_ref().iter().fold(
+        String::with_capacity(digest.as_ref().len() * 2),
+        |mut out, b| {
+            let _ = write!(out, "{b:02x}");
+            out
+        },
+    )
+}
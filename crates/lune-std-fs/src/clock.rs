@@ -0,0 +1,165 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+/**
+    A future returned by [`FsClock::sleep_until`].
+*/
+pub type FsSleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/**
+    The clock that watchers, pollers and monitors of the `fs` standard library measure time with.
+
+    The default clock uses the time of the operating system, through tokio. Embedders
+    may register another one using [`FsConfig::with_clock`], such as a [`ManualClock`],
+    to drive settle delays, coalescing windows and poll intervals from their tests,
+    instead of having to sleep for real and hope that everything finished in time.
+
+    Only the timing of the library itself goes through the clock, backends that
+    poll the filesystem natively, and the timestamps of events, do not.
+
+    [`FsConfig::with_clock`]: crate::FsConfig::with_clock
+*/
+pub trait FsClock: Send + Sync + 'static {
+    /**
+        Returns the current time.
+    */
+    fn now(&self) -> Instant;
+
+    /**
+        Returns a future that completes once the current time is no earlier than `deadline`.
+    */
+    fn sleep_until(&self, deadline: Instant) -> FsSleep;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokioClock;
+
+impl FsClock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> FsSleep {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+/**
+    A clock that only moves forward when told to, using [`ManualClock::advance`].
+
+    Clones share the same time, so one clone can be registered
+    using [`FsConfig::with_clock`] while another one is advanced.
+
+    [`FsConfig::with_clock`]: crate::FsConfig::with_clock
+*/
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Arc<watch::Sender<Duration>>,
+}
+
+impl ManualClock {
+    /**
+        Creates a new clock, stopped at the current time of the operating system.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(watch::Sender::new(Duration::ZERO)),
+        }
+    }
+
+    /**
+        Moves the clock forward by the given duration, waking up anything sleeping until then.
+    */
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FsClock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> FsSleep {
+        let start = self.start;
+        let mut elapsed = self.elapsed.subscribe();
+        Box::pin(async move {
+            while start + *elapsed.borrow_and_update() < deadline {
+                // NOTE: The sender lives as long as the clock itself does
+                if elapsed.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
+
+/**
+    The clock registered in the configuration, cheap to clone.
+*/
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn FsClock>);
+
+impl SharedClock {
+    pub fn new(clock: impl FsClock) -> Self {
+        Self(Arc::new(clock))
+    }
+
+    pub fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    pub fn sleep_until(&self, deadline: Instant) -> FsSleep {
+        self.0.sleep_until(deadline)
+    }
+
+    pub async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.now() + duration).await;
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(TokioClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedClock").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn manual_clock_wakes_sleepers_once_advanced() {
+        let clock = ManualClock::new();
+        let deadline = clock.now() + Duration::from_secs(10);
+        let sleeper = tokio::spawn(clock.sleep_until(deadline));
+
+        clock.advance(Duration::from_secs(5));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        clock.advance(Duration::from_secs(5));
+        sleeper.await.unwrap();
+        assert_eq!(clock.now(), deadline);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::Error as IoError;
+use std::path::Path;
+
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+use crate::device::{device_removed_error, is_device_removed};
+use crate::lengths::{is_name_too_long, name_too_long_error};
+
+/*
+    Some errors thrown by this library start with a code followed by a colon, such
+    as `NameTooLong: The path ...`, so that scripts can tell them apart from other
+    errors. The codes are also exported as `fs.errorCodes`, to avoid magic strings.
+*/
+
+pub const NAME_TOO_LONG: &str = "NameTooLong";
+pub const NOT_READABLE: &str = "NotReadable";
+pub const NOT_WRITABLE: &str = "NotWritable";
+pub const DEVICE_REMOVED: &str = "DeviceRemoved";
+pub const TIMED_OUT: &str = "TimedOut";
+
+const ALL: &[&str] = &[
+    NAME_TOO_LONG,
+    NOT_READABLE,
+    NOT_WRITABLE,
+    DEVICE_REMOVED,
+    TIMED_OUT,
+];
+
+/**
+    Creates the `fs.errorCodes` table, mapping each error code to itself.
+*/
+pub fn create_error_codes(lua: &Lua) -> LuaResult<LuaTable> {
+    ALL.iter()
+        .try_fold(TableBuilder::new(lua)?, |builder, &code| {
+            builder.with_value(code, code)
+        })?
+        .build_readonly()
+}
+
+/**
+    Converts an I/O error for the given path into a Lua error, using the
+    more specific errors for paths that are too long or on removed devices.
+*/
+pub fn map_path_error(err: IoError, path: &Path) -> LuaError {
+    if is_name_too_long(&err) {
+        name_too_long_error(path)
+    } else if is_device_removed(&err) {
+        device_removed_error(&err, path)
+    } else {
+        err.into()
+    }
+}
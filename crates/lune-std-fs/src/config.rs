@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use mlua::prelude::*;
+
+use crate::backend::{FsBackend, SharedBackend};
+use crate::binary::FsBinaryMode;
+use crate::clock::{FsClock, SharedClock};
+use crate::contents::DEFAULT_SMALL_READ_THRESHOLD;
+use crate::hash::{FsHashAlgorithm, FsHashAlgorithms};
+use crate::limit::OpenFileLimit;
+use crate::perms::DefaultModes;
+use crate::resolver::{FsResolver, FsResolvers};
+use crate::watch::{FsPrewatch, FsWatchFeed, WatchDefaults};
+
+/**
+    Configuration for the `fs` standard library.
+
+    This is set by the embedder using [`set_config`], and
+    defaults to [`FsConfig::default`] if it was never set.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct FsConfig {
+    pub(crate) track_leaks: bool,
+    pub(crate) open_files: Option<OpenFileLimit>,
+    pub(crate) disable_chdir: bool,
+    pub(crate) default_modes: DefaultModes,
+    pub(crate) watch_defaults: WatchDefaults,
+    pub(crate) resolvers: FsResolvers,
+    pub(crate) prewatches: BTreeMap<String, FsPrewatch>,
+    pub(crate) backend: SharedBackend,
+    pub(crate) small_read_threshold: Option<u64>,
+    pub(crate) watch_feed: Option<FsWatchFeed>,
+    pub(crate) binary_mode: FsBinaryMode,
+    pub(crate) clock: SharedClock,
+    pub(crate) hash_algorithms: FsHashAlgorithms,
+}
+
+impl FsConfig {
+    /**
+        Creates a new configuration with all the default values.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Sets whether file handles that are garbage collected without being
+        explicitly closed should be reported, along with where they were opened.
+
+        Reports are never printed, they are collected for the embedder to
+        retrieve using [`take_leak_reports`], and report however it wants to.
+
+        [`take_leak_reports`]: crate::take_leak_reports
+    */
+    #[must_use]
+    pub fn with_leak_tracking(mut self, enabled: bool) -> Self {
+        self.track_leaks = enabled;
+        self
+    }
+
+    /**
+        Sets the maximum number of files that may be open at once across the whole library.
+
+        Operations that would open more files than this wait in a queue until other files
+        are closed, instead of failing once the limit of the operating system is reached.
+
+        # Panics
+
+        Panics if `max` is zero.
+    */
+    #[must_use]
+    pub fn with_max_open_files(mut self, max: u32) -> Self {
+        assert!(
+            max > 0,
+            "Maximum number of open files must be greater than zero"
+        );
+        self.open_files = Some(OpenFileLimit::new(max));
+        self
+    }
+
+    /**
+        Sets whether scripts should be prevented from changing the
+        current working directory of the process using `fs.chdir`.
+
+        The working directory is shared by the whole process, so embedders
+        running multiple scripts at once will usually want to disable this.
+    */
+    #[must_use]
+    pub fn with_chdir_disabled(mut self, disabled: bool) -> Self {
+        self.disable_chdir = disabled;
+        self
+    }
+
+    /**
+        Sets the permissions applied to files created by the library, such as using `fs.writeFile`.
+
+        The permissions are set explicitly after creation, so that they do
+        not depend on the umask of the process. Does nothing on Windows.
+
+        # Panics
+
+        Panics if `mode` is greater than `0o7777`.
+    */
+    #[must_use]
+    pub fn with_default_file_mode(mut self, mode: u32) -> Self {
+        assert!(mode <= 0o7777, "File mode must not be greater than 0o7777");
+        self.default_modes.file = Some(mode);
+        self
+    }
+
+    /**
+        Sets the permissions applied to directories created by the library, such as using `fs.writeDir`.
+
+        The permissions are set explicitly after creation, so that they do
+        not depend on the umask of the process. Does nothing on Windows.
+
+        # Panics
+
+        Panics if `mode` is greater than `0o7777`.
+    */
+    #[must_use]
+    pub fn with_default_dir_mode(mut self, mode: u32) -> Self {
+        assert!(
+            mode <= 0o7777,
+            "Directory mode must not be greater than 0o7777"
+        );
+        self.default_modes.dir = Some(mode);
+        self
+    }
+
+    /**
+        Sets the default options for all watchers, such as those created using `fs.watch`.
+
+        Scripts may still override any options that they are able to pass explicitly.
+    */
+    #[must_use]
+    pub fn with_watch_defaults(mut self, defaults: WatchDefaults) -> Self {
+        self.watch_defaults = defaults;
+        self
+    }
+
+    /**
+        Registers a resolver for virtual paths, such as `zip://bundle.zip!/assets/map.json`,
+        which lets scripts read files packed into archives using `fs.readFile`.
+
+        Registering another resolver for the same scheme replaces the previous one.
+    */
+    #[must_use]
+    pub fn with_resolver(mut self, scheme: impl Into<String>, resolver: impl FsResolver) -> Self {
+        self.resolvers.insert(scheme.into(), Arc::new(resolver));
+        self
+    }
+
+    /**
+        Registers a root that is already being watched, which a script can take
+        using `fs.prewatched`, receiving every event since the watch was started.
+
+        This lets hosts that reload scripts keep watching while no script is running,
+        without missing any events. Registering another root with the same name
+        replaces the previous one, and each root can only be taken once.
+    */
+    #[must_use]
+    pub fn with_prewatch(mut self, name: impl Into<String>, prewatch: FsPrewatch) -> Self {
+        self.prewatches.insert(name.into(), prewatch);
+        self
+    }
+
+    /**
+        Sets the backend that the core operations of the library perform their I/O with,
+        instead of using the filesystem through tokio. Refer to [`FsBackend`] for which
+        operations go through the backend.
+    */
+    #[must_use]
+    pub fn with_backend(mut self, backend: impl FsBackend) -> Self {
+        self.backend = SharedBackend::new(backend);
+        self
+    }
+
+    /**
+        Sets the size in bytes up to which `fs.readFile` reads files right away, on the
+        thread running the script, instead of handing them off to a blocking thread.
+
+        Skipping the blocking thread saves time for scripts that read many tiny files, but
+        reading a file on a slow filesystem, such as a network mount, then blocks any other
+        scripts from running until it has been read. Setting this to `0` disables it.
+
+        Defaults to 64 KiB. Only used with the default backend, see [`FsConfig::with_backend`].
+    */
+    #[must_use]
+    pub fn with_small_read_threshold(mut self, bytes: u64) -> Self {
+        self.small_read_threshold = Some(bytes);
+        self
+    }
+
+    /**
+        Sets the feed that watchers started by scripts using `fs.watchServe` forward
+        their events to, so that they can be sent to other programs, such as to
+        the browser tabs connected to a live-reload server, without any handlers
+        written in Lua. Scripts can not use `fs.watchServe` unless a feed is set.
+    */
+    #[must_use]
+    pub fn with_watch_feed(mut self, feed: FsWatchFeed) -> Self {
+        self.watch_feed = Some(feed);
+        self
+    }
+
+    /**
+        Sets if APIs that read the contents of files, such as `fs.readFile`, return
+        strings or buffers, so that applications working mostly with binary data do
+        not have to convert everything they read. Scripts can change this later on
+        using `fs.configure`.
+
+        Defaults to [`FsBinaryMode::String`].
+    */
+    #[must_use]
+    pub fn with_binary_mode(mut self, mode: FsBinaryMode) -> Self {
+        self.binary_mode = mode;
+        self
+    }
+
+    /**
+        Sets the clock that watchers, `fs.poll` and `fs.monitorDir` measure time with,
+        such as a [`ManualClock`] for tests that need to control when settle delays,
+        coalescing windows and poll intervals pass. Refer to [`FsClock`] for details.
+
+        [`ManualClock`]: crate::ManualClock
+    */
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl FsClock) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /**
+        Registers an additional hash algorithm under the given name, which scripts can then
+        use anywhere a hash algorithm is accepted, such as `fs.copyVerified`. Names are
+        case-insensitive, and the built-in `sha256` and `sha512` can not be replaced.
+
+        Registering another algorithm with the same name replaces the previous one.
+    */
+    #[must_use]
+    pub fn with_hash_algorithm(
+        mut self,
+        name: impl AsRef<str>,
+        algorithm: impl FsHashAlgorithm,
+    ) -> Self {
+        self.hash_algorithms
+            .insert(name.as_ref(), Arc::new(algorithm));
+        self
+    }
+
+    pub(crate) fn small_read_threshold(&self) -> u64 {
+        self.small_read_threshold
+            .unwrap_or(DEFAULT_SMALL_READ_THRESHOLD)
+    }
+
+    pub(crate) fn get(lua: &Lua) -> Self {
+        lua.app_data_ref::<Self>()
+            .map(|config| config.clone())
+            .unwrap_or_default()
+    }
+}
+
+/**
+    Sets the configuration to be used by the `fs` standard library.
+
+    Options that are read when a value is created, such as file handles,
+    only apply to values created after the configuration has been set.
+*/
+pub fn set_config(lua: &Lua, config: FsConfig) {
+    lua.set_app_data(config);
+}
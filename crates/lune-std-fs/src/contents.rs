@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result as IoResult};
+use std::path::Path;
+
+use bstr::{BString, ByteSlice};
+use tokio::{fs, io::AsyncWriteExt};
+
+use mlua::prelude::*;
+
+/**
+    Contents larger than this are written in chunks, instead of all at once.
+*/
+const CHUNKED_WRITE_THRESHOLD: usize = 2 * 1024 * 1024;
+
+/**
+    Files up to this size are read right away by default, see [`read_small_file`].
+*/
+pub const DEFAULT_SMALL_READ_THRESHOLD: u64 = 64 * 1024;
+
+/**
+    The contents of a file to write, given either as a string or a buffer.
+
+    Strings are borrowed from Lua instead of being copied, since
+    they are immutable and stay alive for as long as they are referenced.
+*/
+#[derive(Debug)]
+pub enum FileContents<'lua> {
+    String(LuaString<'lua>),
+    Bytes(BString),
+}
+
+impl FileContents<'_> {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::String(s) => s.as_bytes(),
+            Self::Bytes(b) => b.as_bytes(),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FileContents<'lua> {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::String(s) => Ok(Self::String(s)),
+            value => Ok(Self::Bytes(BString::from_lua(value, lua)?)),
+        }
+    }
+}
+
+/**
+    Writes the given contents to a file, creating it if it does not exist.
+
+    Small contents are written all at once, but large contents are written in chunks
+    through a buffered file, so that only one chunk at a time has to be copied out of
+    Lua and handed off to a blocking thread, instead of a copy of the entire contents.
+*/
+pub async fn write_contents(path: &Path, contents: &[u8]) -> IoResult<()> {
+    if contents.len() <= CHUNKED_WRITE_THRESHOLD {
+        return fs::write(path, contents).await;
+    }
+    write_chunks(fs::File::create(path).await?, contents).await
+}
+
+/**
+    Writes the given contents to a new file, returning `false`
+    without touching anything if something exists at the path already.
+
+    Checking for an existing file and creating the new one is a single
+    operation, so there is no window where another process could create it.
+*/
+pub async fn write_contents_if_missing(path: &Path, contents: &[u8]) -> IoResult<bool> {
+    let file = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    write_chunks(file, contents).await?;
+    Ok(true)
+}
+
+async fn write_chunks(mut file: fs::File, contents: &[u8]) -> IoResult<()> {
+    for chunk in contents.chunks(CHUNKED_WRITE_THRESHOLD) {
+        file.write_all(chunk).await?;
+    }
+    file.flush().await
+}
+
+/**
+    Reads the file at the given path right away, on the current thread, if it is a regular
+    file no larger than the given threshold, returning `None` if it is not.
+
+    Reading a small file takes less time than handing it off to a blocking thread, which
+    adds up for scripts that read thousands of tiny files, such as when loading modules.
+    Files are opened without blocking, so that opening something like a named pipe
+    returns right away, and is then left for the caller to read some other way.
+*/
+pub fn read_small_file(path: &Path, threshold: u64) -> IoResult<Option<Vec<u8>>> {
+    let mut file = open_nonblocking(path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() || meta.len() > threshold {
+        return Ok(None);
+    }
+    // NOTE: Files may still grow, or report no size at all, such as
+    // files in procfs, so we keep reading until the end of the file
+    let mut bytes = Vec::with_capacity(usize::try_from(meta.len()).unwrap_or(0) + 1);
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+#[cfg(unix)]
+fn open_nonblocking(path: &Path) -> IoResult<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    File::options()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_nonblocking(path: &Path) -> IoResult<File> {
+    File::open(path)
+}
@@ -1,21 +1,57 @@
 use std::collections::VecDeque;
-use std::io::ErrorKind;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result as IoResult, Write};
 use std::path::{Path, PathBuf};
 
 use mlua::prelude::*;
-use tokio::fs;
+use tokio::{fs, task::spawn_blocking};
 
+use super::attrs::copy_owner;
+use super::backend::SharedBackend;
+use super::backup::{backup_entry, remove_entry};
+use super::cancel::Cancellation;
+use super::codes::map_path_error;
+use super::config::FsConfig;
+use super::lengths::{name_too_long_error, PathLimits};
+use super::link::{copy_symlink, metadata_with};
+use super::mount::FilesystemBoundaries;
 use super::options::FsWriteOptions;
+use super::perms::DefaultModes;
+use super::verify::verify_copy;
+
+/**
+    The size of the buffer used when copying files in userspace, if not given.
+*/
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
 
 pub struct CopyContents {
     // Vec<(relative depth, path)>
     pub dirs: Vec<(usize, PathBuf)>,
     pub files: Vec<(usize, PathBuf)>,
+    pub links: Vec<(usize, PathBuf)>,
+    // Paths that could not be read, relative to the root, which are left out
+    pub skipped: Vec<PathBuf>,
+}
+
+/**
+    The result of a copy, with the number of bytes in all of the copied files, and the
+    paths in the source that were skipped since they could not be read, if allowed to.
+*/
+#[derive(Debug, Default)]
+pub struct CopySummary {
+    pub bytes: u64,
+    pub skipped: Vec<PathBuf>,
 }
 
-async fn get_contents_at(root: PathBuf, _: FsWriteOptions) -> LuaResult<CopyContents> {
+async fn get_contents_at(
+    lua: &Lua,
+    root: PathBuf,
+    options: &FsWriteOptions,
+) -> LuaResult<CopyContents> {
     let mut dirs = Vec::new();
     let mut files = Vec::new();
+    let mut links = Vec::new();
+    let mut skipped = Vec::new();
 
     let mut queue = VecDeque::new();
 
@@ -23,6 +59,13 @@ async fn get_contents_at(root: PathBuf, _: FsWriteOptions) -> LuaResult<CopyCont
         LuaError::RuntimeError(format!("Failed to canonicalize root directory path\n{e}"))
     })?;
 
+    let boundaries = if options.same_filesystem {
+        let root_meta = fs::metadata(&normalized_root).await?;
+        Some(FilesystemBoundaries::new(&normalized_root, &root_meta)?)
+    } else {
+        None
+    };
+
     // Push initial children of the root path into the queue
     let mut entries = fs::read_dir(&normalized_root).await?;
     while let Some(entry) = entries.next_entry().await? {
@@ -33,18 +76,30 @@ async fn get_contents_at(root: PathBuf, _: FsWriteOptions) -> LuaResult<CopyCont
     // when we find any new descendant directories
     // FUTURE: Try to do async reading here concurrently to speed it up a bit
     while let Some((current_depth, current_path)) = queue.pop_front() {
-        let meta = fs::metadata(&current_path).await?;
+        let meta = metadata_with(&current_path, options.follow_symlinks).await?;
         if meta.is_symlink() {
-            return Err(LuaError::RuntimeError(format!(
-                "Symlinks are not yet supported, encountered at path '{}'",
-                current_path.display()
-            )));
+            links.push((current_depth, current_path));
         } else if meta.is_dir() {
-            // FUTURE: Add an option in FsWriteOptions for max depth and limit it here
-            let mut entries = fs::read_dir(&current_path).await?;
-            while let Some(entry) = entries.next_entry().await? {
-                queue.push_back((current_depth + 1, entry.path()));
+            // Directories on other filesystems are skipped entirely, along with their contents
+            if let Some(boundaries) = &boundaries {
+                if boundaries.crosses(&current_path, &meta)? {
+                    continue;
+                }
             }
+            // FUTURE: Add an option in FsWriteOptions for max depth and limit it here
+            let children = match read_children(&current_path).await {
+                Ok(children) => children,
+                Err(e) => {
+                    // SAFETY: See below, we only ever push paths relative to the root
+                    let relative = current_path.strip_prefix(&normalized_root).unwrap();
+                    if options.on_denied.skip(lua, &root.join(relative), &e)? {
+                        skipped.push(relative.to_path_buf());
+                        continue;
+                    }
+                    return Err(e.into());
+                }
+            };
+            queue.extend(children.into_iter().map(|path| (current_depth + 1, path)));
             dirs.push((current_depth, current_path));
         } else {
             files.push((current_depth, current_path));
@@ -59,6 +114,9 @@ async fn get_contents_at(root: PathBuf, _: FsWriteOptions) -> LuaResult<CopyCont
     for (_, file) in &mut files {
         *file = file.strip_prefix(&normalized_root).unwrap().to_path_buf();
     }
+    for (_, link) in &mut links {
+        *link = link.strip_prefix(&normalized_root).unwrap().to_path_buf();
+    }
 
     // FUTURE: Deduplicate paths such that these directories:
     // - foo/
@@ -66,7 +124,21 @@ async fn get_contents_at(root: PathBuf, _: FsWriteOptions) -> LuaResult<CopyCont
     // - foo/bar/baz/
     // turn into a single foo/bar/baz/ and let create_dir_all do the heavy lifting
 
-    Ok(CopyContents { dirs, files })
+    Ok(CopyContents {
+        dirs,
+        files,
+        links,
+        skipped,
+    })
+}
+
+async fn read_children(dir: &Path) -> IoResult<Vec<PathBuf>> {
+    let mut children = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        children.push(entry.path());
+    }
+    Ok(children)
 }
 
 async fn ensure_no_dir_exists(path: impl AsRef<Path>) -> LuaResult<()> {
@@ -92,16 +164,23 @@ async fn ensure_no_file_exists(path: impl AsRef<Path>) -> LuaResult<()> {
 }
 
 pub async fn copy(
+    lua: &Lua,
     source: impl AsRef<Path>,
     target: impl AsRef<Path>,
     options: FsWriteOptions,
-) -> LuaResult<()> {
+    modes: DefaultModes,
+) -> LuaResult<CopySummary> {
     let source = source.as_ref();
     let target = target.as_ref();
+    let backend = FsConfig::get(lua).backend;
 
-    // Check if we got a file or directory - we will handle them differently below
-    let (is_dir, is_file) = match fs::metadata(&source).await {
-        Ok(meta) => (meta.is_dir(), meta.is_file()),
+    // NOTE: Dropping this future, such as when the Lua thread awaiting
+    // it is cancelled, also stops copying the file currently being copied
+    let (_guard, cancel) = Cancellation::new();
+
+    // Check if we got a file, directory or symlink - we will handle them differently below
+    let (is_dir, is_file, is_link) = match metadata_with(&source, options.follow_symlinks).await {
+        Ok(meta) => (meta.is_dir(), meta.is_file(), meta.is_symlink()),
         Err(e) if e.kind() == ErrorKind::NotFound => {
             return Err(LuaError::RuntimeError(format!(
                 "No file or directory exists at the path '{}'",
@@ -110,9 +189,9 @@ pub async fn copy(
         }
         Err(e) => return Err(e.into()),
     };
-    if !is_file && !is_dir {
+    if !is_file && !is_dir && !is_link {
         return Err(LuaError::RuntimeError(format!(
-            "The given path '{}' is not a file or a directory",
+            "The given path '{}' is not a file, directory or symlink",
             source.display()
         )));
     }
@@ -120,47 +199,312 @@ pub async fn copy(
     // Perform copying:
     //
     // 1. If we are not allowed to overwrite, make sure nothing exists at the target path
-    // 2. If we are allowed to overwrite, remove any previous entry at the path
+    // 2. If we are allowed to overwrite, back up or remove any previous entry at the path
     // 3. Write all directories first
     // 4. Write all files
+    //
+    // For directories, all target paths are also checked against the length limits
+    // of the target filesystem first, so that we do not fail halfway through copying
+    //
+    // The number of bytes in all of the copied files is returned, for stats,
+    // along with any paths that were skipped since they could not be read
 
     if !options.overwrite {
-        if is_file {
+        if is_file || is_link {
             ensure_no_file_exists(target).await?;
         } else if is_dir {
             ensure_no_dir_exists(target).await?;
         }
+    } else if let Some(suffix) = &options.backup_suffix {
+        backup_entry(target, suffix).await?;
     }
 
-    if is_file {
-        fs::copy(source, target).await?;
+    let mut summary = CopySummary::default();
+    if is_link {
+        if options.overwrite {
+            remove_entry(target).await?;
+        }
+        copy_symlink(source, target).await?;
+        if options.preserve.owner {
+            copy_owner(source, target, false).await?;
+        }
+    } else if is_file {
+        summary.bytes = copy_file(&backend, source, target, options.buffer_size, &cancel)
+            .await
+            .map_err(|e| map_path_error(e, target))?;
+        if options.verify {
+            verify_copy(source, target).await?;
+        }
+        // NOTE: Owners are changed before modes, since changing
+        // the owner of a file may clear its setuid and setgid bits
+        if options.preserve.owner {
+            copy_owner(source, target, true).await?;
+        }
+        modes.apply_file(target).await?;
     } else if is_dir {
-        let contents = get_contents_at(source.to_path_buf(), options).await?;
+        summary = copy_dir(lua, &backend, source, target, &options, &modes, &cancel).await?;
+    }
 
-        if options.overwrite {
-            let (is_dir, is_file) = match fs::metadata(&target).await {
-                Ok(meta) => (meta.is_dir(), meta.is_file()),
-                Err(e) if e.kind() == ErrorKind::NotFound => (false, false),
-                Err(e) => return Err(e.into()),
-            };
-            if is_dir {
-                fs::remove_dir_all(target).await?;
-            } else if is_file {
-                fs::remove_file(target).await?;
-            }
+    Ok(summary)
+}
+
+async fn copy_dir(
+    lua: &Lua,
+    backend: &SharedBackend,
+    source: &Path,
+    target: &Path,
+    options: &FsWriteOptions,
+    modes: &DefaultModes,
+    cancel: &Cancellation,
+) -> LuaResult<CopySummary> {
+    let contents = get_contents_at(lua, source.to_path_buf(), options).await?;
+    let mut skipped = contents.skipped;
+
+    let limits = PathLimits::for_path(target)?;
+    let too_long = contents
+        .dirs
+        .iter()
+        .chain(&contents.files)
+        .chain(&contents.links)
+        .map(|(_, path)| target.join(path))
+        .find(|path| limits.exceeded_by(path));
+    if let Some(path) = too_long {
+        return Err(name_too_long_error(&path));
+    }
+
+    if options.overwrite {
+        let (is_dir, is_file) = match fs::metadata(&target).await {
+            Ok(meta) => (meta.is_dir(), meta.is_file()),
+            Err(e) if e.kind() == ErrorKind::NotFound => (false, false),
+            Err(e) => return Err(e.into()),
+        };
+        if is_dir {
+            fs::remove_dir_all(target).await?;
+        } else if is_file {
+            fs::remove_file(target).await?;
         }
+    }
 
-        fs::create_dir_all(target).await?;
+    fs::create_dir_all(target).await?;
 
-        // FUTURE: Write dirs / files concurrently
-        // to potentially speed these operations up
+    // FUTURE: Write dirs / files concurrently
+    // to potentially speed these operations up
+    for (_, dir) in &contents.dirs {
+        fs::create_dir_all(target.join(dir)).await?;
+    }
+    let mut bytes = 0;
+    for (_, file) in &contents.files {
+        // NOTE: Unreadable files fail to open before their copy is created
+        bytes += match copy_file(
+            backend,
+            source.join(file),
+            target.join(file),
+            options.buffer_size,
+            cancel,
+        )
+        .await
+        {
+            Ok(copied) => copied,
+            Err(e) if options.on_denied.skip(lua, &source.join(file), &e)? => {
+                skipped.push(file.clone());
+                continue;
+            }
+            Err(e) => return Err(map_path_error(e, &target.join(file))),
+        };
+        if options.verify {
+            verify_copy(source.join(file), target.join(file)).await?;
+        }
+        if options.preserve.owner {
+            copy_owner(source.join(file), target.join(file), true).await?;
+        }
+        modes.apply_file(target.join(file)).await?;
+    }
+    for (_, link) in &contents.links {
+        copy_symlink(source.join(link), target.join(link)).await?;
+        if options.preserve.owner {
+            copy_owner(source.join(link), target.join(link), false).await?;
+        }
+    }
+    if options.preserve.owner {
         for (_, dir) in &contents.dirs {
-            fs::create_dir_all(target.join(dir)).await?;
+            copy_owner(source.join(dir), target.join(dir), true).await?;
+        }
+        copy_owner(source, target, true).await?;
+    }
+
+    // NOTE: Directory modes are applied last and innermost first, so that
+    // restrictive modes can not prevent us from writing their contents
+    for (_, dir) in contents.dirs.iter().rev() {
+        modes.apply_dir(target.join(dir)).await?;
+    }
+    modes.apply_dir(target).await?;
+    Ok(CopySummary {
+        bytes,
+        skipped: skipped.into_iter().map(|path| source.join(path)).collect(),
+    })
+}
+
+/**
+    Copies a single file, using the backend unless it is the default one,
+    which copies the file here instead, so that copying can be cancelled.
+
+    The partially copied file is removed if copying was cancelled.
+*/
+async fn copy_file(
+    backend: &SharedBackend,
+    source: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    buffer_size: Option<usize>,
+    cancel: &Cancellation,
+) -> IoResult<u64> {
+    if !backend.is_default() {
+        return backend.get().copy(source.as_ref(), target.as_ref()).await;
+    }
+    let source = source.as_ref().to_path_buf();
+    let target = target.as_ref().to_path_buf();
+    let cancel = cancel.clone();
+    spawn_blocking(move || {
+        let res = copy_file_blocking(&source, &target, buffer_size, &cancel);
+        if res.is_err() && cancel.check().is_err() {
+            let _ = std::fs::remove_file(&target);
+        }
+        res
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/**
+    Copies the contents and permissions of a single file, returning the number of bytes copied.
+
+    On Linux, the copy is offloaded to the kernel using `copy_file_range`, copying
+    `buffer_size` bytes per call if given, falling back to copying in userspace
+    for filesystems that do not support it. On other platforms, the native copy
+    of the platform is used, unless a buffer size is given.
+
+    Copying stops between chunks once cancelled, except for native
+    copies of the platform, which can not be stopped once started.
+*/
+fn copy_file_blocking(
+    source: &Path,
+    target: &Path,
+    buffer_size: Option<usize>,
+    cancel: &Cancellation,
+) -> IoResult<u64> {
+    cancel.check()?;
+    if !cfg!(target_os = "linux") && buffer_size.is_none() {
+        return std::fs::copy(source, target);
+    }
+    let reader = File::open(source)?;
+    let meta = reader.metadata()?;
+    let writer = File::create(target)?;
+    if !platform::copy_offloaded(&reader, &writer, buffer_size, cancel)? {
+        copy_buffered(
+            reader,
+            &writer,
+            buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE),
+            cancel,
+        )?;
+    }
+    writer.set_permissions(meta.permissions())?;
+    Ok(meta.len())
+}
+
+fn copy_buffered(
+    mut reader: File,
+    mut writer: &File,
+    buffer_size: usize,
+    cancel: &Cancellation,
+) -> IoResult<()> {
+    let mut buffer = vec![0; buffer_size];
+    loop {
+        cancel.check()?;
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => writer.write_all(&buffer[..n])?,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
-        for (_, file) in &contents.files {
-            fs::copy(source.join(file), target.join(file)).await?;
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs::File;
+    use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+    use std::os::fd::AsRawFd;
+
+    use crate::cancel::Cancellation;
+
+    /**
+        The number of bytes to copy per call when no buffer size is given, which keeps
+        well below the limit that a single call may copy, and is small enough for
+        cancelled copies to stop soon, since cancellation is checked between calls.
+    */
+    const DEFAULT_CHUNK_SIZE: usize = 64 << 20;
+
+    /**
+        Copies the whole file using `copy_file_range`, returning `false` without copying
+        anything if the kernel or the filesystems of the files do not support it.
+    */
+    pub fn copy_offloaded(
+        reader: &File,
+        writer: &File,
+        chunk: Option<usize>,
+        cancel: &Cancellation,
+    ) -> IoResult<bool> {
+        let chunk = chunk.unwrap_or(DEFAULT_CHUNK_SIZE);
+        let mut copied_any = false;
+        loop {
+            cancel.check()?;
+            // SAFETY: Both file descriptors are valid and open for the duration of the call,
+            // and passing null offsets makes the call use and advance the offsets of the files
+            let res = unsafe {
+                libc::copy_file_range(
+                    reader.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    writer.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    chunk,
+                    0,
+                )
+            };
+            if res > 0 {
+                copied_any = true;
+                continue;
+            } else if res == 0 {
+                return Ok(true);
+            }
+            let err = IoError::last_os_error();
+            let unsupported = matches!(
+                err.raw_os_error(),
+                Some(libc::ENOSYS | libc::EXDEV | libc::EOPNOTSUPP | libc::EINVAL | libc::EPERM)
+            );
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            if unsupported && !copied_any {
+                return Ok(false);
+            }
+            return Err(err);
         }
     }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::fs::File;
+    use std::io::Result as IoResult;
 
-    Ok(())
+    use crate::cancel::Cancellation;
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn copy_offloaded(
+        _: &File,
+        _: &File,
+        _: Option<usize>,
+        _: &Cancellation,
+    ) -> IoResult<bool> {
+        Ok(false)
+    }
 }
@@ -0,0 +1,132 @@
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
+
+use mlua::prelude::*;
+
+use crate::denied::DeniedHandling;
+use crate::limit::DescriptorPermit;
+use crate::watch::WatchPattern;
+
+/**
+    The number of directories that are read at once when counting recursively.
+*/
+const CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct CountOptions {
+    pub(crate) recursive: bool,
+    pub(crate) glob: Option<WatchPattern>,
+    pub(crate) on_denied: DeniedHandling,
+}
+
+impl<'lua> FromLua<'lua> for CountOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::Table(t) => Ok(Self {
+                recursive: t.get::<_, Option<bool>>("recursive")?.unwrap_or_default(),
+                glob: match t.get::<_, LuaValue>("glob")? {
+                    LuaValue::Nil => None,
+                    value => Some(WatchPattern::from_lua_value(value, "count")?),
+                },
+                on_denied: t.get("onDenied")?,
+            }),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "CountOptions",
+                message: Some(format!(
+                    "Invalid count options - expected table or nil, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    Counts the entries in the directory at the given path, without collecting them.
+
+    When counting recursively, directories are read concurrently on blocking threads,
+    and symlinks to directories are counted without being followed.
+
+    Returns the count, along with the paths of any directories that were
+    skipped since they could not be read, if allowed to skip them.
+*/
+pub async fn count_entries(
+    lua: &Lua,
+    path: String,
+    options: CountOptions,
+) -> LuaResult<(u64, Vec<String>)> {
+    let root = Arc::new(PathBuf::from(path));
+    let glob = options.glob.map(Arc::new);
+
+    let mut pending = vec![PathBuf::new()];
+    let mut running = JoinSet::new();
+    let mut count = 0;
+    let mut skipped = Vec::new();
+    loop {
+        while running.len() < CONCURRENCY {
+            let Some(dir) = pending.pop() else {
+                break;
+            };
+            let permit = DescriptorPermit::acquire(lua, 1).await;
+            let (root, glob) = (Arc::clone(&root), glob.clone());
+            running.spawn_blocking(move || {
+                let _permit = permit;
+                let res = count_dir(&root, &dir, options.recursive, glob.as_deref());
+                (root.join(dir), res)
+            });
+        }
+        let Some(res) = running.join_next().await else {
+            break;
+        };
+        let (path, res) = res.into_lua_err()?;
+        match res {
+            Ok((counted, dirs)) => {
+                count += counted;
+                pending.extend(dirs);
+            }
+            // NOTE: The root itself is never skipped, only the directories inside of it
+            Err(e) if *path != **root && options.on_denied.skip(lua, &path, &e)? => {
+                skipped.push(path.display().to_string());
+            }
+            Err(e) => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Failed to count entries in '{}'\n{e}",
+                    path.display()
+                )))
+            }
+        }
+    }
+    skipped.sort();
+    Ok((count, skipped))
+}
+
+/**
+    Counts the matching entries in a single directory, given relative to the
+    root, returning the count along with any directories to count next.
+*/
+fn count_dir(
+    root: &Path,
+    dir: &Path,
+    recursive: bool,
+    glob: Option<&WatchPattern>,
+) -> IoResult<(u64, Vec<PathBuf>)> {
+    let mut count = 0;
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let relative = dir.join(entry.file_name());
+        if glob.is_none_or(|glob| glob.is_match(&relative)) {
+            count += 1;
+        }
+        if recursive && entry.file_type()?.is_dir() {
+            dirs.push(relative);
+        }
+    }
+    Ok((count, dirs))
+}
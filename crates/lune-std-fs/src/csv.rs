@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use mlua::prelude::*;
+
+use crate::cas::CHUNK_SIZE;
+use crate::limit::DescriptorPermit;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/**
+    How the header row of a csv file is handled.
+*/
+#[derive(Debug, Clone, Default)]
+pub enum CsvHeaders {
+    /// Rows are returned as arrays of fields.
+    #[default]
+    None,
+    /// The first row is used as the names of fields, and the other rows are returned as maps.
+    FirstRow,
+    /// The given names are used for fields, and all rows are returned as maps.
+    Given(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub(crate) delimiter: u8,
+    pub(crate) headers: CsvHeaders,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            headers: CsvHeaders::default(),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for CsvOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let t = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "CsvOptions",
+                    message: Some(format!(
+                        "Invalid csv options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        let delimiter = match t.get::<_, Option<LuaString>>("delimiter")? {
+            None => b',',
+            Some(s) => match s.as_bytes() {
+                [b'"' | b'\r' | b'\n'] => {
+                    return Err(LuaError::runtime(
+                        "Invalid csv delimiter - quotes and newlines are not allowed",
+                    ))
+                }
+                [delimiter] => *delimiter,
+                _ => {
+                    return Err(LuaError::runtime(
+                        "Invalid csv delimiter - expected a single byte",
+                    ))
+                }
+            },
+        };
+        let headers = match t.get::<_, LuaValue>("headers")? {
+            LuaValue::Nil | LuaValue::Boolean(false) => CsvHeaders::None,
+            LuaValue::Boolean(true) => CsvHeaders::FirstRow,
+            LuaValue::Table(names) => CsvHeaders::Given(
+                names
+                    .sequence_values::<String>()
+                    .collect::<LuaResult<Vec<_>>>()?,
+            ),
+            other => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid csv headers - expected boolean or table, got {}",
+                    other.type_name()
+                )))
+            }
+        };
+        Ok(Self { delimiter, headers })
+    }
+}
+
+struct CsvReader {
+    reader: BufReader<File>,
+    delimiter: u8,
+    line: Vec<u8>,
+    started: bool,
+    _permit: DescriptorPermit,
+}
+
+impl CsvReader {
+    /**
+        Reads the next record, which may span multiple lines if it has quoted fields
+        containing newlines, skipping any blank lines. Returns `None` at the end of the file.
+    */
+    fn next_record(&mut self) -> LuaResult<Option<Vec<Vec<u8>>>> {
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        let mut in_quotes = false;
+        let mut at_field_start = true;
+        loop {
+            self.line.clear();
+            let read = self.reader.read_until(b'\n', &mut self.line)?;
+            if read == 0 && in_quotes {
+                return Err(LuaError::runtime(
+                    "Invalid csv - file ended inside of a quoted field",
+                ));
+            } else if read == 0 {
+                return Ok(None);
+            }
+
+            let mut line = self.line.as_slice();
+            if !self.started {
+                self.started = true;
+                line = line.strip_prefix(UTF8_BOM).unwrap_or(line);
+            }
+            if !in_quotes && fields.is_empty() && matches!(line, b"\n" | b"\r\n") {
+                continue;
+            }
+
+            let mut bytes = line.iter().copied().peekable();
+            while let Some(byte) = bytes.next() {
+                if in_quotes {
+                    if byte == b'"' {
+                        if bytes.peek() == Some(&b'"') {
+                            bytes.next();
+                            field.push(b'"');
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(byte);
+                    }
+                } else if byte == self.delimiter {
+                    fields.push(std::mem::take(&mut field));
+                    at_field_start = true;
+                    continue;
+                } else if byte == b'"' && at_field_start {
+                    in_quotes = true;
+                } else if byte == b'\n' || (byte == b'\r' && bytes.peek() == Some(&b'\n')) {
+                    // Handled below, once the whole line has been consumed
+                } else {
+                    field.push(byte);
+                }
+                at_field_start = false;
+            }
+
+            if !in_quotes {
+                fields.push(field);
+                return Ok(Some(fields));
+            }
+        }
+    }
+}
+
+struct CsvState {
+    reader: Option<CsvReader>,
+    headers: Option<Vec<String>>,
+    first_row_headers: bool,
+}
+
+/**
+    Creates an iterator function that lazily reads and returns the rows of a csv file.
+
+    Opening the file yields, but the iterator reads the file in chunks without yielding,
+    since generic for loops can not yield. Only reading a new chunk blocks, briefly.
+
+    The file is closed once the iterator has returned all rows.
+*/
+pub async fn read_csv(lua: &Lua, path: String, options: CsvOptions) -> LuaResult<LuaFunction> {
+    let permit = DescriptorPermit::acquire(lua, 1).await;
+    let file = tokio::fs::File::open(PathBuf::from(path))
+        .await?
+        .into_std()
+        .await;
+    let (headers, first_row_headers) = match options.headers {
+        CsvHeaders::None => (None, false),
+        CsvHeaders::FirstRow => (None, true),
+        CsvHeaders::Given(names) => (Some(names), false),
+    };
+    let state = Mutex::new(CsvState {
+        reader: Some(CsvReader {
+            reader: BufReader::with_capacity(CHUNK_SIZE, file),
+            delimiter: options.delimiter,
+            line: Vec::new(),
+            started: false,
+            _permit: permit,
+        }),
+        headers,
+        first_row_headers,
+    });
+    lua.create_function(move |lua, ()| {
+        let mut state = state.lock().expect("Csv reader lock was poisoned");
+        let Some(fields) = next_row(&mut state)? else {
+            return Ok(LuaValue::Nil);
+        };
+        let row = match &state.headers {
+            None => lua.create_sequence_from(
+                fields
+                    .into_iter()
+                    .map(|field| lua.create_string(field))
+                    .collect::<LuaResult<Vec<_>>>()?,
+            )?,
+            Some(headers) => {
+                // NOTE: Fields without a header are left out of the row
+                let row = lua.create_table_with_capacity(0, headers.len())?;
+                for (name, field) in headers.iter().zip(fields) {
+                    row.set(name.as_str(), lua.create_string(field)?)?;
+                }
+                row
+            }
+        };
+        Ok(LuaValue::Table(row))
+    })
+}
+
+fn next_row(state: &mut CsvState) -> LuaResult<Option<Vec<Vec<u8>>>> {
+    let Some(reader) = state.reader.as_mut() else {
+        return Ok(None);
+    };
+    if state.first_row_headers {
+        state.first_row_headers = false;
+        if let Some(names) = reader.next_record()? {
+            state.headers = Some(
+                names
+                    .into_iter()
+                    .map(|name| String::from_utf8_lossy(&name).into_owned())
+                    .collect(),
+            );
+        }
+    }
+    let record = match state.reader.as_mut() {
+        Some(reader) => reader.next_record(),
+        None => Ok(None),
+    };
+    if !matches!(record, Ok(Some(_))) {
+        state.reader = None;
+    }
+    record
+}
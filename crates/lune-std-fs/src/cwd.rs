@@ -0,0 +1,34 @@
+use std::{env, path::MAIN_SEPARATOR};
+
+use mlua::prelude::*;
+
+use crate::config::FsConfig;
+
+/**
+    Gets the current working directory, which all relative paths are resolved against.
+
+    The path always ends with a separator, same as `process.cwd`.
+*/
+pub fn current_dir() -> LuaResult<String> {
+    let mut cwd = env::current_dir()?.to_string_lossy().into_owned();
+    if !cwd.ends_with(MAIN_SEPARATOR) {
+        cwd.push(MAIN_SEPARATOR);
+    }
+    Ok(cwd)
+}
+
+/**
+    Changes the current working directory of the process, if allowed by the embedder.
+*/
+pub fn change_dir(lua: &Lua, path: &str) -> LuaResult<()> {
+    if FsConfig::get(lua).disable_chdir {
+        return Err(LuaError::runtime(
+            "Changing the working directory has been disabled",
+        ));
+    }
+    env::set_current_dir(path).map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to change the working directory to '{path}'\n{e}"
+        ))
+    })
+}
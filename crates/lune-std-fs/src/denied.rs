@@ -0,0 +1,73 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+use mlua::prelude::*;
+
+/**
+    What to do with paths that can not be read due to missing permissions, while
+    going through the contents of a directory, such as when copying or counting it.
+
+    The directory that the operation was given is never skipped, only its contents.
+*/
+#[derive(Debug, Clone, Default)]
+pub enum DeniedHandling {
+    /// The whole operation fails, which is the default.
+    #[default]
+    Error,
+    /// The path is skipped, along with its contents.
+    Skip,
+    /// The path is skipped after calling the function with it and the error,
+    /// which may throw an error of its own to make the operation fail instead.
+    Callback(Arc<LuaRegistryKey>),
+}
+
+impl DeniedHandling {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error)
+    }
+
+    /**
+        Decides what happens to a path that could not be read, returning
+        `Ok(true)` if it should be skipped, or `Ok(false)` if the error
+        was not caused by missing permissions and should be returned.
+    */
+    pub fn skip(&self, lua: &Lua, path: &Path, err: &IoError) -> LuaResult<bool> {
+        if err.kind() != ErrorKind::PermissionDenied {
+            return Ok(false);
+        }
+        match self {
+            Self::Error => Ok(false),
+            Self::Skip => Ok(true),
+            Self::Callback(key) => {
+                let callback = lua.registry_value::<LuaFunction>(key)?;
+                callback.call::<_, ()>((path.display().to_string(), err.to_string()))?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for DeniedHandling {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => match s.to_str()? {
+                "error" => Ok(Self::Error),
+                "skip" => Ok(Self::Skip),
+                other => Err(LuaError::RuntimeError(format!(
+                    "Invalid onDenied option '{other}' - expected 'skip', 'error' or a function"
+                ))),
+            },
+            LuaValue::Function(f) => Ok(Self::Callback(Arc::new(lua.create_registry_value(f)?))),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "DeniedHandling",
+                message: Some(format!(
+                    "Invalid onDenied option - expected string or function, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
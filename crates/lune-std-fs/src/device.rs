@@ -0,0 +1,59 @@
+use std::io::Error as IoError;
+use std::path::Path;
+
+use mlua::prelude::*;
+
+use crate::codes::DEVICE_REMOVED;
+
+/**
+    Checks if an I/O error means that the device that a path is on was
+    removed or is not ready, such as an ejected USB drive or a disc drive
+    without a disc, which would otherwise surface as a generic error.
+*/
+pub fn is_device_removed(err: &IoError) -> bool {
+    err.raw_os_error()
+        .is_some_and(|code| platform::DEVICE_REMOVED.contains(&code))
+}
+
+/**
+    Creates the error for a path on a device that was removed or is not ready.
+
+    The message starts with `DeviceRemoved`, so that scripts can tell these errors apart from others.
+*/
+pub fn device_removed_error(err: &IoError, path: &Path) -> LuaError {
+    LuaError::RuntimeError(format!(
+        "{DEVICE_REMOVED}: The device containing '{}' was removed or is not ready\n{err}",
+        path.display()
+    ))
+}
+
+#[cfg(unix)]
+mod platform {
+    // NOTE: EIO is also common for removed devices, but far too generic to map
+    #[cfg(target_os = "linux")]
+    pub const DEVICE_REMOVED: &[i32] = &[libc::ENODEV, libc::ENXIO, libc::ENOMEDIUM];
+    #[cfg(not(target_os = "linux"))]
+    pub const DEVICE_REMOVED: &[i32] = &[libc::ENODEV, libc::ENXIO];
+}
+
+#[cfg(windows)]
+mod platform {
+    // ERROR_NOT_READY, ERROR_DEV_NOT_EXIST, ERROR_NO_MEDIA_IN_DRIVE, ERROR_DEVICE_REMOVED
+    pub const DEVICE_REMOVED: &[i32] = &[21, 55, 1112, 1617];
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn detects_removed_devices() {
+        #[cfg(unix)]
+        assert!(is_device_removed(&IoError::from_raw_os_error(libc::ENODEV)));
+        #[cfg(windows)]
+        assert!(is_device_removed(&IoError::from_raw_os_error(21)));
+        assert!(!is_device_removed(&IoError::from(ErrorKind::NotFound)));
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use directories::BaseDirs;
+use mlua::prelude::*;
+
+/**
+    Platform-specific directories for the current user.
+*/
+#[derive(Debug, Clone)]
+pub struct FsDirs {
+    home: Option<PathBuf>,
+    config: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    data: Option<PathBuf>,
+    temp: PathBuf,
+}
+
+impl FsDirs {
+    pub fn discover() -> Self {
+        let base = BaseDirs::new();
+        Self {
+            home: base.as_ref().map(|b| b.home_dir().to_path_buf()),
+            config: base.as_ref().map(|b| b.config_dir().to_path_buf()),
+            cache: base.as_ref().map(|b| b.cache_dir().to_path_buf()),
+            data: base.as_ref().map(|b| b.data_dir().to_path_buf()),
+            temp: std::env::temp_dir(),
+        }
+    }
+}
+
+fn path_to_string(path: Option<PathBuf>) -> Option<String> {
+    path.map(|p| p.to_string_lossy().into_owned())
+}
+
+impl<'lua> IntoLua<'lua> for FsDirs {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 5)?;
+        tab.set("home", path_to_string(self.home))?;
+        tab.set("config", path_to_string(self.config))?;
+        tab.set("cache", path_to_string(self.cache))?;
+        tab.set("data", path_to_string(self.data))?;
+        tab.set("temp", path_to_string(Some(self.temp)))?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
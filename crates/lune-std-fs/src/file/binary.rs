@@ -0,0 +1,170 @@
+use std::str::FromStr;
+
+use mlua::prelude::*;
+
+/**
+    Byte order used when reading and writing binary numbers.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsEndianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl FromStr for FsEndianness {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "little" | "le" => Ok(Self::Little),
+            "big" | "be" => Ok(Self::Big),
+            _ => Err("Invalid endianness - expected 'little' or 'big'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsEndianness {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "FsEndianness",
+                message: Some("Endianness must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+/**
+    A kind of binary number that can be read from and written to a file.
+
+    Note that 64-bit integers are intentionally not supported, since
+    they can not be represented exactly using Luau numbers.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsNumberKind {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+}
+
+macro_rules! decode_as {
+    ($ty:ty, $bytes:expr, $endianness:expr) => {{
+        // SAFETY: The caller always passes exactly as many bytes as the size of the type
+        let arr = $bytes.try_into().unwrap();
+        (match $endianness {
+            FsEndianness::Little => <$ty>::from_le_bytes(arr),
+            FsEndianness::Big => <$ty>::from_be_bytes(arr),
+        }) as f64
+    }};
+}
+
+macro_rules! encode_int_as {
+    ($ty:ty, $value:expr, $endianness:expr) => {{
+        let value = $value;
+        if value.fract() != 0.0 || value < <$ty>::MIN as f64 || value > <$ty>::MAX as f64 {
+            return Err(LuaError::RuntimeError(format!(
+                "Value {value} is out of range for {}",
+                stringify!($ty)
+            )));
+        }
+        match $endianness {
+            FsEndianness::Little => (value as $ty).to_le_bytes().to_vec(),
+            FsEndianness::Big => (value as $ty).to_be_bytes().to_vec(),
+        }
+    }};
+}
+
+impl FsNumberKind {
+    pub const ALL: &'static [Self] = &[
+        Self::U8,
+        Self::I8,
+        Self::U16,
+        Self::I16,
+        Self::U32,
+        Self::I32,
+        Self::F32,
+        Self::F64,
+    ];
+
+    pub fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    pub fn read_method_name(self) -> &'static str {
+        match self {
+            Self::U8 => "readU8",
+            Self::I8 => "readI8",
+            Self::U16 => "readU16",
+            Self::I16 => "readI16",
+            Self::U32 => "readU32",
+            Self::I32 => "readI32",
+            Self::F32 => "readF32",
+            Self::F64 => "readF64",
+        }
+    }
+
+    pub fn write_method_name(self) -> &'static str {
+        match self {
+            Self::U8 => "writeU8",
+            Self::I8 => "writeI8",
+            Self::U16 => "writeU16",
+            Self::I16 => "writeI16",
+            Self::U32 => "writeU32",
+            Self::I32 => "writeI32",
+            Self::F32 => "writeF32",
+            Self::F64 => "writeF64",
+        }
+    }
+
+    /**
+        Decodes a number from exactly [`FsNumberKind::size`] bytes.
+    */
+    pub fn decode(self, bytes: &[u8], endianness: FsEndianness) -> f64 {
+        match self {
+            Self::U8 => decode_as!(u8, bytes, endianness),
+            Self::I8 => decode_as!(i8, bytes, endianness),
+            Self::U16 => decode_as!(u16, bytes, endianness),
+            Self::I16 => decode_as!(i16, bytes, endianness),
+            Self::U32 => decode_as!(u32, bytes, endianness),
+            Self::I32 => decode_as!(i32, bytes, endianness),
+            Self::F32 => decode_as!(f32, bytes, endianness),
+            Self::F64 => decode_as!(f64, bytes, endianness),
+        }
+    }
+
+    /**
+        Encodes a number, erroring if it can not be represented by this kind.
+    */
+    pub fn encode(self, value: f64, endianness: FsEndianness) -> LuaResult<Vec<u8>> {
+        Ok(match self {
+            Self::U8 => encode_int_as!(u8, value, endianness),
+            Self::I8 => encode_int_as!(i8, value, endianness),
+            Self::U16 => encode_int_as!(u16, value, endianness),
+            Self::I16 => encode_int_as!(i16, value, endianness),
+            Self::U32 => encode_int_as!(u32, value, endianness),
+            Self::I32 => encode_int_as!(i32, value, endianness),
+            Self::F32 => match endianness {
+                FsEndianness::Little => (value as f32).to_le_bytes().to_vec(),
+                FsEndianness::Big => (value as f32).to_be_bytes().to_vec(),
+            },
+            Self::F64 => match endianness {
+                FsEndianness::Little => value.to_le_bytes().to_vec(),
+                FsEndianness::Big => value.to_be_bytes().to_vec(),
+            },
+        })
+    }
+}
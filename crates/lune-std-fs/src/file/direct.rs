@@ -0,0 +1,148 @@
+use std::fs::File as StdFile;
+use std::io::Result as IoResult;
+
+use tokio::fs::{File, OpenOptions};
+
+use super::positional;
+
+/*
+    Unbuffered IO bypasses the page cache of the operating system, which
+    requires offsets, lengths and buffer addresses to all be aligned to the
+    block size of the underlying device. We always align to 4096 bytes, which
+    is a multiple of the block size for practically all devices, and read
+    and write whole aligned blocks, so that callers may use any offset and length.
+*/
+
+const ALIGNMENT: usize = 4096;
+
+fn align_down(value: u64) -> u64 {
+    value - value % ALIGNMENT as u64
+}
+
+fn align_up(value: u64) -> u64 {
+    align_down(value + ALIGNMENT as u64 - 1)
+}
+
+/**
+    A zeroed buffer with a start address that is aligned to [`ALIGNMENT`].
+*/
+struct AlignedBuffer {
+    inner: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    fn zeroed(len: usize) -> Self {
+        let inner = vec![0; len + ALIGNMENT];
+        let start = inner.as_ptr().align_offset(ALIGNMENT);
+        Self { inner, start, len }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.inner[self.start..self.start + self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.inner[self.start..self.start + self.len]
+    }
+}
+
+/**
+    Sets the flags needed to open a file for unbuffered IO, where supported at open time.
+*/
+pub fn set_direct_flags(options: &mut OpenOptions) {
+    #[cfg(target_os = "linux")]
+    {
+        options.custom_flags(libc::O_DIRECT);
+    }
+    #[cfg(windows)]
+    {
+        const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+        options.custom_flags(FILE_FLAG_NO_BUFFERING);
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        let _ = options;
+    }
+}
+
+/**
+    Enables unbuffered IO on an already opened file, for platforms
+    that do not support it using flags when the file is opened.
+*/
+pub fn enable_direct(file: &File) -> IoResult<()> {
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::fd::AsRawFd;
+        // SAFETY: The file descriptor is valid for as long as the file is
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = file;
+    }
+    Ok(())
+}
+
+/**
+    Reads whole aligned blocks into `buf`, starting at the aligned `offset`,
+    returning the number of bytes read before the end of the file was reached.
+*/
+fn read_blocks(file: &StdFile, buf: &mut AlignedBuffer, offset: u64) -> IoResult<usize> {
+    let buf = buf.as_mut_slice();
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = positional::read_at(file, &mut buf[filled..], offset + filled as u64)?;
+        filled += read;
+        // NOTE: Only the final block at the end of a file may be
+        // partial, and reading past it with an unaligned offset fails
+        if read == 0 || read % ALIGNMENT != 0 {
+            break;
+        }
+    }
+    Ok(filled)
+}
+
+/**
+    Reads up to `len` bytes at `offset` from a file opened for unbuffered IO.
+*/
+pub fn read_at(file: &StdFile, len: usize, offset: u64) -> IoResult<Vec<u8>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let start = align_down(offset);
+    let end = align_up(offset + len as u64);
+    let mut buf = AlignedBuffer::zeroed((end - start) as usize);
+    let filled = read_blocks(file, &mut buf, start)?;
+    let from = ((offset - start) as usize).min(filled);
+    let to = (from + len).min(filled);
+    Ok(buf.as_slice()[from..to].to_vec())
+}
+
+/**
+    Writes all of `bytes` at `offset` to a file opened for unbuffered IO.
+
+    Any partial blocks at the start and end are read and written back whole,
+    and the file is truncated afterwards if the final block extended it.
+*/
+pub fn write_all_at(file: &StdFile, bytes: &[u8], offset: u64) -> IoResult<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let size = file.metadata()?.len();
+    let start = align_down(offset);
+    let end = align_up(offset + bytes.len() as u64);
+    let mut buf = AlignedBuffer::zeroed((end - start) as usize);
+    read_blocks(file, &mut buf, start)?;
+    let from = (offset - start) as usize;
+    buf.as_mut_slice()[from..from + bytes.len()].copy_from_slice(bytes);
+    positional::write_all_at(file, buf.as_slice(), start)?;
+    let new_size = size.max(offset + bytes.len() as u64);
+    if new_size < end {
+        file.set_len(new_size)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use mlua::prelude::*;
+
+use crate::config::FsConfig;
+
+/**
+    A file handle that was garbage collected without being explicitly closed.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsLeakReport {
+    /// The path that the handle was opened with.
+    pub path: PathBuf,
+    /// The script location that opened the handle, if it could be determined.
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct LeakReports(Arc<Mutex<Vec<FsLeakReport>>>);
+
+impl LeakReports {
+    fn get(lua: &Lua) -> Self {
+        if let Some(reports) = lua.app_data_ref::<Self>() {
+            return reports.clone();
+        }
+        let reports = Self::default();
+        lua.set_app_data(reports.clone());
+        reports
+    }
+}
+
+/**
+    Takes all leak reports collected so far, leaving none behind.
+
+    Reports are only collected if leak tracking was enabled using [`FsConfig::with_leak_tracking`].
+
+    [`FsConfig::with_leak_tracking`]: crate::FsConfig::with_leak_tracking
+*/
+#[must_use]
+pub fn take_leak_reports(lua: &Lua) -> Vec<FsLeakReport> {
+    let reports = LeakReports::get(lua);
+    reports
+        .0
+        .lock()
+        .map(|mut reports| std::mem::take(&mut *reports))
+        .unwrap_or_default()
+}
+
+/**
+    Records a leak report when dropped, unless it was marked as closed first.
+*/
+#[derive(Debug)]
+pub(crate) struct LeakTracker {
+    closed: AtomicBool,
+    report: FsLeakReport,
+    reports: LeakReports,
+}
+
+impl LeakTracker {
+    /**
+        Creates a new tracker for a handle opened from Lua, if enabled by the embedder.
+    */
+    pub fn new(lua: &Lua, path: PathBuf) -> Option<Self> {
+        if !FsConfig::get(lua).track_leaks {
+            return None;
+        }
+        Some(Self {
+            closed: AtomicBool::new(false),
+            report: FsLeakReport {
+                path,
+                location: caller_location(lua),
+            },
+            reports: LeakReports::get(lua),
+        })
+    }
+
+    pub fn mark_closed(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for LeakTracker {
+    fn drop(&mut self) {
+        if self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Ok(mut reports) = self.reports.0.lock() {
+            reports.push(self.report.clone());
+        }
+    }
+}
+
+/**
+    Finds the first Lua function on the stack that is not an internal wrapper.
+*/
+fn caller_location(lua: &Lua) -> Option<String> {
+    let mut level = 1;
+    while let Some(info) = lua.inspect_stack(level) {
+        level += 1;
+        let source = info.source();
+        let Some(src) = source.short_src else {
+            continue;
+        };
+        if source.what != "Lua" || src.contains("__mlua") {
+            continue;
+        }
+        let src = src
+            .strip_prefix("[string \"")
+            .and_then(|s| s.strip_suffix("\"]"))
+            .unwrap_or(&src);
+        return Some(format!("{src}:{}", info.curr_line()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_unclosed_handles() {
+        let lua = Lua::new();
+        crate::set_config(&lua, FsConfig::new().with_leak_tracking(true));
+
+        let closed = LeakTracker::new(&lua, PathBuf::from("closed")).unwrap();
+        closed.mark_closed();
+        drop(closed);
+        drop(LeakTracker::new(&lua, PathBuf::from("leaked")).unwrap());
+
+        let reports = take_leak_reports(&lua);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].path, PathBuf::from("leaked"));
+        assert!(take_leak_reports(&lua).is_empty());
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let lua = Lua::new();
+        assert!(LeakTracker::new(&lua, PathBuf::from("file")).is_none());
+    }
+}
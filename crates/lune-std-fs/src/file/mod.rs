@@ -0,0 +1,537 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use bstr::{BString, ByteSlice};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex as AsyncMutex,
+    task::spawn_blocking,
+};
+
+use mlua::prelude::*;
+
+use lune_utils::stdio::StdioHandle;
+
+use crate::binary::FsBinaryMode;
+use crate::codes::{NOT_READABLE, NOT_WRITABLE};
+use crate::limit::DescriptorPermit;
+
+mod binary;
+mod direct;
+mod leak;
+mod mode;
+mod options;
+mod positional;
+mod scope;
+
+pub use self::leak::{take_leak_reports, FsLeakReport};
+pub use self::mode::FsOpenMode;
+pub use self::options::FsOpenOptions;
+pub use self::scope::create_with_open;
+
+pub(crate) use self::positional::{read_at, write_all_at};
+
+use self::binary::{FsEndianness, FsNumberKind};
+use self::leak::LeakTracker;
+
+/**
+    Where to seek from, mirroring `file:seek` in the Lua standard library.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsSeekWhence {
+    Set,
+    #[default]
+    Current,
+    End,
+}
+
+impl FsSeekWhence {
+    fn to_seek_from(self, offset: i64) -> LuaResult<SeekFrom> {
+        Ok(match self {
+            Self::Set => SeekFrom::Start(u64::try_from(offset).map_err(|_| {
+                LuaError::runtime("Seek offset must not be negative when seeking from the start")
+            })?),
+            Self::Current => SeekFrom::Current(offset),
+            Self::End => SeekFrom::End(offset),
+        })
+    }
+}
+
+impl FromStr for FsSeekWhence {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "set" => Ok(Self::Set),
+            "cur" | "current" => Ok(Self::Current),
+            "end" => Ok(Self::End),
+            _ => Err("Invalid seek position - expected 'set', 'cur' or 'end'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsSeekWhence {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "FsSeekWhence",
+                message: Some("Seek position must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+/**
+    A handle to an open file.
+
+    The underlying file is closed when `close` is called, or once the
+    handle and all of its clones have been garbage collected.
+*/
+#[derive(Debug, Clone)]
+pub struct FsFile {
+    file: Arc<AsyncMutex<Option<File>>>,
+    path: Arc<PathBuf>,
+    leak: Option<Arc<LeakTracker>>,
+    permit: Arc<Mutex<DescriptorPermit>>,
+    mode: FsOpenMode,
+    direct: bool,
+    zero_fill: bool,
+}
+
+impl FsFile {
+    fn new(
+        file: File,
+        path: Arc<PathBuf>,
+        permit: DescriptorPermit,
+        options: FsOpenOptions,
+    ) -> Self {
+        Self {
+            file: Arc::new(AsyncMutex::new(Some(file))),
+            path,
+            leak: None,
+            permit: Arc::new(Mutex::new(permit)),
+            mode: options.mode,
+            direct: options.direct,
+            zero_fill: options.zero_fill,
+        }
+    }
+
+    fn options(&self) -> FsOpenOptions {
+        FsOpenOptions {
+            mode: self.mode,
+            direct: self.direct,
+            zero_fill: self.zero_fill,
+        }
+    }
+
+    /**
+        Checks that the handle was opened in a mode that allows reading, so that
+        reading from it fails with a clear error instead of an obscure OS error.
+    */
+    fn check_readable(&self) -> LuaResult<()> {
+        if self.mode.is_readable() {
+            Ok(())
+        } else {
+            Err(LuaError::RuntimeError(format!(
+                "{NOT_READABLE}: File handle was opened with mode '{}' and can not be read from",
+                self.mode.name()
+            )))
+        }
+    }
+
+    /**
+        Checks that the handle was opened in a mode that allows writing, see
+        [`FsFile::check_readable`]. This also prevents writes from ever
+        succeeding on platforms that do not enforce read-only descriptors.
+    */
+    fn check_writable(&self) -> LuaResult<()> {
+        if self.mode.is_writable() {
+            Ok(())
+        } else {
+            Err(LuaError::RuntimeError(format!(
+                "{NOT_WRITABLE}: File handle was opened with mode '{}' and can not be written to",
+                self.mode.name()
+            )))
+        }
+    }
+
+    /**
+        Enables leak tracking for this handle, if enabled by the embedder.
+
+        This should be called for any handle that is given to Lua without being closed for it.
+    */
+    #[must_use]
+    pub fn track_leaks(mut self, lua: &Lua) -> Self {
+        self.leak = LeakTracker::new(lua, self.path.to_path_buf()).map(Arc::new);
+        self
+    }
+
+    fn mark_closed(&self) {
+        if let Some(leak) = &self.leak {
+            leak.mark_closed();
+        }
+        if let Ok(mut permit) = self.permit.lock() {
+            permit.release();
+        }
+    }
+
+    pub async fn open(
+        lua: &Lua,
+        path: impl AsRef<Path>,
+        options: FsOpenOptions,
+    ) -> LuaResult<Self> {
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let path = path.as_ref().to_path_buf();
+        let mut open_options = options.mode.to_open_options();
+        if options.direct {
+            direct::set_direct_flags(&mut open_options);
+        }
+        let file = open_options.open(&path).await?;
+        if options.direct {
+            direct::enable_direct(&file)?;
+        }
+        Ok(Self::new(file, Arc::new(path), permit, options))
+    }
+
+    /**
+        Duplicates the underlying file descriptor into a new, separate handle.
+
+        The new handle shares its cursor with this one, but can be closed independently.
+    */
+    pub async fn duplicate(&self, lua: &Lua) -> LuaResult<Self> {
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let guard = self.file.lock().await;
+        let file = guard.as_ref().ok_or_else(closed_error)?;
+        Ok(Self::new(
+            file.try_clone().await?,
+            Arc::clone(&self.path),
+            permit,
+            self.options(),
+        ))
+    }
+
+    /**
+        Opens the same file again, using the given mode, into a new handle with its own cursor.
+    */
+    pub async fn reopen(&self, lua: &Lua, mode: FsOpenMode) -> LuaResult<Self> {
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let guard = self.file.lock().await;
+        let file = guard.as_ref().ok_or_else(closed_error)?;
+        let mut open_options = mode.to_open_options();
+        if self.direct {
+            direct::set_direct_flags(&mut open_options);
+        }
+        let reopened = open_options.open(reopen_path(file, &self.path)).await?;
+        if self.direct {
+            direct::enable_direct(&reopened)?;
+        }
+        Ok(Self::new(
+            reopened,
+            Arc::clone(&self.path),
+            permit,
+            FsOpenOptions {
+                mode,
+                ..self.options()
+            },
+        ))
+    }
+
+    /**
+        The positional writer to use for this handle, which must be
+        aligned for handles that were opened for unbuffered IO.
+    */
+    fn positional_writer(&self) -> fn(&std::fs::File, &[u8], u64) -> std::io::Result<()> {
+        if self.direct {
+            direct::write_all_at
+        } else {
+            positional::write_all_at
+        }
+    }
+
+    /**
+        Fills the gap between the end of the file and `offset` with zeros, if enabled.
+
+        Appending handles always write at the end of the file, so they never leave gaps.
+    */
+    async fn fill_gap(&self, file: &File, offset: u64) -> LuaResult<()> {
+        if !self.zero_fill || self.mode.is_append() {
+            return Ok(());
+        }
+        let std_file = file.try_clone().await?.into_std().await;
+        let write = self.positional_writer();
+        spawn_blocking(move || positional::fill_zeros_to(&std_file, offset, write))
+            .await
+            .into_lua_err()??;
+        Ok(())
+    }
+
+    async fn to_std(&self) -> LuaResult<std::fs::File> {
+        let guard = self.file.lock().await;
+        let file = guard.as_ref().ok_or_else(closed_error)?;
+        Ok(file.try_clone().await?.into_std().await)
+    }
+
+    /**
+        Reads at the cursor of a handle opened for unbuffered IO, moving it forward.
+
+        The buffered reads and writes that tokio uses internally do not satisfy the
+        alignment requirements of unbuffered IO, so we use aligned positional IO instead.
+    */
+    async fn read_direct(file: &mut File, len: Option<usize>) -> LuaResult<Vec<u8>> {
+        let position = file.stream_position().await?;
+        let len = match len {
+            Some(len) => len,
+            None => usize::try_from(file.metadata().await?.len().saturating_sub(position))
+                .into_lua_err()?,
+        };
+        let std_file = file.try_clone().await?.into_std().await;
+        let bytes = spawn_blocking(move || direct::read_at(&std_file, len, position))
+            .await
+            .into_lua_err()??;
+        file.seek(SeekFrom::Start(position + bytes.len() as u64))
+            .await?;
+        Ok(bytes)
+    }
+
+    async fn write_direct(file: &mut File, bytes: &[u8]) -> LuaResult<()> {
+        let position = file.stream_position().await?;
+        let std_file = file.try_clone().await?.into_std().await;
+        let owned = bytes.to_vec();
+        spawn_blocking(move || direct::write_all_at(&std_file, &owned, position))
+            .await
+            .into_lua_err()??;
+        file.seek(SeekFrom::Start(position + bytes.len() as u64))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn read(&self, len: Option<usize>) -> LuaResult<Option<Vec<u8>>> {
+        self.check_readable()?;
+        let mut guard = self.file.lock().await;
+        let file = guard.as_mut().ok_or_else(closed_error)?;
+        let mut bytes = Vec::new();
+        match len {
+            _ if self.direct => {
+                bytes = Self::read_direct(file, len).await?;
+            }
+            None => {
+                file.read_to_end(&mut bytes).await?;
+            }
+            Some(len) => {
+                file.take(len as u64).read_to_end(&mut bytes).await?;
+            }
+        }
+        Ok(if bytes.is_empty() && len != Some(0) {
+            None
+        } else {
+            Some(bytes)
+        })
+    }
+
+    pub async fn read_exact(&self, len: usize) -> LuaResult<Vec<u8>> {
+        self.check_readable()?;
+        let mut guard = self.file.lock().await;
+        let file = guard.as_mut().ok_or_else(closed_error)?;
+        if self.direct {
+            let bytes = Self::read_direct(file, Some(len)).await?;
+            if bytes.len() < len {
+                return Err(LuaError::RuntimeError(format!(
+                    "Unexpected end of file while reading {len} bytes"
+                )));
+            }
+            return Ok(bytes);
+        }
+        let mut bytes = vec![0; len];
+        match file.read_exact(&mut bytes).await {
+            Ok(_) => Ok(bytes),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(LuaError::RuntimeError(format!(
+                "Unexpected end of file while reading {len} bytes"
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn write(&self, bytes: &[u8]) -> LuaResult<()> {
+        self.check_writable()?;
+        let mut guard = self.file.lock().await;
+        let file = guard.as_mut().ok_or_else(closed_error)?;
+        if self.zero_fill {
+            let position = file.stream_position().await?;
+            self.fill_gap(file, position).await?;
+        }
+        if self.direct {
+            return Self::write_direct(file, bytes).await;
+        }
+        file.write_all(bytes).await?;
+        // NOTE: Tokio writes in the background, flushing makes sure the write
+        // is visible to positional reads and other handles once we return
+        file.flush().await?;
+        Ok(())
+    }
+
+    pub async fn seek(&self, whence: FsSeekWhence, offset: i64) -> LuaResult<u64> {
+        let mut guard = self.file.lock().await;
+        let file = guard.as_mut().ok_or_else(closed_error)?;
+        Ok(file.seek(whence.to_seek_from(offset)?).await?)
+    }
+
+    pub async fn read_at(&self, offset: u64, len: usize) -> LuaResult<Vec<u8>> {
+        self.check_readable()?;
+        let file = self.to_std().await?;
+        let direct = self.direct;
+        spawn_blocking(move || {
+            if direct {
+                direct::read_at(&file, len, offset)
+            } else {
+                positional::read_exact_at(&file, len, offset)
+            }
+        })
+        .await
+        .into_lua_err()?
+        .into_lua_err()
+    }
+
+    pub async fn write_at(&self, offset: u64, bytes: Vec<u8>) -> LuaResult<()> {
+        self.check_writable()?;
+        let guard = self.file.lock().await;
+        let file = guard.as_ref().ok_or_else(closed_error)?;
+        self.fill_gap(file, offset).await?;
+        let std_file = file.try_clone().await?.into_std().await;
+        drop(guard);
+        let write = self.positional_writer();
+        spawn_blocking(move || write(&std_file, &bytes, offset))
+            .await
+            .into_lua_err()?
+            .into_lua_err()
+    }
+
+    /**
+        Creates a handle that child processes can use as their stdio,
+        sharing the open file, and writing to it at the same position.
+    */
+    pub async fn to_stdio(&self) -> LuaResult<StdioHandle> {
+        if self.direct {
+            return Err(LuaError::runtime(
+                "File handles opened for unbuffered IO can not be used as stdio",
+            ));
+        }
+        Ok(StdioHandle::new(self.to_std().await?))
+    }
+
+    pub async fn close(&self) -> LuaResult<()> {
+        let mut guard = self.file.lock().await;
+        let mut file = guard.take().ok_or_else(closed_error)?;
+        self.mark_closed();
+        file.flush().await?;
+        Ok(())
+    }
+
+    pub async fn close_if_open(&self) -> LuaResult<()> {
+        let mut guard = self.file.lock().await;
+        if let Some(mut file) = guard.take() {
+            self.mark_closed();
+            file.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+/**
+    Gets a path that refers to the exact file that is currently open.
+
+    On Linux this goes through the open file descriptor, so that reopening
+    still works and can not race if the original path was moved or replaced.
+*/
+#[cfg(target_os = "linux")]
+fn reopen_path(file: &File, _: &Path) -> PathBuf {
+    use std::os::fd::AsRawFd;
+    PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reopen_path(_: &File, path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+fn closed_error() -> LuaError {
+    LuaError::runtime("File handle has already been closed")
+}
+
+impl LuaUserData for FsFile {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("read", |lua, this, len: Option<usize>| async move {
+            this.read(len)
+                .await?
+                .map(|bytes| FsBinaryMode::get(lua).create(lua, bytes))
+                .transpose()
+        });
+
+        methods.add_async_method("write", |_, this, bytes: BString| async move {
+            this.write(bytes.as_bytes()).await
+        });
+
+        methods.add_async_method(
+            "seek",
+            |_, this, (whence, offset): (FsSeekWhence, Option<i64>)| async move {
+                this.seek(whence, offset.unwrap_or_default()).await
+            },
+        );
+
+        methods.add_async_method(
+            "readAt",
+            |lua, this, (offset, len): (u64, usize)| async move {
+                FsBinaryMode::get(lua).create(lua, this.read_at(offset, len).await?)
+            },
+        );
+
+        methods.add_async_method(
+            "writeAt",
+            |_, this, (offset, bytes): (u64, BString)| async move {
+                this.write_at(offset, bytes.into()).await
+            },
+        );
+
+        for &kind in FsNumberKind::ALL {
+            methods.add_async_method(
+                kind.read_method_name(),
+                move |_, this, endianness: FsEndianness| async move {
+                    let bytes = this.read_exact(kind.size()).await?;
+                    Ok(kind.decode(&bytes, endianness))
+                },
+            );
+            methods.add_async_method(
+                kind.write_method_name(),
+                move |_, this, (value, endianness): (f64, FsEndianness)| async move {
+                    this.write(&kind.encode(value, endianness)?).await
+                },
+            );
+        }
+
+        methods.add_async_method("clone", |lua, this, (): ()| async move {
+            Ok(this.duplicate(lua).await?.track_leaks(lua))
+        });
+
+        methods.add_async_method("reopen", |lua, this, mode: FsOpenMode| async move {
+            Ok(this.reopen(lua, mode).await?.track_leaks(lua))
+        });
+
+        methods.add_async_method(
+            "toStdio",
+            |_, this, (): ()| async move { this.to_stdio().await },
+        );
+
+        methods.add_async_method("close", |_, this, (): ()| async move { this.close().await });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsFile");
+    }
+}
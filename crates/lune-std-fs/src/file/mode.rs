@@ -0,0 +1,117 @@
+use std::str::FromStr;
+
+use mlua::prelude::*;
+use tokio::fs::OpenOptions;
+
+/**
+    A mode to open a file with, mirroring `io.open` in the Lua standard library.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsOpenMode {
+    #[default]
+    Read,
+    ReadWrite,
+    Write,
+    WriteRead,
+    Append,
+    AppendRead,
+}
+
+impl FsOpenMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Read => "r",
+            Self::ReadWrite => "r+",
+            Self::Write => "w",
+            Self::WriteRead => "w+",
+            Self::Append => "a",
+            Self::AppendRead => "a+",
+        }
+    }
+
+    pub fn is_readable(self) -> bool {
+        !matches!(self, Self::Write | Self::Append)
+    }
+
+    pub fn is_writable(self) -> bool {
+        !matches!(self, Self::Read)
+    }
+
+    pub fn is_append(self) -> bool {
+        matches!(self, Self::Append | Self::AppendRead)
+    }
+
+    pub fn to_open_options(self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        match self {
+            Self::Read => options.read(true),
+            Self::ReadWrite => options.read(true).write(true),
+            Self::Write => options.write(true).create(true).truncate(true),
+            Self::WriteRead => options.read(true).write(true).create(true).truncate(true),
+            Self::Append => options.append(true).create(true),
+            Self::AppendRead => options.read(true).append(true).create(true),
+        };
+        options
+    }
+}
+
+impl FromStr for FsOpenMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // NOTE: A single "b" after the mode or before its "+" is accepted for
+        // compatibility with io.open, since files are always opened in binary mode
+        let s = s.trim();
+        let mode = match s.strip_suffix('b') {
+            Some(mode) => mode.to_string(),
+            None => s.replacen("b+", "+", 1),
+        };
+        match mode.as_str() {
+            "r" => Ok(Self::Read),
+            "r+" => Ok(Self::ReadWrite),
+            "w" => Ok(Self::Write),
+            "w+" => Ok(Self::WriteRead),
+            "a" => Ok(Self::Append),
+            "a+" => Ok(Self::AppendRead),
+            _ => Err("Invalid open mode - expected 'r', 'r+', 'w', 'w+', 'a' or 'a+'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsOpenMode {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "FsOpenMode",
+                message: Some("Open mode must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_binary_modes() {
+        for (s, mode) in [
+            ("rb", FsOpenMode::Read),
+            ("r+b", FsOpenMode::ReadWrite),
+            ("rb+", FsOpenMode::ReadWrite),
+            ("wb", FsOpenMode::Write),
+            ("ab+", FsOpenMode::AppendRead),
+        ] {
+            assert_eq!(s.parse(), Ok(mode), "{s} should be accepted");
+        }
+    }
+
+    #[test]
+    fn rejects_misplaced_binary_flags() {
+        for s in ["bbr", "rb+b", "bw", "rbb", "b", "b+", "r+bb"] {
+            assert!(s.parse::<FsOpenMode>().is_err(), "{s} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use mlua::prelude::*;
+
+use super::FsOpenMode;
+
+/**
+    Options for opening a file handle.
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsOpenOptions {
+    /// The mode to open the file with, defaults to reading like `io.open`.
+    pub(crate) mode: FsOpenMode,
+    pub(crate) direct: bool,
+    /// Whether gaps left by writing past the end of the file should
+    /// be filled with zeros, instead of being left as sparse holes.
+    pub(crate) zero_fill: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsOpenOptions {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            value @ LuaValue::String(_) => Self {
+                mode: FsOpenMode::from_lua(value, lua)?,
+                ..Self::default()
+            },
+            LuaValue::Table(t) => Self {
+                mode: t.get::<_, Option<_>>("mode")?.unwrap_or_default(),
+                direct: t.get::<_, Option<bool>>("direct")?.unwrap_or(false),
+                zero_fill: t.get::<_, Option<bool>>("zeroFill")?.unwrap_or(false),
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsOpenOptions",
+                    message: Some(format!(
+                        "Invalid open options - expected table, string or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs::File as StdFile;
+use std::io::Result as IoResult;
+
+/*
+    Positional reads and writes, which do not use or modify the cursor of the
+    file on unix platforms. Note that on Windows the cursor will be moved, so
+    we restore it manually afterwards to keep the behavior consistent.
+*/
+
+#[cfg(unix)]
+pub fn read_at(file: &StdFile, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(unix)]
+pub fn write_all_at(file: &StdFile, buf: &[u8], offset: u64) -> IoResult<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(windows)]
+pub fn read_at(file: &StdFile, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+    use std::io::{Seek, SeekFrom};
+    use std::os::windows::fs::FileExt;
+    let mut file = file;
+    let position = file.stream_position()?;
+    let res = file.seek_read(buf, offset);
+    file.seek(SeekFrom::Start(position))?;
+    res
+}
+
+#[cfg(windows)]
+pub fn write_all_at(file: &StdFile, mut buf: &[u8], mut offset: u64) -> IoResult<()> {
+    use std::io::{Error, ErrorKind, Seek, SeekFrom};
+    use std::os::windows::fs::FileExt;
+    let mut file = file;
+    let position = file.stream_position()?;
+    let mut res = Ok(());
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => {
+                res = Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ));
+                break;
+            }
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => {
+                res = Err(e);
+                break;
+            }
+        }
+    }
+    file.seek(SeekFrom::Start(position))?;
+    res
+}
+
+/**
+    Reads exactly `len` bytes at `offset`, or fewer if the end of the file is reached.
+*/
+pub fn read_exact_at(file: &StdFile, len: usize, offset: u64) -> IoResult<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let mut filled = 0;
+    while filled < len {
+        match read_at(file, &mut buf[filled..], offset + filled as u64)? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    buf.truncate(filled);
+    Ok(buf)
+}
+
+/**
+    How many zeros to write at once when filling gaps.
+*/
+const ZERO_FILL_CHUNK: usize = 64 * 1024;
+
+/**
+    Writes zeros from the current end of the file up to `offset`, using the given writer,
+    so that the gap is allocated and zeroed instead of being left as a sparse hole.
+
+    Does nothing if the file already extends to `offset`.
+*/
+pub fn fill_zeros_to(
+    file: &StdFile,
+    offset: u64,
+    write: fn(&StdFile, &[u8], u64) -> IoResult<()>,
+) -> IoResult<()> {
+    let mut position = file.metadata()?.len();
+    let zeros = vec![0; ZERO_FILL_CHUNK];
+    while position < offset {
+        let len = usize::try_from(offset - position)
+            .map_or(ZERO_FILL_CHUNK, |len| len.min(ZERO_FILL_CHUNK));
+        write(file, &zeros[..len], position)?;
+        position += len as u64;
+    }
+    Ok(())
+}
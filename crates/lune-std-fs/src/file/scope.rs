@@ -0,0 +1,42 @@
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+use super::{FsFile, FsOpenMode, FsOpenOptions};
+use crate::wrapper::create_luau_wrapper;
+
+const WITH_OPEN_IMPL_LUA: &str = r"
+return function(path, mode, callback)
+	local file = open(path, mode)
+	local results = pack(pcall(callback, file))
+	closeIfOpen(file)
+	if not results[1] then
+		error(results[2], 0)
+	end
+	return unpack(results, 2, results.n)
+end
+";
+
+/**
+    Creates the `fs.withOpen` function, which opens a file handle, passes
+    it to a callback, and always closes it once the callback returns or errors.
+*/
+pub fn create_with_open(lua: &Lua) -> LuaResult<LuaFunction> {
+    let env = TableBuilder::new(lua)?
+        .with_async_function(
+            "open",
+            |lua, (path, mode): (String, Option<FsOpenMode>)| async move {
+                let options = FsOpenOptions {
+                    mode: mode.unwrap_or_default(),
+                    ..FsOpenOptions::default()
+                };
+                FsFile::open(lua, path, options).await
+            },
+        )?
+        .with_async_function("closeIfOpen", |_, file: LuaUserDataRef<FsFile>| {
+            let file = file.clone();
+            async move { file.close_if_open().await }
+        })?;
+
+    create_luau_wrapper(lua, "withOpen", WITH_OPEN_IMPL_LUA, env)
+}
@@ -0,0 +1,171 @@
+use std::str::FromStr;
+
+use mlua::prelude::*;
+
+const WINDOWS_RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/**
+    The platform whose rules a file name is checked against.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FilenamePlatform {
+    /// Names must be valid on every platform.
+    #[default]
+    Any,
+    Windows,
+    Unix,
+}
+
+impl FilenamePlatform {
+    fn checks_windows(self) -> bool {
+        matches!(self, Self::Any | Self::Windows)
+    }
+
+    fn is_invalid_char(self, c: char) -> bool {
+        match self {
+            Self::Unix => c == '/' || c == '\0',
+            Self::Any | Self::Windows => c.is_control() || WINDOWS_RESERVED_CHARS.contains(&c),
+        }
+    }
+}
+
+impl FromStr for FilenamePlatform {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "any" => Ok(Self::Any),
+            "windows" => Ok(Self::Windows),
+            "unix" | "linux" | "macos" => Ok(Self::Unix),
+            _ => Err("Invalid platform - expected 'any', 'windows' or 'unix'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilenameOptions {
+    pub(crate) platform: FilenamePlatform,
+}
+
+impl<'lua> FromLua<'lua> for FilenameOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => match t.get::<_, Option<String>>("platform")? {
+                Some(platform) => Self {
+                    platform: platform.parse().map_err(LuaError::runtime)?,
+                },
+                None => Self::default(),
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FilenameOptions",
+                    message: Some(format!(
+                        "Invalid filename options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    Checks if the given name is valid as the name of a single file
+    or directory, returning the reason it is invalid if it is not.
+*/
+pub fn validate_filename(name: &str, platform: FilenamePlatform) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!("'{name}' can not be used as a file name"));
+    }
+    if let Some(c) = name.chars().find(|c| platform.is_invalid_char(*c)) {
+        return Err(format!("File names must not contain the character {c:?}"));
+    }
+    if platform.checks_windows() {
+        if name.ends_with('.') || name.ends_with(' ') {
+            return Err(String::from("File names must not end with a dot or space"));
+        }
+        if is_windows_reserved(name) {
+            return Err(format!("'{name}' is a reserved file name on Windows"));
+        }
+    }
+    Ok(())
+}
+
+/**
+    Converts the given name into a valid file name, by replacing invalid
+    characters with underscores, trimming trailing dots and spaces, and
+    prefixing names that are reserved with an underscore.
+*/
+pub fn sanitize_filename(name: &str, platform: FilenamePlatform) -> String {
+    let mut sanitized = name
+        .chars()
+        .map(|c| if platform.is_invalid_char(c) { '_' } else { c })
+        .collect::<String>();
+    if platform.checks_windows() {
+        sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+        if is_windows_reserved(&sanitized) {
+            sanitized.insert(0, '_');
+        }
+    }
+    if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
+        sanitized = "_".repeat(sanitized.len().max(1));
+    }
+    sanitized
+}
+
+/**
+    Checks if the name is reserved on Windows, which is the case
+    for device names, even when followed by an extension.
+*/
+fn is_windows_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_windows_names() {
+        let windows = FilenamePlatform::Windows;
+        assert!(validate_filename("report.txt", windows).is_ok());
+        assert!(validate_filename("con", windows).is_err());
+        assert!(validate_filename("NUL.tar.gz", windows).is_err());
+        assert!(validate_filename("console", windows).is_ok());
+        assert!(validate_filename("trailing.", windows).is_err());
+        assert!(validate_filename("a:b", windows).is_err());
+        assert!(validate_filename("a:b", FilenamePlatform::Unix).is_ok());
+        assert!(validate_filename("a/b", FilenamePlatform::Unix).is_err());
+    }
+
+    #[test]
+    fn sanitized_names_are_valid() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "AUX",
+            "com1.log",
+            "what?.txt. ",
+            "a/b\\c",
+            "ok.txt",
+        ] {
+            let sanitized = sanitize_filename(name, FilenamePlatform::Any);
+            assert!(
+                validate_filename(&sanitized, FilenamePlatform::Any).is_ok(),
+                "{name:?} was sanitized into invalid name {sanitized:?}"
+            );
+        }
+        assert_eq!(sanitize_filename("ok.txt", FilenamePlatform::Any), "ok.txt");
+        assert_eq!(sanitize_filename("AUX", FilenamePlatform::Any), "_AUX");
+    }
+}
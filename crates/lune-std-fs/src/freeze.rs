@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+use crate::codes::map_path_error;
+use crate::limit::DescriptorPermit;
+use crate::metadata::FsMetadata;
+
+/**
+    Captures the names and metadata of all entries in the directory at the given path,
+    returning an iterator function over the captured entries, sorted by name.
+
+    Everything is read up front, so changes made to the directory while
+    iterating are never seen, unlike when using `fs.readDir` and `fs.metadata`.
+*/
+pub async fn freeze_dir(lua: &Lua, path: String) -> LuaResult<LuaFunction<'_>> {
+    let dir = PathBuf::from(path);
+    let permit = DescriptorPermit::acquire(lua, 1).await;
+    let frozen = dir.clone();
+    let entries = spawn_blocking(move || {
+        let _permit = permit;
+        capture_entries(&frozen)
+    })
+    .await
+    .into_lua_err()??;
+
+    let entries = RefCell::new(VecDeque::from(entries));
+    lua.create_function(move |_, ()| {
+        Ok(match entries.borrow_mut().pop_front() {
+            Some((name, meta)) => (Some(name), Some(meta)),
+            None => (None, None),
+        })
+    })
+}
+
+fn capture_entries(dir: &Path) -> LuaResult<Vec<(String, FsMetadata)>> {
+    let listing = fs::read_dir(dir).map_err(|e| map_path_error(e, dir))?;
+    let mut entries = Vec::new();
+    for entry in listing {
+        let entry = entry.map_err(|e| map_path_error(e, dir))?;
+        // NOTE: Entries removed while capturing are left out, as if they had been removed before
+        let meta = match entry.metadata() {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            res => res.map_err(|e| map_path_error(e, &entry.path()))?,
+        };
+        let Ok(name) = entry.file_name().into_string() else {
+            return Err(LuaError::RuntimeError(format!(
+                "File name could not be converted into a string: '{}'",
+                entry.file_name().to_string_lossy()
+            )));
+        };
+        entries.push((name, FsMetadata::from(meta)));
+    }
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(entries)
+}
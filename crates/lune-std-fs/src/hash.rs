@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/**
+    A hash algorithm that embedders can make available to scripts, in addition
+    to the built-in `sha256` and `sha512`, such as a faster non-cryptographic hash.
+
+    Algorithms are registered by name using [`FsConfig::with_hash_algorithm`],
+    after which every function that takes a hash algorithm accepts the name,
+    including `fs.copyVerified`, `fs.verifySidecar` and the `sidecarHash`
+    option of `fs.writeFile`. Hashes are returned as lowercase hex.
+
+    [`FsConfig::with_hash_algorithm`]: crate::FsConfig::with_hash_algorithm
+*/
+pub trait FsHashAlgorithm: Send + Sync + 'static {
+    /**
+        Creates a new hasher, which is given all of the bytes to hash in order.
+    */
+    fn hasher(&self) -> Box<dyn FsHasher>;
+}
+
+/**
+    The state of a single hash being computed, created using [`FsHashAlgorithm::hasher`].
+*/
+pub trait FsHasher: Send {
+    /**
+        Adds the given bytes to the hash.
+    */
+    fn update(&mut self, bytes: &[u8]);
+
+    /**
+        Finishes the hash, returning its digest.
+    */
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+/**
+    All of the hash algorithms registered by the embedder, keyed by their lowercase name.
+*/
+#[derive(Clone, Default)]
+pub(crate) struct FsHashAlgorithms {
+    algorithms: BTreeMap<String, Arc<dyn FsHashAlgorithm>>,
+}
+
+impl FsHashAlgorithms {
+    pub fn insert(&mut self, name: &str, algorithm: Arc<dyn FsHashAlgorithm>) {
+        self.algorithms.insert(name.to_ascii_lowercase(), algorithm);
+    }
+
+    pub fn get(&self, name: &str) -> Option<(&str, Arc<dyn FsHashAlgorithm>)> {
+        let (name, algorithm) = self.algorithms.get_key_value(name)?;
+        Some((name, Arc::clone(algorithm)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<dyn FsHashAlgorithm>)> {
+        self.algorithms
+            .iter()
+            .map(|(name, algorithm)| (name.as_str(), algorithm))
+    }
+}
+
+impl fmt::Debug for FsHashAlgorithms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.algorithms.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::prelude::*;
+
+    use super::*;
+    use crate::config::{set_config, FsConfig};
+    use crate::verify::HashAlgorithm;
+
+    struct Xor;
+
+    struct XorHasher(u8);
+
+    impl FsHashAlgorithm for Xor {
+        fn hasher(&self) -> Box<dyn FsHasher> {
+            Box::new(XorHasher(0))
+        }
+    }
+
+    impl FsHasher for XorHasher {
+        fn update(&mut self, bytes: &[u8]) {
+            self.0 = bytes.iter().fold(self.0, |acc, b| acc ^ b);
+        }
+
+        fn finish(self: Box<Self>) -> Vec<u8> {
+            vec![self.0]
+        }
+    }
+
+    #[test]
+    fn resolves_registered_algorithms_by_name() {
+        let lua = Lua::new();
+        set_config(&lua, FsConfig::new().with_hash_algorithm("XOR", Xor));
+
+        let algorithm = lua
+            .unpack::<HashAlgorithm>(LuaValue::String(lua.create_string("xor").unwrap()))
+            .unwrap();
+        assert_eq!(algorithm.name(), "xor");
+        assert_eq!(algorithm.hash_bytes(&[0x0f, 0xf1]), "fe");
+        assert_eq!(HashAlgorithm::all(&lua).len(), 3);
+
+        let unknown =
+            lua.unpack::<HashAlgorithm>(LuaValue::String(lua.create_string("md5").unwrap()));
+        assert!(unknown.unwrap_err().to_string().contains("'xor'"));
+    }
+}
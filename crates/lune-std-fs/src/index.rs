@@ -0,0 +1,494 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::Metadata;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use globset::{Glob, GlobMatcher};
+use notify::RecursiveMode;
+use tokio::{
+    fs,
+    sync::{watch::Sender, Mutex as AsyncMutex},
+};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSpawnExt;
+
+use lune_std_serde::{decode, encode, EncodeDecodeConfig, EncodeDecodeFormat};
+
+use crate::atomic::write_atomic;
+use crate::cas::hash_file;
+use crate::limit::DescriptorPermit;
+use crate::watch::WatchOptions;
+
+/*
+    Saved indexes are json files in the following format, where
+    modification times are split into seconds and nanoseconds,
+    since they would not fit into a single number exactly:
+
+    {
+        "version": 1,
+        "files": {
+            "src/main.luau": { "hash": "ab12...", "size": 42, "modified": 1700000000, "modifiedNanos": 0 }
+        }
+    }
+*/
+
+const INDEX_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default)]
+pub struct IndexOptions {
+    pub(crate) file: Option<PathBuf>,
+    pub(crate) pattern: Option<GlobMatcher>,
+}
+
+impl<'lua> FromLua<'lua> for IndexOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let t = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "IndexOptions",
+                    message: Some(format!(
+                        "Invalid index options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        let pattern = match t.get::<_, Option<String>>("pattern")? {
+            Some(pattern) => Some(
+                Glob::new(&pattern)
+                    .map_err(|e| {
+                        LuaError::RuntimeError(format!(
+                            "Invalid index pattern '{pattern}'\n{}",
+                            e.kind()
+                        ))
+                    })?
+                    .compile_matcher(),
+            ),
+            None => None,
+        };
+        Ok(Self {
+            file: t.get::<_, Option<String>>("file")?.map(PathBuf::from),
+            pattern,
+        })
+    }
+}
+
+/**
+    The size and modification time of a file, used to tell
+    if a file may have changed without having to hash it.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    size: u64,
+    modified: (u64, u32),
+}
+
+impl From<&Metadata> for FileStamp {
+    fn from(meta: &Metadata) -> Self {
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or((0, 0), |d| (d.as_secs(), d.subsec_nanos()));
+        Self {
+            size: meta.len(),
+            modified,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    hash: String,
+    stamp: FileStamp,
+    generation: u64,
+}
+
+#[derive(Debug)]
+struct IndexState {
+    root: PathBuf,
+    file: Option<PathBuf>,
+    ignored: Option<String>,
+    pattern: Option<GlobMatcher>,
+    entries: BTreeMap<String, IndexEntry>,
+    removed: BTreeMap<String, u64>,
+    generation: u64,
+}
+
+impl IndexState {
+    /**
+        Converts a path inside of the root into the key used for it
+        in the index, which always uses forward slashes as separators.
+    */
+    fn key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        Some(path_key(relative))
+    }
+
+    fn includes(&self, key: &str) -> bool {
+        // NOTE: The index file itself, and temporary files created
+        // while saving it, would otherwise change on every save
+        if let Some(ignored) = &self.ignored {
+            let (dir, name) = ignored.rsplit_once('/').unwrap_or(("", ignored));
+            let (key_dir, key_name) = key.rsplit_once('/').unwrap_or(("", key));
+            if key == ignored || (dir == key_dir && key_name.starts_with(&format!(".{name}.tmp-")))
+            {
+                return false;
+            }
+        }
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(key))
+    }
+
+    /**
+        Finds all files at or below the given key, along with their stamps.
+
+        Symlinks are not followed, and entries that are removed while
+        scanning are skipped instead of resulting in an error.
+    */
+    async fn scan(&self, key: &str) -> LuaResult<BTreeMap<String, FileStamp>> {
+        let mut found = BTreeMap::new();
+        let mut queue = VecDeque::from([self.root.join(key)]);
+        while let Some(path) = queue.pop_front() {
+            let meta = match fs::symlink_metadata(&path).await {
+                Ok(meta) => meta,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if meta.is_dir() {
+                let mut entries = match fs::read_dir(&path).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    queue.push_back(entry.path());
+                }
+            } else if meta.is_file() {
+                if let Some(key) = self.key(&path).filter(|key| self.includes(key)) {
+                    found.insert(key, FileStamp::from(&meta));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /**
+        Updates all entries at or below the given key, hashing only the
+        files whose stamps have changed, and bumping the generation of
+        the index if the contents of any files were added, changed or removed.
+    */
+    async fn refresh(&mut self, lua: &Lua, key: &str) -> LuaResult<()> {
+        let mut found = self.scan(key).await?;
+        let mut vanished = Vec::new();
+        let generation = self.generation + 1;
+        let mut changed = false;
+
+        for (path, stamp) in &found {
+            if self
+                .entries
+                .get(path)
+                .is_some_and(|entry| entry.stamp == *stamp)
+            {
+                continue;
+            }
+            let full_path = self.root.join(path);
+            let permit = DescriptorPermit::acquire(lua, 1).await;
+            let hash = match hash_file(&full_path).await {
+                Ok(hash) => hash,
+                // The file was removed after scanning, it is removed from the index below
+                Err(_) if !fs::try_exists(&full_path).await? => {
+                    vanished.push(path.clone());
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            drop(permit);
+            match self.entries.get_mut(path) {
+                Some(entry) if entry.hash == hash => entry.stamp = *stamp,
+                _ => {
+                    self.entries.insert(
+                        path.clone(),
+                        IndexEntry {
+                            hash,
+                            stamp: *stamp,
+                            generation,
+                        },
+                    );
+                    self.removed.remove(path);
+                    changed = true;
+                }
+            }
+        }
+
+        for path in vanished {
+            found.remove(&path);
+        }
+        let stale = self
+            .entries
+            .keys()
+            .filter(|path| is_within(path, key) && !found.contains_key(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in stale {
+            self.entries.remove(&path);
+            self.removed.insert(path, generation);
+            changed = true;
+        }
+
+        if changed {
+            self.generation = generation;
+        }
+        Ok(())
+    }
+
+    fn changed_since(&self, token: u64) -> Vec<String> {
+        let mut changed = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.generation > token)
+            .map(|(path, _)| path)
+            .chain(
+                self.removed
+                    .iter()
+                    .filter(|(_, generation)| **generation > token)
+                    .map(|(path, _)| path),
+            )
+            .cloned()
+            .collect::<Vec<_>>();
+        changed.sort_unstable();
+        changed
+    }
+
+    fn load(&mut self, saved: &LuaValue) -> LuaResult<()> {
+        let LuaValue::Table(saved) = saved else {
+            return Err(LuaError::runtime("Invalid index file - expected an object"));
+        };
+        let version: Option<u32> = saved.get("version")?;
+        if version != Some(INDEX_VERSION) {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid index file - unsupported version {}",
+                version.map_or_else(|| String::from("nil"), |v| v.to_string())
+            )));
+        }
+        let files: LuaTable = saved.get("files")?;
+        for pair in files.pairs::<String, LuaTable>() {
+            let (path, file) = pair?;
+            self.entries.insert(
+                path,
+                IndexEntry {
+                    hash: file.get("hash")?,
+                    stamp: FileStamp {
+                        size: file.get("size")?,
+                        modified: (file.get("modified")?, file.get("modifiedNanos")?),
+                    },
+                    generation: 0,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn save<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaString<'lua>> {
+        let files = lua.create_table_with_capacity(0, self.entries.len())?;
+        for (path, entry) in &self.entries {
+            let file = lua.create_table_with_capacity(0, 4)?;
+            file.set("hash", entry.hash.as_str())?;
+            file.set("size", entry.stamp.size)?;
+            file.set("modified", entry.stamp.modified.0)?;
+            file.set("modifiedNanos", entry.stamp.modified.1)?;
+            files.set(path.as_str(), file)?;
+        }
+        let saved = lua.create_table_with_capacity(0, 2)?;
+        saved.set("version", INDEX_VERSION)?;
+        saved.set("files", files)?;
+        encode(
+            LuaValue::Table(saved),
+            lua,
+            EncodeDecodeConfig::from(EncodeDecodeFormat::Json),
+        )
+    }
+}
+
+/**
+    An index of the hashes of all files in a directory, kept up to date by a watcher.
+*/
+#[derive(Debug)]
+pub struct FsIndex {
+    state: Arc<AsyncMutex<IndexState>>,
+    shutdown_tx: Sender<bool>,
+}
+
+impl FsIndex {
+    /**
+        Builds the index for the given root directory, starting from the saved
+        index file, if there is one, and then starts watching the directory.
+    */
+    pub async fn open(lua: &Lua, root: String, options: IndexOptions) -> LuaResult<Self> {
+        let given_root = PathBuf::from(root);
+        let root = fs::canonicalize(&given_root).await.map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to resolve index root '{}'\n{e}",
+                given_root.display()
+            ))
+        })?;
+
+        let mut state = IndexState {
+            ignored: match &options.file {
+                Some(file) => resolve_ignored(&root, file).await,
+                None => None,
+            },
+            root,
+            file: options.file,
+            pattern: options.pattern,
+            entries: BTreeMap::new(),
+            removed: BTreeMap::new(),
+            generation: 0,
+        };
+
+        if let Some(file) = &state.file {
+            match fs::read(file).await {
+                Ok(bytes) => {
+                    let saved = decode(
+                        bytes,
+                        lua,
+                        EncodeDecodeConfig::from(EncodeDecodeFormat::Json),
+                    )
+                    .map_err(|e| {
+                        LuaError::RuntimeError(format!(
+                            "Failed to load index file '{}'\n{e}",
+                            file.display()
+                        ))
+                    })?;
+                    state.load(&saved)?;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // NOTE: The watcher is started before the initial
+        // scan, so that no changes are missed in between
+        let options = WatchOptions::defaults(lua);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(options.channel_capacity);
+        let mut watcher = options.create_watcher(tx).into_lua_err()?;
+        watcher
+            .watch(&state.root, RecursiveMode::Recursive)
+            .into_lua_err()?;
+
+        state.refresh(lua, "").await?;
+
+        let state = Arc::new(AsyncMutex::new(state));
+        let task_state = Arc::clone(&state);
+        let lua_inner: Rc<Lua> = lua
+            .app_data_ref::<Weak<Lua>>()
+            .expect("Missing weak lua ref")
+            .upgrade()
+            .expect("Lua was dropped unexpectedly");
+
+        let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+        lua.spawn_local(async move {
+            let _watcher = watcher;
+            loop {
+                let event = tokio::select! {
+                    res = rx.recv() => match res {
+                        Some((Ok(event), _)) => event,
+                        Some((Err(_), _)) => continue,
+                        None => break,
+                    },
+                    // NOTE: Unlike watchers, an index that has been garbage
+                    // collected can no longer be used, so it stops updating
+                    _ = shutdown_rx.changed() => break,
+                };
+                let mut state = task_state.lock().await;
+                for path in &event.paths {
+                    if let Some(key) = state.key(path) {
+                        // NOTE: Errors here usually concern a single path that
+                        // could not be read, and it is retried on the next event
+                        let _ = state.refresh(&lua_inner, &key).await;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { state, shutdown_tx })
+    }
+}
+
+impl LuaUserData for FsIndex {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("lookup", |_, this, path: String| async move {
+            let key = path_key(Path::new(&path));
+            let state = this.state.lock().await;
+            Ok(state.entries.get(&key).map(|entry| entry.hash.clone()))
+        });
+
+        methods.add_async_method("changedSince", |_, this, token: Option<u64>| async move {
+            let state = this.state.lock().await;
+            Ok((state.changed_since(token.unwrap_or(0)), state.generation))
+        });
+
+        methods.add_async_method("save", |lua, this, ()| async move {
+            let state = this.state.lock().await;
+            let Some(file) = state.file.clone() else {
+                return Err(LuaError::runtime(
+                    "Index has no file to save to - pass the 'file' option to fs.index",
+                ));
+            };
+            let contents = state.save(lua)?.as_bytes().to_vec();
+            write_atomic(file, contents).await
+        });
+
+        methods.add_method("close", |_, this, ()| {
+            if *this.shutdown_tx.borrow() {
+                Err(LuaError::runtime("Index already closed"))
+            } else {
+                this.shutdown_tx.send_replace(true);
+                Ok(())
+            }
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsIndex");
+    }
+}
+
+fn path_key(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_within(path: &str, key: &str) -> bool {
+    key.is_empty()
+        || path
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/**
+    Finds the key of the index file, if it is stored inside of the
+    root, so that it can be left out of the index.
+*/
+async fn resolve_ignored(root: &Path, file: &Path) -> Option<String> {
+    let parent = match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent = fs::canonicalize(parent).await.ok()?;
+    let relative = parent.join(file.file_name()?);
+    Some(path_key(relative.strip_prefix(root).ok()?))
+}
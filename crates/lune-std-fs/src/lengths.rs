@@ -0,0 +1,143 @@
+use std::io::{Error as IoError, Result as IoResult};
+use std::path::Path;
+
+use mlua::prelude::*;
+
+use crate::codes::NAME_TOO_LONG;
+
+/**
+    The maximum lengths of paths and file names on a filesystem.
+
+    Lengths are in bytes on unix, and in UTF-16 code units on Windows.
+    A limit of `None` means that the filesystem does not have a limit.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathLimits {
+    pub max_name: Option<usize>,
+    pub max_path: Option<usize>,
+}
+
+impl PathLimits {
+    /**
+        Gets the limits for the filesystem containing the given path.
+
+        The path does not need to exist, in which case the limits
+        of its closest existing ancestor are read instead.
+    */
+    pub fn for_path(path: &Path) -> IoResult<Self> {
+        let existing = path
+            .ancestors()
+            .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+            .unwrap_or(Path::new("."));
+        platform::limits(existing)
+    }
+
+    /**
+        Checks if the given path, or any of its components, are longer than the limits allow.
+    */
+    pub fn exceeded_by(&self, path: &Path) -> bool {
+        let name_too_long = self.max_name.is_some_and(|max| {
+            path.components()
+                .any(|component| platform::len(component.as_os_str()) > max)
+        });
+        let path_too_long = self
+            .max_path
+            .is_some_and(|max| platform::len(path.as_os_str()) > max);
+        name_too_long || path_too_long
+    }
+}
+
+impl<'lua> IntoLua<'lua> for PathLimits {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 2)?;
+        tab.set("maxNameLength", self.max_name)?;
+        tab.set("maxPathLength", self.max_path)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    Checks if an I/O error was caused by a path or file name being too long.
+*/
+pub fn is_name_too_long(err: &IoError) -> bool {
+    err.raw_os_error() == Some(platform::NAME_TOO_LONG)
+}
+
+/**
+    Creates the error for when the given path is too long for its filesystem.
+
+    The message starts with `NameTooLong`, so that scripts can tell these errors apart from others.
+*/
+pub fn name_too_long_error(path: &Path) -> LuaError {
+    let limits = match PathLimits::for_path(path) {
+        Ok(PathLimits {
+            max_name: Some(name),
+            max_path: Some(path),
+        }) => format!(" (max name length {name}, max path length {path})"),
+        _ => String::new(),
+    };
+    LuaError::RuntimeError(format!(
+        "{NAME_TOO_LONG}: The path '{}' is too long for its filesystem{limits}",
+        path.display()
+    ))
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::{CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    use super::{IoError, IoResult, Path, PathLimits};
+
+    pub const NAME_TOO_LONG: i32 = libc::ENAMETOOLONG;
+
+    pub fn len(s: &OsStr) -> usize {
+        s.len()
+    }
+
+    pub fn limits(path: &Path) -> IoResult<PathLimits> {
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(IoError::other)?;
+        Ok(PathLimits {
+            max_name: pathconf(&path, libc::_PC_NAME_MAX)?,
+            max_path: pathconf(&path, libc::_PC_PATH_MAX)?,
+        })
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn pathconf(path: &CString, name: libc::c_int) -> IoResult<Option<usize>> {
+        // NOTE: The path is known to exist, so a return value of -1
+        // here means that the filesystem does not have a limit
+        // SAFETY: The path is a valid null-terminated string for the duration of the call
+        let value = unsafe { libc::pathconf(path.as_ptr(), name) };
+        Ok(usize::try_from(value).ok())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+
+    use super::{IoResult, Path, PathLimits};
+
+    // ERROR_FILENAME_EXCED_RANGE
+    pub const NAME_TOO_LONG: i32 = 206;
+
+    const MAX_NAME: usize = 255;
+    const MAX_PATH: usize = 260;
+
+    pub fn len(s: &OsStr) -> usize {
+        s.encode_wide().count()
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn limits(_: &Path) -> IoResult<PathLimits> {
+        // NOTE: Long paths may be enabled system-wide, but since that also requires
+        // an opt-in from each application, the classic limit is the one to stay under
+        Ok(PathLimits {
+            max_name: Some(MAX_NAME),
+            max_path: Some(MAX_PATH),
+        })
+    }
+}
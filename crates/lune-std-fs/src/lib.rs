@@ -1,28 +1,149 @@
 #![allow(clippy::cargo_common_metadata)]
 
 use std::io::ErrorKind as IoErrorKind;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use bstr::{BString, ByteSlice};
-use globset::Glob;
-use notify::event::{AccessKind, ModifyKind, RenameMode};
-use notify::{EventKind, RecursiveMode, Watcher};
-use tokio::fs;
+use bstr::BString;
+use tokio::{fs, task::spawn_blocking};
 
 use mlua::prelude::*;
-use mlua_luau_scheduler::LuaSchedulerExt;
 
+use lune_std_serde::{encode, EncodeDecodeConfig, EncodeDecodeFormat};
 use lune_utils::TableBuilder;
-use watch::WatchOptions;
 
+mod access;
+mod append;
+mod atomic;
+mod attrs;
+mod backend;
+mod backup;
+mod batch;
+mod binary;
+mod cancel;
+mod cas;
+mod clock;
+mod codes;
+mod config;
+mod contents;
 mod copy;
+mod count;
+mod csv;
+mod cwd;
+mod denied;
+mod device;
+mod dirs;
+mod file;
+mod filename;
+mod freeze;
+mod hash;
+mod index;
+mod lengths;
+mod limit;
+mod link;
+mod listing;
 mod metadata;
+mod monitor;
+mod mount;
+mod normalize;
 mod options;
+mod parallel;
+mod patch;
+mod perms;
+mod poll;
+mod remove;
+mod resolver;
+mod save;
+mod separators;
+mod shared;
+mod sidecar;
+mod snapshot;
+mod sort;
+mod statfs;
+mod stats;
+mod stream;
+mod temp;
+mod transaction;
+mod trash;
+mod tree;
+mod typedefs;
+mod verify;
 mod watch;
-
+mod which;
+mod wrapper;
+
+use self::access::check_access;
+use self::append::append_line;
+use self::attrs::{change_attributes, parse_time, AttributeChange};
+use self::backup::{backup_entry, backup_file, restore_backup, DEFAULT_BACKUP_SUFFIX};
+use self::batch::{
+    remove_paths, write_files, RemovePathResult, RemovePathsOptions, WriteFilesOptions,
+};
+use self::binary::ConfigureOptions;
+use self::cas::create_cas;
+use self::codes::create_error_codes;
+use self::codes::map_path_error;
+use self::contents::{read_small_file, write_contents_if_missing, FileContents};
 use self::copy::copy;
+use self::count::{count_entries, CountOptions};
+use self::csv::{read_csv, CsvOptions};
+use self::cwd::{change_dir, current_dir};
+use self::dirs::FsDirs;
+use self::file::{create_with_open, FsFile, FsOpenOptions};
+use self::filename::{sanitize_filename, validate_filename, FilenameOptions};
+use self::freeze::freeze_dir;
+use self::index::{FsIndex, IndexOptions};
+use self::lengths::PathLimits;
+use self::limit::DescriptorPermit;
+use self::link::{create_junction, link_dir, metadata_with};
+use self::listing::{read_dir_with_metadata, DirEntryInfo};
 use self::metadata::FsMetadata;
-use self::options::FsWriteOptions;
+use self::monitor::{monitor_dir, MonitorOptions};
+use self::mount::{mount_point, MountInfo};
+use self::normalize::PathNormalization;
+use self::options::{
+    FsAccessOptions, FsAttributeOptions, FsCopyVerifiedOptions, FsMetadataOptions,
+    FsReadDirOptions, FsReadFileOptions, FsReadTreeOptions, FsRemoveOptions, FsSaveOptions,
+    FsWriteDirOptions, FsWriteFileOptions, FsWriteOptions,
+};
+use self::parallel::{run_parallel, ParallelOptions};
+use self::patch::{
+    apply_patch, create_patch, delta, find_bytes, patch_bytes, patch_key_value, DeltaOptions,
+    FsDelta, KvPatch, KvPatchOptions,
+};
+use self::perms::{missing_ancestors, parse_mode, set_mode, DefaultModes};
+use self::poll::{poll_metadata, PollOptions};
+use self::remove::{empty_dir, remove_dir, remove_file, remove_file_if_exists};
+use self::save::{save_atomic, SaveTags};
+use self::separators::create_path;
+use self::shared::FsSharedFile;
+use self::sidecar::{verify_sidecar, write_sidecar};
+use self::snapshot::FsSnapshot;
+use self::statfs::FilesystemInfo;
+use self::stats::{FsOpStats, OpTimer};
+use self::stream::{FsReadStream, FsReadStreamOptions, FsWriteStream, FsWriteStreamOptions};
+use self::temp::FsTempFile;
+use self::transaction::create_transaction;
+use self::tree::{read_tree, write_tree, FsTreeContents, FsTreeEntry};
+use self::verify::{copy_verified, verify_contents, HashAlgorithm};
+use self::watch::{
+    await_quiet, create_event_kinds, prewatched, watch, watch_cost, watch_serve, FsWatcher,
+    QuietOptions, WatchCost, WatchCostOptions, WatchOptions,
+};
+use self::which::which;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use self::backend::IoUringBackend;
+pub use self::backend::{FsBackend, FsEntryKind, FsEntryStat, FsFuture};
+pub use self::binary::FsBinaryMode;
+pub use self::clock::{FsClock, FsSleep, ManualClock};
+pub use self::config::{set_config, FsConfig};
+pub use self::file::{take_leak_reports, FsLeakReport};
+pub use self::hash::{FsHashAlgorithm, FsHasher};
+pub use self::resolver::FsResolver;
+pub use self::typedefs::typedefs;
+pub use self::watch::{
+    FsPrewatch, FsPrewatchOptions, FsWatchFeed, FsWatchFeedEvent, WatchBackend, WatchDefaults,
+};
 
 /**
     Creates the `fs` standard library module.
@@ -35,166 +156,706 @@ pub fn module(lua: &Lua) -> LuaResult<LuaTable> {
     TableBuilder::new(lua)?
         .with_async_function("readFile", fs_read_file)?
         .with_async_function("readDir", fs_read_dir)?
+        .with_async_function("readDirWithMetadata", fs_read_dir_with_metadata)?
+        .with_async_function("freezeDir", fs_freeze_dir)?
+        .with_async_function("readTree", fs_read_tree)?
+        .with_async_function("readCsv", fs_read_csv)?
+        .with_async_function("countEntries", fs_count_entries)?
         .with_async_function("writeFile", fs_write_file)?
+        .with_async_function("writeFileIfMissing", fs_write_file_if_missing)?
+        .with_async_function("writeFiles", fs_write_files)?
+        .with_async_function("saveAtomic", fs_save_atomic)?
+        .with_async_function("appendJsonLine", fs_append_json_line)?
+        .with_async_function("patchKeyValue", fs_patch_key_value)?
+        .with_async_function("patchBytes", fs_patch_bytes)?
+        .with_async_function("findBytes", fs_find_bytes)?
+        .with_async_function("delta", fs_delta)?
+        .with_async_function("createPatch", fs_create_patch)?
+        .with_async_function("applyPatch", fs_apply_patch)?
         .with_async_function("writeDir", fs_write_dir)?
+        .with_async_function("writeTree", fs_write_tree)?
+        .with_async_function("ensureDir", fs_ensure_dir)?
+        .with_async_function("ensureFile", fs_ensure_file)?
         .with_async_function("removeFile", fs_remove_file)?
+        .with_async_function("removeFileIfExists", fs_remove_file_if_exists)?
         .with_async_function("removeDir", fs_remove_dir)?
+        .with_async_function("emptyDir", fs_empty_dir)?
+        .with_async_function("removePaths", fs_remove_paths)?
         .with_async_function("metadata", fs_metadata)?
+        .with_async_function("access", fs_access)?
+        .with_async_function("mountPoint", fs_mount_point)?
+        .with_async_function("statFs", fs_stat_fs)?
+        .with_async_function("limits", fs_limits)?
+        .with_function("pollMetadata", fs_poll_metadata)?
+        .with_async_function("monitor", fs_monitor)?
+        .with_function("normalizePath", fs_normalize_path)?
+        .with_function("isValidFilename", fs_is_valid_filename)?
+        .with_function("sanitizeFilename", fs_sanitize_filename)?
+        .with_function("dirs", fs_dirs)?
+        .with_function("cwd", fs_cwd)?
+        .with_function("chdir", fs_chdir)?
+        .with_function("setDefaultMode", fs_set_default_mode)?
+        .with_function("configure", fs_configure)?
+        .with_async_function("setPermissions", fs_set_permissions)?
+        .with_async_function("chown", fs_chown)?
+        .with_async_function("setTimes", fs_set_times)?
+        .with_async_function("which", fs_which)?
         .with_async_function("isFile", fs_is_file)?
         .with_async_function("isDir", fs_is_dir)?
         .with_async_function("move", fs_move)?
         .with_async_function("copy", fs_copy)?
-        .with_async_function("watch", fs_watch)?
+        .with_async_function("copyVerified", fs_copy_verified)?
+        .with_async_function("verifySidecar", fs_verify_sidecar)?
+        .with_async_function("restoreBackup", fs_restore_backup)?
+        .with_async_function("snapshotDir", fs_snapshot_dir)?
+        .with_async_function("parallel", fs_parallel)?
+        .with_value("transaction", create_transaction(lua)?)?
+        .with_value("cas", create_cas(lua)?)?
+        .with_value("path", create_path(lua)?)?
+        .with_value("eventKinds", create_event_kinds(lua)?)?
+        .with_value("errorCodes", create_error_codes(lua)?)?
+        .with_async_function("index", fs_index)?
+        .with_async_function("junction", fs_junction)?
+        .with_async_function("linkDir", fs_link_dir)?
+        .with_function("watch", fs_watch)?
+        .with_function("prewatched", fs_prewatched)?
+        .with_function("watchServe", fs_watch_serve)?
+        .with_async_function("awaitQuiet", fs_await_quiet)?
+        .with_async_function("watchCost", fs_watch_cost)?
+        .with_async_function("open", fs_open)?
+        .with_value("withOpen", create_with_open(lua)?)?
+        .with_async_function("openShared", fs_open_shared)?
+        .with_async_function("tempFileIn", fs_temp_file_in)?
+        .with_async_function("createReadStream", fs_create_read_stream)?
+        .with_async_function("createWriteStream", fs_create_write_stream)?
         .build_readonly()
 }
 
-async fn fs_read_file(lua: &Lua, path: String) -> LuaResult<LuaString> {
-    let bytes = fs::read(&path).await.into_lua_err()?;
+async fn fs_read_file(
+    lua: &Lua,
+    (path, options): (String, FsReadFileOptions),
+) -> LuaResult<(LuaValue, Option<FsOpStats>)> {
+    let mut timer = OpTimer::start();
+    let bytes = read_file_bytes(lua, path, &mut timer).await?;
+    let stats = options.stats.then(|| timer.finish(bytes.len() as u64));
+    Ok((FsConfig::get(lua).binary_mode.create(lua, bytes)?, stats))
+}
 
-    lua.create_string(bytes)
+async fn read_file_bytes(lua: &Lua, path: String, timer: &mut OpTimer) -> LuaResult<Vec<u8>> {
+    let config = FsConfig::get(lua);
+    if let Some((resolver, path)) = config.resolvers.resolve(&path) {
+        let path = path.to_string();
+        return spawn_blocking(move || resolver.read(&path))
+            .await
+            .into_lua_err()?
+            .into_lua_err();
+    }
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    timer.acquired();
+    let threshold = config.small_read_threshold();
+    if config.backend.is_default() && threshold > 0 {
+        let small = read_small_file(path.as_ref(), threshold)
+            .map_err(|e| map_path_error(e, path.as_ref()))?;
+        if let Some(bytes) = small {
+            return Ok(bytes);
+        }
+    }
+    config
+        .backend
+        .get()
+        .read(path.as_ref())
+        .await
+        .map_err(|e| map_path_error(e, path.as_ref()))
 }
 
-async fn fs_read_dir(_: &Lua, path: String) -> LuaResult<Vec<String>> {
+async fn fs_read_dir(
+    lua: &Lua,
+    (path, options): (String, FsReadDirOptions),
+) -> LuaResult<Vec<String>> {
+    let backend = FsConfig::get(lua).backend;
     let mut dir_strings = Vec::new();
-    let mut dir = fs::read_dir(&path).await.into_lua_err()?;
-    while let Some(dir_entry) = dir.next_entry().await.into_lua_err()? {
-        if let Some(dir_name_str) = dir_entry.file_name().to_str() {
-            dir_strings.push(dir_name_str.to_owned());
+    let names = backend
+        .get()
+        .read_dir(path.as_ref())
+        .await
+        .map_err(|e| map_path_error(e, path.as_ref()))?;
+    for dir_name in names {
+        if let Some(dir_name_str) = dir_name.to_str() {
+            dir_strings.push(match options.normalize {
+                Some(form) => form.apply(dir_name_str),
+                None => dir_name_str.to_owned(),
+            });
         } else {
             return Err(LuaError::RuntimeError(format!(
                 "File name could not be converted into a string: '{}'",
-                dir_entry.file_name().to_string_lossy()
+                dir_name.to_string_lossy()
             )));
         }
     }
+    if let Some(sort) = options.sort {
+        sort.sort(&mut dir_strings);
+    }
     Ok(dir_strings)
 }
 
-async fn fs_write_file(_: &Lua, (path, contents): (String, BString)) -> LuaResult<()> {
-    fs::write(&path, contents.as_bytes()).await.into_lua_err()
+async fn fs_read_tree(
+    _: &Lua,
+    (path, options): (String, FsReadTreeOptions),
+) -> LuaResult<FsTreeEntry> {
+    read_tree(path, options).await
+}
+
+async fn fs_read_dir_with_metadata(lua: &Lua, path: String) -> LuaResult<Vec<DirEntryInfo>> {
+    read_dir_with_metadata(lua, path).await
 }
 
-async fn fs_write_dir(_: &Lua, path: String) -> LuaResult<()> {
-    fs::create_dir_all(&path).await.into_lua_err()
+async fn fs_freeze_dir(lua: &Lua, path: String) -> LuaResult<LuaFunction> {
+    freeze_dir(lua, path).await
 }
 
-async fn fs_remove_file(_: &Lua, path: String) -> LuaResult<()> {
-    fs::remove_file(&path).await.into_lua_err()
+async fn fs_count_entries(
+    lua: &Lua,
+    (path, options): (String, CountOptions),
+) -> LuaResult<(u64, Vec<String>)> {
+    count_entries(lua, path, options).await
 }
 
-async fn fs_remove_dir(_: &Lua, path: String) -> LuaResult<()> {
-    fs::remove_dir_all(&path).await.into_lua_err()
+async fn fs_read_csv(lua: &Lua, (path, options): (String, CsvOptions)) -> LuaResult<LuaFunction> {
+    read_csv(lua, path, options).await
 }
 
-async fn fs_metadata(_: &Lua, path: String) -> LuaResult<FsMetadata> {
-    match fs::metadata(path).await {
+async fn fs_write_file(
+    lua: &Lua,
+    (path, contents, options): (String, FileContents<'_>, FsWriteFileOptions),
+) -> LuaResult<Option<FsOpStats>> {
+    let mut timer = OpTimer::start();
+    let modes = DefaultModes::get(lua);
+    let backend = FsConfig::get(lua).backend;
+    let backend = backend.get();
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    timer.acquired();
+    let created = matches!(
+        backend.kind(path.as_ref()).await,
+        Err(e) if e.kind() == IoErrorKind::NotFound
+    );
+    if let Some(suffix) = &options.backup_suffix {
+        backup_file(&path, suffix).await?;
+    }
+    backend
+        .write(path.as_ref(), contents.as_bytes())
+        .await
+        .map_err(|e| map_path_error(e, path.as_ref()))?;
+    if options.verify {
+        verify_contents(backend, &path, contents.as_bytes()).await?;
+    }
+    if let Some(algorithm) = &options.sidecar_hash {
+        write_sidecar(backend, path.as_ref(), contents.as_bytes(), algorithm).await?;
+    }
+    let bytes = contents.as_bytes().len() as u64;
+    // The contents are no longer needed, so the string can be collected early
+    drop(contents);
+    if created {
+        modes.apply_file(&path).await?;
+    }
+    Ok(options.stats.then(|| timer.finish(bytes)))
+}
+
+async fn fs_write_files(
+    lua: &Lua,
+    (files, options): (LuaTable<'_>, WriteFilesOptions),
+) -> LuaResult<()> {
+    write_files(lua, files, options).await
+}
+
+async fn fs_write_file_if_missing(
+    lua: &Lua,
+    (path, contents): (String, FileContents<'_>),
+) -> LuaResult<bool> {
+    let modes = DefaultModes::get(lua);
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    let created = write_contents_if_missing(path.as_ref(), contents.as_bytes())
+        .await
+        .map_err(|e| map_path_error(e, path.as_ref()))?;
+    drop(contents);
+    if created {
+        modes.apply_file(&path).await?;
+    }
+    Ok(created)
+}
+
+async fn fs_save_atomic(
+    lua: &Lua,
+    (path, contents, options): (String, FileContents<'_>, FsSaveOptions),
+) -> LuaResult<()> {
+    save_atomic(lua, path.as_ref(), contents.as_bytes(), options).await
+}
+
+async fn fs_append_json_line(lua: &Lua, (path, value): (String, LuaValue<'_>)) -> LuaResult<()> {
+    // NOTE: Compact json never contains newlines, since any in strings are escaped
+    let line = encode(
+        value,
+        lua,
+        EncodeDecodeConfig::from(EncodeDecodeFormat::Json),
+    )?;
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    append_line(path, line.as_bytes()).await
+}
+
+async fn fs_patch_key_value(
+    lua: &Lua,
+    (path, values, options): (String, LuaTable<'_>, KvPatchOptions),
+) -> LuaResult<()> {
+    let patch = KvPatch::from_table(&values, options.format)?;
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    patch_key_value(path, &patch, options).await
+}
+
+async fn fs_patch_bytes(lua: &Lua, (path, offset, bytes): (String, u64, BString)) -> LuaResult<()> {
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    patch_bytes(path.into(), offset, bytes.into()).await
+}
+
+async fn fs_find_bytes(
+    lua: &Lua,
+    (path, needle, start): (String, BString, Option<u64>),
+) -> LuaResult<Option<u64>> {
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    find_bytes(path.into(), needle.into(), start.unwrap_or(0)).await
+}
+
+async fn fs_delta(
+    lua: &Lua,
+    (a, b, options): (String, String, DeltaOptions),
+) -> LuaResult<FsDelta> {
+    let _permit = DescriptorPermit::acquire(lua, 2).await;
+    delta(a.into(), b.into(), options).await
+}
+
+async fn fs_create_patch(
+    lua: &Lua,
+    (old, new, patch_path, options): (String, String, String, DeltaOptions),
+) -> LuaResult<u64> {
+    let _permit = DescriptorPermit::acquire(lua, 3).await;
+    create_patch(old.into(), new.into(), patch_path.into(), options).await
+}
+
+async fn fs_apply_patch(
+    lua: &Lua,
+    (old, patch_path, out): (String, String, String),
+) -> LuaResult<()> {
+    let _permit = DescriptorPermit::acquire(lua, 3).await;
+    apply_patch(old.into(), patch_path.into(), out.into()).await
+}
+
+async fn fs_write_dir(lua: &Lua, (path, options): (String, FsWriteDirOptions)) -> LuaResult<bool> {
+    let modes = DefaultModes::get(lua);
+    let backend = FsConfig::get(lua).backend;
+    let backend = backend.get();
+    let created = missing_ancestors(backend, &path).await;
+    if created.is_empty()
+        && (!options.exist_ok || backend.kind(path.as_ref()).await? != FsEntryKind::Dir)
+    {
+        return Err(LuaError::RuntimeError(format!(
+            "A file or directory already exists at the path '{path}'"
+        )));
+    }
+    // NOTE: Without recursion, only the directory itself is created,
+    // which fails like it should if any of its parents are missing
+    let skipped = if options.recursive {
+        0
+    } else {
+        created.len().saturating_sub(1)
+    };
+    for dir in &created[skipped..] {
+        match backend.create_dir(dir).await {
+            Err(e) if e.kind() == IoErrorKind::AlreadyExists && options.recursive => {}
+            res => res.into_lua_err()?,
+        }
+    }
+    for dir in &created {
+        match options.mode {
+            Some(mode) if dir.as_path() == Path::new(&path) => set_mode(dir, mode).await?,
+            _ => modes.apply_dir(dir).await?,
+        }
+    }
+    Ok(!created.is_empty())
+}
+
+async fn fs_write_tree(lua: &Lua, (root, tree): (String, FsTreeContents)) -> LuaResult<()> {
+    write_tree(lua, root, tree, DefaultModes::get(lua)).await
+}
+
+async fn fs_ensure_dir(lua: &Lua, path: String) -> LuaResult<bool> {
+    fs_write_dir(lua, (path, FsWriteDirOptions::default())).await
+}
+
+async fn fs_ensure_file(lua: &Lua, path: String) -> LuaResult<bool> {
+    let path = PathBuf::from(path);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        let parent = parent.to_string_lossy().into_owned();
+        fs_write_dir(lua, (parent, FsWriteDirOptions::default())).await?;
+    }
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await
+    {
+        Ok(_) => {
+            DefaultModes::get(lua).apply_file(&path).await?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == IoErrorKind::AlreadyExists && path.is_file() => Ok(false),
+        Err(e) if e.kind() == IoErrorKind::AlreadyExists => Err(LuaError::RuntimeError(format!(
+            "A directory already exists at the path '{}'",
+            path.display()
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn fs_remove_file(lua: &Lua, (path, options): (String, FsRemoveOptions)) -> LuaResult<()> {
+    let backend = FsConfig::get(lua).backend;
+    remove_file(backend.get(), path, options).await
+}
+
+async fn fs_remove_file_if_exists(lua: &Lua, path: String) -> LuaResult<bool> {
+    let backend = FsConfig::get(lua).backend;
+    remove_file_if_exists(backend.get(), path).await
+}
+
+async fn fs_remove_dir(lua: &Lua, (path, options): (String, FsRemoveOptions)) -> LuaResult<()> {
+    let backend = FsConfig::get(lua).backend;
+    remove_dir(backend.get(), path, options).await
+}
+
+async fn fs_empty_dir(_: &Lua, (path, options): (String, FsRemoveOptions)) -> LuaResult<()> {
+    empty_dir(path, options).await
+}
+
+async fn fs_remove_paths(
+    lua: &Lua,
+    (paths, options): (Vec<String>, RemovePathsOptions),
+) -> LuaResult<Vec<RemovePathResult>> {
+    remove_paths(lua, paths, options).await
+}
+
+async fn fs_access(_: &Lua, (path, options): (String, FsAccessOptions)) -> LuaResult<bool> {
+    check_access(path, options).await
+}
+
+async fn fs_metadata(
+    _: &Lua,
+    (path, options): (String, FsMetadataOptions),
+) -> LuaResult<FsMetadata> {
+    match metadata_with(&path, options.follow_symlinks).await {
         Err(e) if e.kind() == IoErrorKind::NotFound => Ok(FsMetadata::not_found()),
-        Ok(meta) => Ok(FsMetadata::from(meta)),
+        Ok(meta) => {
+            let mount = spawn_blocking(move || MountInfo::for_path(path.as_ref()).ok())
+                .await
+                .into_lua_err()?;
+            Ok(FsMetadata::from(meta).with_mount_info(mount))
+        }
         Err(e) => Err(e.into()),
     }
 }
 
-async fn fs_is_file(_: &Lua, path: String) -> LuaResult<bool> {
-    match fs::metadata(path).await {
+async fn fs_mount_point(_: &Lua, path: String) -> LuaResult<String> {
+    let mount = spawn_blocking(move || mount_point(path.as_ref()))
+        .await
+        .into_lua_err()??;
+    Ok(mount.to_string_lossy().into_owned())
+}
+
+async fn fs_stat_fs(_: &Lua, path: String) -> LuaResult<FilesystemInfo> {
+    let info = spawn_blocking(move || FilesystemInfo::for_path(path.as_ref()))
+        .await
+        .into_lua_err()??;
+    Ok(info)
+}
+
+fn fs_poll_metadata(lua: &Lua, (path, options): (String, PollOptions)) -> LuaResult<LuaFunction> {
+    poll_metadata(lua, path, options)
+}
+
+async fn fs_parallel<'lua>(
+    lua: &'lua Lua,
+    (tasks, options): (LuaTable<'lua>, ParallelOptions),
+) -> LuaResult<LuaValue<'lua>> {
+    run_parallel(lua, tasks, options).await
+}
+
+async fn fs_monitor(
+    lua: &Lua,
+    (root, options): (String, MonitorOptions),
+) -> LuaResult<LuaFunction> {
+    monitor_dir(lua, root, options).await
+}
+
+async fn fs_limits(_: &Lua, path: String) -> LuaResult<PathLimits> {
+    let limits = spawn_blocking(move || PathLimits::for_path(path.as_ref()))
+        .await
+        .into_lua_err()??;
+    Ok(limits)
+}
+
+async fn fs_is_file(lua: &Lua, path: String) -> LuaResult<bool> {
+    let backend = FsConfig::get(lua).backend;
+    match backend.get().kind(path.as_ref()).await {
         Err(e) if e.kind() == IoErrorKind::NotFound => Ok(false),
-        Ok(meta) => Ok(meta.is_file()),
+        Ok(kind) => Ok(kind == FsEntryKind::File),
         Err(e) => Err(e.into()),
     }
 }
 
-async fn fs_is_dir(_: &Lua, path: String) -> LuaResult<bool> {
-    match fs::metadata(path).await {
+async fn fs_is_dir(lua: &Lua, path: String) -> LuaResult<bool> {
+    let backend = FsConfig::get(lua).backend;
+    match backend.get().kind(path.as_ref()).await {
         Err(e) if e.kind() == IoErrorKind::NotFound => Ok(false),
-        Ok(meta) => Ok(meta.is_dir()),
+        Ok(kind) => Ok(kind == FsEntryKind::Dir),
         Err(e) => Err(e.into()),
     }
 }
 
-async fn fs_move(_: &Lua, (from, to, options): (String, String, FsWriteOptions)) -> LuaResult<()> {
+async fn fs_move(
+    lua: &Lua,
+    (from, to, options): (String, String, FsWriteOptions),
+) -> LuaResult<()> {
+    let backend = FsConfig::get(lua).backend;
+    let backend = backend.get();
     let path_from = PathBuf::from(from);
-    if !path_from.exists() {
+    if backend.kind(&path_from).await.is_err() {
         return Err(LuaError::RuntimeError(format!(
             "No file or directory exists at the path '{}'",
             path_from.display()
         )));
     }
     let path_to = PathBuf::from(to);
-    if !options.overwrite && path_to.exists() {
+    if !options.overwrite && backend.kind(&path_to).await.is_ok() {
         return Err(LuaError::RuntimeError(format!(
             "A file or directory already exists at the path '{}'",
             path_to.display()
         )));
     }
-    fs::rename(path_from, path_to).await.into_lua_err()?;
+    // NOTE: Both paths are tagged, since watchers may see the
+    // move as a removal of the source and a creation of the target
+    let _writes = if options.internal {
+        let tags = SaveTags::get(lua);
+        Some((
+            tags.begin_internal_write(&path_from).await,
+            tags.begin_internal_write(&path_to).await,
+        ))
+    } else {
+        None
+    };
+    if let Some(suffix) = &options.backup_suffix {
+        backup_entry(&path_to, suffix).await?;
+    }
+    backend.rename(&path_from, &path_to).await.into_lua_err()?;
     Ok(())
 }
 
-async fn fs_copy(_: &Lua, (from, to, options): (String, String, FsWriteOptions)) -> LuaResult<()> {
-    copy(from, to, options).await
+async fn fs_copy(
+    lua: &Lua,
+    (from, to, options): (String, String, FsWriteOptions),
+) -> LuaResult<Option<FsOpStats>> {
+    let mut timer = OpTimer::start();
+    let stats = options.stats;
+    // NOTE: Files are copied one at a time, so copying
+    // never has more than a source and a target open
+    let _permit = DescriptorPermit::acquire(lua, 2).await;
+    timer.acquired();
+    let _write = if options.internal {
+        Some(SaveTags::get(lua).begin_internal_write(to.as_ref()).await)
+    } else {
+        None
+    };
+    // NOTE: Skipped paths are only ever part of the stats, so
+    // they are returned whenever paths are allowed to be skipped
+    let stats = stats || !options.on_denied.is_error();
+    let summary = copy(lua, from, to, options, DefaultModes::get(lua)).await?;
+    let skipped = summary
+        .skipped
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    Ok(stats.then(|| timer.finish(summary.bytes).with_skipped(skipped)))
 }
 
-async fn fs_watch(
+async fn fs_copy_verified(
     lua: &Lua,
-    (root_path, options, handlers): (String, WatchOptions, LuaTable<'_>),
-) -> LuaResult<()> {
-    let to_watch_files = options.watch_diretories;
-    let to_watch_dirs = options.watch_files;
+    (from, to, options): (String, String, FsCopyVerifiedOptions),
+) -> LuaResult<String> {
+    let _permit = DescriptorPermit::acquire(lua, 2).await;
+    copy_verified(from, to, options, DefaultModes::get(lua)).await
+}
+
+async fn fs_verify_sidecar(
+    lua: &Lua,
+    (path, algorithm): (String, Option<HashAlgorithm>),
+) -> LuaResult<bool> {
+    let algorithms = match algorithm {
+        Some(algorithm) => vec![algorithm],
+        None => HashAlgorithm::all(lua),
+    };
+    let _permit = DescriptorPermit::acquire(lua, 2).await;
+    verify_sidecar(path.as_ref(), algorithms).await
+}
 
-    let added_handler = handlers.get::<_, LuaFunction>("added").ok();
-    let read_handler = handlers.get::<_, LuaFunction>("read").ok();
-    let removed_handler = handlers.get::<_, LuaFunction>("removed").ok();
-    let changed_handler = handlers.get::<_, LuaFunction>("changed").ok();
-    let renamed_handler = handlers.get::<_, LuaFunction>("renamed").ok();
+async fn fs_snapshot_dir(lua: &Lua, path: String) -> LuaResult<FsSnapshot> {
+    FsSnapshot::capture(lua, path).await
+}
 
-    let glob = Glob::new(&options.pattern)
-        .into_lua_err()?
-        .compile_matcher();
+async fn fs_restore_backup(_: &Lua, (path, suffix): (String, Option<String>)) -> LuaResult<()> {
+    restore_backup(path, suffix.as_deref().unwrap_or(DEFAULT_BACKUP_SUFFIX)).await
+}
 
-    let recursive_mode = if options.recursive {
-        RecursiveMode::Recursive
-    } else {
-        RecursiveMode::NonRecursive
-    };
+fn fs_normalize_path(
+    _: &Lua,
+    (path, form): (String, Option<PathNormalization>),
+) -> LuaResult<String> {
+    Ok(form.unwrap_or_default().apply(&path))
+}
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
-    let mut watcher = options.into_watcher(tx).into_lua_err()?;
-
-    watcher
-        .watch(&PathBuf::from(root_path), recursive_mode)
-        .into_lua_err()?;
-
-    while let Some(res) = rx.recv().await {
-        let event = res.into_lua_err()?;
-        let filtered_paths = event
-            .paths
-            .iter()
-            .filter(|elem| (elem.is_file() && to_watch_files) || (elem.is_dir() && to_watch_dirs))
-            .filter(|elem| (glob.is_match(elem)))
-            .map(|elem| elem.to_string_lossy())
-            .collect::<Vec<_>>();
-
-        if filtered_paths.is_empty() {
-            continue;
-        }
+fn fs_is_valid_filename(
+    _: &Lua,
+    (name, options): (String, FilenameOptions),
+) -> LuaResult<(bool, Option<String>)> {
+    match validate_filename(&name, options.platform) {
+        Ok(()) => Ok((true, None)),
+        Err(reason) => Ok((false, Some(reason))),
+    }
+}
 
-        let handler = match event.kind {
-            EventKind::Access(AccessKind::Read) => &read_handler, // File was read
-            EventKind::Remove(_) => &removed_handler,             // File was removed
-            EventKind::Create(_) => &added_handler,               // File was created
-            EventKind::Modify(ModifyKind::Data(_)) => &changed_handler, // File was edited
+fn fs_sanitize_filename(_: &Lua, (name, options): (String, FilenameOptions)) -> LuaResult<String> {
+    Ok(sanitize_filename(&name, options.platform))
+}
 
-            // NOTE: Ideally, it would be nice to supply the handler with the old and new file names
-            // but notify-rs currently doesn't support this; see https://github.com/notify-rs/notify/issues/376
-            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => &renamed_handler, // File was renamed
+fn fs_dirs(_: &Lua, (): ()) -> LuaResult<FsDirs> {
+    Ok(FsDirs::discover())
+}
 
-            // Unsupported Events
-            _ => continue,
-        };
+fn fs_set_default_mode(lua: &Lua, modes: DefaultModes) -> LuaResult<()> {
+    let mut config = FsConfig::get(lua);
+    config.default_modes = modes;
+    set_config(lua, config);
+    Ok(())
+}
 
-        if let Some(handler) = handler {
-            lua.push_thread_back(handler, filtered_paths)?;
-        }
+fn fs_configure(lua: &Lua, options: ConfigureOptions) -> LuaResult<()> {
+    let mut config = FsConfig::get(lua);
+    if let Some(mode) = options.binary {
+        config.binary_mode = mode;
     }
-
+    set_config(lua, config);
     Ok(())
 }
+
+async fn fs_set_permissions(
+    _: &Lua,
+    (path, mode, options): (String, LuaValue<'_>, FsAttributeOptions),
+) -> LuaResult<()> {
+    let Some(mode) = parse_mode(mode)? else {
+        return Err(LuaError::runtime("Missing permission mode"));
+    };
+    change_attributes(path, AttributeChange::Mode(mode), options).await
+}
+
+async fn fs_chown(
+    _: &Lua,
+    (path, uid, gid, options): (String, Option<u32>, Option<u32>, FsAttributeOptions),
+) -> LuaResult<()> {
+    change_attributes(path, AttributeChange::Owner { uid, gid }, options).await
+}
+
+async fn fs_set_times(
+    _: &Lua,
+    (path, accessed, modified, options): (String, LuaValue<'_>, LuaValue<'_>, FsAttributeOptions),
+) -> LuaResult<()> {
+    let change = AttributeChange::Times {
+        accessed: parse_time(accessed)?,
+        modified: parse_time(modified)?,
+    };
+    change_attributes(path, change, options).await
+}
+
+fn fs_cwd(_: &Lua, (): ()) -> LuaResult<String> {
+    current_dir()
+}
+
+fn fs_chdir(lua: &Lua, path: String) -> LuaResult<()> {
+    change_dir(lua, &path)
+}
+
+async fn fs_which(_: &Lua, name: String) -> LuaResult<Option<String>> {
+    let found = spawn_blocking(move || which(&name)).await.into_lua_err()?;
+    Ok(found.map(|path| path.to_string_lossy().into_owned()))
+}
+
+async fn fs_index(lua: &Lua, (root, options): (String, IndexOptions)) -> LuaResult<FsIndex> {
+    FsIndex::open(lua, root, options).await
+}
+
+async fn fs_junction(_: &Lua, (target, link): (String, String)) -> LuaResult<()> {
+    create_junction(target, link).await
+}
+
+async fn fs_link_dir(_: &Lua, (target, link): (String, String)) -> LuaResult<()> {
+    link_dir(target, link).await
+}
+
+async fn fs_open(lua: &Lua, (path, options): (String, FsOpenOptions)) -> LuaResult<FsFile> {
+    Ok(FsFile::open(lua, path, options).await?.track_leaks(lua))
+}
+
+async fn fs_open_shared(lua: &Lua, path: String) -> LuaResult<FsSharedFile> {
+    FsSharedFile::open(lua, path).await
+}
+
+async fn fs_temp_file_in(lua: &Lua, dir: String) -> LuaResult<FsTempFile> {
+    FsTempFile::create_in(lua, dir).await
+}
+
+async fn fs_create_read_stream(
+    lua: &Lua,
+    (path, options): (String, FsReadStreamOptions),
+) -> LuaResult<FsReadStream> {
+    FsReadStream::open(lua, path, options).await
+}
+
+async fn fs_create_write_stream(
+    lua: &Lua,
+    (path, options): (String, FsWriteStreamOptions),
+) -> LuaResult<FsWriteStream> {
+    FsWriteStream::open(lua, path, options).await
+}
+
+fn fs_watch(
+    lua: &Lua,
+    (root_path, options, handlers): (String, WatchOptions, LuaTable<'_>),
+) -> LuaResult<FsWatcher> {
+    watch(lua, root_path, options, handlers)
+}
+
+fn fs_prewatched(
+    lua: &Lua,
+    (name, options, handlers): (String, WatchOptions, LuaTable<'_>),
+) -> LuaResult<FsWatcher> {
+    prewatched(lua, name, options, handlers)
+}
+
+fn fs_watch_serve(lua: &Lua, (root_path, options): (String, WatchOptions)) -> LuaResult<FsWatcher> {
+    watch_serve(lua, root_path, options)
+}
+
+async fn fs_await_quiet(lua: &Lua, (path, options): (String, QuietOptions)) -> LuaResult<()> {
+    await_quiet(lua, path, options).await
+}
+
+async fn fs_watch_cost(
+    lua: &Lua,
+    (root_path, options): (String, WatchCostOptions),
+) -> LuaResult<WatchCost> {
+    watch_cost(lua, root_path, options).await
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use mlua::prelude::*;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::FsConfig;
+
+/**
+    A shared limit on the number of files that may be open at once.
+*/
+#[derive(Debug, Clone)]
+pub(crate) struct OpenFileLimit {
+    max: u32,
+    semaphore: Arc<Semaphore>,
+}
+
+impl OpenFileLimit {
+    pub fn new(max: u32) -> Self {
+        Self {
+            max,
+            semaphore: Arc::new(Semaphore::new(max as usize)),
+        }
+    }
+}
+
+/**
+    A permit for keeping some number of files open, acquired from the
+    limit set using [`FsConfig::with_max_open_files`], if there is one.
+
+    The permit is released when dropped.
+*/
+#[derive(Debug, Default)]
+pub(crate) struct DescriptorPermit(Option<OwnedSemaphorePermit>);
+
+impl DescriptorPermit {
+    /**
+        Waits until `count` files may be opened.
+
+        Waiting operations are queued fairly, in the order they started waiting.
+    */
+    pub async fn acquire(lua: &Lua, count: u32) -> Self {
+        let Some(limit) = FsConfig::get(lua).open_files else {
+            return Self(None);
+        };
+        // NOTE: An operation that needs more files than the limit allows
+        // would wait forever, so it gets to use the entire limit instead
+        let count = count.min(limit.max);
+        // The semaphore is never closed, so acquiring can not fail here
+        Self(limit.semaphore.acquire_many_owned(count).await.ok())
+    }
+
+    pub fn release(&mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn permits_are_limited_and_released() {
+        let lua = Lua::new();
+        let config = FsConfig::new().with_max_open_files(2);
+        let semaphore = Arc::clone(&config.open_files.as_ref().unwrap().semaphore);
+        crate::set_config(&lua, config);
+
+        let mut first = DescriptorPermit::acquire(&lua, 1).await;
+        assert_eq!(semaphore.available_permits(), 1);
+        first.release();
+        assert_eq!(semaphore.available_permits(), 2);
+
+        let oversized = DescriptorPermit::acquire(&lua, 5).await;
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(oversized);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn unlimited_by_default() {
+        let lua = Lua::new();
+        assert!(DescriptorPermit::acquire(&lua, 1).await.0.is_none());
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs::Metadata;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::*;
+use tokio::{fs, task::spawn_blocking};
+
+/**
+    Creates a directory junction at `link` pointing to `target`.
+
+    Junctions only exist on Windows, where unlike directory symlinks
+    they can be created without administrator privileges.
+*/
+pub async fn create_junction(target: impl AsRef<Path>, link: impl AsRef<Path>) -> LuaResult<()> {
+    let target = std::path::absolute(target.as_ref())?;
+    let link = link.as_ref().to_path_buf();
+    if !target.is_dir() {
+        return Err(LuaError::RuntimeError(format!(
+            "No directory exists at the junction target path '{}'",
+            target.display()
+        )));
+    }
+    spawn_blocking(move || junction(&target, &link))
+        .await
+        .into_lua_err()?
+}
+
+/**
+    Links the directory at `target` to `link`, using a junction on
+    Windows and a symbolic link on all other platforms.
+
+    The target is resolved relative to the current working directory, same
+    as for junctions, instead of relative to the link like symbolic links are.
+*/
+pub async fn link_dir(target: impl AsRef<Path>, link: impl AsRef<Path>) -> LuaResult<()> {
+    if cfg!(windows) {
+        create_junction(target, link).await
+    } else {
+        let target = std::path::absolute(target.as_ref())?;
+        symlink_dir(target, link.as_ref().to_path_buf()).await
+    }
+}
+
+/**
+    Gets the metadata of a path, or of whatever it points
+    to if it is a symlink and `follow_symlinks` is set.
+*/
+pub async fn metadata_with(path: impl AsRef<Path>, follow_symlinks: bool) -> IoResult<Metadata> {
+    if follow_symlinks {
+        fs::metadata(path).await
+    } else {
+        fs::symlink_metadata(path).await
+    }
+}
+
+/**
+    Creates a symlink at `link` pointing to the same path as the symlink at `source`.
+*/
+pub async fn copy_symlink(source: impl AsRef<Path>, link: impl AsRef<Path>) -> LuaResult<()> {
+    let source = source.as_ref();
+    let target = fs::read_link(source).await?;
+    create_symlink(source, target, link.as_ref()).await
+}
+
+#[cfg(windows)]
+fn junction(target: &Path, link: &Path) -> LuaResult<()> {
+    use std::process::Command;
+
+    // NOTE: Creating junctions natively requires building a reparse point
+    // by hand, mklink is always available and does exactly that for us
+    let output = Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(link)
+        .arg(target)
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Failed to create junction at '{}'\n{}",
+            link.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(not(windows))]
+fn junction(_: &Path, _: &Path) -> LuaResult<()> {
+    Err(LuaError::runtime(
+        "Junctions are only supported on Windows, use linkDir to link directories on any platform",
+    ))
+}
+
+#[cfg(unix)]
+async fn create_symlink(_: &Path, target: PathBuf, link: &Path) -> LuaResult<()> {
+    fs::symlink(target, link).await.into_lua_err()
+}
+
+#[cfg(not(unix))]
+async fn create_symlink(source: &Path, target: PathBuf, link: &Path) -> LuaResult<()> {
+    // NOTE: Windows needs to know if a symlink points to a directory when
+    // creating it, which we can only find out if the source is not broken
+    if fs::metadata(source).await.is_ok_and(|meta| meta.is_dir()) {
+        fs::symlink_dir(target, link).await.into_lua_err()
+    } else {
+        fs::symlink_file(target, link).await.into_lua_err()
+    }
+}
+
+#[cfg(unix)]
+async fn symlink_dir(target: PathBuf, link: PathBuf) -> LuaResult<()> {
+    fs::symlink(target, link).await.into_lua_err()
+}
+
+#[cfg(not(unix))]
+async fn symlink_dir(target: PathBuf, link: PathBuf) -> LuaResult<()> {
+    create_junction(target, link).await
+}
@@ -0,0 +1,173 @@
+use std::fs::{self, DirEntry, FileType};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::PathBuf;
+
+use tokio::task::{spawn_blocking, JoinSet};
+
+use mlua::prelude::*;
+
+use lune_std_datetime::DateTime;
+
+use crate::backend::{FsEntryKind, SharedBackend};
+use crate::codes::map_path_error;
+use crate::config::FsConfig;
+use crate::limit::DescriptorPermit;
+use crate::metadata::{system_time_to_timestamp, FsMetadataKind};
+
+/**
+    The number of batches that the metadata of entries is fetched in at once.
+*/
+const CONCURRENCY: usize = 8;
+
+/**
+    An entry in a directory, along with the parts of its metadata that listings usually need.
+*/
+#[derive(Debug, Clone)]
+pub struct DirEntryInfo {
+    name: String,
+    kind: FsMetadataKind,
+    size: u64,
+    modified_at: Option<DateTime>,
+}
+
+impl<'lua> IntoLua<'lua> for DirEntryInfo {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 4)?;
+        tab.set("name", self.name)?;
+        tab.set("kind", self.kind)?;
+        tab.set("size", self.size)?;
+        tab.set("modifiedAt", self.modified_at)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    Lists the entries in the directory at the given path, along with their metadata.
+
+    The metadata comes from the directory entries themselves, which is free on Windows and a
+    single `fstatat` relative to the already open directory on unix, and is fetched in batches
+    on blocking threads, instead of resolving the full path of every entry one after another.
+
+    Backends registered by the embedder get the metadata of all entries in a single batch instead.
+*/
+pub async fn read_dir_with_metadata(lua: &Lua, path: String) -> LuaResult<Vec<DirEntryInfo>> {
+    let dir = PathBuf::from(path);
+    let backend = FsConfig::get(lua).backend;
+    if !backend.is_default() {
+        return read_dir_with_backend(lua, &backend, dir).await;
+    }
+    let permit = DescriptorPermit::acquire(lua, 1).await;
+    let listed = dir.clone();
+    let mut entries = spawn_blocking(move || {
+        let _permit = permit;
+        fs::read_dir(listed)?.collect::<IoResult<Vec<_>>>()
+    })
+    .await
+    .into_lua_err()?
+    .map_err(|e| map_path_error(e, &dir))?;
+
+    let batch_size = entries.len().div_ceil(CONCURRENCY).max(1);
+    let mut running = JoinSet::new();
+    let mut index = 0;
+    while !entries.is_empty() {
+        let rest = entries.split_off(batch_size.min(entries.len()));
+        let batch = std::mem::replace(&mut entries, rest);
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        running.spawn_blocking(move || {
+            let _permit = permit;
+            (index, read_batch(batch))
+        });
+        index += 1;
+    }
+
+    let mut batches = vec![Vec::new(); index];
+    while let Some(res) = running.join_next().await {
+        let (index, batch) = res.into_lua_err()?;
+        batches[index] = batch?;
+    }
+    Ok(batches.into_iter().flatten().collect())
+}
+
+async fn read_dir_with_backend(
+    lua: &Lua,
+    backend: &SharedBackend,
+    dir: PathBuf,
+) -> LuaResult<Vec<DirEntryInfo>> {
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    let backend = backend.get();
+    let names = backend
+        .read_dir(&dir)
+        .await
+        .map_err(|e| map_path_error(e, &dir))?;
+    let paths = names.iter().map(|name| dir.join(name)).collect::<Vec<_>>();
+    let stats = backend
+        .stat_many(&paths)
+        .await
+        .map_err(|e| map_path_error(e, &dir))?;
+
+    let mut infos = Vec::with_capacity(names.len());
+    for ((name, path), stat) in names.into_iter().zip(&paths).zip(stats) {
+        let stat = match stat {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            res => res.map_err(|e| map_path_error(e, path))?,
+        };
+        let Ok(name) = name.into_string() else {
+            return Err(LuaError::RuntimeError(format!(
+                "File name could not be converted into a string: '{}'",
+                path.display()
+            )));
+        };
+        infos.push(DirEntryInfo {
+            name,
+            kind: match stat.kind {
+                FsEntryKind::File => FsMetadataKind::File,
+                FsEntryKind::Dir => FsMetadataKind::Dir,
+                FsEntryKind::Symlink => FsMetadataKind::Symlink,
+                FsEntryKind::Other => FsMetadataKind::None,
+            },
+            size: stat.size,
+            modified_at: system_time_to_timestamp(
+                stat.modified
+                    .ok_or_else(|| IoError::from(ErrorKind::Unsupported)),
+            ),
+        });
+    }
+    Ok(infos)
+}
+
+fn read_batch(batch: Vec<DirEntry>) -> LuaResult<Vec<DirEntryInfo>> {
+    let mut infos = Vec::with_capacity(batch.len());
+    for entry in batch {
+        // NOTE: Entries removed after listing the directory are left out, like they would have been
+        let meta = match entry.metadata() {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            res => res.map_err(|e| map_path_error(e, &entry.path()))?,
+        };
+        let Ok(name) = entry.file_name().into_string() else {
+            return Err(LuaError::RuntimeError(format!(
+                "File name could not be converted into a string: '{}'",
+                entry.file_name().to_string_lossy()
+            )));
+        };
+        infos.push(DirEntryInfo {
+            name,
+            kind: entry_kind(meta.file_type()),
+            size: meta.len(),
+            modified_at: system_time_to_timestamp(meta.modified()),
+        });
+    }
+    Ok(infos)
+}
+
+/**
+    Gets the kind of an entry, without panicking for entries that are not files, directories
+    or symlinks, such as sockets and named pipes, which have no kind instead.
+*/
+fn entry_kind(file_type: FileType) -> FsMetadataKind {
+    if file_type.is_file() || file_type.is_dir() || file_type.is_symlink() {
+        file_type.into()
+    } else {
+        FsMetadataKind::None
+    }
+}
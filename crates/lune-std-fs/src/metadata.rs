@@ -10,6 +10,8 @@ use mlua::prelude::*;
 
 use lune_std_datetime::DateTime;
 
+use crate::mount::MountInfo;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FsMetadataKind {
     None,
@@ -97,10 +99,13 @@ impl<'lua> IntoLua<'lua> for FsPermissions {
 pub struct FsMetadata {
     pub(crate) kind: FsMetadataKind,
     pub(crate) exists: bool,
+    pub(crate) size: Option<u64>,
+    pub(crate) allocated_size: Option<u64>,
     pub(crate) created_at: Option<DateTime>,
     pub(crate) modified_at: Option<DateTime>,
     pub(crate) accessed_at: Option<DateTime>,
     pub(crate) permissions: Option<FsPermissions>,
+    pub(crate) mount: Option<MountInfo>,
 }
 
 impl FsMetadata {
@@ -108,23 +113,39 @@ impl FsMetadata {
         Self {
             kind: FsMetadataKind::None,
             exists: false,
+            size: None,
+            allocated_size: None,
             created_at: None,
             modified_at: None,
             accessed_at: None,
             permissions: None,
+            mount: None,
         }
     }
+
+    #[must_use]
+    pub fn with_mount_info(mut self, mount: Option<MountInfo>) -> Self {
+        self.mount = mount;
+        self
+    }
 }
 
 impl<'lua> IntoLua<'lua> for FsMetadata {
     fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
-        let tab = lua.create_table_with_capacity(0, 6)?;
+        let tab = lua.create_table_with_capacity(0, 11)?;
         tab.set("kind", self.kind)?;
         tab.set("exists", self.exists)?;
+        tab.set("size", self.size)?;
+        tab.set("allocatedSize", self.allocated_size)?;
         tab.set("createdAt", self.created_at)?;
         tab.set("modifiedAt", self.modified_at)?;
         tab.set("accessedAt", self.accessed_at)?;
         tab.set("permissions", self.permissions)?;
+        if let Some(mount) = self.mount {
+            tab.set("isMountPoint", mount.is_mount_point)?;
+            tab.set("isBindMount", mount.is_bind_mount)?;
+            tab.set("isJunction", mount.is_junction)?;
+        }
         tab.set_readonly(true);
         Ok(LuaValue::Table(tab))
     }
@@ -135,15 +156,35 @@ impl From<StdMetadata> for FsMetadata {
         Self {
             kind: value.file_type().into(),
             exists: true,
+            size: Some(value.len()),
+            allocated_size: allocated_size(&value),
             created_at: system_time_to_timestamp(value.created()),
             modified_at: system_time_to_timestamp(value.modified()),
             accessed_at: system_time_to_timestamp(value.accessed()),
             permissions: Some(FsPermissions::from(value.permissions())),
+            mount: None,
         }
     }
 }
 
-fn system_time_to_timestamp(res: IoResult<SystemTime>) -> Option<DateTime> {
+/**
+    Gets the number of bytes that the filesystem allocated for an entry, which is
+    less than its size for sparse and compressed files, and usually more for others.
+
+    Only known on Unix, where it is always counted in 512 byte blocks.
+*/
+#[cfg(unix)]
+fn allocated_size(meta: &StdMetadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.blocks() * 512)
+}
+
+#[cfg(not(unix))]
+fn allocated_size(_: &StdMetadata) -> Option<u64> {
+    None
+}
+
+pub fn system_time_to_timestamp(res: IoResult<SystemTime>) -> Option<DateTime> {
     match res {
         Ok(t) => match t.duration_since(SystemTime::UNIX_EPOCH) {
             Ok(d) => DateTime::from_unix_timestamp_float(d.as_secs_f64()).ok(),
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::{sync::Mutex as AsyncMutex, task::spawn_blocking};
+
+use mlua::prelude::*;
+
+use crate::config::FsConfig;
+use crate::limit::DescriptorPermit;
+use crate::snapshot::display_path;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MonitorOptions {
+    pub(crate) interval: Duration,
+    pub(crate) top: usize,
+}
+
+impl Default for MonitorOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            top: 10,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for MonitorOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let defaults = Self::default();
+        Ok(match value {
+            LuaValue::Nil => defaults,
+            LuaValue::Table(t) => Self {
+                interval: match t.get::<_, Option<f64>>("interval")? {
+                    None => defaults.interval,
+                    Some(secs) if secs.is_finite() && secs > 0.0 => Duration::from_secs_f64(secs),
+                    Some(secs) => {
+                        return Err(LuaError::RuntimeError(format!(
+                            "Invalid interval - expected a positive number of seconds, got {secs}"
+                        )))
+                    }
+                },
+                top: t.get::<_, Option<usize>>("top")?.unwrap_or(defaults.top),
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "MonitorOptions",
+                    message: Some(format!(
+                        "Invalid monitor options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    The parts of file metadata that are compared to detect changes.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+type Scan = HashMap<PathBuf, FileState>;
+
+/**
+    The aggregate changes to the files in a directory between two scans.
+*/
+#[derive(Debug, Clone, Default)]
+struct MonitorDelta {
+    files_added: usize,
+    files_removed: usize,
+    files_changed: usize,
+    bytes_changed: u64,
+    top_changed_paths: Vec<String>,
+}
+
+impl MonitorDelta {
+    /**
+        Compares two scans, where the bytes changed for each file are the size
+        of added and removed files, and the size difference of changed files.
+    */
+    fn between(previous: &Scan, current: &Scan, top: usize) -> Self {
+        let mut delta = Self::default();
+        let mut changed = Vec::new();
+        for (path, state) in current {
+            match previous.get(path) {
+                None => {
+                    delta.files_added += 1;
+                    changed.push((state.size, path));
+                }
+                Some(old) if old != state => {
+                    delta.files_changed += 1;
+                    changed.push((old.size.abs_diff(state.size), path));
+                }
+                Some(_) => {}
+            }
+        }
+        for (path, state) in previous {
+            if !current.contains_key(path) {
+                delta.files_removed += 1;
+                changed.push((state.size, path));
+            }
+        }
+        delta.bytes_changed = changed.iter().map(|(bytes, _)| bytes).sum();
+        changed.sort_by(|(a_bytes, a_path), (b_bytes, b_path)| {
+            b_bytes.cmp(a_bytes).then_with(|| a_path.cmp(b_path))
+        });
+        delta.top_changed_paths = changed
+            .into_iter()
+            .take(top)
+            .map(|(_, path)| display_path(path))
+            .collect();
+        delta
+    }
+}
+
+impl<'lua> IntoLua<'lua> for MonitorDelta {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 5)?;
+        tab.set("filesAdded", self.files_added)?;
+        tab.set("filesRemoved", self.files_removed)?;
+        tab.set("filesChanged", self.files_changed)?;
+        tab.set("bytesChanged", self.bytes_changed)?;
+        tab.set("topChangedPaths", self.top_changed_paths)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+#[derive(Debug)]
+struct MonitorState {
+    scan: Scan,
+    scanned_at: Instant,
+}
+
+/**
+    Creates an iterator function that, every time it is called, waits until the
+    interval has passed since the previous scan of the directory, scans it again,
+    and returns the aggregate changes to its files since the previous scan.
+
+    The directory is first scanned when the monitor is created, so that
+    the first delta contains all changes made after creating it.
+*/
+pub async fn monitor_dir(
+    lua: &Lua,
+    root: String,
+    options: MonitorOptions,
+) -> LuaResult<LuaFunction> {
+    let root = Arc::new(PathBuf::from(root));
+    let clock = FsConfig::get(lua).clock;
+    let initial = scan(lua, &root).await?;
+    let state = Arc::new(AsyncMutex::new(MonitorState {
+        scan: initial,
+        scanned_at: clock.now(),
+    }));
+    lua.create_async_function(move |lua, ()| {
+        let root = Arc::clone(&root);
+        let state = Arc::clone(&state);
+        let clock = clock.clone();
+        async move {
+            // NOTE: Holding the lock while waiting makes concurrent calls
+            // take turns, instead of comparing against the same scan
+            let mut state = state.lock().await;
+            clock.sleep_until(state.scanned_at + options.interval).await;
+            let current = scan(lua, &root).await?;
+            let delta = MonitorDelta::between(&state.scan, &current, options.top);
+            state.scan = current;
+            state.scanned_at = clock.now();
+            Ok(delta)
+        }
+    })
+}
+
+async fn scan(lua: &Lua, root: &Arc<PathBuf>) -> LuaResult<Scan> {
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    let scanned = Arc::clone(root);
+    spawn_blocking(move || scan_blocking(&scanned))
+        .await
+        .into_lua_err()?
+        .map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to scan monitored directory at '{}'\n{e}",
+                root.display()
+            ))
+        })
+}
+
+/**
+    Reads the state of all files inside of the given directory, without following symlinks.
+*/
+fn scan_blocking(root: &Path) -> IoResult<Scan> {
+    let mut files = Scan::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            // NOTE: Entries may be removed while scanning, which is not an error
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                dirs.push(relative);
+            } else if meta.is_file() {
+                let state = FileState {
+                    size: meta.len(),
+                    modified: meta.modified().ok(),
+                };
+                files.insert(relative, state);
+            }
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,334 @@
+use std::{
+    fs::Metadata as StdMetadata,
+    io::Result as IoResult,
+    path::{Path, PathBuf},
+};
+
+/*
+    Filesystem boundaries are detected differently on each platform:
+
+    - On Linux, mount points and bind mounts are read from the mount table of the
+      current process. Bind mounts usually share a device with their source, so
+      comparing devices alone would not be enough to detect those boundaries.
+    - On other unix platforms, a directory is a mount point if its device differs
+      from the device of its parent directory.
+    - On Windows, volumes may be mounted into directories using mount point reparse
+      points, which also back directory junctions, so the reparse target is used to
+      tell the two apart.
+*/
+
+/**
+    Information about how a path relates to the filesystems it is a part of.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountInfo {
+    pub is_mount_point: bool,
+    pub is_bind_mount: bool,
+    pub is_junction: bool,
+}
+
+impl MountInfo {
+    pub fn for_path(path: &Path) -> IoResult<Self> {
+        let is_junction = platform::is_junction(path)?;
+        let canonical = path.canonicalize()?;
+        let mount = platform::MountTable::load()?.entry(&canonical)?;
+        Ok(Self {
+            is_mount_point: mount.is_some(),
+            is_bind_mount: mount.is_some_and(|entry| entry.is_bind),
+            is_junction,
+        })
+    }
+}
+
+/**
+    Finds the mount point of the filesystem that contains the given path.
+*/
+pub fn mount_point(path: &Path) -> IoResult<PathBuf> {
+    let canonical = path.canonicalize()?;
+    let table = platform::MountTable::load()?;
+    for ancestor in canonical.ancestors() {
+        if table.entry(ancestor)?.is_some() {
+            return Ok(ancestor.to_path_buf());
+        }
+    }
+    // NOTE: The root of a path is always a mount point, so this should
+    // only happen if the mount table could not be read for some reason
+    Ok(canonical
+        .ancestors()
+        .last()
+        .map_or_else(|| canonical.clone(), Path::to_path_buf))
+}
+
+/**
+    Detects filesystem boundaries below a root directory, for
+    operations that should stay on the filesystem of the root.
+*/
+#[derive(Debug)]
+pub struct FilesystemBoundaries {
+    root: PathBuf,
+    root_device: Option<u64>,
+    table: platform::MountTable,
+}
+
+impl FilesystemBoundaries {
+    /**
+        Creates boundaries for the given root, which must be a canonical path.
+    */
+    pub fn new(root: &Path, root_meta: &StdMetadata) -> IoResult<Self> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            root_device: platform::device(root_meta),
+            table: platform::MountTable::load()?,
+        })
+    }
+
+    /**
+        Checks if the given path, which must be a canonical
+        path below the root, is on a different filesystem.
+    */
+    pub fn crosses(&self, path: &Path, meta: &StdMetadata) -> IoResult<bool> {
+        if path == self.root {
+            return Ok(false);
+        }
+        if let (Some(root), Some(device)) = (self.root_device, platform::device(meta)) {
+            if root != device {
+                return Ok(true);
+            }
+        }
+        Ok(meta.is_dir() && self.table.entry(path)?.is_some())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use platform::filesystem_type;
+
+pub use platform::device;
+
+#[derive(Debug, Clone, Copy)]
+struct MountEntry {
+    is_bind: bool,
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{IoResult, MountEntry, Path, PathBuf, StdMetadata};
+
+    pub fn device(meta: &StdMetadata) -> Option<u64> {
+        use std::os::unix::fs::MetadataExt;
+        Some(meta.dev())
+    }
+
+    pub fn is_junction(_: &Path) -> IoResult<bool> {
+        Ok(false)
+    }
+
+    #[derive(Debug)]
+    pub struct MountTable {
+        // Vec<(mount point, root of the mount within its filesystem)>
+        entries: Vec<(PathBuf, PathBuf)>,
+    }
+
+    impl MountTable {
+        pub fn load() -> IoResult<Self> {
+            let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+            Ok(Self {
+                entries: mountinfo.lines().filter_map(parse_mountinfo_line).collect(),
+            })
+        }
+
+        #[allow(clippy::unnecessary_wraps)]
+        pub fn entry(&self, path: &Path) -> IoResult<Option<MountEntry>> {
+            // NOTE: Later entries shadow earlier ones mounted at the same path
+            Ok(self
+                .entries
+                .iter()
+                .rev()
+                .find(|(mount_point, _)| mount_point == path)
+                .map(|(_, root)| MountEntry {
+                    is_bind: root != Path::new("/"),
+                }))
+        }
+    }
+
+    /**
+        Gets the type of the filesystem mounted at the given mount point, such as `ext4`.
+    */
+    pub fn filesystem_type(mount_point: &Path) -> IoResult<Option<String>> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+        // NOTE: Later entries shadow earlier ones mounted at the same path
+        Ok(mountinfo
+            .lines()
+            .rev()
+            .filter(|line| parse_mountinfo_line(line).is_some_and(|(path, _)| path == mount_point))
+            .find_map(|line| {
+                let (_, optional) = line.split_once(" - ")?;
+                optional.split(' ').next().map(str::to_string)
+            }))
+    }
+
+    /**
+        Parses the root and mount point fields of a line in `/proc/self/mountinfo`.
+    */
+    fn parse_mountinfo_line(line: &str) -> Option<(PathBuf, PathBuf)> {
+        let mut fields = line.split(' ').skip(3);
+        let root = fields.next()?;
+        let mount_point = fields.next()?;
+        Some((unescape(mount_point), unescape(root)))
+    }
+
+    /**
+        Unescapes whitespace and backslashes, which the kernel escapes as octal.
+    */
+    fn unescape(field: &str) -> PathBuf {
+        let bytes = field.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+                let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or_default();
+                if let Ok(byte) = u8::from_str_radix(digits, 8) {
+                    out.push(byte);
+                    i += 4;
+                    continue;
+                }
+            }
+            out.push(bytes[i]);
+            i += 1;
+        }
+        PathBuf::from(String::from_utf8_lossy(&out).into_owned())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod platform {
+    use super::{IoResult, MountEntry, Path, StdMetadata};
+
+    pub fn device(meta: &StdMetadata) -> Option<u64> {
+        use std::os::unix::fs::MetadataExt;
+        Some(meta.dev())
+    }
+
+    pub fn is_junction(_: &Path) -> IoResult<bool> {
+        Ok(false)
+    }
+
+    #[derive(Debug)]
+    pub struct MountTable;
+
+    impl MountTable {
+        #[allow(clippy::unnecessary_wraps)]
+        pub fn load() -> IoResult<Self> {
+            Ok(Self)
+        }
+
+        #[allow(clippy::unused_self)]
+        pub fn entry(&self, path: &Path) -> IoResult<Option<MountEntry>> {
+            use std::os::unix::fs::MetadataExt;
+            let meta = std::fs::metadata(path)?;
+            let is_mount_point = match path.parent() {
+                Some(parent) => {
+                    let parent_meta = std::fs::metadata(parent)?;
+                    parent_meta.dev() != meta.dev() || parent_meta.ino() == meta.ino()
+                }
+                None => true,
+            };
+            Ok(is_mount_point.then_some(MountEntry { is_bind: false }))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{io::Error as IoError, iter::once, mem::MaybeUninit, os::windows::ffi::OsStrExt};
+
+    use super::{IoResult, MountEntry, Path, StdMetadata};
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x0000_0400;
+    const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+    const INVALID_HANDLE_VALUE: isize = -1;
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct Win32FindDataW {
+        file_attributes: u32,
+        creation_time: [u32; 2],
+        last_access_time: [u32; 2],
+        last_write_time: [u32; 2],
+        file_size_high: u32,
+        file_size_low: u32,
+        reserved0: u32,
+        reserved1: u32,
+        file_name: [u16; 260],
+        alternate_file_name: [u16; 14],
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn FindFirstFileW(file_name: *const u16, find_data: *mut Win32FindDataW) -> isize;
+        fn FindClose(find_file: isize) -> i32;
+    }
+
+    /**
+        Gets the reparse tag of the given path, if it is a reparse point.
+
+        The standard library treats junctions, volume mount points and symbolic
+        links the same, so the only way to tell them apart is using this tag.
+    */
+    fn reparse_tag(path: &Path) -> IoResult<Option<u32>> {
+        let wide = path
+            .as_os_str()
+            .encode_wide()
+            .chain(once(0))
+            .collect::<Vec<_>>();
+        let mut data = MaybeUninit::<Win32FindDataW>::zeroed();
+        // SAFETY: The path is null-terminated and the find data is valid for writes
+        let handle = unsafe { FindFirstFileW(wide.as_ptr(), data.as_mut_ptr()) };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(IoError::last_os_error());
+        }
+        // SAFETY: The handle is valid since the call above succeeded, and
+        // the struct was zero-initialized so it is valid even if not written
+        let data = unsafe {
+            FindClose(handle);
+            data.assume_init()
+        };
+        Ok((data.file_attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0).then_some(data.reserved0))
+    }
+
+    /**
+        Checks if the path is a mount point reparse point, and
+        if it is, whether it mounts a volume or is a junction.
+    */
+    fn mount_reparse_target(path: &Path) -> IoResult<Option<bool>> {
+        if path.parent().is_none() || reparse_tag(path)? != Some(IO_REPARSE_TAG_MOUNT_POINT) {
+            return Ok(None);
+        }
+        let target = std::fs::read_link(path)?;
+        Ok(Some(target.to_string_lossy().contains("Volume{")))
+    }
+
+    pub fn device(_: &StdMetadata) -> Option<u64> {
+        None
+    }
+
+    pub fn is_junction(path: &Path) -> IoResult<bool> {
+        Ok(mount_reparse_target(path)? == Some(false))
+    }
+
+    #[derive(Debug)]
+    pub struct MountTable;
+
+    impl MountTable {
+        #[allow(clippy::unnecessary_wraps)]
+        pub fn load() -> IoResult<Self> {
+            Ok(Self)
+        }
+
+        #[allow(clippy::unused_self)]
+        pub fn entry(&self, path: &Path) -> IoResult<Option<MountEntry>> {
+            let is_root = path.parent().is_none();
+            let is_volume = mount_reparse_target(path)? == Some(true);
+            Ok((is_root || is_volume).then_some(MountEntry { is_bind: false }))
+        }
+    }
+}
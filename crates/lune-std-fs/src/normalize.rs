@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+use unicode_normalization::UnicodeNormalization;
+
+use mlua::prelude::*;
+
+/**
+    A unicode normalization form to convert paths into.
+
+    Some filesystems, such as HFS+ on macOS, store names decomposed
+    while most input is composed, so names that look the same may
+    not compare equal unless both are normalized to the same form.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PathNormalization {
+    #[default]
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl PathNormalization {
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            Self::Nfc => s.nfc().collect(),
+            Self::Nfd => s.nfd().collect(),
+            Self::Nfkc => s.nfkc().collect(),
+            Self::Nfkd => s.nfkd().collect(),
+        }
+    }
+}
+
+impl FromStr for PathNormalization {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "nfc" => Ok(Self::Nfc),
+            "nfd" => Ok(Self::Nfd),
+            "nfkc" => Ok(Self::Nfkc),
+            "nfkd" => Ok(Self::Nfkd),
+            _ => Err("Invalid normalization form - expected 'NFC', 'NFD', 'NFKC' or 'NFKD'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for PathNormalization {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "PathNormalization",
+                message: Some("Normalization form must be a string".to_string()),
+            }),
+        }
+    }
+}
@@ -1,19 +1,225 @@
 use mlua::prelude::*;
 
+use crate::backup::parse_backup_suffix;
+use crate::denied::DeniedHandling;
+use crate::normalize::PathNormalization;
+use crate::perms::parse_mode;
+use crate::sort::DirSort;
+use crate::verify::HashAlgorithm;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsReadFileOptions {
+    pub(crate) stats: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsReadFileOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => Self {
+                stats: t.get::<_, Option<bool>>("stats")?.unwrap_or(false),
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsReadFileOptions",
+                    message: Some(format!(
+                        "Invalid read file options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsReadDirOptions {
+    pub(crate) normalize: Option<PathNormalization>,
+    pub(crate) sort: Option<DirSort>,
+}
+
+impl<'lua> FromLua<'lua> for FsReadDirOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => Self {
+                normalize: t.get("normalize")?,
+                sort: t.get("sort")?,
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsReadDirOptions",
+                    message: Some(format!(
+                        "Invalid read dir options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsReadTreeOptions {
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) with_metadata: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsReadTreeOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let with_metadata: Option<bool> = t.get("withMetadata")?;
+                Self {
+                    max_depth: t.get("maxDepth")?,
+                    with_metadata: with_metadata.unwrap_or(false),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsReadTreeOptions",
+                    message: Some(format!(
+                        "Invalid read tree options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
+pub struct FsMetadataOptions {
+    pub(crate) follow_symlinks: bool,
+}
+
+impl Default for FsMetadataOptions {
+    fn default() -> Self {
+        Self {
+            follow_symlinks: true,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsMetadataOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let follow_symlinks: Option<bool> = t.get("followSymlinks")?;
+                Self {
+                    follow_symlinks: follow_symlinks.unwrap_or(true),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsMetadataOptions",
+                    message: Some(format!(
+                        "Invalid metadata options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsPreserveOptions {
+    pub(crate) owner: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsPreserveOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let owner: Option<bool> = t.get("owner")?;
+                Self {
+                    owner: owner.unwrap_or(false),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsPreserveOptions",
+                    message: Some(format!(
+                        "Invalid preserve options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct FsWriteOptions {
     pub(crate) overwrite: bool,
+    pub(crate) same_filesystem: bool,
+    pub(crate) backup_suffix: Option<String>,
+    pub(crate) verify: bool,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) preserve: FsPreserveOptions,
+    pub(crate) buffer_size: Option<usize>,
+    pub(crate) stats: bool,
+    pub(crate) internal: bool,
+    pub(crate) on_denied: DeniedHandling,
+}
+
+impl Default for FsWriteOptions {
+    fn default() -> Self {
+        Self {
+            overwrite: false,
+            same_filesystem: false,
+            backup_suffix: None,
+            verify: false,
+            follow_symlinks: true,
+            preserve: FsPreserveOptions::default(),
+            buffer_size: None,
+            stats: false,
+            internal: false,
+            on_denied: DeniedHandling::default(),
+        }
+    }
 }
 
 impl<'lua> FromLua<'lua> for FsWriteOptions {
     fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
         Ok(match value {
-            LuaValue::Nil => Self { overwrite: false },
-            LuaValue::Boolean(b) => Self { overwrite: b },
+            LuaValue::Nil => Self::default(),
+            LuaValue::Boolean(b) => Self {
+                overwrite: b,
+                ..Self::default()
+            },
             LuaValue::Table(t) => {
                 let overwrite: Option<bool> = t.get("overwrite")?;
+                let same_filesystem: Option<bool> = t.get("sameFilesystem")?;
+                let verify: Option<bool> = t.get("verify")?;
+                let follow_symlinks: Option<bool> = t.get("followSymlinks")?;
+                let buffer_size: Option<usize> = t.get("bufferSize")?;
+                if buffer_size == Some(0) {
+                    return Err(LuaError::runtime(
+                        "Invalid write options - bufferSize must be greater than zero",
+                    ));
+                }
                 Self {
                     overwrite: overwrite.unwrap_or(false),
+                    same_filesystem: same_filesystem.unwrap_or(false),
+                    backup_suffix: parse_backup_suffix(&t)?,
+                    verify: verify.unwrap_or(false),
+                    follow_symlinks: follow_symlinks.unwrap_or(true),
+                    preserve: t.get("preserve")?,
+                    buffer_size,
+                    stats: t.get::<_, Option<bool>>("stats")?.unwrap_or(false),
+                    internal: t.get::<_, Option<bool>>("internal")?.unwrap_or(false),
+                    on_denied: t.get("onDenied")?,
                 }
             }
             _ => {
@@ -29,3 +235,259 @@ impl<'lua> FromLua<'lua> for FsWriteOptions {
         })
     }
 }
+
+#[derive(Debug, Clone, Default)]
+pub struct FsWriteFileOptions {
+    pub(crate) backup_suffix: Option<String>,
+    pub(crate) verify: bool,
+    pub(crate) sidecar_hash: Option<HashAlgorithm>,
+    pub(crate) stats: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsWriteFileOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let verify: Option<bool> = t.get("verify")?;
+                Self {
+                    backup_suffix: parse_backup_suffix(&t)?,
+                    verify: verify.unwrap_or(false),
+                    sidecar_hash: t.get("sidecarHash")?,
+                    stats: t.get::<_, Option<bool>>("stats")?.unwrap_or(false),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsWriteFileOptions",
+                    message: Some(format!(
+                        "Invalid write file options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FsSaveOptions {
+    pub(crate) fsync: bool,
+    pub(crate) fsync_dir: bool,
+    pub(crate) mode: Option<u32>,
+}
+
+impl Default for FsSaveOptions {
+    fn default() -> Self {
+        Self {
+            fsync: true,
+            fsync_dir: false,
+            mode: None,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsSaveOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let defaults = Self::default();
+        Ok(match value {
+            LuaValue::Nil => defaults,
+            LuaValue::Table(t) => {
+                let fsync: Option<bool> = t.get("fsync")?;
+                let fsync_dir: Option<bool> = t.get("fsyncDir")?;
+                Self {
+                    fsync: fsync.unwrap_or(defaults.fsync),
+                    fsync_dir: fsync_dir.unwrap_or(defaults.fsync_dir),
+                    mode: parse_mode(t.get("mode")?)?.map(|mode| mode.for_entry(false)),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsSaveOptions",
+                    message: Some(format!(
+                        "Invalid save options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsAccessOptions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) execute: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsAccessOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let read: Option<bool> = t.get("read")?;
+                let write: Option<bool> = t.get("write")?;
+                let execute: Option<bool> = t.get("execute")?;
+                Self {
+                    read: read.unwrap_or_default(),
+                    write: write.unwrap_or_default(),
+                    execute: execute.unwrap_or_default(),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsAccessOptions",
+                    message: Some(format!(
+                        "Invalid access options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FsCopyVerifiedOptions {
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) overwrite: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsCopyVerifiedOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let overwrite: Option<bool> = t.get("overwrite")?;
+                Self {
+                    algorithm: t.get("algorithm")?,
+                    overwrite: overwrite.unwrap_or(false),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsCopyVerifiedOptions",
+                    message: Some(format!(
+                        "Invalid verified copy options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsRemoveOptions {
+    pub(crate) same_filesystem: bool,
+    pub(crate) follow_symlinks: bool,
+}
+
+impl<'lua> FromLua<'lua> for FsRemoveOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let same_filesystem: Option<bool> = t.get("sameFilesystem")?;
+                let follow_symlinks: Option<bool> = t.get("followSymlinks")?;
+                Self {
+                    same_filesystem: same_filesystem.unwrap_or(false),
+                    follow_symlinks: follow_symlinks.unwrap_or(false),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsRemoveOptions",
+                    message: Some(format!(
+                        "Invalid remove options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsAttributeOptions {
+    pub(crate) recursive: bool,
+    /// If symlinks should be followed, or changed themselves. When not given, the
+    /// given path is followed, and symlinks inside of directories are skipped.
+    pub(crate) follow_symlinks: Option<bool>,
+}
+
+impl<'lua> FromLua<'lua> for FsAttributeOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let recursive: Option<bool> = t.get("recursive")?;
+                Self {
+                    recursive: recursive.unwrap_or(false),
+                    follow_symlinks: t.get("followSymlinks")?,
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsAttributeOptions",
+                    message: Some(format!(
+                        "Invalid attribute options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FsWriteDirOptions {
+    pub(crate) mode: Option<u32>,
+    pub(crate) recursive: bool,
+    pub(crate) exist_ok: bool,
+}
+
+impl Default for FsWriteDirOptions {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            recursive: true,
+            exist_ok: true,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsWriteDirOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let defaults = Self::default();
+                let recursive: Option<bool> = t.get("recursive")?;
+                let exist_ok: Option<bool> = t.get("existOk")?;
+                Self {
+                    mode: parse_mode(t.get("mode")?)?.map(|mode| mode.for_entry(true)),
+                    recursive: recursive.unwrap_or(defaults.recursive),
+                    exist_ok: exist_ok.unwrap_or(defaults.exist_ok),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsWriteDirOptions",
+                    message: Some(format!(
+                        "Invalid write dir options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
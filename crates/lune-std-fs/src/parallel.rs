@@ -0,0 +1,232 @@
+use std::cell::Cell;
+use std::rc::{Rc, Weak};
+
+use bstr::BString;
+use tokio::sync::{mpsc, Semaphore};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSpawnExt;
+
+use crate::contents::FileContents;
+use crate::options::{FsRemoveOptions, FsWriteDirOptions, FsWriteFileOptions, FsWriteOptions};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParallelOptions {
+    pub(crate) concurrency: usize,
+    pub(crate) stop_on_error: bool,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            stop_on_error: false,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for ParallelOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let defaults = Self::default();
+        Ok(match value {
+            LuaValue::Nil => defaults,
+            LuaValue::Table(t) => {
+                let concurrency: Option<usize> = t.get("concurrency")?;
+                let stop_on_error: Option<bool> = t.get("stopOnError")?;
+                if concurrency == Some(0) {
+                    return Err(LuaError::runtime(
+                        "Invalid parallel options - concurrency must be greater than zero",
+                    ));
+                }
+                Self {
+                    concurrency: concurrency.unwrap_or(defaults.concurrency),
+                    stop_on_error: stop_on_error.unwrap_or(defaults.stop_on_error),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "ParallelOptions",
+                    message: Some(format!(
+                        "Invalid parallel options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    A single declarative filesystem operation, run using the
+    same implementation as the function of the same name.
+*/
+#[derive(Debug, Clone)]
+enum FsTask {
+    Copy(String, String, FsWriteOptions),
+    Move(String, String, FsWriteOptions),
+    WriteFile(String, BString, FsWriteFileOptions),
+    WriteDir(String, FsWriteDirOptions),
+    RemoveFile(String, FsRemoveOptions),
+    RemoveDir(String, FsRemoveOptions),
+}
+
+impl<'lua> FromLua<'lua> for FsTask {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        let LuaValue::Table(t) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "FsTask",
+                message: Some(format!(
+                    "Invalid task - expected table, got {}",
+                    value.type_name()
+                )),
+            });
+        };
+        let op: String = t.get("op")?;
+        let options: LuaValue = t.get("options")?;
+        Ok(match op.as_str() {
+            "copy" => Self::Copy(t.get("from")?, t.get("to")?, lua.unpack(options)?),
+            "move" => Self::Move(t.get("from")?, t.get("to")?, lua.unpack(options)?),
+            "writeFile" => {
+                Self::WriteFile(t.get("path")?, t.get("contents")?, lua.unpack(options)?)
+            }
+            "writeDir" => Self::WriteDir(t.get("path")?, lua.unpack(options)?),
+            "removeFile" => Self::RemoveFile(t.get("path")?, lua.unpack(options)?),
+            "removeDir" => Self::RemoveDir(t.get("path")?, lua.unpack(options)?),
+            _ => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid task - unknown op '{op}'\nValid ops are: \
+                    copy, move, writeFile, writeDir, removeFile, removeDir"
+                )))
+            }
+        })
+    }
+}
+
+impl FsTask {
+    async fn run(self, lua: &Lua) -> LuaResult<()> {
+        match self {
+            Self::Copy(from, to, options) => {
+                crate::fs_copy(lua, (from, to, options)).await.map(|_| ())
+            }
+            Self::Move(from, to, options) => crate::fs_move(lua, (from, to, options)).await,
+            Self::WriteFile(path, contents, options) => {
+                let contents = FileContents::Bytes(contents);
+                crate::fs_write_file(lua, (path, contents, options))
+                    .await
+                    .map(|_| ())
+            }
+            Self::WriteDir(path, options) => {
+                crate::fs_write_dir(lua, (path, options)).await.map(|_| ())
+            }
+            Self::RemoveFile(path, options) => crate::fs_remove_file(lua, (path, options)).await,
+            Self::RemoveDir(path, options) => crate::fs_remove_dir(lua, (path, options)).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TaskResult {
+    Ok,
+    Error(String),
+    Skipped,
+}
+
+impl<'lua> IntoLua<'lua> for TaskResult {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 2)?;
+        match self {
+            Self::Ok => tab.set("status", "ok")?,
+            Self::Skipped => tab.set("status", "skipped")?,
+            Self::Error(message) => {
+                tab.set("status", "error")?;
+                tab.set("error", message)?;
+            }
+        }
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    The combined results of running tasks in parallel, in the same order as the tasks.
+*/
+#[derive(Debug, Clone)]
+struct ParallelReport {
+    results: Vec<TaskResult>,
+}
+
+impl<'lua> IntoLua<'lua> for ParallelReport {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let count = |f: fn(&TaskResult) -> bool| self.results.iter().filter(|r| f(r)).count();
+        let tab = lua.create_table_with_capacity(0, 4)?;
+        tab.set("succeeded", count(|r| matches!(r, TaskResult::Ok)))?;
+        tab.set("failed", count(|r| matches!(r, TaskResult::Error(_))))?;
+        tab.set("skipped", count(|r| matches!(r, TaskResult::Skipped)))?;
+        tab.set("results", self.results)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    Runs the given tasks concurrently, with at most `concurrency` of them running at once.
+
+    Tasks are started in order, and when stopping on errors, tasks that
+    have not started yet by the time any task fails are skipped.
+*/
+pub async fn run_parallel<'lua>(
+    lua: &'lua Lua,
+    tasks: LuaTable<'lua>,
+    options: ParallelOptions,
+) -> LuaResult<LuaValue<'lua>> {
+    // NOTE: All tasks are validated before any of them
+    // are started, so that mistakes never run half of them
+    let tasks = tasks
+        .sequence_values::<FsTask>()
+        .collect::<LuaResult<Vec<_>>>()?;
+
+    let lua_inner: Rc<Lua> = lua
+        .app_data_ref::<Weak<Lua>>()
+        .expect("Missing weak lua ref")
+        .upgrade()
+        .expect("Lua was dropped unexpectedly");
+
+    let count = tasks.len();
+    let semaphore = Rc::new(Semaphore::new(options.concurrency));
+    let stopped = Rc::new(Cell::new(false));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for (index, task) in tasks.into_iter().enumerate() {
+        let lua_inner = Rc::clone(&lua_inner);
+        let semaphore = Rc::clone(&semaphore);
+        let stopped = Rc::clone(&stopped);
+        let tx = tx.clone();
+        lua.spawn_local(async move {
+            // The semaphore is never closed, so acquiring can not fail here
+            let _permit = semaphore.acquire().await.ok();
+            let result = if stopped.get() {
+                TaskResult::Skipped
+            } else {
+                match task.run(&lua_inner).await {
+                    Ok(()) => TaskResult::Ok,
+                    Err(e) => {
+                        if options.stop_on_error {
+                            stopped.set(true);
+                        }
+                        TaskResult::Error(e.to_string())
+                    }
+                }
+            };
+            let _ = tx.send((index, result));
+        });
+    }
+    drop(tx);
+
+    let mut results = vec![TaskResult::Skipped; count];
+    while let Some((index, result)) = rx.recv().await {
+        results[index] = result;
+    }
+    ParallelReport { results }.into_lua(lua)
+}
@@ -0,0 +1,72 @@
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+
+use memchr::memmem::Finder;
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+use crate::file::{read_at, write_all_at};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/**
+    Overwrites bytes in the file at the given path, starting at the given offset,
+    without reading the rest of the file. The patched range must be within the file.
+*/
+pub async fn patch_bytes(path: PathBuf, offset: u64, bytes: Vec<u8>) -> LuaResult<()> {
+    spawn_blocking(move || {
+        let file = OpenOptions::new().write(true).open(&path)?;
+        let len = file.metadata()?.len();
+        let end = offset.checked_add(bytes.len() as u64);
+        if end.is_none_or(|end| end > len) {
+            return Err(LuaError::RuntimeError(format!(
+                "Can not patch {} bytes at offset {offset} in '{}', which is only {len} bytes long",
+                bytes.len(),
+                path.display()
+            )));
+        }
+        write_all_at(&file, &bytes, offset)?;
+        Ok(())
+    })
+    .await
+    .into_lua_err()?
+}
+
+/**
+    Finds the offset of the first occurrence of the needle in the file at the
+    given path, at or after the given offset, reading the file in chunks.
+*/
+pub async fn find_bytes(path: PathBuf, needle: Vec<u8>, start: u64) -> LuaResult<Option<u64>> {
+    if needle.is_empty() {
+        return Err(LuaError::RuntimeError(String::from(
+            "Can not search for an empty sequence of bytes",
+        )));
+    }
+    spawn_blocking(move || {
+        let file = File::open(&path)?;
+        let finder = Finder::new(&needle);
+        // NOTE: Consecutive chunks overlap by one byte less than the needle
+        // length, so that matches spanning two chunks are still found
+        let overlap = needle.len() - 1;
+        let mut buf = vec![0; CHUNK_SIZE.max(needle.len() * 2)];
+        let mut offset = start;
+        let mut filled = 0;
+        loop {
+            let read = read_at(&file, &mut buf[filled..], offset + filled as u64)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            filled += read;
+            if let Some(index) = finder.find(&buf[..filled]) {
+                return Ok(Some(offset + index as u64));
+            }
+            let keep = overlap.min(filled);
+            buf.copy_within(filled - keep..filled, 0);
+            offset += (filled - keep) as u64;
+            filled = keep;
+        }
+    })
+    .await
+    .into_lua_err()?
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Result as IoResult};
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/**
+    The largest block size that can be used, since comparing buffers up to three
+    blocks of the second file, and blocks much larger only make patches larger.
+*/
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaOptions {
+    pub(super) block_size: usize,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self {
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for DeltaOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::Table(t) => match t.get::<_, Option<usize>>("blockSize")? {
+                None => Ok(Self::default()),
+                Some(0) => Err(LuaError::runtime(
+                    "Invalid delta options - blockSize must be greater than zero",
+                )),
+                Some(block_size) if block_size > MAX_BLOCK_SIZE => Err(LuaError::runtime(format!(
+                    "Invalid delta options - blockSize must be at most {MAX_BLOCK_SIZE}"
+                ))),
+                Some(block_size) => Ok(Self { block_size }),
+            },
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "DeltaOptions",
+                message: Some(format!(
+                    "Invalid delta options - expected table or nil, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    A range of bytes in the second file, which either matches a range
+    of the same length in the first file, or differs from all of it.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DeltaRange {
+    Match {
+        offset: u64,
+        length: u64,
+        source_offset: u64,
+    },
+    Differ {
+        offset: u64,
+        length: u64,
+    },
+}
+
+/**
+    The ranges of the second file, in order, covering all of it.
+*/
+#[derive(Debug, Default)]
+pub struct FsDelta {
+    pub(super) ranges: Vec<DeltaRange>,
+}
+
+impl FsDelta {
+    fn push_match(&mut self, offset: u64, length: u64, source_offset: u64) {
+        // NOTE: Consecutive blocks are usually consecutive in both files, so they are merged
+        if let Some(DeltaRange::Match {
+            offset: last_offset,
+            length: last_length,
+            source_offset: last_source,
+        }) = self.ranges.last_mut()
+        {
+            if *last_offset + *last_length == offset && *last_source + *last_length == source_offset
+            {
+                *last_length += length;
+                return;
+            }
+        }
+        self.ranges.push(DeltaRange::Match {
+            offset,
+            length,
+            source_offset,
+        });
+    }
+
+    fn push_differ(&mut self, offset: u64, length: u64) {
+        if length == 0 {
+            return;
+        }
+        if let Some(DeltaRange::Differ {
+            offset: last_offset,
+            length: last_length,
+        }) = self.ranges.last_mut()
+        {
+            if *last_offset + *last_length == offset {
+                *last_length += length;
+                return;
+            }
+        }
+        self.ranges.push(DeltaRange::Differ { offset, length });
+    }
+}
+
+impl IntoLua<'_> for FsDelta {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let mut matching = 0;
+        let mut differing = 0;
+        let ranges = lua.create_table_with_capacity(self.ranges.len(), 0)?;
+        for range in self.ranges {
+            let tab = lua.create_table_with_capacity(0, 4)?;
+            match range {
+                DeltaRange::Match {
+                    offset,
+                    length,
+                    source_offset,
+                } => {
+                    matching += length;
+                    tab.set("kind", "match")?;
+                    tab.set("offset", offset)?;
+                    tab.set("length", length)?;
+                    tab.set("sourceOffset", source_offset)?;
+                }
+                DeltaRange::Differ { offset, length } => {
+                    differing += length;
+                    tab.set("kind", "differ")?;
+                    tab.set("offset", offset)?;
+                    tab.set("length", length)?;
+                }
+            }
+            tab.set_readonly(true);
+            ranges.push(tab)?;
+        }
+        ranges.set_readonly(true);
+
+        let tab = lua.create_table_with_capacity(0, 3)?;
+        tab.set("ranges", ranges)?;
+        tab.set("matchingBytes", matching)?;
+        tab.set("differingBytes", differing)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    The weak checksum used by rsync, which can be rolled forward one byte at a time.
+*/
+#[derive(Debug, Clone, Copy, Default)]
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    #[allow(clippy::cast_possible_truncation)]
+    fn new(block: &[u8]) -> Self {
+        let len = block.len() as u32;
+        let mut sum = Self {
+            len,
+            ..Self::default()
+        };
+        for (i, &byte) in block.iter().enumerate() {
+            sum.a = sum.a.wrapping_add(u32::from(byte));
+            sum.b = sum
+                .b
+                .wrapping_add((len - i as u32).wrapping_mul(u32::from(byte)));
+        }
+        sum
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self
+            .a
+            .wrapping_sub(u32::from(out))
+            .wrapping_add(u32::from(into));
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(u32::from(out)))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+type StrongHash = [u8; 32];
+
+fn strong_hash(block: &[u8]) -> StrongHash {
+    Sha256::digest(block).into()
+}
+
+/**
+    The checksums of every block in the first file, which blocks of the second one are looked up in.
+*/
+#[derive(Debug, Default)]
+struct Signature {
+    blocks: HashMap<u32, Vec<(u64, StrongHash)>>,
+    /// The last block of the file, if it is shorter than the others.
+    tail: Option<(u64, StrongHash, usize)>,
+}
+
+impl Signature {
+    fn read(mut reader: impl Read, block_size: usize) -> IoResult<Self> {
+        let mut signature = Self::default();
+        let mut block = vec![0; block_size];
+        let mut offset = 0;
+        loop {
+            let read = read_full(&mut reader, &mut block)?;
+            if read == block_size {
+                let weak = RollingChecksum::new(&block).digest();
+                let entry = signature.blocks.entry(weak).or_default();
+                entry.push((offset, strong_hash(&block)));
+            } else if read > 0 {
+                signature.tail = Some((offset, strong_hash(&block[..read]), read));
+            }
+            if read < block_size {
+                return Ok(signature);
+            }
+            offset += block_size as u64;
+        }
+    }
+
+    /**
+        Finds a block of the first file with the same contents, preferring the one at
+        `preferred`, so that runs of repeated blocks are matched in order and merged.
+    */
+    fn find(&self, weak: u32, block: &[u8], preferred: Option<u64>) -> Option<u64> {
+        let candidates = self.blocks.get(&weak)?;
+        // NOTE: The strong hash is only computed once the weak checksum matches
+        let strong = strong_hash(block);
+        let mut matching = candidates
+            .iter()
+            .filter(|(_, hash)| *hash == strong)
+            .map(|(offset, _)| *offset);
+        let first = matching.next()?;
+        if preferred.is_none_or(|preferred| preferred == first) {
+            return Some(first);
+        }
+        Some(
+            matching
+                .find(|offset| Some(*offset) == preferred)
+                .unwrap_or(first),
+        )
+    }
+}
+
+/**
+    Compares the second reader to blocks of the first one, finding all ranges of
+    the second that also appear in the first, wherever they moved, like rsync does.
+*/
+pub(super) fn compute_delta(
+    a: impl Read,
+    mut b: impl Read,
+    block_size: usize,
+) -> IoResult<FsDelta> {
+    let signature = Signature::read(a, block_size)?;
+    let mut delta = FsDelta::default();
+
+    // NOTE: The buffer holds the current window, along with any bytes
+    // before it that have not been reported as a differing range yet
+    let mut buf = Vec::with_capacity(block_size * 3);
+    let mut buf_offset = 0u64;
+    let mut window = 0;
+    let mut eof = false;
+    let mut sum: Option<RollingChecksum> = None;
+    let mut next_source = None;
+    loop {
+        if !eof && buf.len() < window + block_size {
+            // NOTE: Bytes before the window are reported early, so
+            // that files that differ entirely are not held in memory
+            if window >= block_size {
+                delta.push_differ(buf_offset, window as u64);
+                buf.drain(..window);
+                buf_offset += window as u64;
+                window = 0;
+            }
+            let start = buf.len();
+            let end = window + block_size * 2;
+            buf.resize(end, 0);
+            let read = read_full(&mut b, &mut buf[start..])?;
+            buf.truncate(start + read);
+            eof = start + read < end;
+        }
+
+        if buf.len() - window < block_size {
+            // NOTE: The rest of the file is shorter than a block, and
+            // can only match the equally short tail of the first file
+            let rest = &buf[window..];
+            let tail = signature
+                .tail
+                .filter(|(_, hash, len)| *len == rest.len() && *hash == strong_hash(rest));
+            let differ_end = if tail.is_some() { window } else { buf.len() };
+            delta.push_differ(buf_offset, differ_end as u64);
+            if let Some((source_offset, _, len)) = tail {
+                delta.push_match(buf_offset + window as u64, len as u64, source_offset);
+            }
+            return Ok(delta);
+        }
+
+        let block = &buf[window..window + block_size];
+        let weak = *sum.get_or_insert_with(|| RollingChecksum::new(block));
+        if let Some(source_offset) = signature.find(weak.digest(), block, next_source) {
+            next_source = Some(source_offset + block_size as u64);
+            delta.push_differ(buf_offset, window as u64);
+            delta.push_match(buf_offset + window as u64, block_size as u64, source_offset);
+            buf.drain(..window + block_size);
+            buf_offset += (window + block_size) as u64;
+            window = 0;
+            sum = None;
+        } else {
+            if let (Some(sum), Some(&into)) = (sum.as_mut(), buf.get(window + block_size)) {
+                sum.roll(buf[window], into);
+            } else {
+                sum = None;
+            }
+            window += 1;
+        }
+    }
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> IoResult<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/**
+    Finds the ranges of the file at `b` that match blocks of the file at `a`, and the
+    ranges in between that differ, reading both files once without holding them in memory.
+*/
+pub async fn delta(a: PathBuf, b: PathBuf, options: DeltaOptions) -> LuaResult<FsDelta> {
+    spawn_blocking(move || {
+        let a = BufReader::new(File::open(&a)?);
+        let b = File::open(&b)?;
+        Ok(compute_delta(a, b, options.block_size)?)
+    })
+    .await
+    .into_lua_err()?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(a: &[u8], b: &[u8], block_size: usize) -> Vec<DeltaRange> {
+        compute_delta(a, b, block_size).unwrap().ranges
+    }
+
+    #[test]
+    fn finds_moved_blocks_around_changes() {
+        let a = b"aaaabbbbccccdd";
+        let b = b"bbbbXXaaaaccccdd";
+        assert_eq!(
+            ranges(a, b, 4),
+            [
+                DeltaRange::Match {
+                    offset: 0,
+                    length: 4,
+                    source_offset: 4
+                },
+                DeltaRange::Differ {
+                    offset: 4,
+                    length: 2
+                },
+                DeltaRange::Match {
+                    offset: 6,
+                    length: 4,
+                    source_offset: 0
+                },
+                DeltaRange::Match {
+                    offset: 10,
+                    length: 6,
+                    source_offset: 8
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn covers_the_second_file_with_exact_matches() {
+        let mut seed = 7u32;
+        let a = (0..10_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect::<Vec<_>>();
+        let mut b = a.clone();
+        b.splice(3000..3100, b"inserted".iter().copied());
+        b.extend_from_slice(&a[..500]);
+
+        let mut covered = 0;
+        for range in ranges(&a, &b, 64) {
+            match range {
+                DeltaRange::Match {
+                    offset,
+                    length,
+                    source_offset,
+                } => {
+                    let (offset, length, source) =
+                        (offset as usize, length as usize, source_offset as usize);
+                    assert_eq!(offset, covered);
+                    assert_eq!(b[offset..offset + length], a[source..source + length]);
+                    covered += length;
+                }
+                DeltaRange::Differ { offset, length } => {
+                    assert_eq!(offset as usize, covered);
+                    covered += length as usize;
+                }
+            }
+        }
+        assert_eq!(covered, b.len());
+    }
+
+    #[test]
+    fn reports_entirely_different_files_as_one_range() {
+        let a = vec![1; 64];
+        let b = (0..=255).collect::<Vec<u8>>();
+        assert_eq!(
+            ranges(&a, &b, 8),
+            [DeltaRange::Differ {
+                offset: 0,
+                length: 256
+            }]
+        );
+        assert!(ranges(&a, &[], 8).is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::{
+    self, BufReader, BufWriter, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write,
+};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tokio::{fs, task::spawn_blocking};
+
+use mlua::prelude::*;
+
+use super::delta::{compute_delta, DeltaOptions, DeltaRange};
+use crate::atomic::{rename_into_place, sibling_temp_path};
+
+/*
+    Patches start with a header, followed by instructions until the end of the file:
+
+    - The magic bytes `LUNEDIFF` and the format version, as a single byte
+    - The length and SHA-256 hash of the old file the patch was created from
+    - The length and SHA-256 hash of the new file the patch creates
+
+    Each instruction is a single byte for its kind, followed by little-endian numbers:
+
+    - Copy: the offset and length of bytes in the old file to copy to the output
+    - Insert: the length of bytes that follow the instruction, to write to the output
+*/
+
+const MAGIC: &[u8; 8] = b"LUNEDIFF";
+const VERSION: u8 = 1;
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+type Hash = [u8; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileInfo {
+    len: u64,
+    hash: Hash,
+}
+
+impl FileInfo {
+    fn of(path: &Path) -> IoResult<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut hasher = Sha256::new();
+        let len = io::copy(&mut file, &mut hasher)?;
+        Ok(Self {
+            len,
+            hash: hasher.finalize().into(),
+        })
+    }
+
+    fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        writer.write_all(&self.len.to_le_bytes())?;
+        writer.write_all(&self.hash)
+    }
+
+    fn read(reader: &mut impl Read) -> IoResult<Self> {
+        let len = read_u64(reader)?;
+        let mut hash = Hash::default();
+        reader.read_exact(&mut hash)?;
+        Ok(Self { len, hash })
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> IoResult<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/**
+    Creates a patch at `patch_path` that turns the file at `old` into the file at
+    `new`, copying the blocks they share and only storing the bytes that differ.
+
+    Returns the size of the patch in bytes.
+*/
+pub async fn create_patch(
+    old: PathBuf,
+    new: PathBuf,
+    patch_path: PathBuf,
+    options: DeltaOptions,
+) -> LuaResult<u64> {
+    let temp = sibling_temp_path(&patch_path);
+    let written = temp.clone();
+    let res = spawn_blocking(move || write_patch(&old, &new, &written, options)).await;
+    let res = match res.into_lua_err()? {
+        Ok(size) => rename_into_place(&temp, &patch_path).await.map(|()| size),
+        Err(e) => Err(e),
+    };
+    if res.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    Ok(res?)
+}
+
+fn write_patch(old: &Path, new: &Path, patch: &Path, options: DeltaOptions) -> IoResult<u64> {
+    let delta = compute_delta(
+        BufReader::new(File::open(old)?),
+        BufReader::new(File::open(new)?),
+        options.block_size,
+    )?;
+
+    let mut writer = BufWriter::new(File::create(patch)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    FileInfo::of(old)?.write(&mut writer)?;
+    FileInfo::of(new)?.write(&mut writer)?;
+
+    let mut new = File::open(new)?;
+    for range in delta.ranges {
+        match range {
+            DeltaRange::Match {
+                length,
+                source_offset,
+                ..
+            } => {
+                writer.write_all(&[OP_COPY])?;
+                writer.write_all(&source_offset.to_le_bytes())?;
+                writer.write_all(&length.to_le_bytes())?;
+            }
+            DeltaRange::Differ { offset, length } => {
+                writer.write_all(&[OP_INSERT])?;
+                writer.write_all(&length.to_le_bytes())?;
+                new.seek(SeekFrom::Start(offset))?;
+                copy_exact(&mut new, &mut writer, length)?;
+            }
+        }
+    }
+
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()?;
+    Ok(file.metadata()?.len())
+}
+
+/**
+    Applies the patch at `patch_path` to the file at `old`, writing the patched file to `out`.
+
+    The old file and the output are checked against the hashes stored in the patch, so that
+    patches are never applied to the wrong file, and `out` is only replaced once verified.
+*/
+pub async fn apply_patch(old: PathBuf, patch_path: PathBuf, out: PathBuf) -> LuaResult<()> {
+    let temp = sibling_temp_path(&out);
+    let written = temp.clone();
+    let res = spawn_blocking(move || read_patch(&old, &patch_path, &written)).await;
+    let res = match res.into_lua_err()? {
+        Ok(()) => rename_into_place(&temp, &out).await.map_err(LuaError::from),
+        Err(e) => Err(e),
+    };
+    if res.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    res
+}
+
+fn read_patch(old: &Path, patch_path: &Path, out: &Path) -> LuaResult<()> {
+    let invalid = |reason: &str| {
+        LuaError::RuntimeError(format!(
+            "Invalid patch file '{}' - {reason}",
+            patch_path.display()
+        ))
+    };
+    let truncated = |e: io::Error| {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            invalid("the file ends unexpectedly")
+        } else {
+            e.into()
+        }
+    };
+
+    let mut patch = BufReader::new(File::open(patch_path)?);
+    let mut magic = [0; 9];
+    patch.read_exact(&mut magic).map_err(truncated)?;
+    if magic[..8] != *MAGIC {
+        return Err(invalid("it is not a patch created using fs.createPatch"));
+    }
+    if magic[8] != VERSION {
+        return Err(invalid(&format!("unsupported format version {}", magic[8])));
+    }
+    let expected_old = FileInfo::read(&mut patch).map_err(truncated)?;
+    let expected_new = FileInfo::read(&mut patch).map_err(truncated)?;
+    if FileInfo::of(old)? != expected_old {
+        return Err(LuaError::RuntimeError(format!(
+            "The patch '{}' was not created from the file at '{}'",
+            patch_path.display(),
+            old.display()
+        )));
+    }
+
+    let mut old = File::open(old)?;
+    let mut writer = BufWriter::new(File::create(out)?);
+    loop {
+        let mut op = [0; 1];
+        match patch.read(&mut op) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+        match op[0] {
+            OP_COPY => {
+                let source_offset = read_u64(&mut patch).map_err(truncated)?;
+                let length = read_u64(&mut patch).map_err(truncated)?;
+                if source_offset
+                    .checked_add(length)
+                    .is_none_or(|end| end > expected_old.len)
+                {
+                    return Err(invalid("it copies bytes past the end of the old file"));
+                }
+                old.seek(SeekFrom::Start(source_offset))?;
+                copy_exact(&mut old, &mut writer, length).map_err(truncated)?;
+            }
+            OP_INSERT => {
+                let length = read_u64(&mut patch).map_err(truncated)?;
+                copy_exact(&mut patch, &mut writer, length).map_err(truncated)?;
+            }
+            other => return Err(invalid(&format!("unknown instruction {other}"))),
+        }
+    }
+
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()?;
+    drop(file);
+    if FileInfo::of(out)? != expected_new {
+        return Err(invalid(
+            "the patched file does not match the hash stored in the patch",
+        ));
+    }
+    Ok(())
+}
+
+fn copy_exact(reader: &mut impl Read, writer: &mut impl Write, length: u64) -> IoResult<()> {
+    let copied = io::copy(&mut reader.take(length), writer)?;
+    if copied < length {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use mlua::prelude::*;
+
+/**
+    The format of a key-value file to patch.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KvFormat {
+    /// Lines of `KEY=value`, optionally prefixed with `export`, and `#` comments.
+    #[default]
+    Env,
+    /// Lines of `key = value` in `[section]`s, and `;` or `#` comments.
+    Ini,
+}
+
+impl FromStr for KvFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "env" => Ok(Self::Env),
+            "ini" => Ok(Self::Ini),
+            _ => Err("Invalid key-value format - expected 'env' or 'ini'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KvPatchOptions {
+    pub(crate) format: KvFormat,
+    pub(crate) create_missing: bool,
+}
+
+impl<'lua> FromLua<'lua> for KvPatchOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let format: Option<String> = t.get("format")?;
+                let create_missing: Option<bool> = t.get("createMissing")?;
+                Self {
+                    format: match format {
+                        Some(format) => format.parse().map_err(LuaError::runtime)?,
+                        None => KvFormat::default(),
+                    },
+                    create_missing: create_missing.unwrap_or(false),
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "KvPatchOptions",
+                    message: Some(format!(
+                        "Invalid patch options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    Values to set in a key-value file, grouped by section.
+
+    Keys that are not in any section use `None` as their section, which for
+    ini files means that they are placed before the first section header.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct KvPatch {
+    sections: BTreeMap<Option<String>, BTreeMap<String, String>>,
+}
+
+impl KvPatch {
+    pub fn from_table(table: &LuaTable, format: KvFormat) -> LuaResult<Self> {
+        let mut patch = Self::default();
+        for pair in table.clone().pairs::<String, LuaValue>() {
+            let (key, value) = pair?;
+            match value {
+                LuaValue::Table(t) if format == KvFormat::Ini => {
+                    for pair in t.pairs::<String, LuaValue>() {
+                        let (inner, value) = pair?;
+                        patch.insert(Some(&key), inner, &value, format)?;
+                    }
+                }
+                value => patch.insert(None, key, &value, format)?,
+            }
+        }
+        Ok(patch)
+    }
+
+    fn insert(
+        &mut self,
+        section: Option<&str>,
+        key: String,
+        value: &LuaValue,
+        format: KvFormat,
+    ) -> LuaResult<()> {
+        if !is_valid_key(&key, format) {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid key '{key}' for a {} file",
+                format_name(format)
+            )));
+        }
+        let value = match value {
+            LuaValue::String(s) => s.to_str()?.to_string(),
+            LuaValue::Integer(i) => i.to_string(),
+            #[allow(clippy::cast_possible_truncation)]
+            LuaValue::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => (*n as i64).to_string(),
+            LuaValue::Number(n) => n.to_string(),
+            LuaValue::Boolean(b) => b.to_string(),
+            other => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid value for key '{key}' - expected string, number or boolean, got {}",
+                    other.type_name()
+                )))
+            }
+        };
+        if value.contains(['\n', '\r']) {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid value for key '{key}' - values must not contain newlines"
+            )));
+        }
+        self.sections
+            .entry(section.map(ToString::to_string))
+            .or_default()
+            .insert(key, value);
+        Ok(())
+    }
+
+    fn get(&self, section: Option<&String>, key: &str) -> Option<&String> {
+        self.sections.get(&section.cloned())?.get(key)
+    }
+}
+
+/**
+    Applies a patch to the contents of a key-value file, replacing the values
+    of matching lines, while keeping all other lines exactly as they were.
+
+    Keys that are missing from the contents are added if `create_missing` is
+    set, and otherwise result in an error, without anything being patched.
+*/
+pub fn apply_kv_patch(
+    contents: &str,
+    patch: &KvPatch,
+    options: KvPatchOptions,
+) -> LuaResult<String> {
+    let format = options.format;
+    let newline = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines = contents
+        .split_inclusive('\n')
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    let mut section: Option<String> = None;
+    let mut last_lines: HashMap<Option<String>, usize> = HashMap::new();
+    let mut found: HashSet<(Option<String>, String)> = HashSet::new();
+    for (index, line) in lines.iter_mut().enumerate() {
+        let trimmed = line.trim();
+        if format == KvFormat::Ini && trimmed.starts_with('[') && trimmed.ends_with(']') {
+            section = Some(trimmed[1..trimmed.len() - 1].trim().to_string());
+            last_lines.insert(section.clone(), index);
+            continue;
+        }
+        let Some((key, value_start)) = parse_line(line, format) else {
+            continue;
+        };
+        last_lines.insert(section.clone(), index);
+        if let Some(value) = patch.get(section.as_ref(), &key) {
+            let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+            *line = format!(
+                "{}{}{ending}",
+                &line[..value_start],
+                format_value(value, format)
+            );
+            found.insert((section.clone(), key));
+        }
+    }
+
+    let missing = patch
+        .sections
+        .iter()
+        .flat_map(|(section, values)| values.iter().map(move |(key, value)| (section, key, value)))
+        .filter(|(section, key, _)| !found.contains(&((*section).clone(), (*key).clone())))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok(lines.concat());
+    }
+    if !options.create_missing {
+        let keys = missing
+            .iter()
+            .map(|(section, key, _)| match section {
+                Some(section) => format!("{section}.{key}"),
+                None => (*key).clone(),
+            })
+            .collect::<Vec<_>>();
+        return Err(LuaError::RuntimeError(format!(
+            "Keys were not found and createMissing is not set: {}",
+            keys.join(", ")
+        )));
+    }
+
+    // Insert missing keys after the last line of their section, going from the
+    // last insertion point to the first, so that earlier indices stay valid
+    if lines.last().is_some_and(|line| !line.ends_with('\n')) {
+        if let Some(last) = lines.last_mut() {
+            last.push_str(newline);
+        }
+    }
+    let mut insertions: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let mut new_sections: Vec<String> = Vec::new();
+    for (section, key, value) in missing {
+        let line = format!("{}{newline}", format_line(key, value, format));
+        let position = match (section, last_lines.get(section)) {
+            (_, Some(index)) => index + 1,
+            (None, None) if format == KvFormat::Ini => 0,
+            (None, None) => lines.len(),
+            (Some(name), None) => {
+                if !new_sections.iter().any(|s| s == name) {
+                    if !lines.is_empty() || !new_sections.is_empty() {
+                        new_sections.push(String::new());
+                    }
+                    new_sections.push(format!("[{name}]"));
+                }
+                new_sections.push(line.trim_end().to_string());
+                continue;
+            }
+        };
+        insertions.entry(position).or_default().push(line);
+    }
+    for (position, inserted) in insertions.into_iter().rev() {
+        lines.splice(position..position, inserted);
+    }
+    for line in new_sections {
+        lines.push(format!("{line}{newline}"));
+    }
+    Ok(lines.concat())
+}
+
+/**
+    Parses a line, returning its key and the byte index its value starts
+    at, or `None` if the line is blank, a comment, or not a key-value line.
+*/
+fn parse_line(line: &str, format: KvFormat) -> Option<(String, usize)> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty()
+        || trimmed.starts_with('#')
+        || (format == KvFormat::Ini && trimmed.starts_with(';'))
+    {
+        return None;
+    }
+    let equals = line.find('=')?;
+    let mut key = line[..equals].trim();
+    if format == KvFormat::Env {
+        key = key.strip_prefix("export ").map_or(key, str::trim_start);
+    }
+    if key.is_empty() {
+        return None;
+    }
+    let after = &line[equals + 1..];
+    let value_start = equals + 1 + (after.len() - after.trim_start_matches([' ', '\t']).len());
+    Some((key.to_string(), value_start))
+}
+
+fn format_line(key: &str, value: &str, format: KvFormat) -> String {
+    match format {
+        KvFormat::Env => format!("{key}={}", format_value(value, format)),
+        KvFormat::Ini => format!("{key} = {}", format_value(value, format)),
+    }
+}
+
+fn format_value(value: &str, format: KvFormat) -> String {
+    let needs_quotes = format == KvFormat::Env
+        && !value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.,/:@%+".contains(c));
+    if needs_quotes && !value.is_empty() {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for c in value.chars() {
+            if matches!(c, '"' | '\\' | '$' | '`') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    } else {
+        value.to_string()
+    }
+}
+
+fn is_valid_key(key: &str, format: KvFormat) -> bool {
+    match format {
+        KvFormat::Env => {
+            key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        KvFormat::Ini => !key.trim().is_empty() && !key.contains(['=', '\n', '\r', '[', ';', '#']),
+    }
+}
+
+fn format_name(format: KvFormat) -> &'static str {
+    match format {
+        KvFormat::Env => "env",
+        KvFormat::Ini => "ini",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(values: &[(Option<&str>, &str, &str)]) -> KvPatch {
+        let mut patch = KvPatch::default();
+        for (section, key, value) in values {
+            patch
+                .sections
+                .entry(section.map(ToString::to_string))
+                .or_default()
+                .insert((*key).to_string(), (*value).to_string());
+        }
+        patch
+    }
+
+    #[test]
+    fn patches_env_lines_in_place() {
+        let contents = "# comment\nexport PORT = 80\nHOST=localhost\n";
+        let options = KvPatchOptions::default();
+        let patched = apply_kv_patch(contents, &patch(&[(None, "PORT", "8080")]), options).unwrap();
+        assert_eq!(patched, "# comment\nexport PORT = 8080\nHOST=localhost\n");
+
+        let quoted = apply_kv_patch(contents, &patch(&[(None, "HOST", "a b")]), options).unwrap();
+        assert_eq!(quoted, "# comment\nexport PORT = 80\nHOST=\"a b\"\n");
+
+        assert!(apply_kv_patch(contents, &patch(&[(None, "MISSING", "1")]), options).is_err());
+    }
+
+    #[test]
+    fn creates_missing_ini_keys_in_sections() {
+        let contents = "top = 1\r\n\r\n[server]\r\n; comment\r\nport = 80\r\n";
+        let options = KvPatchOptions {
+            format: KvFormat::Ini,
+            create_missing: true,
+        };
+        let values = patch(&[
+            (None, "other", "2"),
+            (Some("server"), "port", "8080"),
+            (Some("server"), "host", "example.com"),
+            (Some("client"), "retries", "3"),
+        ]);
+        assert_eq!(
+            apply_kv_patch(contents, &values, options).unwrap(),
+            "top = 1\r\nother = 2\r\n\r\n[server]\r\n; comment\r\nport = 8080\r\nhost = example.com\r\n\r\n[client]\r\nretries = 3\r\n"
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
+use mlua::prelude::*;
+use tokio::fs;
+
+use crate::atomic::write_atomic;
+
+mod bytes;
+mod delta;
+mod diff;
+mod kv;
+
+pub use self::bytes::{find_bytes, patch_bytes};
+pub use self::delta::{delta, DeltaOptions, FsDelta};
+pub use self::diff::{apply_patch, create_patch};
+pub use self::kv::{KvPatch, KvPatchOptions};
+
+use self::kv::apply_kv_patch;
+
+/**
+    Patches the values of keys in a key-value file, keeping comments and
+    the order of all lines, and replaces the file atomically when done.
+*/
+pub async fn patch_key_value(
+    path: impl AsRef<Path>,
+    patch: &KvPatch,
+    options: KvPatchOptions,
+) -> LuaResult<()> {
+    let path = path.as_ref();
+    let contents = match fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound && options.create_missing => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let patched = apply_kv_patch(&contents, patch, options)?;
+    if patched != contents {
+        write_atomic(path, patched).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::io::{ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::*;
+use tokio::fs;
+
+use crate::backend::FsBackend;
+use crate::config::FsConfig;
+
+/**
+    Default permissions for newly created files and directories.
+
+    These are applied explicitly after creation, so they are not
+    affected by the umask of the process. Does nothing on Windows.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultModes {
+    pub(crate) file: Option<u32>,
+    pub(crate) dir: Option<u32>,
+}
+
+impl DefaultModes {
+    pub fn get(lua: &Lua) -> Self {
+        FsConfig::get(lua).default_modes
+    }
+
+    pub async fn apply_file(&self, path: impl AsRef<Path>) -> IoResult<()> {
+        match self.file {
+            Some(mode) => set_mode(path.as_ref(), mode).await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn apply_dir(&self, path: impl AsRef<Path>) -> IoResult<()> {
+        match self.dir {
+            Some(mode) => set_mode(path.as_ref(), mode).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for DefaultModes {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Table(t) => Ok(Self {
+                file: parse_mode(t.get("file")?)?.map(|mode| mode.for_entry(false)),
+                dir: parse_mode(t.get("dir")?)?.map(|mode| mode.for_entry(true)),
+            }),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "DefaultModes",
+                message: Some(format!(
+                    "Invalid default modes - expected table, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    A named set of permissions, which expands to a different mode
+    for files and directories, such as `0o600` and `0o700` for private.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModePreset {
+    /// Only the owner may read and write files, and enter directories.
+    Private,
+    /// Everyone may read files and enter directories, only the owner may write.
+    Shared,
+    /// Like shared, but everyone may also run files.
+    Executable,
+}
+
+impl ModePreset {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "private" => Some(Self::Private),
+            "shared" => Some(Self::Shared),
+            "executable" => Some(Self::Executable),
+            _ => None,
+        }
+    }
+}
+
+/**
+    A permission mode given by a script, either explicitly or as a preset.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsMode {
+    Octal(u32),
+    Preset(ModePreset),
+}
+
+impl FsMode {
+    /**
+        Gets the mode to apply to a file, or to a directory.
+    */
+    pub fn for_entry(self, is_dir: bool) -> u32 {
+        match (self, is_dir) {
+            (Self::Octal(mode), _) => mode,
+            (Self::Preset(ModePreset::Private), false) => 0o600,
+            (Self::Preset(ModePreset::Private), true) => 0o700,
+            (Self::Preset(ModePreset::Shared), false) => 0o644,
+            (Self::Preset(ModePreset::Shared | ModePreset::Executable), _) => 0o755,
+        }
+    }
+}
+
+/**
+    Parses a permission mode from Lua, which may be given either as a
+    number, or as a string of octal digits such as `"755"` or `"0o755"`,
+    since Luau does not have any syntax for octal number literals.
+
+    The names of presets, `"private"`, `"shared"` and `"executable"`, are accepted
+    too, so that scripts can express their intent without knowing octal modes.
+*/
+pub fn parse_mode(value: LuaValue) -> LuaResult<Option<FsMode>> {
+    let mode = match value {
+        LuaValue::Nil => return Ok(None),
+        LuaValue::Integer(i) => u32::try_from(i).ok(),
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        LuaValue::Number(n) if n.fract() == 0.0 && n >= 0.0 => Some(n as u32),
+        LuaValue::String(s) => {
+            let s = s.to_str()?;
+            if let Some(preset) = ModePreset::from_name(s) {
+                return Ok(Some(FsMode::Preset(preset)));
+            }
+            let digits = s.strip_prefix("0o").unwrap_or(s);
+            u32::from_str_radix(digits, 8).ok()
+        }
+        _ => None,
+    };
+    match mode {
+        Some(mode) if mode <= 0o7777 => Ok(Some(FsMode::Octal(mode))),
+        _ => Err(LuaError::RuntimeError(
+            "Invalid permission mode - expected a number or octal string between 0 and 0o7777, \
+             or one of 'private', 'shared' or 'executable'"
+                .to_string(),
+        )),
+    }
+}
+
+/**
+    Gets the given path and all of its ancestors that do not yet
+    exist, ordered from the outermost to the innermost directory.
+*/
+pub async fn missing_ancestors(backend: &dyn FsBackend, path: impl AsRef<Path>) -> Vec<PathBuf> {
+    let mut missing = Vec::new();
+    for ancestor in path.as_ref().ancestors() {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        match backend.kind(ancestor).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            _ => break,
+        }
+        missing.push(ancestor.to_path_buf());
+    }
+    missing.reverse();
+    missing
+}
+
+#[cfg(unix)]
+pub async fn set_mode(path: &Path, mode: u32) -> IoResult<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, Permissions::from_mode(mode)).await
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unused_async)]
+pub async fn set_mode(_: &Path, _: u32) -> IoResult<()> {
+    Ok(())
+}
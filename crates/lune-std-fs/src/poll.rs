@@ -0,0 +1,111 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::{fs, sync::Mutex as AsyncMutex};
+
+use mlua::prelude::*;
+
+use crate::config::FsConfig;
+use crate::metadata::FsMetadata;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PollOptions {
+    pub(crate) interval: Duration,
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for PollOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => match t.get::<_, Option<f64>>("interval")? {
+                None => Self::default(),
+                Some(secs) if secs.is_finite() && secs > 0.0 => Self {
+                    interval: Duration::from_secs_f64(secs),
+                },
+                Some(secs) => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Invalid interval - expected a positive number of seconds, got {secs}"
+                    )))
+                }
+            },
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "PollOptions",
+                    message: Some(format!(
+                        "Invalid poll options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    The parts of metadata that are compared to detect changes.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    exists: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/**
+    Creates an iterator function that returns the metadata for the given path,
+    first immediately, and then each time its size or modification time changes.
+
+    Metadata is read at the given interval while waiting for changes.
+*/
+pub fn poll_metadata(lua: &Lua, path: String, options: PollOptions) -> LuaResult<LuaFunction> {
+    let path = Arc::new(PathBuf::from(path));
+    let last = Arc::new(AsyncMutex::new(None::<Snapshot>));
+    let clock = FsConfig::get(lua).clock;
+    lua.create_async_function(move |_, ()| {
+        let path = Arc::clone(&path);
+        let last = Arc::clone(&last);
+        let clock = clock.clone();
+        async move {
+            // NOTE: Holding the lock while polling makes concurrent calls
+            // wait for the same change, instead of racing for the snapshot
+            let mut last = last.lock().await;
+            loop {
+                let (snapshot, metadata) = match fs::metadata(path.as_ref()).await {
+                    Ok(meta) => (
+                        Snapshot {
+                            exists: true,
+                            size: meta.len(),
+                            modified: meta.modified().ok(),
+                        },
+                        FsMetadata::from(meta),
+                    ),
+                    Err(e) if e.kind() == ErrorKind::NotFound => (
+                        Snapshot {
+                            exists: false,
+                            size: 0,
+                            modified: None,
+                        },
+                        FsMetadata::not_found(),
+                    ),
+                    Err(e) => return Err(e.into()),
+                };
+                if last.as_ref() != Some(&snapshot) {
+                    *last = Some(snapshot);
+                    return Ok(metadata);
+                }
+                clock.sleep(options.interval).await;
+            }
+        }
+    })
+}
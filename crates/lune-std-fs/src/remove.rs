@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::*;
+use tokio::fs;
+
+use super::backend::FsBackend;
+use super::mount::FilesystemBoundaries;
+use super::options::FsRemoveOptions;
+
+/**
+    Removes a file.
+
+    If the path is a symlink, the symlink itself is removed, unless the
+    options allow following symlinks, in which case its target is removed.
+*/
+pub async fn remove_file(
+    backend: &dyn FsBackend,
+    path: impl AsRef<Path>,
+    options: FsRemoveOptions,
+) -> LuaResult<()> {
+    let path = resolve_path(path.as_ref(), options.follow_symlinks).await?;
+    backend.remove_file(&path).await.into_lua_err()
+}
+
+/**
+    Removes a file, returning `false` instead of erroring if nothing exists at the path.
+*/
+pub async fn remove_file_if_exists(
+    backend: &dyn FsBackend,
+    path: impl AsRef<Path>,
+) -> LuaResult<bool> {
+    match backend.remove_file(path.as_ref()).await {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into_lua_err()),
+    }
+}
+
+/**
+    Removes a directory and all of its contents.
+
+    Symlinks inside of the directory are always removed themselves, without
+    removing what they point to. The options only control if the given path
+    is followed when it is a symlink.
+
+    If the options require staying on the same filesystem, the whole
+    tree is checked for any boundaries before anything is removed.
+*/
+pub async fn remove_dir(
+    backend: &dyn FsBackend,
+    path: impl AsRef<Path>,
+    options: FsRemoveOptions,
+) -> LuaResult<()> {
+    let path = resolve_path(path.as_ref(), options.follow_symlinks).await?;
+    if options.same_filesystem {
+        ensure_same_filesystem(&path).await?;
+    }
+    backend.remove_dir_all(&path).await.into_lua_err()
+}
+
+/**
+    Removes all of the contents of a directory, keeping the directory itself.
+
+    Just like when removing directories, symlinks inside of the directory are
+    removed themselves, so that emptying a directory containing a symlink to
+    a directory elsewhere never removes anything outside of it.
+*/
+pub async fn empty_dir(path: impl AsRef<Path>, options: FsRemoveOptions) -> LuaResult<()> {
+    let path = resolve_path(path.as_ref(), options.follow_symlinks).await?;
+    if options.same_filesystem {
+        ensure_same_filesystem(&path).await?;
+    }
+    let mut entries = fs::read_dir(&path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let entry_path = entry.path();
+        // NOTE: The file type of an entry is never that of a symlink target
+        let file_type = entry.file_type().await?;
+        if file_type.is_dir() {
+            fs::remove_dir_all(entry_path).await?;
+        } else if file_type.is_symlink() && cfg!(windows) && is_dir_link(&entry_path).await {
+            // NOTE: Directory symlinks on Windows are removed as directories
+            fs::remove_dir(entry_path).await?;
+        } else {
+            fs::remove_file(entry_path).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn is_dir_link(path: &Path) -> bool {
+    fs::metadata(path).await.is_ok_and(|meta| meta.is_dir())
+}
+
+async fn resolve_path(path: &Path, follow_symlinks: bool) -> LuaResult<PathBuf> {
+    if follow_symlinks && fs::symlink_metadata(path).await?.is_symlink() {
+        Ok(fs::canonicalize(path).await?)
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+async fn ensure_same_filesystem(path: &Path) -> LuaResult<()> {
+    let root = fs::canonicalize(path).await?;
+    let root_meta = fs::metadata(&root).await?;
+    let boundaries = FilesystemBoundaries::new(&root, &root_meta)?;
+
+    let mut queue = VecDeque::from([root]);
+    while let Some(current) = queue.pop_front() {
+        let mut entries = fs::read_dir(&current).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // NOTE: Removal never follows symlinks, so neither do we
+            let meta = fs::symlink_metadata(entry.path()).await?;
+            if !meta.is_dir() {
+                continue;
+            }
+            let entry_path = entry.path();
+            if boundaries.crosses(&entry_path, &meta)? {
+                return Err(LuaError::RuntimeError(format!(
+                    "Refusing to remove '{}' since it contains another filesystem mounted at '{}'",
+                    path.display(),
+                    entry_path.display()
+                )));
+            }
+            queue.push_back(entry_path);
+        }
+    }
+
+    Ok(())
+}
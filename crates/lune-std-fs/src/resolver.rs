@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Result as IoResult;
+use std::sync::Arc;
+
+/**
+    A resolver for virtual paths, such as files packed into an archive,
+    which can then be read using the regular `fs` functions.
+
+    Resolvers are registered for a scheme using [`FsConfig::with_resolver`], after which
+    any path of the form `<scheme>://<path>` is passed to the resolver, without the scheme.
+
+    Resolvers are called from a blocking thread, so they may do blocking I/O.
+
+    [`FsConfig::with_resolver`]: crate::FsConfig::with_resolver
+*/
+pub trait FsResolver: Send + Sync + 'static {
+    /**
+        Reads the entire contents of the file at the given path.
+
+        # Errors
+
+        Errors if the file does not exist or could not be read.
+    */
+    fn read(&self, path: &str) -> IoResult<Vec<u8>>;
+}
+
+/**
+    All of the resolvers registered by the embedder, keyed by their scheme.
+*/
+#[derive(Clone, Default)]
+pub(crate) struct FsResolvers {
+    resolvers: BTreeMap<String, Arc<dyn FsResolver>>,
+}
+
+impl FsResolvers {
+    pub fn insert(&mut self, scheme: String, resolver: Arc<dyn FsResolver>) {
+        self.resolvers.insert(scheme, resolver);
+    }
+
+    /**
+        Finds the resolver for the scheme of a path, returning it along
+        with the rest of the path, or `None` if the path is a regular one.
+
+        Paths with schemes that have no resolver are regular paths, so that
+        Windows drive letters and unusual file names keep working as before.
+    */
+    pub fn resolve<'a>(&self, path: &'a str) -> Option<(Arc<dyn FsResolver>, &'a str)> {
+        let (scheme, rest) = path.split_once("://")?;
+        let resolver = self.resolvers.get(scheme)?;
+        Some((Arc::clone(resolver), rest))
+    }
+}
+
+impl fmt::Debug for FsResolvers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.resolvers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl FsResolver for Echo {
+        fn read(&self, path: &str) -> IoResult<Vec<u8>> {
+            Ok(path.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn resolves_registered_schemes_only() {
+        let mut resolvers = FsResolvers::default();
+        resolvers.insert(String::from("zip"), Arc::new(Echo));
+
+        let (resolver, rest) = resolvers.resolve("zip://bundle.zip!/map.json").unwrap();
+        assert_eq!(rest, "bundle.zip!/map.json");
+        assert_eq!(resolver.read(rest).unwrap(), b"bundle.zip!/map.json");
+
+        assert!(resolvers.resolve("tar://bundle.tar!/map.json").is_none());
+        assert!(resolvers.resolve("C:\\assets\\map.json").is_none());
+        assert!(resolvers.resolve("assets/map.json").is_none());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::{fs, io::AsyncWriteExt};
+
+use mlua::prelude::*;
+
+use crate::atomic::{rename_into_place, sibling_temp_path};
+use crate::limit::DescriptorPermit;
+use crate::options::FsSaveOptions;
+use crate::perms::{set_mode, DefaultModes};
+
+/**
+    How long after a save watchers may ignore events for the saved paths.
+
+    Native backends deliver events shortly after they happen, but
+    may batch them up, so this is deliberately not too strict.
+*/
+const SAVE_TAG_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy)]
+struct SaveTag {
+    at: Instant,
+    /// Whether everything inside of the path is tagged too.
+    tree: bool,
+    /// The number of internal writes that are still writing to the path,
+    /// which keep it tagged until they finish, however long that takes.
+    writing: usize,
+}
+
+impl SaveTag {
+    fn is_live(&self, now: Instant) -> bool {
+        self.writing > 0 || now.duration_since(self.at) < SAVE_TAG_DURATION
+    }
+}
+
+/**
+    The paths recently written by `fs.saveAtomic`, and by copies and moves
+    using the `internal` option, so that watchers created by the same
+    script can tell its own writes apart.
+
+    Paths are canonical, to match the paths reported by native backends.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct SaveTags {
+    paths: Arc<Mutex<HashMap<PathBuf, SaveTag>>>,
+}
+
+impl SaveTags {
+    pub fn get(lua: &Lua) -> Self {
+        if let Some(tags) = lua.app_data_ref::<Self>() {
+            return tags.clone();
+        }
+        let tags = Self::default();
+        lua.set_app_data(tags.clone());
+        tags
+    }
+
+    fn tag(&self, path: &Path) {
+        let now = Instant::now();
+        let mut paths = self.paths.lock().expect("Save tags lock was poisoned");
+        paths.retain(|_, tag| tag.is_live(now));
+        let tag = paths.entry(path.to_path_buf()).or_insert(SaveTag {
+            at: now,
+            tree: false,
+            writing: 0,
+        });
+        tag.at = now;
+    }
+
+    /**
+        Tags the given path, and everything inside of it, for as long as the returned
+        guard is alive and shortly after, while an internal write is writing to it.
+    */
+    pub async fn begin_internal_write(&self, path: &Path) -> InternalWrite {
+        let path = match canonical_parent(path).await {
+            Some(dir) => dir.join(path.file_name().unwrap_or_default()),
+            None => return InternalWrite(None),
+        };
+        let now = Instant::now();
+        let mut paths = self.paths.lock().expect("Save tags lock was poisoned");
+        paths.retain(|_, tag| tag.is_live(now));
+        let tag = paths.entry(path.clone()).or_insert(SaveTag {
+            at: now,
+            tree: true,
+            writing: 0,
+        });
+        tag.tree = true;
+        tag.writing += 1;
+        InternalWrite(Some((self.clone(), path)))
+    }
+
+    /**
+        Checks if the given canonical path was recently saved using `fs.saveAtomic`,
+        or is inside of a path that is, or was recently, written to by an internal write.
+    */
+    pub fn is_tagged(&self, path: &Path) -> bool {
+        let now = Instant::now();
+        let paths = self.paths.lock().expect("Save tags lock was poisoned");
+        path.ancestors().any(|ancestor| {
+            paths
+                .get(ancestor)
+                .is_some_and(|tag| (tag.tree || ancestor == path) && tag.is_live(now))
+        })
+    }
+}
+
+/**
+    A guard for an internal write, see [`SaveTags::begin_internal_write`].
+*/
+#[derive(Debug)]
+pub struct InternalWrite(Option<(SaveTags, PathBuf)>);
+
+impl Drop for InternalWrite {
+    fn drop(&mut self) {
+        let Some((tags, path)) = self.0.take() else {
+            return;
+        };
+        let mut paths = tags.paths.lock().expect("Save tags lock was poisoned");
+        if let Some(tag) = paths.get_mut(&path) {
+            tag.writing = tag.writing.saturating_sub(1);
+            tag.at = Instant::now();
+        }
+    }
+}
+
+/**
+    Saves contents to a file atomically, by writing them to a temporary file,
+    syncing it to disk, and then renaming it over the path, so that other
+    processes watching or reading the path never see a partially written file.
+
+    Without an explicit mode, the permissions of any previous file at the path are
+    kept, and new files are given the default file mode, same as `fs.writeFile`.
+*/
+pub async fn save_atomic(
+    lua: &Lua,
+    path: &Path,
+    contents: &[u8],
+    options: FsSaveOptions,
+) -> LuaResult<()> {
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    let modes = DefaultModes::get(lua);
+    let temp = sibling_temp_path(path);
+
+    // NOTE: Tags are added before writing anything, since
+    // watchers may receive the events before this function returns
+    let tags = SaveTags::get(lua);
+    if let Some(dir) = canonical_parent(path).await {
+        tags.tag(&dir.join(temp.file_name().unwrap_or_default()));
+        tags.tag(&dir.join(path.file_name().unwrap_or_default()));
+    }
+
+    let res = async {
+        let created = !fs::try_exists(path).await.unwrap_or(true);
+        write_temp(&temp, contents, &options).await?;
+        if let Some(mode) = options.mode {
+            set_mode(&temp, mode).await?;
+            fs::rename(&temp, path).await?;
+        } else {
+            if created {
+                modes.apply_file(&temp).await?;
+            }
+            rename_into_place(&temp, path).await?;
+        }
+        if options.fsync_dir {
+            sync_parent(path).await?;
+        }
+        IoResult::Ok(())
+    }
+    .await;
+
+    if res.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    res.map_err(|e| {
+        LuaError::RuntimeError(format!("Failed to save file at '{}'\n{e}", path.display()))
+    })
+}
+
+async fn write_temp(temp: &Path, contents: &[u8], options: &FsSaveOptions) -> IoResult<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)
+        .await?;
+    file.write_all(contents).await?;
+    file.flush().await?;
+    if options.fsync {
+        file.sync_all().await?;
+    }
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+async fn canonical_parent(path: &Path) -> Option<PathBuf> {
+    fs::canonicalize(parent_dir(path)).await.ok()
+}
+
+/**
+    Syncs the directory containing the given path, which makes sure that
+    the rename itself is durable, and not just the contents of the file.
+*/
+#[cfg(unix)]
+async fn sync_parent(path: &Path) -> IoResult<()> {
+    fs::File::open(parent_dir(path)).await?.sync_all().await
+}
+
+// NOTE: Directories can not be opened for syncing on Windows without special flags,
+// and the rename is flushed along with the metadata of the volume instead
+
+#[cfg(not(unix))]
+#[allow(clippy::unused_async)]
+async fn sync_parent(_: &Path) -> IoResult<()> {
+    Ok(())
+}
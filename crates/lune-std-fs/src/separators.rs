@@ -0,0 +1,27 @@
+use std::path::MAIN_SEPARATOR_STR;
+
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+/**
+    Creates the `fs.path` table, with utilities for converting path separators.
+
+    Conversions only ever change separators, so paths with drive letters or
+    other platform-specific prefixes are passed through otherwise unchanged.
+*/
+pub fn create_path(lua: &Lua) -> LuaResult<LuaTable> {
+    TableBuilder::new(lua)?
+        .with_value("sep", MAIN_SEPARATOR_STR)?
+        .with_function("toPosix", |_, path: String| Ok(to_posix(&path)))?
+        .with_function("toWindows", |_, path: String| Ok(to_windows(&path)))?
+        .build_readonly()
+}
+
+fn to_posix(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+fn to_windows(path: &str) -> String {
+    path.replace('/', "\\")
+}
@@ -0,0 +1,99 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use bstr::BString;
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+use crate::limit::DescriptorPermit;
+
+/**
+    A handle for appending to a file that may be shared with other processes.
+
+    Every append holds an advisory lock on the whole file while writing, so
+    that lines appended by other handles and processes using the same kind
+    of lock are never interleaved, no matter how long the lines are.
+*/
+#[derive(Debug, Clone)]
+pub struct FsSharedFile {
+    file: Arc<Mutex<Option<Arc<File>>>>,
+    permit: Arc<Mutex<DescriptorPermit>>,
+}
+
+impl FsSharedFile {
+    pub async fn open(lua: &Lua, path: impl AsRef<Path>) -> LuaResult<Self> {
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let path = path.as_ref().to_path_buf();
+        let file = spawn_blocking(move || OpenOptions::new().create(true).append(true).open(path))
+            .await
+            .into_lua_err()??;
+        Ok(Self {
+            file: Arc::new(Mutex::new(Some(Arc::new(file)))),
+            permit: Arc::new(Mutex::new(permit)),
+        })
+    }
+
+    fn file(&self) -> LuaResult<Arc<File>> {
+        self.file
+            .lock()
+            .ok()
+            .and_then(|guard| guard.clone())
+            .ok_or_else(|| LuaError::runtime("Shared file has already been closed"))
+    }
+
+    /**
+        Appends a line to the file, followed by a newline character.
+
+        The line is written using a single write while holding an exclusive
+        lock on the file, waiting for any other process holding it to finish.
+    */
+    pub async fn append(&self, line: Vec<u8>) -> LuaResult<()> {
+        if line.contains(&b'\n') {
+            return Err(LuaError::runtime(
+                "Lines must not contain newline characters",
+            ));
+        }
+        let file = self.file()?;
+        let mut contents = line;
+        contents.push(b'\n');
+        spawn_blocking(move || {
+            file.lock()?;
+            let res = file.as_ref().write_all(&contents);
+            file.unlock()?;
+            res
+        })
+        .await
+        .into_lua_err()??;
+        Ok(())
+    }
+
+    pub fn close(&self) -> LuaResult<()> {
+        let closed = self.file.lock().ok().and_then(|mut guard| guard.take());
+        if closed.is_none() {
+            return Err(LuaError::runtime("Shared file has already been closed"));
+        }
+        if let Ok(mut permit) = self.permit.lock() {
+            permit.release();
+        }
+        Ok(())
+    }
+}
+
+impl LuaUserData for FsSharedFile {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("append", |_, this, line: BString| async move {
+            this.append(line.into()).await
+        });
+
+        methods.add_method("close", |_, this, (): ()| this.close());
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsSharedFile");
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+
+use mlua::prelude::*;
+
+use crate::backend::FsBackend;
+use crate::verify::{hash_file_with, HashAlgorithm};
+
+/**
+    Returns the path of the sidecar file for the given path and algorithm, such as `app.zip.sha256`.
+*/
+pub fn sidecar_path(path: &Path, algorithm: &HashAlgorithm) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_os_string();
+    sidecar.push(".");
+    sidecar.push(algorithm.name());
+    PathBuf::from(sidecar)
+}
+
+/**
+    Writes the sidecar file for a file that was just written with the given contents.
+
+    Sidecars use the same format as `sha256sum` and `sha512sum`, with the hash followed by
+    two spaces and the name of the file, so that they can also be checked using `sha256sum -c`.
+*/
+pub async fn write_sidecar(
+    backend: &dyn FsBackend,
+    path: &Path,
+    contents: &[u8],
+    algorithm: &HashAlgorithm,
+) -> LuaResult<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let line = format!("{}  {name}\n", algorithm.hash_bytes(contents));
+    let sidecar = sidecar_path(path, algorithm);
+    backend.write(&sidecar, line.as_bytes()).await.map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to write the sidecar file '{}'\n{e}",
+            sidecar.display()
+        ))
+    })
+}
+
+/**
+    Checks the file at the given path against its sidecar file, returning if the hashes match.
+
+    Sidecars of the given algorithms are looked for, in order,
+    and the first one that exists is checked against the file.
+*/
+pub async fn verify_sidecar(path: &Path, algorithms: Vec<HashAlgorithm>) -> LuaResult<bool> {
+    for algorithm in &algorithms {
+        let sidecar = sidecar_path(path, algorithm);
+        let line = match fs::read_to_string(&sidecar).await {
+            Ok(line) => line,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let expected = parse_sidecar(&line, algorithm).ok_or_else(|| {
+            LuaError::RuntimeError(format!(
+                "Failed to verify '{}' - the sidecar file '{}' is malformed",
+                path.display(),
+                sidecar.display()
+            ))
+        })?;
+        let hash = hash_file_with(path, algorithm).await?;
+        return Ok(hash.eq_ignore_ascii_case(expected));
+    }
+    Err(LuaError::RuntimeError(format!(
+        "Failed to verify '{}' - no sidecar file exists for it",
+        path.display()
+    )))
+}
+
+/**
+    Parses the hash from the first line of a sidecar file, which may either contain
+    just the hash, or the hash followed by the name of the file, like `sha256sum` writes.
+*/
+fn parse_sidecar<'a>(contents: &'a str, algorithm: &HashAlgorithm) -> Option<&'a str> {
+    let hash = contents.lines().next()?.split_whitespace().next()?;
+    let expected_len = algorithm.hash_bytes(&[]).len();
+    let valid = hash.len() == expected_len && hash.bytes().all(|b| b.is_ascii_hexdigit());
+    valid.then_some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sidecar_formats() {
+        let hash = HashAlgorithm::Sha256.hash_bytes(b"contents");
+        let with_name = format!("{hash}  app.zip\n");
+        let uppercase = hash.to_ascii_uppercase();
+        assert_eq!(
+            parse_sidecar(&with_name, &HashAlgorithm::Sha256),
+            Some(&*hash)
+        );
+        assert_eq!(parse_sidecar(&hash, &HashAlgorithm::Sha256), Some(&*hash));
+        assert_eq!(
+            parse_sidecar(&uppercase, &HashAlgorithm::Sha256),
+            Some(&*uppercase)
+        );
+        assert_eq!(parse_sidecar(&with_name, &HashAlgorithm::Sha512), None);
+        assert_eq!(parse_sidecar("not a hash", &HashAlgorithm::Sha256), None);
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, Permissions};
+use std::io::{ErrorKind, Result as IoResult};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+use crate::limit::DescriptorPermit;
+
+/**
+    An entry captured in a snapshot, keyed by its path relative to the snapshot root.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SnapshotEntry {
+    File {
+        contents: Vec<u8>,
+        permissions: Permissions,
+        modified: Option<SystemTime>,
+    },
+    Dir {
+        permissions: Permissions,
+    },
+    Symlink {
+        target: PathBuf,
+        // Needed to recreate the symlink on Windows, where
+        // file and directory symlinks are created differently
+        is_dir: bool,
+    },
+}
+
+impl SnapshotEntry {
+    /**
+        Checks if the entry has the same kind and contents as another entry,
+        ignoring permissions and modification times, which restoring sets anyway.
+    */
+    fn same_contents(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::File { contents: a, .. }, Self::File { contents: b, .. }) => a == b,
+            (Self::Dir { .. }, Self::Dir { .. }) => true,
+            (Self::Symlink { target: a, .. }, Self::Symlink { target: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+type SnapshotEntries = BTreeMap<PathBuf, SnapshotEntry>;
+
+/**
+    The differences between a snapshot and the current state of a directory.
+*/
+#[derive(Debug, Clone, Default)]
+struct SnapshotDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+impl<'lua> IntoLua<'lua> for SnapshotDiff {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 3)?;
+        tab.set("added", self.added)?;
+        tab.set("removed", self.removed)?;
+        tab.set("changed", self.changed)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    The contents and metadata of a directory, kept in memory, which
+    the directory or any other directory can be restored to later on.
+*/
+#[derive(Debug, Clone)]
+pub struct FsSnapshot {
+    root: Arc<PathBuf>,
+    entries: Arc<SnapshotEntries>,
+}
+
+impl FsSnapshot {
+    pub async fn capture(lua: &Lua, path: impl AsRef<Path>) -> LuaResult<Self> {
+        let root = path.as_ref().to_path_buf();
+        let _permit = DescriptorPermit::acquire(lua, 1).await;
+        let captured = root.clone();
+        let entries = spawn_blocking(move || capture_blocking(&captured))
+            .await
+            .into_lua_err()?
+            .map_err(|e| snapshot_error("capture", &root, &e))?;
+        Ok(Self {
+            root: Arc::new(root),
+            entries: Arc::new(entries),
+        })
+    }
+
+    async fn restore(&self, lua: &Lua, path: Option<PathBuf>) -> LuaResult<()> {
+        let path = path.unwrap_or_else(|| self.root.to_path_buf());
+        let _permit = DescriptorPermit::acquire(lua, 1).await;
+        let entries = Arc::clone(&self.entries);
+        let restored = path.clone();
+        spawn_blocking(move || restore_blocking(&entries, &restored))
+            .await
+            .into_lua_err()?
+            .map_err(|e| snapshot_error("restore", &path, &e))
+    }
+
+    async fn diff(&self, lua: &Lua, path: Option<PathBuf>) -> LuaResult<SnapshotDiff> {
+        let path = path.unwrap_or_else(|| self.root.to_path_buf());
+        let _permit = DescriptorPermit::acquire(lua, 1).await;
+        let entries = Arc::clone(&self.entries);
+        let compared = path.clone();
+        spawn_blocking(move || diff_blocking(&entries, &compared))
+            .await
+            .into_lua_err()?
+            .map_err(|e| snapshot_error("compare", &path, &e))
+    }
+}
+
+impl LuaUserData for FsSnapshot {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("restore", |lua, this, path: Option<String>| async move {
+            this.restore(lua, path.map(PathBuf::from)).await
+        });
+
+        methods.add_async_method("diff", |lua, this, path: Option<String>| async move {
+            this.diff(lua, path.map(PathBuf::from)).await
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsSnapshot");
+    }
+}
+
+fn snapshot_error(action: &str, path: &Path, err: &std::io::Error) -> LuaError {
+    LuaError::RuntimeError(format!(
+        "Failed to {action} snapshot at '{}'\n{err}",
+        path.display()
+    ))
+}
+
+/**
+    Captures all entries inside of the given directory, without following symlinks.
+
+    Entries that are not files, directories or symlinks,
+    such as sockets and named pipes, are skipped.
+*/
+fn capture_blocking(root: &Path) -> IoResult<SnapshotEntries> {
+    if !fs::metadata(root)?.is_dir() {
+        return Err(std::io::Error::new(
+            ErrorKind::NotADirectory,
+            "The path is not a directory",
+        ));
+    }
+    let mut entries = SnapshotEntries::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            let meta = entry.metadata()?;
+            let captured = if meta.is_symlink() {
+                SnapshotEntry::Symlink {
+                    target: fs::read_link(entry.path())?,
+                    is_dir: fs::metadata(entry.path()).is_ok_and(|meta| meta.is_dir()),
+                }
+            } else if meta.is_dir() {
+                dirs.push(relative.clone());
+                SnapshotEntry::Dir {
+                    permissions: meta.permissions(),
+                }
+            } else if meta.is_file() {
+                SnapshotEntry::File {
+                    contents: fs::read(entry.path())?,
+                    permissions: meta.permissions(),
+                    modified: meta.modified().ok(),
+                }
+            } else {
+                continue;
+            };
+            entries.insert(relative, captured);
+        }
+    }
+    Ok(entries)
+}
+
+/**
+    Makes the directory at `root` match the snapshot, removing entries that
+    are not in the snapshot and writing entries that differ from it.
+
+    Entries are written in order, so that directories are always created before
+    their contents, and directory permissions are set last, innermost first.
+*/
+fn restore_blocking(snapshot: &SnapshotEntries, root: &Path) -> IoResult<()> {
+    fs::create_dir_all(root)?;
+    let current = capture_blocking(root)?;
+
+    for (path, entry) in &current {
+        let stale = snapshot
+            .get(path)
+            .is_none_or(|expected| !expected.same_contents(entry));
+        // NOTE: Entries inside of directories that were already removed no longer exist
+        if stale && fs::symlink_metadata(root.join(path)).is_ok() {
+            remove_blocking(&root.join(path), entry)?;
+        }
+    }
+
+    for (path, entry) in snapshot {
+        let target = root.join(path);
+        let unchanged = current
+            .get(path)
+            .is_some_and(|existing| existing.same_contents(entry));
+        match entry {
+            SnapshotEntry::Dir { .. } => fs::create_dir_all(&target)?,
+            SnapshotEntry::File {
+                contents,
+                permissions,
+                modified,
+            } => {
+                if !unchanged {
+                    fs::write(&target, contents)?;
+                }
+                if let Some(modified) = modified {
+                    File::options()
+                        .write(true)
+                        .open(&target)
+                        .or_else(|_| File::open(&target))?
+                        .set_modified(*modified)?;
+                }
+                fs::set_permissions(&target, permissions.clone())?;
+            }
+            SnapshotEntry::Symlink {
+                target: link,
+                is_dir,
+            } if !unchanged => {
+                symlink_blocking(link, &target, *is_dir)?;
+            }
+            SnapshotEntry::Symlink { .. } => {}
+        }
+    }
+
+    for (path, entry) in snapshot.iter().rev() {
+        if let SnapshotEntry::Dir { permissions } = entry {
+            fs::set_permissions(root.join(path), permissions.clone())?;
+        }
+    }
+    Ok(())
+}
+
+fn diff_blocking(snapshot: &SnapshotEntries, root: &Path) -> IoResult<SnapshotDiff> {
+    let current = match capture_blocking(root) {
+        Err(e) if e.kind() == ErrorKind::NotFound => SnapshotEntries::new(),
+        res => res?,
+    };
+    let mut diff = SnapshotDiff::default();
+    for (path, entry) in &current {
+        match snapshot.get(path) {
+            None => diff.added.push(display_path(path)),
+            Some(expected) if !expected.same_contents(entry) => {
+                diff.changed.push(display_path(path));
+            }
+            Some(_) => {}
+        }
+    }
+    for path in snapshot.keys() {
+        if !current.contains_key(path) {
+            diff.removed.push(display_path(path));
+        }
+    }
+    Ok(diff)
+}
+
+fn remove_blocking(path: &Path, entry: &SnapshotEntry) -> IoResult<()> {
+    match entry {
+        SnapshotEntry::Dir { .. } => fs::remove_dir_all(path),
+        // NOTE: Directory symlinks on Windows are removed as directories
+        SnapshotEntry::Symlink { is_dir: true, .. } if cfg!(windows) => fs::remove_dir(path),
+        _ => fs::remove_file(path),
+    }
+}
+
+/**
+    Joins the components of a relative path using forward slashes, so
+    that paths in diffs look the same no matter the current platform.
+*/
+pub fn display_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn symlink_blocking(target: &Path, link: &Path, _: bool) -> IoResult<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_blocking(target: &Path, link: &Path, is_dir: bool) -> IoResult<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
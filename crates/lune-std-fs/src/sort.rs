@@ -0,0 +1,155 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use mlua::prelude::*;
+
+/**
+    How the entries of directory listings are sorted.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirSort {
+    /// Sorted by the code points of names.
+    Name,
+    /// Sorted by name, but with runs of digits compared by their numeric value.
+    Natural,
+    /// Sorted by name, ignoring case and accents, similar to a dictionary.
+    Locale,
+}
+
+impl DirSort {
+    pub fn sort(self, names: &mut [String]) {
+        match self {
+            Self::Name => names.sort_unstable(),
+            Self::Natural => names.sort_by(|a, b| natural_cmp(a, b)),
+            // NOTE: Collation keys are somewhat expensive to
+            // create, so we create them only once for each name
+            Self::Locale => names.sort_by_cached_key(|name| (collation_key(name), name.clone())),
+        }
+    }
+}
+
+impl FromStr for DirSort {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "name" => Ok(Self::Name),
+            "natural" => Ok(Self::Natural),
+            "locale" => Ok(Self::Locale),
+            _ => Err("Invalid sort mode - expected 'name', 'natural' or 'locale'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for DirSort {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "DirSort",
+                message: Some("Sort mode must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+/**
+    Compares two strings, treating runs of ascii digits as numbers, so that `file2` comes before `file10`.
+
+    Numbers that are equal, but have a different amount of leading zeros, are ordered by the amount of zeros.
+*/
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(a_first), Some(b_first)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        let is_numeric = a_first.is_ascii_digit() && b_first.is_ascii_digit();
+        let (a_chunk, a_rest) = split_chunk(a);
+        let (b_chunk, b_rest) = split_chunk(b);
+        let ordering = if is_numeric {
+            numeric_cmp(a_chunk, b_chunk)
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (a, b) = (a_rest, b_rest);
+    }
+}
+
+/**
+    Splits a string after its leading run of either digits or non-digits.
+*/
+fn split_chunk(s: &str) -> (&str, &str) {
+    let is_digit = s.starts_with(|c: char| c.is_ascii_digit());
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != is_digit)
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+fn numeric_cmp(a: &str, b: &str) -> Ordering {
+    let a_trimmed = a.trim_start_matches('0');
+    let b_trimmed = b.trim_start_matches('0');
+    a_trimmed
+        .len()
+        .cmp(&b_trimmed.len())
+        .then_with(|| a_trimmed.cmp(b_trimmed))
+        .then_with(|| b.len().cmp(&a.len()))
+}
+
+/**
+    Creates a key for sorting names without regard to case or accents.
+*/
+fn collation_key(name: &str) -> String {
+    name.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mode: DirSort, names: &[&str]) -> Vec<String> {
+        let mut names = names.iter().map(ToString::to_string).collect::<Vec<_>>();
+        mode.sort(&mut names);
+        names
+    }
+
+    #[test]
+    fn natural_sorts_numbers_by_value() {
+        assert_eq!(
+            sorted(
+                DirSort::Natural,
+                &["file10", "file2", "file1", "file02", "file"]
+            ),
+            ["file", "file1", "file02", "file2", "file10"]
+        );
+        assert_eq!(
+            sorted(DirSort::Natural, &["v1.10.0", "v1.9.2", "v1.9.10"]),
+            ["v1.9.2", "v1.9.10", "v1.10.0"]
+        );
+    }
+
+    #[test]
+    fn locale_ignores_case_and_accents() {
+        assert_eq!(
+            sorted(
+                DirSort::Locale,
+                &["banana", "Cherry", "\u{e9}clair", "apple"]
+            ),
+            ["apple", "banana", "Cherry", "\u{e9}clair"]
+        );
+        assert_eq!(
+            sorted(DirSort::Name, &["banana", "Cherry", "apple"]),
+            ["Cherry", "apple", "banana"]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::*;
+
+use crate::mount::mount_point;
+
+/**
+    Information about the filesystem that contains a path.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilesystemInfo {
+    /// The name of the filesystem type, such as `ext4`, `apfs` or `NTFS`, if known.
+    pub kind: Option<String>,
+    /// The preferred block size of the filesystem, in bytes.
+    pub block_size: u64,
+    pub read_only: bool,
+    pub mount_point: PathBuf,
+}
+
+impl FilesystemInfo {
+    /**
+        Gets information about the filesystem containing the given path, which must exist.
+    */
+    pub fn for_path(path: &Path) -> IoResult<Self> {
+        let mount_point = mount_point(path)?;
+        platform::info(path, mount_point)
+    }
+}
+
+impl<'lua> IntoLua<'lua> for FilesystemInfo {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 4)?;
+        tab.set("type", self.kind)?;
+        tab.set("blockSize", self.block_size)?;
+        tab.set("readOnly", self.read_only)?;
+        tab.set("mountPoint", self.mount_point.to_string_lossy())?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::CString;
+    use std::io::Error as IoError;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    use super::{FilesystemInfo, IoResult, Path, PathBuf};
+
+    pub fn info(path: &Path, mount_point: PathBuf) -> IoResult<FilesystemInfo> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(IoError::other)?;
+        let mut stat = MaybeUninit::<libc::statvfs>::zeroed();
+        // SAFETY: The path is a valid null-terminated string and stat is valid for writes
+        if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            return Err(IoError::last_os_error());
+        }
+        // SAFETY: The call above succeeded, so it has written the struct
+        let stat = unsafe { stat.assume_init() };
+        // NOTE: The block size is not a 64-bit integer on all platforms
+        #[allow(clippy::unnecessary_cast)]
+        Ok(FilesystemInfo {
+            kind: filesystem_type(&c_path, &mount_point)?,
+            block_size: stat.f_bsize as u64,
+            read_only: stat.f_flag & libc::ST_RDONLY != 0,
+            mount_point,
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn filesystem_type(_: &CString, mount_point: &Path) -> IoResult<Option<String>> {
+        crate::mount::filesystem_type(mount_point)
+    }
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd"
+    ))]
+    fn filesystem_type(path: &CString, _: &Path) -> IoResult<Option<String>> {
+        use std::ffi::CStr;
+
+        let mut stat = MaybeUninit::<libc::statfs>::zeroed();
+        // SAFETY: The path is a valid null-terminated string and stat is valid for writes
+        if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            return Err(IoError::last_os_error());
+        }
+        // SAFETY: The call above succeeded, so it has written the
+        // struct, and the type name is always null-terminated
+        let name = unsafe { CStr::from_ptr(stat.assume_init().f_fstypename.as_ptr()) };
+        Ok(Some(name.to_string_lossy().into_owned()))
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd"
+    )))]
+    #[allow(clippy::unnecessary_wraps)]
+    fn filesystem_type(_: &CString, _: &Path) -> IoResult<Option<String>> {
+        Ok(None)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io::Error as IoError;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+
+    use super::{FilesystemInfo, IoResult, Path, PathBuf};
+
+    const FILE_READ_ONLY_VOLUME: u32 = 0x0008_0000;
+    const MAX_PATH: usize = 260;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetVolumeInformationW(
+            root_path_name: *const u16,
+            volume_name_buffer: *mut u16,
+            volume_name_size: u32,
+            volume_serial_number: *mut u32,
+            maximum_component_length: *mut u32,
+            file_system_flags: *mut u32,
+            file_system_name_buffer: *mut u16,
+            file_system_name_size: u32,
+        ) -> i32;
+        fn GetDiskFreeSpaceW(
+            root_path_name: *const u16,
+            sectors_per_cluster: *mut u32,
+            bytes_per_sector: *mut u32,
+            number_of_free_clusters: *mut u32,
+            total_number_of_clusters: *mut u32,
+        ) -> i32;
+    }
+
+    pub fn info(_: &Path, mount_point: PathBuf) -> IoResult<FilesystemInfo> {
+        // NOTE: Volume roots must end with a backslash to be accepted here
+        let mut root = mount_point.as_os_str().encode_wide().collect::<Vec<_>>();
+        if root.last() != Some(&u16::from(b'\\')) {
+            root.push(u16::from(b'\\'));
+        }
+        let root = root.into_iter().chain(once(0)).collect::<Vec<_>>();
+
+        let mut flags = 0;
+        let mut name = [0u16; MAX_PATH + 1];
+        // SAFETY: The root is null-terminated, and all out pointers are either
+        // null or valid for writes, with the name buffer being as long as given
+        let ok = unsafe {
+            GetVolumeInformationW(
+                root.as_ptr(),
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut flags,
+                name.as_mut_ptr(),
+                name.len() as u32,
+            )
+        };
+        if ok == 0 {
+            return Err(IoError::last_os_error());
+        }
+        let name_len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+
+        let (mut sectors_per_cluster, mut bytes_per_sector) = (0, 0);
+        // SAFETY: The root is null-terminated, and all out pointers are either null or valid for writes
+        let ok = unsafe {
+            GetDiskFreeSpaceW(
+                root.as_ptr(),
+                &mut sectors_per_cluster,
+                &mut bytes_per_sector,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok(FilesystemInfo {
+            kind: Some(String::from_utf16_lossy(&name[..name_len])),
+            block_size: u64::from(sectors_per_cluster) * u64::from(bytes_per_sector),
+            read_only: flags & FILE_READ_ONLY_VOLUME != 0,
+            mount_point,
+        })
+    }
+}
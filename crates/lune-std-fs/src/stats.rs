@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+use mlua::prelude::*;
+
+/**
+    Statistics for a single operation, returned when passing `stats = true` to it.
+*/
+#[derive(Debug, Clone)]
+pub struct FsOpStats {
+    queue_wait: Duration,
+    io_time: Duration,
+    bytes: u64,
+    skipped: Option<Vec<String>>,
+}
+
+impl FsOpStats {
+    /**
+        Adds the paths that the operation skipped, since they could not be read.
+    */
+    pub fn with_skipped(mut self, skipped: Vec<String>) -> Self {
+        self.skipped = Some(skipped);
+        self
+    }
+}
+
+impl IntoLua<'_> for FsOpStats {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let tab = lua.create_table_with_capacity(0, 4)?;
+        tab.set("queueWait", self.queue_wait.as_secs_f64())?;
+        tab.set("ioTime", self.io_time.as_secs_f64())?;
+        tab.set("bytes", self.bytes)?;
+        tab.set("skipped", self.skipped)?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    Times an operation, separating the time spent waiting for a permit to open files
+    from the time spent doing IO, which both start once the timer is started.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct OpTimer {
+    started: Instant,
+    acquired: Option<Instant>,
+}
+
+impl OpTimer {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            acquired: None,
+        }
+    }
+
+    /**
+        Marks the end of waiting in the queue, and the start of doing IO.
+    */
+    pub fn acquired(&mut self) {
+        self.acquired = Some(Instant::now());
+    }
+
+    /**
+        Finishes timing the operation, which moved the given number of bytes.
+
+        Operations that never had to wait in a queue spent all of their time doing IO.
+    */
+    pub fn finish(self, bytes: u64) -> FsOpStats {
+        let acquired = self.acquired.unwrap_or(self.started);
+        FsOpStats {
+            queue_wait: acquired - self.started,
+            io_time: acquired.elapsed(),
+            bytes,
+            skipped: None,
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod read;
+mod write;
+
+pub use self::read::{FsReadStream, FsReadStreamOptions};
+pub use self::write::{FsWriteStream, FsWriteStreamOptions};
@@ -0,0 +1,196 @@
+use std::{io::SeekFrom, path::Path, sync::Arc};
+
+use bstr::{BString, ByteSlice};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, BufReader, Take},
+    sync::Mutex as AsyncMutex,
+};
+
+use mlua::prelude::*;
+
+use crate::binary::FsBinaryMode;
+use crate::limit::DescriptorPermit;
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/**
+    Options for creating a read stream.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct FsReadStreamOptions {
+    pub(crate) chunk_size: usize,
+    pub(crate) start: u64,
+    pub(crate) end: Option<u64>,
+}
+
+impl Default for FsReadStreamOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            start: 0,
+            end: None,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsReadStreamOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let defaults = Self::default();
+                let options = Self {
+                    chunk_size: t
+                        .get::<_, Option<usize>>("chunkSize")?
+                        .unwrap_or(defaults.chunk_size),
+                    start: t.get::<_, Option<u64>>("start")?.unwrap_or(defaults.start),
+                    end: t.get("end")?,
+                };
+                if options.chunk_size == 0 {
+                    return Err(LuaError::runtime(
+                        "Invalid read stream options - chunkSize must be greater than zero",
+                    ));
+                }
+                if options.end.is_some_and(|end| end < options.start) {
+                    return Err(LuaError::runtime(
+                        "Invalid read stream options - end must not be less than start",
+                    ));
+                }
+                options
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsReadStreamOptions",
+                    message: Some(format!(
+                        "Invalid read stream options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+struct FsReadStreamState {
+    reader: Take<BufReader<File>>,
+    pushback: Vec<u8>,
+    chunk_size: usize,
+    _permit: DescriptorPermit,
+}
+
+impl FsReadStreamState {
+    async fn read(&mut self, len: usize) -> LuaResult<Vec<u8>> {
+        // Bytes that were pushed back are always read first, and we only
+        // need to read from the file if they did not fill the whole chunk
+        let from_pushback = len.min(self.pushback.len());
+        let mut bytes = self.pushback.drain(..from_pushback).collect::<Vec<_>>();
+        let mut filled = bytes.len();
+        bytes.resize(len, 0);
+        while filled < len {
+            let read = self.reader.read(&mut bytes[filled..]).await?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        bytes.truncate(filled);
+        Ok(bytes)
+    }
+
+    fn unread(&mut self, bytes: &[u8]) {
+        self.pushback.splice(0..0, bytes.iter().copied());
+    }
+}
+
+/**
+    A buffered stream for reading a file incrementally, which
+    supports peeking at and pushing back already read contents.
+*/
+#[derive(Debug, Clone)]
+pub struct FsReadStream {
+    state: Arc<AsyncMutex<FsReadStreamState>>,
+}
+
+impl FsReadStream {
+    pub async fn open(
+        lua: &Lua,
+        path: impl AsRef<Path>,
+        options: FsReadStreamOptions,
+    ) -> LuaResult<Self> {
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let mut file = File::open(path).await?;
+        if options.start > 0 {
+            file.seek(SeekFrom::Start(options.start)).await?;
+        }
+        let limit = match options.end {
+            Some(end) => end - options.start,
+            None => u64::MAX,
+        };
+        let reader = BufReader::with_capacity(options.chunk_size, file).take(limit);
+        Ok(Self {
+            state: Arc::new(AsyncMutex::new(FsReadStreamState {
+                reader,
+                pushback: Vec::new(),
+                chunk_size: options.chunk_size,
+                _permit: permit,
+            })),
+        })
+    }
+
+    pub async fn read(&self, len: Option<usize>) -> LuaResult<Option<Vec<u8>>> {
+        let mut state = self.state.lock().await;
+        let len = len.unwrap_or(state.chunk_size);
+        let bytes = state.read(len).await?;
+        Ok(if bytes.is_empty() && len > 0 {
+            None
+        } else {
+            Some(bytes)
+        })
+    }
+
+    pub async fn peek(&self, len: Option<usize>) -> LuaResult<Option<Vec<u8>>> {
+        let mut state = self.state.lock().await;
+        let len = len.unwrap_or(state.chunk_size);
+        let bytes = state.read(len).await?;
+        state.unread(&bytes);
+        Ok(if bytes.is_empty() && len > 0 {
+            None
+        } else {
+            Some(bytes)
+        })
+    }
+
+    pub async fn unread(&self, bytes: &[u8]) {
+        self.state.lock().await.unread(bytes);
+    }
+}
+
+impl LuaUserData for FsReadStream {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("read", |lua, this, len: Option<usize>| async move {
+            this.read(len)
+                .await?
+                .map(|bytes| FsBinaryMode::get(lua).create(lua, bytes))
+                .transpose()
+        });
+
+        methods.add_async_method("peek", |lua, this, len: Option<usize>| async move {
+            this.peek(len)
+                .await?
+                .map(|bytes| FsBinaryMode::get(lua).create(lua, bytes))
+                .transpose()
+        });
+
+        methods.add_async_method("unread", |_, this, bytes: BString| async move {
+            this.unread(bytes.as_bytes()).await;
+            Ok(())
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsReadStream");
+    }
+}
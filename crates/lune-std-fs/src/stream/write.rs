@@ -0,0 +1,135 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use bstr::{BString, ByteSlice};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWriteExt, BufWriter},
+    sync::Mutex as AsyncMutex,
+};
+
+use mlua::prelude::*;
+
+use crate::limit::DescriptorPermit;
+
+/**
+    Options for creating a write stream.
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsWriteStreamOptions {
+    pub(crate) append: bool,
+    pub(crate) buffer_size: Option<usize>,
+}
+
+impl<'lua> FromLua<'lua> for FsWriteStreamOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        Ok(match value {
+            LuaValue::Nil => Self::default(),
+            LuaValue::Table(t) => {
+                let buffer_size: Option<usize> = t.get("bufferSize")?;
+                if buffer_size == Some(0) {
+                    return Err(LuaError::runtime(
+                        "Invalid write stream options - bufferSize must be greater than zero",
+                    ));
+                }
+                Self {
+                    append: t.get::<_, Option<bool>>("append")?.unwrap_or(false),
+                    buffer_size,
+                }
+            }
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FsWriteStreamOptions",
+                    message: Some(format!(
+                        "Invalid write stream options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        })
+    }
+}
+
+/**
+    A buffered stream for writing to a file incrementally.
+
+    The stream is finished, and any buffered contents flushed, by calling `finish`.
+*/
+#[derive(Debug, Clone)]
+pub struct FsWriteStream {
+    writer: Arc<AsyncMutex<Option<BufWriter<tokio::fs::File>>>>,
+    permit: Arc<Mutex<DescriptorPermit>>,
+}
+
+impl FsWriteStream {
+    pub async fn open(
+        lua: &Lua,
+        path: impl AsRef<Path>,
+        options: FsWriteStreamOptions,
+    ) -> LuaResult<Self> {
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(options.append)
+            .truncate(!options.append)
+            .open(path)
+            .await?;
+        let writer = match options.buffer_size {
+            Some(size) => BufWriter::with_capacity(size, file),
+            None => BufWriter::new(file),
+        };
+        Ok(Self {
+            writer: Arc::new(AsyncMutex::new(Some(writer))),
+            permit: Arc::new(Mutex::new(permit)),
+        })
+    }
+
+    pub async fn write(&self, chunks: &[&[u8]]) -> LuaResult<()> {
+        let mut guard = self.writer.lock().await;
+        let Some(writer) = guard.as_mut() else {
+            return Err(LuaError::runtime("Write stream has already been finished"));
+        };
+        for chunk in chunks {
+            writer.write_all(chunk).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(&self) -> LuaResult<()> {
+        let mut guard = self.writer.lock().await;
+        let Some(mut writer) = guard.take() else {
+            return Err(LuaError::runtime("Write stream has already been finished"));
+        };
+        writer.flush().await?;
+        writer.into_inner().sync_all().await?;
+        if let Ok(mut permit) = self.permit.lock() {
+            permit.release();
+        }
+        Ok(())
+    }
+}
+
+impl LuaUserData for FsWriteStream {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("write", |_, this, chunk: BString| async move {
+            this.write(&[chunk.as_bytes()]).await
+        });
+
+        methods.add_async_method("writeLine", |_, this, line: BString| async move {
+            this.write(&[line.as_bytes(), b"\n"]).await
+        });
+
+        methods.add_async_method(
+            "finish",
+            |_, this, (): ()| async move { this.finish().await },
+        );
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsWriteStream");
+    }
+}
@@ -0,0 +1,261 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bstr::{BString, ByteSlice};
+use tokio::{sync::Mutex as AsyncMutex, task::spawn_blocking};
+
+use mlua::prelude::*;
+
+use crate::atomic::{rename_into_place, sibling_temp_path};
+use crate::limit::DescriptorPermit;
+use crate::perms::DefaultModes;
+
+/**
+    Where the contents of a temporary file live until it is persisted.
+*/
+#[derive(Debug)]
+enum TempLocation {
+    /// An anonymous file without any name, which is never visible
+    /// to other processes and vanishes when closed, if not persisted.
+    Anonymous,
+    /// A hidden file in the directory, for platforms and filesystems
+    /// without anonymous files, which is removed when dropped.
+    Named(PathBuf),
+}
+
+#[derive(Debug)]
+struct TempFileState {
+    file: Arc<File>,
+    location: TempLocation,
+    _permit: DescriptorPermit,
+}
+
+impl Drop for TempFileState {
+    fn drop(&mut self) {
+        if let TempLocation::Named(path) = &self.location {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/**
+    A temporary file that is only linked into place once it has been completely written.
+
+    The file is discarded if it is garbage collected or closed without being persisted.
+*/
+#[derive(Debug, Clone)]
+pub struct FsTempFile {
+    state: Arc<AsyncMutex<Option<TempFileState>>>,
+}
+
+impl FsTempFile {
+    pub async fn create_in(lua: &Lua, dir: impl AsRef<Path>) -> LuaResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        if !tokio::fs::metadata(&dir)
+            .await
+            .is_ok_and(|meta| meta.is_dir())
+        {
+            return Err(LuaError::RuntimeError(format!(
+                "No directory exists at the path '{}'",
+                dir.display()
+            )));
+        }
+        let permit = DescriptorPermit::acquire(lua, 1).await;
+        let modes = DefaultModes::get(lua);
+        let (file, location) = spawn_blocking(move || create_blocking(&dir, modes))
+            .await
+            .into_lua_err()??;
+        Ok(Self {
+            state: Arc::new(AsyncMutex::new(Some(TempFileState {
+                file: Arc::new(file),
+                location,
+                _permit: permit,
+            }))),
+        })
+    }
+
+    async fn write(&self, contents: Vec<u8>) -> LuaResult<()> {
+        let guard = self.state.lock().await;
+        let file = Arc::clone(&guard.as_ref().ok_or_else(finished_error)?.file);
+        spawn_blocking(move || file.as_ref().write_all(&contents))
+            .await
+            .into_lua_err()??;
+        Ok(())
+    }
+
+    /**
+        Syncs the contents of the file to disk, and then atomically replaces
+        the given path with it, keeping the permissions of any previous file.
+    */
+    async fn persist(&self, path: PathBuf) -> LuaResult<()> {
+        let mut guard = self.state.lock().await;
+        let state = guard.as_ref().ok_or_else(finished_error)?;
+        let file = Arc::clone(&state.file);
+        spawn_blocking(move || file.sync_all())
+            .await
+            .into_lua_err()??;
+        let res = match &state.location {
+            TempLocation::Named(temp) => rename_into_place(temp, &path).await,
+            TempLocation::Anonymous => {
+                // NOTE: Anonymous files can only be linked to paths that do not
+                // exist yet, so we link it next to the path and rename it over
+                let staged = sibling_temp_path(&path);
+                let file = Arc::clone(&state.file);
+                let target = staged.clone();
+                let res = match spawn_blocking(move || link_anonymous(&file, &target)).await {
+                    Ok(Ok(())) => rename_into_place(&staged, &path).await,
+                    Ok(Err(e)) => Err(e),
+                    Err(e) => return Err(e.into_lua_err()),
+                };
+                if res.is_err() {
+                    let _ = tokio::fs::remove_file(&staged).await;
+                }
+                res
+            }
+        };
+        res.map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to persist temporary file to '{}'\n{e}",
+                path.display()
+            ))
+        })?;
+        if let Some(mut state) = guard.take() {
+            // The file has been renamed into place, so there is nothing left to remove
+            state.location = TempLocation::Anonymous;
+        }
+        Ok(())
+    }
+
+    async fn discard(&self) -> LuaResult<()> {
+        let state = self.state.lock().await.take().ok_or_else(finished_error)?;
+        spawn_blocking(move || drop(state)).await.into_lua_err()
+    }
+}
+
+impl LuaUserData for FsTempFile {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("write", |_, this, contents: BString| async move {
+            this.write(contents.as_bytes().to_vec()).await
+        });
+
+        methods.add_async_method("persist", |_, this, path: String| async move {
+            this.persist(PathBuf::from(path)).await
+        });
+
+        methods.add_async_method(
+            "discard",
+            |_, this, (): ()| async move { this.discard().await },
+        );
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsTempFile");
+    }
+}
+
+fn finished_error() -> LuaError {
+    LuaError::runtime("Temporary file has already been persisted or discarded")
+}
+
+fn create_blocking(dir: &Path, modes: DefaultModes) -> IoResult<(File, TempLocation)> {
+    let (file, location) = if let Some(file) = open_anonymous(dir)? {
+        (file, TempLocation::Anonymous)
+    } else {
+        let path = sibling_temp_path(&dir.join("temp"));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        (file, TempLocation::Named(path))
+    };
+    set_default_mode(&file, modes)?;
+    Ok((file, location))
+}
+
+#[cfg(unix)]
+fn set_default_mode(file: &File, modes: DefaultModes) -> IoResult<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+    match modes.file {
+        Some(mode) => file.set_permissions(Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn set_default_mode(_: &File, _: DefaultModes) -> IoResult<()> {
+    Ok(())
+}
+
+/**
+    Opens an anonymous file in the given directory using `O_TMPFILE`,
+    or returns `None` if the filesystem of the directory does not support it.
+*/
+#[cfg(target_os = "linux")]
+fn open_anonymous(dir: &Path) -> IoResult<Option<File>> {
+    use std::os::unix::fs::OpenOptionsExt;
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_TMPFILE)
+        .mode(0o666)
+        .open(dir)
+    {
+        Ok(file) => Ok(Some(file)),
+        Err(e)
+            if matches!(
+                e.raw_os_error(),
+                Some(libc::EOPNOTSUPP | libc::EISDIR | libc::EINVAL)
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+#[allow(clippy::unnecessary_wraps)]
+fn open_anonymous(_: &Path) -> IoResult<Option<File>> {
+    Ok(None)
+}
+
+/**
+    Gives an anonymous file a name, by linking it through its file descriptor.
+*/
+#[cfg(target_os = "linux")]
+fn link_anonymous(file: &File, path: &Path) -> IoResult<()> {
+    use std::ffi::CString;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::ffi::OsStrExt;
+
+    let source = CString::new(format!("/proc/self/fd/{}", file.as_raw_fd()))?;
+    let target = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: Both paths are valid null-terminated strings for the duration of the call
+    let res = unsafe {
+        libc::linkat(
+            libc::AT_FDCWD,
+            source.as_ptr(),
+            libc::AT_FDCWD,
+            target.as_ptr(),
+            libc::AT_SYMLINK_FOLLOW,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn link_anonymous(_: &File, _: &Path) -> IoResult<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Anonymous files are only supported on Linux",
+    ))
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::{fs, sync::Mutex as AsyncMutex};
+
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+use crate::atomic::{
+    backup_file, remove_backups, rename_into_place, restore_backups, sibling_temp_path,
+};
+use crate::binary::FsBinaryMode;
+use crate::contents::FileContents;
+use crate::limit::DescriptorPermit;
+use crate::wrapper::create_luau_wrapper;
+
+const TRANSACTION_IMPL_LUA: &str = r"
+return function(callback)
+	local tx = begin()
+	local results = pack(pcall(callback, tx))
+	if not results[1] then
+		discard(tx)
+		error(results[2], 0)
+	end
+	commit(tx)
+	return unpack(results, 2, results.n)
+end
+";
+
+/**
+    A change to a file that has been staged, but not yet committed.
+*/
+#[derive(Debug, Clone)]
+enum StagedChange {
+    /// The file is replaced with the contents of a temporary file next to it.
+    Write(PathBuf),
+    /// The file is removed.
+    Remove,
+}
+
+#[derive(Debug, Default)]
+struct TransactionState {
+    staged: BTreeMap<PathBuf, StagedChange>,
+    finished: bool,
+}
+
+/**
+    A proxy for staging changes to files, which are only
+    applied once the whole transaction has completed.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct FsTransaction {
+    state: Arc<AsyncMutex<TransactionState>>,
+}
+
+impl FsTransaction {
+    async fn write_file(&self, lua: &Lua, path: PathBuf, contents: &[u8]) -> LuaResult<()> {
+        let mut state = self.state.lock().await;
+        ensure_unfinished(&state)?;
+        let temp = match state.staged.get(&path) {
+            Some(StagedChange::Write(temp)) => temp.clone(),
+            _ => sibling_temp_path(&path),
+        };
+        let _permit = DescriptorPermit::acquire(lua, 1).await;
+        fs::write(&temp, contents).await.map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to stage write to '{}'\n{e}",
+                path.display()
+            ))
+        })?;
+        state.staged.insert(path, StagedChange::Write(temp));
+        Ok(())
+    }
+
+    async fn read_file<'lua>(&self, lua: &'lua Lua, path: PathBuf) -> LuaResult<LuaValue<'lua>> {
+        let state = self.state.lock().await;
+        ensure_unfinished(&state)?;
+        let source = match state.staged.get(&path) {
+            Some(StagedChange::Write(temp)) => temp.clone(),
+            Some(StagedChange::Remove) => {
+                return Err(LuaError::RuntimeError(format!(
+                    "The file '{}' has been removed in this transaction",
+                    path.display()
+                )))
+            }
+            None => path,
+        };
+        let _permit = DescriptorPermit::acquire(lua, 1).await;
+        FsBinaryMode::get(lua).create(lua, fs::read(source).await?)
+    }
+
+    async fn remove_file(&self, path: PathBuf) -> LuaResult<()> {
+        let mut state = self.state.lock().await;
+        ensure_unfinished(&state)?;
+        match state.staged.get(&path) {
+            Some(StagedChange::Write(temp)) => fs::remove_file(temp).await?,
+            Some(StagedChange::Remove) => {
+                return Err(LuaError::RuntimeError(format!(
+                    "The file '{}' has already been removed in this transaction",
+                    path.display()
+                )))
+            }
+            // NOTE: Missing files are reported now instead of at commit
+            // time, same as they would be when removed outside of a transaction
+            None => {
+                if !fs::metadata(&path).await?.is_file() {
+                    return Err(LuaError::RuntimeError(format!(
+                        "The path '{}' is not a file",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        state.staged.insert(path, StagedChange::Remove);
+        Ok(())
+    }
+
+    /**
+        Applies all staged changes, renaming each staged file into place.
+
+        The previous contents of every changed file are kept as a backup next to it
+        until all changes have been applied. If applying a change fails, the remaining
+        changes are discarded, and the files that were already changed are restored.
+    */
+    async fn commit(&self) -> LuaResult<()> {
+        let mut state = self.state.lock().await;
+        ensure_unfinished(&state)?;
+        state.finished = true;
+        let mut changes = std::mem::take(&mut state.staged).into_iter();
+        let mut applied = Vec::new();
+        while let Some((path, change)) = changes.next() {
+            match apply_change(&path, &change).await {
+                Ok(backup) => applied.push((path, backup)),
+                Err(e) => {
+                    if let StagedChange::Write(temp) = change {
+                        let _ = fs::remove_file(temp).await;
+                    }
+                    discard_changes(changes).await;
+                    restore_backups(applied).await;
+                    return Err(LuaError::RuntimeError(format!(
+                        "Failed to commit transaction at '{}', no files were changed\n{e}",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        remove_backups(applied).await;
+        Ok(())
+    }
+
+    async fn discard(&self) -> LuaResult<()> {
+        let mut state = self.state.lock().await;
+        ensure_unfinished(&state)?;
+        state.finished = true;
+        discard_changes(std::mem::take(&mut state.staged)).await;
+        Ok(())
+    }
+}
+
+impl LuaUserData for FsTransaction {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method(
+            "writeFile",
+            |lua, this, (path, contents): (String, FileContents)| async move {
+                this.write_file(lua, PathBuf::from(path), contents.as_bytes())
+                    .await
+            },
+        );
+
+        methods.add_async_method("readFile", |lua, this, path: String| async move {
+            this.read_file(lua, PathBuf::from(path)).await
+        });
+
+        methods.add_async_method("removeFile", |_, this, path: String| async move {
+            this.remove_file(PathBuf::from(path)).await
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsTransaction");
+    }
+}
+
+/**
+    Creates the `fs.transaction` function, which passes a transaction to a callback,
+    and commits it when the callback returns, or discards it if the callback errors.
+*/
+pub fn create_transaction(lua: &Lua) -> LuaResult<LuaFunction> {
+    let env = TableBuilder::new(lua)?
+        .with_function("begin", |_, ()| Ok(FsTransaction::default()))?
+        .with_async_function("commit", |_, tx: LuaUserDataRef<FsTransaction>| {
+            let tx = tx.clone();
+            async move { tx.commit().await }
+        })?
+        .with_async_function("discard", |_, tx: LuaUserDataRef<FsTransaction>| {
+            let tx = tx.clone();
+            async move { tx.discard().await }
+        })?;
+
+    create_luau_wrapper(lua, "transaction", TRANSACTION_IMPL_LUA, env)
+}
+
+fn ensure_unfinished(state: &TransactionState) -> LuaResult<()> {
+    if state.finished {
+        Err(LuaError::runtime("Transaction has already finished"))
+    } else {
+        Ok(())
+    }
+}
+
+/**
+    Applies a single staged change, returning the backup of the
+    previous file at the path, if there was a file there before.
+*/
+async fn apply_change(path: &Path, change: &StagedChange) -> IoResult<Option<PathBuf>> {
+    let backup = backup_file(path).await?;
+    let res = match change {
+        StagedChange::Write(temp) => rename_into_place(temp, path).await,
+        StagedChange::Remove if backup.is_some() => fs::remove_file(path).await,
+        StagedChange::Remove => Ok(()),
+    };
+    match res {
+        Ok(()) => Ok(backup),
+        Err(e) => {
+            if let Some(backup) = backup {
+                let _ = fs::remove_file(backup).await;
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn discard_changes(changes: impl IntoIterator<Item = (PathBuf, StagedChange)>) {
+    for (_, change) in changes {
+        if let StagedChange::Write(temp) = change {
+            let _ = fs::remove_file(temp).await;
+        }
+    }
+}
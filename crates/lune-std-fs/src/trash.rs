@@ -0,0 +1,175 @@
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+/**
+    Moves the file or directory at the given path into the trash of the current user,
+    instead of removing it, so that it can still be restored from the trash later on.
+
+    Only the trash in the home directory of the user is supported, so paths on
+    other filesystems than the home directory can not be moved to the trash.
+*/
+pub fn move_to_trash(path: &Path) -> IoResult<()> {
+    let path = std::path::absolute(path)?;
+    // NOTE: Checked first so that missing paths never get an entry in the trash
+    std::fs::symlink_metadata(&path)?;
+    platform::move_to_trash(&path)
+}
+
+#[cfg_attr(windows, allow(dead_code))]
+fn home_dir() -> IoResult<PathBuf> {
+    directories::BaseDirs::new()
+        .map(|base| base.home_dir().to_path_buf())
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "Failed to find the home directory"))
+}
+
+#[cfg_attr(windows, allow(dead_code))]
+fn cross_device_error(err: IoError) -> IoError {
+    if err.kind() == ErrorKind::CrossesDevices {
+        IoError::new(
+            ErrorKind::CrossesDevices,
+            "Moving paths on other filesystems than the home directory to the trash is not supported",
+        )
+    } else {
+        err
+    }
+}
+
+/*
+    Follows the freedesktop.org trash specification, where trashed entries are moved into
+    `files` and described by a file of the same name in `info`, which trash apps read to
+    restore them. The info file is created first, exclusively, to claim the name.
+*/
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::fmt::Write as _;
+    use std::fs;
+    use std::io::{ErrorKind, Result as IoResult, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use lune_std_datetime::DateTime;
+
+    use super::{cross_device_error, home_dir};
+
+    pub fn move_to_trash(path: &Path) -> IoResult<()> {
+        let trash = trash_dir()?;
+        let (files, info) = (trash.join("files"), trash.join("info"));
+        fs::create_dir_all(&files)?;
+        fs::create_dir_all(&info)?;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut index = 1;
+        let (info_path, mut info_file, target) = loop {
+            let name = match index {
+                1 => name.to_string(),
+                _ => format!("{name}.{index}"),
+            };
+            index += 1;
+            let info_path = info.join(format!("{name}.trashinfo"));
+            let file = match fs::File::options()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            };
+            // NOTE: Entries without an info file may be left behind by other
+            // programs, and must never be replaced by the trashed path
+            let target = files.join(name);
+            if fs::symlink_metadata(&target).is_ok() {
+                drop(file);
+                let _ = fs::remove_file(info_path);
+                continue;
+            }
+            break (info_path, file, target);
+        };
+
+        let deleted_at = DateTime::now().format_string_local(Some("%Y-%m-%dT%H:%M:%S"), None);
+        let res = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={deleted_at}\n",
+            percent_encode(path.as_os_str().as_bytes())
+        )
+        .and_then(|()| fs::rename(path, &target).map_err(cross_device_error));
+        if res.is_err() {
+            let _ = fs::remove_file(info_path);
+        }
+        res
+    }
+
+    fn trash_dir() -> IoResult<PathBuf> {
+        match std::env::var_os("XDG_DATA_HOME") {
+            Some(data) if Path::new(&data).is_absolute() => Ok(Path::new(&data).join("Trash")),
+            _ => Ok(home_dir()?.join(".local/share/Trash")),
+        }
+    }
+
+    pub fn percent_encode(bytes: &[u8]) -> String {
+        let mut encoded = String::with_capacity(bytes.len());
+        for &byte in bytes {
+            if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+                encoded.push(char::from(byte));
+            } else {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+        encoded
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::fs;
+    use std::io::{ErrorKind, Result as IoResult};
+    use std::path::Path;
+
+    use super::{cross_device_error, home_dir};
+
+    pub fn move_to_trash(path: &Path) -> IoResult<()> {
+        let trash = home_dir()?.join(".Trash");
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut index = 1;
+        loop {
+            let target = match index {
+                1 => trash.join(&*name),
+                _ => trash.join(format!("{name} {index}")),
+            };
+            index += 1;
+            match fs::symlink_metadata(&target) {
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    return fs::rename(path, target).map_err(cross_device_error);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+    use std::path::Path;
+
+    pub fn move_to_trash(_: &Path) -> IoResult<()> {
+        Err(IoError::new(
+            ErrorKind::Unsupported,
+            "Moving paths to the recycle bin is not supported on Windows",
+        ))
+    }
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::platform::percent_encode;
+
+    #[test]
+    fn percent_encodes_trashed_paths() {
+        assert_eq!(
+            percent_encode(b"/home/a b/c%d.txt"),
+            "/home/a%20b/c%25d.txt"
+        );
+    }
+}
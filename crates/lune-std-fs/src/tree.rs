@@ -0,0 +1,263 @@
+use std::ffi::c_void;
+use std::fs::{self as std_fs, FileType};
+use std::path::{Component, Path, PathBuf};
+
+use bstr::BString;
+use tokio::{fs, task::spawn_blocking};
+
+use mlua::prelude::*;
+
+use crate::backend::TokioBackend;
+use crate::lengths::{is_name_too_long, name_too_long_error};
+use crate::limit::DescriptorPermit;
+use crate::metadata::{FsMetadata, FsMetadataKind};
+use crate::options::FsReadTreeOptions;
+use crate::perms::{missing_ancestors, DefaultModes};
+
+/**
+    A file, directory or symlink in a tree read using `fs.readTree`.
+
+    Children are only read for directories that are not deeper than the maximum depth.
+*/
+#[derive(Debug, Clone)]
+pub struct FsTreeEntry {
+    kind: FsMetadataKind,
+    metadata: Option<FsMetadata>,
+    children: Option<Vec<(String, FsTreeEntry)>>,
+}
+
+impl<'lua> IntoLua<'lua> for FsTreeEntry {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 3)?;
+        tab.set("kind", self.kind)?;
+        tab.set("metadata", self.metadata)?;
+        if let Some(children) = self.children {
+            let children_tab = lua.create_table_with_capacity(0, children.len())?;
+            for (name, child) in children {
+                children_tab.set(name, child)?;
+            }
+            tab.set("children", children_tab)?;
+        }
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    Reads the directory at the given path, and all of the directories
+    inside of it up to the maximum depth, into a tree of entries.
+
+    Symlinks are never followed, and entries that are not files, directories
+    or symlinks, such as sockets and named pipes, are left out of the tree.
+*/
+pub async fn read_tree(
+    path: impl AsRef<Path>,
+    options: FsReadTreeOptions,
+) -> LuaResult<FsTreeEntry> {
+    let path = path.as_ref().to_path_buf();
+    let meta = fs::symlink_metadata(&path).await?;
+    if !meta.is_dir() {
+        return Err(LuaError::RuntimeError(format!(
+            "No directory exists at the path '{}'",
+            path.display()
+        )));
+    }
+    spawn_blocking(move || read_entry_blocking(path, meta, 0, options))
+        .await
+        .into_lua_err()?
+}
+
+fn read_entry_blocking(
+    path: PathBuf,
+    meta: std_fs::Metadata,
+    depth: usize,
+    options: FsReadTreeOptions,
+) -> LuaResult<FsTreeEntry> {
+    let kind = FsMetadataKind::from(meta.file_type());
+    let within_depth = options.max_depth.is_none_or(|max| depth < max);
+    let children = if kind == FsMetadataKind::Dir && within_depth {
+        let mut children = Vec::new();
+        for entry in std_fs::read_dir(&path)? {
+            let entry = entry?;
+            let child_meta = entry.metadata()?;
+            if !is_tree_entry(child_meta.file_type()) {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                return Err(LuaError::RuntimeError(format!(
+                    "File name could not be converted into a string: '{}'",
+                    entry.file_name().to_string_lossy()
+                )));
+            };
+            let child = read_entry_blocking(entry.path(), child_meta, depth + 1, options)?;
+            children.push((name, child));
+        }
+        Some(children)
+    } else {
+        None
+    };
+    Ok(FsTreeEntry {
+        kind,
+        metadata: options.with_metadata.then(|| FsMetadata::from(meta)),
+        children,
+    })
+}
+
+fn is_tree_entry(file_type: FileType) -> bool {
+    file_type.is_file() || file_type.is_dir() || file_type.is_symlink()
+}
+
+/**
+    The contents of a tree to write using `fs.writeTree`, where strings and
+    buffers are the contents of files, and tables are directories.
+*/
+#[derive(Debug, Clone)]
+pub enum FsTreeContents {
+    File(Vec<u8>),
+    Dir(Vec<(String, FsTreeContents)>),
+}
+
+impl FsTreeContents {
+    fn from_table(
+        lua: &Lua,
+        table: &LuaTable,
+        parents: &mut Vec<*const c_void>,
+    ) -> LuaResult<Self> {
+        if parents.contains(&table.to_pointer()) {
+            return Err(LuaError::runtime(
+                "Invalid tree - tables must not contain themselves",
+            ));
+        }
+        parents.push(table.to_pointer());
+        let mut children = Vec::new();
+        for pair in table.clone().pairs::<LuaValue, LuaValue>() {
+            let (key, value) = pair?;
+            let LuaValue::String(name) = key else {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid tree - expected names to be strings, got {}",
+                    key.type_name()
+                )));
+            };
+            let name = name.to_str()?.to_string();
+            validate_entry_name(&name)?;
+            let child = match value {
+                LuaValue::Table(t) => Self::from_table(lua, &t, parents)?,
+                LuaValue::String(s) => Self::File(s.as_bytes().to_vec()),
+                value if value.is_buffer() => Self::File(BString::from_lua(value, lua)?.into()),
+                value => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Invalid tree entry '{name}' - expected string, buffer or table, got {}",
+                        value.type_name()
+                    )))
+                }
+            };
+            children.push((name, child));
+        }
+        parents.pop();
+        // NOTE: Sorting makes the order that entries are written in deterministic
+        children.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(Self::Dir(children))
+    }
+
+    /**
+        Flattens the tree into paths relative to its root, with
+        every directory coming before the entries inside of it.
+    */
+    fn flatten(self, prefix: &Path, out: &mut Vec<(PathBuf, Option<Vec<u8>>)>) {
+        match self {
+            Self::File(contents) => out.push((prefix.to_path_buf(), Some(contents))),
+            Self::Dir(children) => {
+                out.push((prefix.to_path_buf(), None));
+                for (name, child) in children {
+                    child.flatten(&prefix.join(name), out);
+                }
+            }
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for FsTreeContents {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Table(t) => Self::from_table(lua, &t, &mut Vec::new()),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "FsTreeContents",
+                message: Some(format!(
+                    "Invalid tree - expected table, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+fn validate_entry_name(name: &str) -> LuaResult<()> {
+    let is_single_component = matches!(
+        Path::new(name).components().collect::<Vec<_>>().as_slice(),
+        [Component::Normal(component)] if component.len() == name.len()
+    );
+    if is_single_component && !name.contains(['/', '\\']) {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid tree entry name '{name}' - names must not be empty, '.' or '..', or contain separators"
+        )))
+    }
+}
+
+/**
+    Writes a tree of files and directories to the given root, creating any
+    missing directories, and overwriting the contents of any existing files.
+
+    The whole tree is validated before anything is written. Default modes are applied
+    to created files and directories, with directories changed last and innermost first.
+*/
+pub async fn write_tree(
+    lua: &Lua,
+    root: impl AsRef<Path>,
+    tree: FsTreeContents,
+    modes: DefaultModes,
+) -> LuaResult<()> {
+    let root = root.as_ref();
+    let mut entries = Vec::new();
+    tree.flatten(root, &mut entries);
+
+    let mut created_dirs = missing_ancestors(&TokioBackend, root).await;
+    created_dirs.retain(|dir| dir != root);
+    let _permit = DescriptorPermit::acquire(lua, 1).await;
+    for (path, contents) in entries {
+        let created = !fs::try_exists(&path).await.unwrap_or(true);
+        match contents {
+            None => {
+                fs::create_dir_all(&path)
+                    .await
+                    .map_err(|e| write_error(&path, e))?;
+                if created {
+                    created_dirs.push(path);
+                }
+            }
+            Some(contents) => {
+                fs::write(&path, contents)
+                    .await
+                    .map_err(|e| write_error(&path, e))?;
+                if created {
+                    modes.apply_file(&path).await?;
+                }
+            }
+        }
+    }
+    for dir in created_dirs.iter().rev() {
+        modes.apply_dir(dir).await?;
+    }
+    Ok(())
+}
+
+fn write_error(path: &Path, err: std::io::Error) -> LuaError {
+    if is_name_too_long(&err) {
+        return name_too_long_error(path);
+    }
+    LuaError::RuntimeError(format!(
+        "Failed to write tree entry at '{}'\n{err}",
+        path.display()
+    ))
+}
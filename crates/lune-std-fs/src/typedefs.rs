@@ -0,0 +1,196 @@
+/**
+    The Luau type definitions for the `fs` standard library, as a `.d.luau`
+    file that can be given to editor tooling such as `luau-lsp`.
+
+    These are the same definitions that `lune setup` writes, embedded when the
+    library is built, so they always describe the version of the library in use.
+*/
+#[must_use]
+pub fn typedefs() -> &'static str {
+    include_str!("../types/fs.luau")
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::prelude::*;
+
+    use super::*;
+
+    /**
+        A parameter of a function, either as declared in the definitions, or as taken in Rust.
+    */
+    #[derive(Debug)]
+    struct Param<'a> {
+        name: &'a str,
+        optional: bool,
+    }
+
+    /**
+        Gets the names of all of the functions and values that the definitions declare on `fs`.
+    */
+    fn declared_names(defs: &str) -> Vec<&str> {
+        let mut names = defs
+            .lines()
+            .filter_map(|line| {
+                let rest = line
+                    .strip_prefix("function fs.")
+                    .or_else(|| line.strip_prefix("fs."))?;
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric())?;
+                Some(&rest[..end])
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /**
+        Gets the contents of the parentheses that `s` starts with, skipping any generics before them.
+    */
+    fn parenthesized(s: &str) -> &str {
+        let start = s.find('(').expect("missing parameters");
+        let mut depth = 0;
+        for (i, c) in s.char_indices().skip(start) {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return &s[start + 1..i];
+            }
+        }
+        panic!("unbalanced parameters")
+    }
+
+    /**
+        Splits on the commas in `s` that are not nested in any brackets, skipping empty parts.
+    */
+    fn split_top_level(s: &str) -> Vec<&str> {
+        let mut parts = Vec::new();
+        let mut depth = 0i32;
+        let mut start = 0;
+        let mut prev = ' ';
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' | '[' | '{' | '<' => depth += 1,
+                // NOTE: Arrows in function types are not closing brackets
+                '>' if prev == '-' => {}
+                ')' | ']' | '}' | '>' => depth -= 1,
+                ',' if depth == 0 => {
+                    parts.push(&s[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            prev = c;
+        }
+        parts.push(&s[start..]);
+        parts
+            .into_iter()
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect()
+    }
+
+    /**
+        Gets the parameters that the definitions declare for `fs.<name>`.
+    */
+    fn declared_params<'a>(defs: &'a str, name: &str) -> Option<Vec<Param<'a>>> {
+        let start = defs
+            .find(&format!("function fs.{name}("))
+            .or_else(|| defs.find(&format!("function fs.{name}<")))?;
+        let params = split_top_level(parenthesized(&defs[start..]))
+            .into_iter()
+            .map(|param| {
+                let (name, ty) = param.split_once(':').unwrap_or((param, "any"));
+                Param {
+                    name: name.trim(),
+                    optional: ty.trim().ends_with('?'),
+                }
+            })
+            .collect();
+        Some(params)
+    }
+
+    /**
+        Gets the parameters that the Rust function with the given name takes from
+        Lua, which are either a single one, or all of those in a destructured tuple.
+    */
+    fn rust_params<'a>(source: &'a str, function: &str) -> Vec<Param<'a>> {
+        let start = source
+            .find(&format!("fn {function}("))
+            .or_else(|| source.find(&format!("fn {function}<")))
+            .unwrap_or_else(|| panic!("missing function {function}"));
+        let args = split_top_level(parenthesized(&source[start..]));
+        let Some(arg) = args.get(1) else {
+            return Vec::new();
+        };
+        let (pattern, ty) = arg.split_once(':').expect("argument without a type");
+        let (pattern, ty) = (pattern.trim(), ty.trim());
+        let (names, types) = if pattern.starts_with('(') {
+            (
+                split_top_level(parenthesized(pattern)),
+                split_top_level(parenthesized(ty)),
+            )
+        } else {
+            (vec![pattern], vec![ty])
+        };
+        names
+            .into_iter()
+            .zip(types)
+            .map(|(name, ty)| Param {
+                name,
+                optional: ty.starts_with("Option<"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn declares_everything_in_the_module() {
+        let lua = Lua::new();
+        let module = crate::module(&lua).unwrap();
+        let mut exported = module
+            .pairs::<String, LuaValue>()
+            .map(|pair| pair.unwrap().0)
+            .collect::<Vec<_>>();
+        exported.sort_unstable();
+
+        let declared = declared_names(typedefs());
+        assert_eq!(
+            declared, exported,
+            "Type definitions in types/fs.luau are out of date"
+        );
+    }
+
+    #[test]
+    fn declares_the_parameters_of_every_function() {
+        let source = include_str!("lib.rs");
+        let registrations = [".with_async_function(\"", ".with_function(\""]
+            .into_iter()
+            .flat_map(|method| source.split(method).skip(1))
+            .filter_map(|rest| {
+                let (name, rest) = rest.split_once("\", ")?;
+                let (function, _) = rest.split_once(')')?;
+                Some((name, function))
+            });
+
+        for (name, function) in registrations {
+            let declared = declared_params(typedefs(), name)
+                .unwrap_or_else(|| panic!("fs.{name} is not declared as a function"));
+            let taken = rust_params(source, function);
+            assert_eq!(
+                declared.len(),
+                taken.len(),
+                "fs.{name} is declared with parameters {declared:?}, but takes {taken:?}"
+            );
+            for (declared, taken) in declared.iter().zip(&taken) {
+                assert!(
+                    declared.optional || !taken.optional,
+                    "Parameter '{}' of fs.{name} is optional, but is not declared as such",
+                    declared.name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256, Sha512};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use mlua::prelude::*;
+
+use crate::atomic::sibling_temp_path;
+use crate::backend::FsBackend;
+use crate::cas::{hash_file, hash_hex, CHUNK_SIZE};
+use crate::config::FsConfig;
+use crate::hash::{FsHashAlgorithm, FsHasher};
+use crate::options::FsCopyVerifiedOptions;
+use crate::perms::DefaultModes;
+
+/**
+    Reads back the file at the given path and makes sure it has the expected contents.
+*/
+pub async fn verify_contents(
+    backend: &dyn FsBackend,
+    path: impl AsRef<Path>,
+    expected: &[u8],
+) -> LuaResult<()> {
+    let path = path.as_ref();
+    let written = backend.read(path).await?;
+    if written != expected {
+        return Err(verification_error(path, written.len(), expected.len()));
+    }
+    Ok(())
+}
+
+/**
+    Makes sure that the file at the target path has the same contents as the source file.
+*/
+pub async fn verify_copy(source: impl AsRef<Path>, target: impl AsRef<Path>) -> LuaResult<()> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    if hash_file(source).await? != hash_file(target).await? {
+        let source_len = fs::metadata(source).await?.len();
+        let target_len = fs::metadata(target).await?.len();
+        return Err(verification_error(
+            target,
+            usize::try_from(target_len).unwrap_or(usize::MAX),
+            usize::try_from(source_len).unwrap_or(usize::MAX),
+        ));
+    }
+    Ok(())
+}
+
+fn verification_error(path: &Path, written: usize, expected: usize) -> LuaError {
+    let detail = if written == expected {
+        String::from("the contents differ")
+    } else {
+        format!("expected {expected} bytes, found {written} bytes")
+    };
+    LuaError::RuntimeError(format!(
+        "Failed to verify the file written at '{}' - {detail}",
+        path.display()
+    ))
+}
+
+/**
+    A hash algorithm that verified copies can be checked with, either one of the
+    built-in algorithms, or one registered by the embedder, see [`FsHashAlgorithm`].
+*/
+#[derive(Clone, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Registered(Arc<str>, Arc<dyn FsHashAlgorithm>),
+}
+
+impl HashAlgorithm {
+    pub const BUILTIN: [Self; 2] = [Self::Sha256, Self::Sha512];
+
+    /**
+        Every algorithm that may be used by the given Lua state, built-in algorithms first.
+    */
+    pub fn all(lua: &Lua) -> Vec<Self> {
+        let registered = FsConfig::get(lua).hash_algorithms;
+        Self::BUILTIN
+            .into_iter()
+            .chain(
+                registered
+                    .iter()
+                    .map(|(name, algorithm)| Self::Registered(name.into(), Arc::clone(algorithm))),
+            )
+            .collect()
+    }
+
+    /**
+        The name of the algorithm, such as `"sha256"`, which is also the extension of its sidecar files.
+    */
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Registered(name, _) => name,
+        }
+    }
+
+    /**
+        Hashes the given bytes, returning the hash as lowercase hex.
+    */
+    pub fn hash_bytes(&self, bytes: &[u8]) -> String {
+        let mut hasher = Hasher::new(self);
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    fn from_name(lua: &Lua, name: &str) -> LuaResult<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        match name.replace('-', "").as_ref() {
+            "sha256" => return Ok(Self::Sha256),
+            "sha512" => return Ok(Self::Sha512),
+            _ => {}
+        }
+        let registered = FsConfig::get(lua).hash_algorithms;
+        if let Some((name, algorithm)) = registered.get(&name) {
+            return Ok(Self::Registered(name.into(), algorithm));
+        }
+        let names = Self::all(lua)
+            .iter()
+            .map(|algorithm| format!("'{}'", algorithm.name()))
+            .collect::<Vec<_>>();
+        Err(LuaError::RuntimeError(format!(
+            "Invalid hash algorithm - expected one of {}",
+            names.join(", ")
+        )))
+    }
+}
+
+impl fmt::Debug for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HashAlgorithm").field(&self.name()).finish()
+    }
+}
+
+impl<'lua> FromLua<'lua> for HashAlgorithm {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => Self::from_name(lua, s.to_str()?),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "HashAlgorithm",
+                message: Some("Hash algorithm must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Registered(Box<dyn FsHasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: &HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+            HashAlgorithm::Registered(_, algorithm) => Self::Registered(algorithm.hasher()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(bytes),
+            Self::Sha512(hasher) => hasher.update(bytes),
+            Self::Registered(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Self::Sha256(hasher) => hash_hex(hasher.finalize()),
+            Self::Sha512(hasher) => hash_hex(hasher.finalize()),
+            Self::Registered(hasher) => hash_hex(hasher.finish()),
+        }
+    }
+}
+
+/**
+    A partially written file, which is removed when dropped unless it was
+    renamed into place, so that copies that fail or are cancelled part
+    of the way through never leave anything behind at the target path.
+*/
+struct PartialFile {
+    path: Option<PathBuf>,
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub async fn hash_file_with(path: &Path, algorithm: &HashAlgorithm) -> LuaResult<String> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+/**
+    Copies the file at `source` to `target`, hashing the source while copying, and then
+    reads back and hashes the copy before moving it into place, returning the hash.
+
+    The copy is written next to the target first, and is removed if copying fails, the
+    hashes do not match, or the copy is cancelled, so the target is never left partial.
+*/
+pub async fn copy_verified(
+    source: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    options: FsCopyVerifiedOptions,
+    modes: DefaultModes,
+) -> LuaResult<String> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    if !fs::metadata(source).await?.is_file() {
+        return Err(LuaError::RuntimeError(format!(
+            "Failed to copy '{}' - only files can be copied with verification",
+            source.display()
+        )));
+    }
+    if !options.overwrite && fs::symlink_metadata(target).await.is_ok() {
+        return Err(LuaError::RuntimeError(format!(
+            "Failed to copy to '{}' - a file already exists at the path",
+            target.display()
+        )));
+    }
+
+    let temp = sibling_temp_path(target);
+    let mut partial = PartialFile {
+        path: Some(temp.clone()),
+    };
+
+    let mut reader = fs::File::open(source).await?;
+    let mut writer = fs::File::create(&temp).await?;
+    let mut hasher = Hasher::new(&options.algorithm);
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n]).await?;
+    }
+    writer.flush().await?;
+    writer.sync_all().await?;
+    drop(writer);
+
+    let expected = hasher.finish();
+    let copied = hash_file_with(&temp, &options.algorithm).await?;
+    if copied != expected {
+        return Err(LuaError::RuntimeError(format!(
+            "Failed to verify the copy of '{}' - expected hash {expected}, found {copied}",
+            source.display()
+        )));
+    }
+
+    // NOTE: Copies keep the permissions of their source, same as fs.copy
+    fs::set_permissions(&temp, fs::metadata(source).await?.permissions()).await?;
+    modes.apply_file(&temp).await?;
+    fs::rename(&temp, target).await?;
+    partial.path = None;
+    Ok(expected)
+}
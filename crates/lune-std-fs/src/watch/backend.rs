@@ -0,0 +1,104 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::time::SystemTime;
+
+use notify::{Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use super::prewatch::PrewatchCallback;
+
+/**
+    The sending half of the channel that backends deliver events through,
+    along with the time that each event was received from the backend.
+*/
+pub type EventSender = Sender<(notify::Result<Event>, SystemTime)>;
+
+/**
+    A running watcher backend, created using the backend in the watch options.
+*/
+#[derive(Debug)]
+pub enum ActiveWatcher {
+    Native(RecommendedWatcher),
+    Poll(PollWatcher),
+}
+
+impl ActiveWatcher {
+    /**
+        Makes the backend look for any changes to the given watched path that it may have missed.
+
+        Poll watchers scan the watched paths right away, delivering events for
+        anything that changed since their last scan. Native backends can not
+        tell what they missed, so they are replaced with a freshly created watcher
+        instead, which recovers watches that the backend silently dropped.
+    */
+    pub fn rescan(
+        &mut self,
+        path: &Path,
+        recursive_mode: RecursiveMode,
+        recreate: impl FnOnce() -> notify::Result<Self>,
+    ) -> notify::Result<()> {
+        match self {
+            Self::Poll(watcher) => watcher.poll(),
+            Self::Native(_) => {
+                // NOTE: Native backends deliver events from the same thread that
+                // handles unwatching and watching, and block while our channel is
+                // full, so re-establishing the watch in place could deadlock here
+                let mut fresh = recreate()?;
+                fresh.watch(path, recursive_mode)?;
+                *self = fresh;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Deref for ActiveWatcher {
+    type Target = dyn Watcher;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Native(watcher) => watcher,
+            Self::Poll(watcher) => watcher,
+        }
+    }
+}
+
+impl DerefMut for ActiveWatcher {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Native(watcher) => watcher,
+            Self::Poll(watcher) => watcher,
+        }
+    }
+}
+
+/**
+    A backend that is already watching a root, along with the channel it
+    delivers events through, which a watcher loop can then receive from.
+*/
+pub struct WatchSource {
+    pub watcher: ActiveWatcher,
+    pub tx: EventSender,
+    pub rx: Receiver<(notify::Result<Event>, SystemTime)>,
+    /// A callback that the embedder wants called for every event, which recreated
+    /// backends must also call, see [`WatchOptions::create_watcher_with`].
+    ///
+    /// [`WatchOptions::create_watcher_with`]: super::WatchOptions::create_watcher_with
+    pub callback: Option<PrewatchCallback>,
+    /// Whether the root is watched non-recursively, even though recursion was
+    /// requested, since the backend does not support it, see [`watch_root`].
+    ///
+    /// [`watch_root`]: super::recursion::watch_root
+    pub emulate_recursion: bool,
+}
+
+impl fmt::Debug for WatchSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchSource")
+            .field("watcher", &self.watcher)
+            .field("callback", &self.callback.is_some())
+            .field("emulate_recursion", &self.emulate_recursion)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use notify::event::{CreateKind, DataChange, ModifyKind, RenameMode};
+use notify::{Event, EventKind};
+
+/**
+    How long events for newly created files are held back, waiting
+    to find out if the file is about to be renamed over another one.
+*/
+const COALESCE_WINDOW: Duration = Duration::from_millis(100);
+
+/**
+    An event that is ready to be delivered, along with the paths
+    that a file went through before ending up at its final path.
+*/
+#[derive(Debug)]
+pub struct CoalescedEvent {
+    pub event: Event,
+    pub received_at: SystemTime,
+    pub chain: Vec<PathBuf>,
+}
+
+impl CoalescedEvent {
+    fn passthrough(event: Event, received_at: SystemTime) -> Self {
+        Self {
+            event,
+            received_at,
+            chain: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HeldFile {
+    path: PathBuf,
+    events: Vec<(Event, SystemTime)>,
+}
+
+/**
+    Coalesces the events of editors that save files by writing a temporary file and
+    renaming it over the original, so that the save is reported as a single change of
+    the original file, instead of events for a temporary file that nobody cares about.
+
+    Events for newly created files are held back for a short while. When a held file is
+    renamed, its events are replaced by one change at the destination, and otherwise they
+    are delivered unchanged once the window passes. Renames are matched up using the
+    cookies of the backend, or by being reported back to back on backends without them.
+*/
+#[derive(Debug, Default)]
+pub struct RenameCoalescer {
+    enabled: bool,
+    held: Vec<HeldFile>,
+    renaming: HashMap<Option<usize>, usize>,
+    resolved: Vec<usize>,
+    deadline: Option<Instant>,
+}
+
+impl RenameCoalescer {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    /**
+        When any held events must be delivered, if no rename arrives for them until then.
+    */
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /**
+        Takes an event received from the backend, returning the events that are ready to be delivered.
+    */
+    pub fn push(
+        &mut self,
+        event: Event,
+        received_at: SystemTime,
+        now: Instant,
+    ) -> Vec<CoalescedEvent> {
+        if !self.enabled {
+            return vec![CoalescedEvent::passthrough(event, received_at)];
+        }
+        let tracker = event.tracker();
+        match (event.kind, event.paths.as_slice()) {
+            (EventKind::Create(CreateKind::File | CreateKind::Any), [path])
+                if self.held_index(path).is_none() =>
+            {
+                self.held.push(HeldFile {
+                    path: path.clone(),
+                    events: vec![(event, received_at)],
+                });
+                self.deadline = Some(now + COALESCE_WINDOW);
+                return Vec::new();
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::From)), [from]) => {
+                if let Some(index) = self.held_index(from) {
+                    self.renaming.insert(tracker, index);
+                    return Vec::new();
+                }
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [to]) => {
+                if let Some(index) = self.renaming.remove(&tracker) {
+                    if let Some(tracker) = tracker {
+                        self.resolved.push(tracker);
+                    }
+                    return self.resolve(index, to.clone(), received_at);
+                }
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+                // NOTE: Backends with cookies may report both halves of a rename and
+                // then the rename as a whole, which was already resolved by then
+                if let Some(pos) = tracker.and_then(|t| self.resolved.iter().position(|r| *r == t))
+                {
+                    self.resolved.swap_remove(pos);
+                    return Vec::new();
+                }
+                if let Some(index) = self.held_index(from) {
+                    return self.resolve(index, to.clone(), received_at);
+                }
+            }
+            (EventKind::Modify(ModifyKind::Name(_)), _) => {}
+            (EventKind::Modify(_) | EventKind::Access(_), paths) if !paths.is_empty() => {
+                if let Some(index) = self.held_index(&paths[0]) {
+                    if paths.iter().all(|path| self.held[index].path == *path) {
+                        self.held[index].events.push((event, received_at));
+                        return Vec::new();
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // NOTE: Anything else touching a held file means that it is not a temporary file
+        // being saved, so its held events go first, to keep all of them in order
+        let mut ready = Vec::new();
+        for path in &event.paths {
+            if let Some(index) = self.held_index(path) {
+                ready.extend(self.release(index));
+            }
+        }
+        ready.push(CoalescedEvent::passthrough(event, received_at));
+        ready
+    }
+
+    /**
+        Delivers all of the held events, if the window for renaming them has passed.
+    */
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<CoalescedEvent> {
+        if self.deadline.is_none_or(|deadline| now < deadline) {
+            return Vec::new();
+        }
+        self.deadline = None;
+        self.renaming.clear();
+        self.held
+            .drain(..)
+            .flat_map(|held| held.events)
+            .map(|(event, received_at)| CoalescedEvent::passthrough(event, received_at))
+            .collect()
+    }
+
+    fn held_index(&self, path: &PathBuf) -> Option<usize> {
+        self.held.iter().position(|held| held.path == *path)
+    }
+
+    fn release(&mut self, index: usize) -> Vec<CoalescedEvent> {
+        let held = self.remove_held(index);
+        held.events
+            .into_iter()
+            .map(|(event, received_at)| CoalescedEvent::passthrough(event, received_at))
+            .collect()
+    }
+
+    fn resolve(
+        &mut self,
+        index: usize,
+        to: PathBuf,
+        received_at: SystemTime,
+    ) -> Vec<CoalescedEvent> {
+        let held = self.remove_held(index);
+        let event =
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(to.clone());
+        vec![CoalescedEvent {
+            event,
+            received_at,
+            chain: vec![held.path, to],
+        }]
+    }
+
+    fn remove_held(&mut self, index: usize) -> HeldFile {
+        let held = self.held.remove(index);
+        // NOTE: Indices of files held after the removed one shift down by one
+        self.renaming.retain(|_, i| *i != index);
+        for i in self.renaming.values_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+        if self.held.is_empty() {
+            self.deadline = None;
+        }
+        held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        let mut event = Event::new(kind);
+        for path in paths {
+            event = event.add_path(PathBuf::from(path));
+        }
+        event
+    }
+
+    #[test]
+    fn coalesces_saves_renamed_over_the_original() {
+        let (at, now) = (SystemTime::now(), Instant::now());
+        let mut coalescer = RenameCoalescer::new(true);
+        let created = event(EventKind::Create(CreateKind::File), &["/a/.file.tmp"]);
+        let written = event(
+            EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+            &["/a/.file.tmp"],
+        );
+        let renamed = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/a/.file.tmp", "/a/file"],
+        );
+
+        assert!(coalescer.push(created, at, now).is_empty());
+        assert!(coalescer.push(written, at, now).is_empty());
+        let ready = coalescer.push(renamed, at, now);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].event.paths, [PathBuf::from("/a/file")]);
+        assert_eq!(
+            ready[0].chain,
+            [PathBuf::from("/a/.file.tmp"), PathBuf::from("/a/file")]
+        );
+        assert!(coalescer.deadline().is_none());
+    }
+
+    #[test]
+    fn delivers_held_events_when_touched_otherwise() {
+        let (at, now) = (SystemTime::now(), Instant::now());
+        let mut coalescer = RenameCoalescer::new(true);
+        let created = event(EventKind::Create(CreateKind::File), &["/a/file"]);
+        let removed = event(
+            EventKind::Remove(notify::event::RemoveKind::File),
+            &["/a/file"],
+        );
+
+        assert!(coalescer.push(created, at, now).is_empty());
+        let ready = coalescer.push(removed, at, now);
+        assert_eq!(ready.len(), 2);
+        assert!(ready.iter().all(|ready| ready.chain.is_empty()));
+        assert!(matches!(ready[0].event.kind, EventKind::Create(_)));
+    }
+
+    #[test]
+    fn delivers_held_events_once_the_window_passes() {
+        let (at, now) = (SystemTime::now(), Instant::now());
+        let mut coalescer = RenameCoalescer::new(true);
+        let created = event(EventKind::Create(CreateKind::File), &["/a/file"]);
+
+        assert!(coalescer.push(created, at, now).is_empty());
+        assert!(coalescer.flush_expired(now).is_empty());
+        assert_eq!(coalescer.flush_expired(now + COALESCE_WINDOW).len(), 1);
+        assert!(coalescer.deadline().is_none());
+    }
+}
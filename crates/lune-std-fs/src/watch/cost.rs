@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::PathBuf;
+
+use notify::WatcherKind;
+use tokio::task::spawn_blocking;
+
+use mlua::prelude::*;
+
+use super::defaults::WatchBackend;
+use super::info::WatcherInfo;
+use super::options::WatchOptions;
+use crate::codes::map_path_error;
+use crate::limit::DescriptorPermit;
+
+/**
+    Kernel memory used by a single inotify watch on 64-bit systems, as documented for `max_user_watches`.
+*/
+const INOTIFY_WATCH_BYTES: u64 = 1024;
+
+/**
+    Kernel memory used by a single kqueue watch, which also keeps its path open.
+*/
+const KQUEUE_WATCH_BYTES: u64 = 512;
+
+/**
+    The buffer that events for a watch on Windows are read into.
+*/
+const WINDOWS_BUFFER_BYTES: u64 = 16 * 1024;
+
+/**
+    Memory used by the watcher for every path it keeps track of, on top of the path itself.
+*/
+const TRACKED_PATH_BYTES: u64 = 64;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WatchCostOptions {
+    recursive: bool,
+    backend: Option<WatchBackend>,
+}
+
+impl<'lua> FromLua<'lua> for WatchCostOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let t = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "WatchCostOptions",
+                    message: Some(format!(
+                        "Invalid watch cost options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        let backend = match t.get::<_, Option<String>>("backend")?.as_deref() {
+            None => None,
+            Some("native") => Some(WatchBackend::Native),
+            Some("poll") => Some(WatchBackend::Poll),
+            Some(other) => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid backend '{other}' - expected 'native' or 'poll'"
+                )))
+            }
+        };
+        Ok(Self {
+            recursive: t.get::<_, Option<bool>>("recursive")?.unwrap_or_default(),
+            backend,
+        })
+    }
+}
+
+/**
+    The directories and files that a watch would cover.
+*/
+#[derive(Debug, Clone, Copy, Default)]
+struct WatchedTree {
+    directories: u64,
+    files: u64,
+    path_bytes: u64,
+}
+
+/**
+    An estimate of the resources that watching a tree would need, using a given backend.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct WatchCost {
+    info: WatcherInfo,
+    tree: WatchedTree,
+    watches: u64,
+    descriptors: u64,
+    memory: u64,
+    limit: Option<u64>,
+}
+
+impl WatchCost {
+    fn estimate(kind: WatcherKind, tree: WatchedTree) -> Self {
+        let entries = tree.directories + tree.files;
+        let tracked = |count: u64| count * TRACKED_PATH_BYTES + tree.path_bytes * count / entries;
+        let (watches, descriptors, memory, limit) = match kind {
+            // NOTE: Every directory needs a watch of its own, and they all share one descriptor
+            WatcherKind::Inotify => (
+                tree.directories,
+                1,
+                tree.directories * INOTIFY_WATCH_BYTES + tracked(tree.directories),
+                platform::max_inotify_watches(),
+            ),
+            // Every file and directory is kept open, to be told about changes to it
+            WatcherKind::Kqueue => (
+                entries,
+                entries + 1,
+                entries * KQUEUE_WATCH_BYTES + tracked(entries),
+                platform::max_open_files(),
+            ),
+            WatcherKind::Fsevent => (1, 0, tracked(1), None),
+            WatcherKind::ReadDirectoryChangesWatcher => {
+                (1, 1, WINDOWS_BUFFER_BYTES + tracked(1), None)
+            }
+            // Polling needs no native watches, but remembers every entry between polls
+            _ => (0, 1, tracked(entries), None),
+        };
+        Self {
+            info: WatcherInfo::new(kind),
+            tree,
+            watches,
+            descriptors,
+            memory,
+            limit,
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for WatchCost {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let usage = match self.info.backend() {
+            "kqueue" => self.descriptors,
+            _ => self.watches,
+        };
+        let tab = lua.create_table_with_capacity(0, 8)?;
+        tab.set("backend", self.info.backend())?;
+        tab.set("directories", self.tree.directories)?;
+        tab.set("files", self.tree.files)?;
+        tab.set("watches", self.watches)?;
+        tab.set("descriptors", self.descriptors)?;
+        tab.set("memory", self.memory)?;
+        tab.set("limit", self.limit)?;
+        tab.set(
+            "exceedsLimit",
+            self.limit.is_some_and(|limit| usage > limit),
+        )?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    Estimates the native watches, descriptors and memory that watching the given root would
+    need, without watching it, by going through the tree the same way that a watch would.
+
+    Symlinks are counted as files, since watches do not follow them.
+*/
+pub async fn watch_cost(
+    lua: &Lua,
+    root: String,
+    options: WatchCostOptions,
+) -> LuaResult<WatchCost> {
+    let mut watch_options = WatchOptions::defaults(lua);
+    if let Some(backend) = options.backend {
+        watch_options.backend = backend;
+    }
+    let kind = watch_options.watcher_kind();
+
+    let permit = DescriptorPermit::acquire(lua, 1).await;
+    let tree = spawn_blocking(move || {
+        let _permit = permit;
+        scan_tree(PathBuf::from(root), options.recursive)
+    })
+    .await
+    .into_lua_err()??;
+    Ok(WatchCost::estimate(kind, tree))
+}
+
+fn scan_tree(root: PathBuf, recursive: bool) -> LuaResult<WatchedTree> {
+    let mut tree = WatchedTree::default();
+    let meta = fs::symlink_metadata(&root).map_err(|e| map_path_error(e, &root))?;
+    if !meta.is_dir() {
+        tree.files = 1;
+        tree.path_bytes = root.as_os_str().len() as u64;
+        return Ok(tree);
+    }
+
+    let mut pending = vec![root];
+    while let Some(dir) = pending.pop() {
+        tree.directories += 1;
+        tree.path_bytes += dir.as_os_str().len() as u64;
+        for entry in fs::read_dir(&dir).map_err(|e| map_path_error(e, &dir))? {
+            let entry = entry.map_err(|e| map_path_error(e, &dir))?;
+            let is_dir = entry
+                .file_type()
+                .map_err(|e| map_path_error(e, &entry.path()))?
+                .is_dir();
+            if is_dir && recursive {
+                pending.push(entry.path());
+            } else if !is_dir {
+                tree.files += 1;
+                tree.path_bytes += entry.path().as_os_str().len() as u64;
+            }
+        }
+    }
+    Ok(tree)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    pub fn max_inotify_watches() -> Option<u64> {
+        std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    pub fn max_open_files() -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod platform {
+    pub fn max_inotify_watches() -> Option<u64> {
+        None
+    }
+
+    pub fn max_open_files() -> Option<u64> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: The pointer is valid for the duration of the call
+        let res = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+        (res == 0 && limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur as u64)
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    pub fn max_inotify_watches() -> Option<u64> {
+        None
+    }
+
+    pub fn max_open_files() -> Option<u64> {
+        None
+    }
+}
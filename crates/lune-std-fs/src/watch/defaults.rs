@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+/**
+    The backend that watchers use to receive events.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WatchBackend {
+    /// The native backend for the current platform, such as inotify on Linux.
+    #[default]
+    Native,
+    /// A backend that polls for changes, which works on any filesystem,
+    /// including network filesystems that native backends do not support.
+    Poll,
+}
+
+/**
+    Default options for all watchers created by the `fs` standard library.
+
+    Set by the embedder using [`FsConfig::with_watch_defaults`], and used by
+    both `fs.watch` and indexes. Options given by scripts take precedence.
+
+    [`FsConfig::with_watch_defaults`]: crate::FsConfig::with_watch_defaults
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchDefaults {
+    pub(crate) interval: Duration,
+    pub(crate) settle_delay: Option<Duration>,
+    pub(crate) channel_capacity: usize,
+    pub(crate) backend: WatchBackend,
+}
+
+impl WatchDefaults {
+    /**
+        Creates new watch defaults with all the default values.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Creates new watch defaults from environment variables,
+        using the default values for any variables that are not set.
+
+        The following variables are read:
+
+        - `LUNE_FS_WATCH_INTERVAL_MS` - see [`WatchDefaults::with_interval`]
+        - `LUNE_FS_WATCH_SETTLE_DELAY_MS` - see [`WatchDefaults::with_settle_delay`]
+        - `LUNE_FS_WATCH_CHANNEL_CAPACITY` - see [`WatchDefaults::with_channel_capacity`]
+        - `LUNE_FS_WATCH_BACKEND` - either `native` or `poll`, see [`WatchDefaults::with_backend`]
+
+        Variables that can not be parsed are ignored, just like variables that are not set.
+    */
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let millis = |name| {
+            var(name)
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_millis)
+        };
+        let mut defaults = Self::default();
+        if let Some(interval) = millis("LUNE_FS_WATCH_INTERVAL_MS").filter(|i| !i.is_zero()) {
+            defaults.interval = interval;
+        }
+        if let Some(delay) = millis("LUNE_FS_WATCH_SETTLE_DELAY_MS") {
+            defaults.settle_delay = Some(delay).filter(|d| !d.is_zero());
+        }
+        if let Some(capacity) = var("LUNE_FS_WATCH_CHANNEL_CAPACITY")
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .filter(|c| *c > 0)
+        {
+            defaults.channel_capacity = capacity;
+        }
+        match var("LUNE_FS_WATCH_BACKEND")
+            .map(|value| value.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("native") => defaults.backend = WatchBackend::Native,
+            Some("poll") => defaults.backend = WatchBackend::Poll,
+            _ => {}
+        }
+        defaults
+    }
+
+    /**
+        Sets the interval to poll for changes at, for backends that poll.
+
+        # Panics
+
+        Panics if `interval` is zero.
+    */
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "Watch interval must not be zero");
+        self.interval = interval;
+        self
+    }
+
+    /**
+        Sets how long watchers must go without receiving any events before they
+        start delivering them, or `None` to deliver events immediately.
+    */
+    #[must_use]
+    pub fn with_settle_delay(mut self, delay: Option<Duration>) -> Self {
+        self.settle_delay = delay;
+        self
+    }
+
+    /**
+        Sets how many native events may be queued up before the backend has
+        to wait for watchers to catch up, which smooths out bursts of events.
+
+        # Panics
+
+        Panics if `capacity` is zero.
+    */
+    #[must_use]
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "Watch channel capacity must not be zero");
+        self.channel_capacity = capacity;
+        self
+    }
+
+    /**
+        Sets the backend that watchers use to receive events.
+    */
+    #[must_use]
+    pub fn with_backend(mut self, backend: WatchBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+}
+
+impl Default for WatchDefaults {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            settle_delay: None,
+            channel_capacity: 1,
+            backend: WatchBackend::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_variables() {
+        let defaults = WatchDefaults::from_vars(|name| {
+            match name {
+                "LUNE_FS_WATCH_INTERVAL_MS" => Some("250"),
+                "LUNE_FS_WATCH_SETTLE_DELAY_MS" => Some("50"),
+                "LUNE_FS_WATCH_CHANNEL_CAPACITY" => Some("16"),
+                "LUNE_FS_WATCH_BACKEND" => Some("Poll"),
+                _ => None,
+            }
+            .map(str::to_string)
+        });
+        assert_eq!(defaults.interval, Duration::from_millis(250));
+        assert_eq!(defaults.settle_delay, Some(Duration::from_millis(50)));
+        assert_eq!(defaults.channel_capacity, 16);
+        assert_eq!(defaults.backend, WatchBackend::Poll);
+    }
+
+    #[test]
+    fn ignores_invalid_variables() {
+        let defaults = WatchDefaults::from_vars(|name| match name {
+            "LUNE_FS_WATCH_INTERVAL_MS" => Some("0".to_string()),
+            "LUNE_FS_WATCH_CHANNEL_CAPACITY" => Some("many".to_string()),
+            "LUNE_FS_WATCH_BACKEND" => Some("carrier pigeon".to_string()),
+            _ => None,
+        });
+        assert_eq!(defaults, WatchDefaults::default());
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::str::FromStr;
+
+use notify::event::{AccessKind, ModifyKind, RenameMode};
+use notify::EventKind;
+
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+/**
+    A kind of event that watch handlers can be invoked for.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEventKind {
+    Added,
+    Read,
+    Removed,
+    Changed,
+    Renamed,
+    RootRemoved,
+    RootRecreated,
+    DeviceRemoved,
+}
+
+impl WatchEventKind {
+    pub const ALL: &'static [Self] = &[
+        Self::Added,
+        Self::Read,
+        Self::Removed,
+        Self::Changed,
+        Self::Renamed,
+        Self::RootRemoved,
+        Self::RootRecreated,
+        Self::DeviceRemoved,
+    ];
+
+    /**
+        Converts a native event kind into the kind of handler that should be
+        invoked for it, returning `None` for events that are not supported.
+    */
+    pub fn from_notify(kind: EventKind) -> Option<Self> {
+        match kind {
+            EventKind::Access(AccessKind::Read) => Some(Self::Read), // File was read
+            EventKind::Remove(_) => Some(Self::Removed),             // File was removed
+            EventKind::Create(_) => Some(Self::Added),               // File was created
+            EventKind::Modify(ModifyKind::Data(_)) => Some(Self::Changed), // File was edited
+
+            // NOTE: Ideally, it would be nice to supply the handler with the old and new file names
+            // but notify-rs currently doesn't support this; see https://github.com/notify-rs/notify/issues/376
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some(Self::Renamed), // File was renamed
+
+            // Unsupported Events
+            _ => None,
+        }
+    }
+
+    /**
+        Whether this kind of event concerns the watched root itself.
+    */
+    pub fn is_root(self) -> bool {
+        matches!(
+            self,
+            Self::RootRemoved | Self::RootRecreated | Self::DeviceRemoved
+        )
+    }
+
+    /**
+        The name of the handler for this kind of event.
+    */
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Read => "read",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+            Self::Renamed => "renamed",
+            Self::RootRemoved => "rootRemoved",
+            Self::RootRecreated => "rootRecreated",
+            Self::DeviceRemoved => "deviceRemoved",
+        }
+    }
+}
+
+impl FromStr for WatchEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("Invalid watch event kind '{s}'"))
+    }
+}
+
+impl fmt::Display for WatchEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/**
+    Creates the `fs.eventKinds` table, mapping the name of each
+    kind of event in `PascalCase` to the name used for handlers.
+*/
+pub fn create_event_kinds(lua: &Lua) -> LuaResult<LuaTable> {
+    WatchEventKind::ALL
+        .iter()
+        .try_fold(TableBuilder::new(lua)?, |builder, kind| {
+            builder.with_value(format!("{kind:?}"), kind.name())
+        })?
+        .build_readonly()
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use mlua::prelude::*;
+
+use super::event::WatchEventKind;
+
+/**
+    An event that a watcher started using `fs.watchServe` forwarded to a feed.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsWatchFeedEvent {
+    root: Arc<str>,
+    kind: &'static str,
+    paths: Vec<String>,
+}
+
+impl FsWatchFeedEvent {
+    /**
+        The root of the watcher that the event came from, as it was given by the script.
+    */
+    #[must_use]
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /**
+        The name of the kind of the event, such as `"changed"`.
+    */
+    #[must_use]
+    pub fn kind(&self) -> &str {
+        self.kind
+    }
+
+    /**
+        The paths of the event, the same as those given to watch handlers.
+    */
+    #[must_use]
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /**
+        Encodes the event as a JSON object with `root`, `kind` and `paths`
+        fields, ready to be sent as a text message over a websocket.
+    */
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn to_json(&self) -> String {
+        let json = JsonFeedEvent {
+            root: &self.root,
+            kind: self.kind,
+            paths: &self.paths,
+        };
+        serde_json::to_string(&json).expect("Failed to encode feed event")
+    }
+}
+
+/**
+    The fields of a [`FsWatchFeedEvent`], in the order that they are encoded in.
+*/
+#[derive(Serialize)]
+struct JsonFeedEvent<'a> {
+    root: &'a str,
+    kind: &'a str,
+    paths: &'a [String],
+}
+
+/**
+    A feed that watchers started by scripts using `fs.watchServe` forward all of their
+    events to, which the embedder can subscribe to any number of times, such as once
+    for every websocket client of a live-reload server.
+
+    Registered using [`FsConfig::with_watch_feed`]. Subscribers that fall behind by more
+    than the capacity of the feed miss the oldest events, instead of slowing down watchers.
+
+    [`FsConfig::with_watch_feed`]: crate::FsConfig::with_watch_feed
+*/
+#[derive(Debug, Clone)]
+pub struct FsWatchFeed {
+    tx: broadcast::Sender<FsWatchFeedEvent>,
+}
+
+impl FsWatchFeed {
+    /**
+        Creates a new feed that buffers up to `capacity` events for each subscriber.
+
+        # Panics
+
+        Panics if `capacity` is zero.
+    */
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Feed capacity must be greater than zero");
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    /**
+        Subscribes to the feed, receiving every event forwarded from now on.
+    */
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<FsWatchFeedEvent> {
+        self.tx.subscribe()
+    }
+
+    /**
+        Creates watch handlers for every kind of event, which forward events to this feed.
+    */
+    pub(crate) fn create_handlers<'lua>(
+        &self,
+        lua: &'lua Lua,
+        root: &str,
+    ) -> LuaResult<LuaTable<'lua>> {
+        let root: Arc<str> = Arc::from(root);
+        let handlers = lua.create_table_with_capacity(0, WatchEventKind::ALL.len())?;
+        for kind in WatchEventKind::ALL {
+            let (tx, root, kind) = (self.tx.clone(), Arc::clone(&root), kind.name());
+            let handler = lua.create_function(move |_, paths: Vec<String>| {
+                // NOTE: Sending only fails when nobody is subscribed, which is fine
+                let _ = tx.send(FsWatchFeedEvent {
+                    root: Arc::clone(&root),
+                    kind,
+                    paths,
+                });
+                Ok(())
+            })?;
+            handlers.set(kind, handler)?;
+        }
+        Ok(handlers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_events_as_json() {
+        let event = FsWatchFeedEvent {
+            root: Arc::from("src"),
+            kind: "changed",
+            paths: vec!["a \"quoted\"\nname".to_string(), "b\\c".to_string()],
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"root":"src","kind":"changed","paths":["a \"quoted\"\nname","b\\c"]}"#
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use notify::event::{CreateKind, RemoveKind};
+use notify::{Event, EventKind};
+
+use mlua::prelude::*;
+
+use crate::normalize::PathNormalization;
+
+use super::options::{WatchDirectoryMatching, WatchOptions, WatchPathReporting};
+use super::pattern::WatchPattern;
+
+/**
+    The patterns that paths are matched against, which can be replaced
+    while the watcher is running using `watcher:updateOptions`.
+*/
+#[derive(Debug, Clone)]
+pub struct WatchMatchers {
+    pattern: WatchPattern,
+    ignore: Option<WatchPattern>,
+}
+
+impl WatchMatchers {
+    fn is_match(&self, path: &Path, is_dir: bool, match_dirs: WatchDirectoryMatching) -> bool {
+        if self
+            .ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.is_match(path))
+        {
+            return false;
+        }
+        match (is_dir, match_dirs) {
+            (true, WatchDirectoryMatching::Always) => true,
+            (true, WatchDirectoryMatching::Never) => false,
+            _ => self.pattern.is_match(path),
+        }
+    }
+}
+
+/**
+    A handle to the matchers of a running watcher, shared between the watcher and its handle.
+
+    Both are only ever used on the Lua thread, and each event clones the current matchers
+    out before filtering its paths, so replacing them never blocks the watcher, and never
+    changes the matchers that an event is filtered with part of the way through.
+*/
+#[derive(Debug, Clone)]
+pub struct SharedMatchers(Rc<RefCell<Rc<WatchMatchers>>>);
+
+impl SharedMatchers {
+    fn current(&self) -> Rc<WatchMatchers> {
+        Rc::clone(&self.0.borrow())
+    }
+
+    /**
+        Replaces the given matchers, keeping any that were not given the same as before.
+    */
+    pub fn update(&self, update: WatchMatchersUpdate) {
+        let mut matchers = WatchMatchers::clone(&self.current());
+        if let Some(pattern) = update.pattern {
+            matchers.pattern = pattern;
+        }
+        if let Some(ignore) = update.ignore {
+            matchers.ignore = ignore;
+        }
+        *self.0.borrow_mut() = Rc::new(matchers);
+    }
+}
+
+/**
+    New matchers given to `watcher:updateOptions`, where every pattern is
+    compiled up front so that invalid patterns never replace only some of them.
+*/
+#[derive(Debug, Default)]
+pub struct WatchMatchersUpdate {
+    pattern: Option<WatchPattern>,
+    /// Set to `Some(None)` when given `false`, which removes the ignore patterns
+    #[allow(clippy::option_option)]
+    ignore: Option<Option<WatchPattern>>,
+}
+
+const UPDATE_OPTION_KEYS: &[&str] = &["pattern", "ignore"];
+
+impl<'lua> FromLua<'lua> for WatchMatchersUpdate {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let LuaValue::Table(t) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "WatchMatchersUpdate",
+                message: Some(format!(
+                    "Invalid watcher options - expected table, got {}",
+                    value.type_name()
+                )),
+            });
+        };
+        for pair in t.clone().pairs::<LuaValue, LuaValue>() {
+            let (key, _) = pair?;
+            let known = match &key {
+                LuaValue::String(s) => UPDATE_OPTION_KEYS.contains(&s.to_str()?),
+                _ => false,
+            };
+            if !known {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid watcher options - only {} can be updated",
+                    UPDATE_OPTION_KEYS.join(" and ")
+                )));
+            }
+        }
+        Ok(Self {
+            pattern: match t.get::<_, LuaValue>("pattern")? {
+                LuaValue::Nil => None,
+                value => Some(WatchPattern::from_lua_value(value, "watch")?),
+            },
+            ignore: match t.get::<_, LuaValue>("ignore")? {
+                LuaValue::Nil => None,
+                LuaValue::Boolean(false) => Some(None),
+                value => Some(Some(WatchPattern::from_lua_value(value, "ignore")?)),
+            },
+        })
+    }
+}
+
+/**
+    Filters and converts the paths of native events
+    into the paths that should be passed to handlers.
+*/
+#[derive(Debug, Clone)]
+pub struct WatchFilter {
+    matchers: SharedMatchers,
+    watch_files: bool,
+    watch_dirs: bool,
+    match_dirs: WatchDirectoryMatching,
+    report_paths: WatchPathReporting,
+    normalize: Option<PathNormalization>,
+    given_root: PathBuf,
+    canonical_root: PathBuf,
+}
+
+impl WatchFilter {
+    pub fn new(
+        options: &WatchOptions,
+        given_root: PathBuf,
+        canonical_root: PathBuf,
+    ) -> LuaResult<Self> {
+        let matchers = WatchMatchers {
+            pattern: options.pattern.clone(),
+            ignore: options.ignore.clone(),
+        };
+        Ok(Self {
+            matchers: SharedMatchers(Rc::new(RefCell::new(Rc::new(matchers)))),
+            watch_files: options.watch_files,
+            watch_dirs: options.watch_diretories,
+            match_dirs: options.match_directories,
+            report_paths: options.report_paths,
+            normalize: options.normalize,
+            given_root,
+            canonical_root,
+        })
+    }
+
+    /**
+        Returns a handle to the matchers of this filter, which can be used to replace them.
+    */
+    pub fn matchers(&self) -> SharedMatchers {
+        self.matchers.clone()
+    }
+
+    /**
+        Returns the reported path of the watched root itself.
+    */
+    pub fn root_path(&self) -> String {
+        self.report_paths
+            .report(&self.canonical_root, &self.given_root, &self.canonical_root)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /**
+        Returns the reported paths for the given event that pass the filter.
+    */
+    pub fn filter_paths(&self, event: &Event) -> Vec<String> {
+        let matchers = self.matchers.current();
+        event
+            .paths
+            .iter()
+            .filter_map(|path| self.filter_path(&matchers, event.kind, path))
+            .collect()
+    }
+
+    /**
+        Returns the reported paths that a coalesced file went through, without filtering them.
+    */
+    pub fn report_chain(&self, chain: &[PathBuf]) -> Vec<String> {
+        chain
+            .iter()
+            .map(|path| self.report_path(path).to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn report_path(&self, path: &Path) -> PathBuf {
+        let reported = self
+            .report_paths
+            .report(path, &self.given_root, &self.canonical_root);
+        match self.normalize {
+            Some(form) => PathBuf::from(form.apply(&reported.to_string_lossy())),
+            None => reported,
+        }
+    }
+
+    fn filter_path(
+        &self,
+        matchers: &WatchMatchers,
+        kind: EventKind,
+        path: &Path,
+    ) -> Option<String> {
+        let is_dir = match path_kind(kind, path)? {
+            PathKind::File if self.watch_files => false,
+            PathKind::Dir if self.watch_dirs => true,
+            _ => return None,
+        };
+        let reported = self.report_path(path);
+        matchers
+            .is_match(&reported, is_dir, self.match_dirs)
+            .then(|| reported.to_string_lossy().to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathKind {
+    File,
+    Dir,
+}
+
+/**
+    Determines if an event path is a file or a directory.
+
+    Uses the kind reported by the backend when available, since paths for
+    removal events no longer exist, and falls back to checking the filesystem.
+*/
+fn path_kind(kind: EventKind, path: &Path) -> Option<PathKind> {
+    match kind {
+        EventKind::Create(CreateKind::File) | EventKind::Remove(RemoveKind::File) => {
+            Some(PathKind::File)
+        }
+        EventKind::Create(CreateKind::Folder) | EventKind::Remove(RemoveKind::Folder) => {
+            Some(PathKind::Dir)
+        }
+        _ if path.is_dir() => Some(PathKind::Dir),
+        _ if path.is_file() => Some(PathKind::File),
+        _ => None,
+    }
+}
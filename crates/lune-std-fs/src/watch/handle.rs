@@ -0,0 +1,97 @@
+use mlua::prelude::*;
+use tokio::sync::{mpsc::UnboundedSender, watch::Sender};
+
+use super::filter::{SharedMatchers, WatchMatchersUpdate};
+use super::history::WatchHistory;
+use super::info::WatcherInfo;
+use super::subscription::{SubscriptionOptions, WatchSubscriptions};
+
+/**
+    A handle to a running filesystem watcher.
+
+    Dropping the handle without calling `stop` keeps the watcher running.
+*/
+#[derive(Debug)]
+pub struct FsWatcher {
+    info: WatcherInfo,
+    history: WatchHistory,
+    subscriptions: WatchSubscriptions,
+    matchers: SharedMatchers,
+    shutdown_tx: Sender<bool>,
+    rescan_tx: UnboundedSender<()>,
+}
+
+impl FsWatcher {
+    pub fn new(
+        info: WatcherInfo,
+        history: WatchHistory,
+        subscriptions: WatchSubscriptions,
+        matchers: SharedMatchers,
+        shutdown_tx: Sender<bool>,
+        rescan_tx: UnboundedSender<()>,
+    ) -> Self {
+        Self {
+            info,
+            history,
+            subscriptions,
+            matchers,
+            shutdown_tx,
+            rescan_tx,
+        }
+    }
+
+    /**
+        Stops the watcher, returning `false` if it was already stopped.
+    */
+    pub fn stop(&self) -> bool {
+        !self.shutdown_tx.send_replace(true)
+    }
+}
+
+impl LuaUserData for FsWatcher {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("info", |_, this, ()| Ok(this.info));
+
+        methods.add_method("recent", |_, this, count: Option<usize>| {
+            Ok(this.history.recent(count))
+        });
+
+        methods.add_method(
+            "subscribe",
+            |lua, this, (handlers, options): (LuaTable, SubscriptionOptions)| {
+                this.subscriptions.subscribe(lua, handlers, options)
+            },
+        );
+
+        methods.add_method("updateOptions", |_, this, update: WatchMatchersUpdate| {
+            if *this.shutdown_tx.borrow() {
+                return Err(LuaError::runtime("Watcher already stopped"));
+            }
+            // NOTE: Only the matchers are replaced, native watches are kept as they are
+            this.matchers.update(update);
+            Ok(())
+        });
+
+        methods.add_method("rescan", |_, this, ()| {
+            // NOTE: Sending fails if the watcher task has ended, which
+            // only happens once the watcher has been stopped in some way
+            if *this.shutdown_tx.borrow() || this.rescan_tx.send(()).is_err() {
+                Err(LuaError::runtime("Watcher already stopped"))
+            } else {
+                Ok(())
+            }
+        });
+
+        methods.add_method("stop", |_, this, ()| {
+            if this.stop() {
+                Ok(())
+            } else {
+                Err(LuaError::runtime("Watcher already stopped"))
+            }
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsWatcher");
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use mlua::prelude::*;
+
+use lune_std_datetime::DateTime;
+
+use super::event::WatchEventKind;
+
+/**
+    An event that was delivered by a watcher, passed to
+    handlers and kept around for inspection.
+*/
+#[derive(Debug, Clone)]
+pub struct RecordedEvent {
+    kind: WatchEventKind,
+    paths: Vec<String>,
+    chain: Vec<String>,
+    pid: Option<u32>,
+    time: DateTime,
+    timestamp: Option<DateTime>,
+    latency: Duration,
+}
+
+impl RecordedEvent {
+    /**
+        Creates an event that is being delivered right now,
+        for something that the watcher received at `received_at`.
+    */
+    pub fn new(kind: WatchEventKind, paths: Vec<String>, received_at: SystemTime) -> Self {
+        let timestamp = received_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .and_then(|d| DateTime::from_unix_timestamp_float(d.as_secs_f64()).ok());
+        Self {
+            kind,
+            paths,
+            chain: Vec::new(),
+            pid: None,
+            time: DateTime::now(),
+            timestamp,
+            latency: received_at.elapsed().unwrap_or_default(),
+        }
+    }
+
+    pub fn kind(&self) -> WatchEventKind {
+        self.kind
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /**
+        Sets the paths that the file of this event went through before
+        ending up at its final path, for coalesced renames.
+    */
+    #[must_use]
+    pub fn with_chain(self, chain: Vec<String>) -> Self {
+        Self { chain, ..self }
+    }
+
+    /**
+        Sets the process that caused this event, if it is known.
+    */
+    #[must_use]
+    pub fn with_pid(self, pid: Option<u32>) -> Self {
+        Self { pid, ..self }
+    }
+
+    /**
+        Creates a copy of this event with only some of its paths, for subscriptions.
+    */
+    pub fn with_paths(&self, paths: Vec<String>) -> Self {
+        Self {
+            paths,
+            ..self.clone()
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for RecordedEvent {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let tab = lua.create_table_with_capacity(0, 7)?;
+        tab.set("kind", self.kind.name())?;
+        tab.set("paths", self.paths)?;
+        if !self.chain.is_empty() {
+            tab.set("chain", self.chain)?;
+        }
+        tab.set("pid", self.pid)?;
+        tab.set("time", self.time)?;
+        tab.set("timestamp", self.timestamp)?;
+        tab.set("latency", self.latency.as_secs_f64())?;
+        tab.set_readonly(true);
+        Ok(LuaValue::Table(tab))
+    }
+}
+
+/**
+    A bounded history of the most recent events delivered by a watcher,
+    shared between the watcher task and its handle.
+
+    Once full, recording a new event discards the oldest one.
+*/
+#[derive(Debug, Clone)]
+pub struct WatchHistory {
+    capacity: usize,
+    events: Arc<Mutex<VecDeque<RecordedEvent>>>,
+}
+
+impl WatchHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    pub fn record(&self, event: &RecordedEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock().expect("Watch history lock was poisoned");
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+    }
+
+    /**
+        Gets up to `count` of the most recent events, oldest first,
+        or all of the recorded events if no count is given.
+    */
+    pub fn recent(&self, count: Option<usize>) -> Vec<RecordedEvent> {
+        let events = self.events.lock().expect("Watch history lock was poisoned");
+        let skip = count.map_or(0, |count| events.len().saturating_sub(count));
+        events.iter().skip(skip).cloned().collect()
+    }
+}
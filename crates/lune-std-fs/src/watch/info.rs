@@ -0,0 +1,97 @@
+use mlua::prelude::*;
+use notify::WatcherKind;
+
+use lune_utils::TableBuilder;
+
+use super::event::WatchEventKind;
+
+/**
+    Information about the backend that a watcher is using.
+
+    Lets scripts adapt their expectations at runtime, since
+    different platforms deliver different kinds of events.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct WatcherInfo {
+    kind: WatcherKind,
+    origins: bool,
+}
+
+impl WatcherInfo {
+    pub fn new(kind: WatcherKind) -> Self {
+        Self {
+            kind,
+            origins: false,
+        }
+    }
+
+    /**
+        Sets whether the watcher knows which processes caused its events.
+    */
+    #[must_use]
+    pub fn with_origins(self, origins: bool) -> Self {
+        Self { origins, ..self }
+    }
+
+    pub fn backend(self) -> &'static str {
+        match self.kind {
+            WatcherKind::Inotify => "inotify",
+            WatcherKind::Fsevent => "fsevents",
+            WatcherKind::Kqueue => "kqueue",
+            WatcherKind::ReadDirectoryChangesWatcher => "windows",
+            WatcherKind::PollWatcher => "poll",
+            _ => "unknown",
+        }
+    }
+
+    /**
+        Whether the backend natively supports recursive watches, or if
+        recursion is emulated by adding a watch for every subdirectory.
+    */
+    pub fn native_recursion(self) -> bool {
+        matches!(
+            self.kind,
+            WatcherKind::Fsevent | WatcherKind::ReadDirectoryChangesWatcher
+        )
+    }
+
+    /**
+        The names of handlers that the backend is able to invoke.
+    */
+    pub fn event_kinds(self) -> &'static [WatchEventKind] {
+        match self.kind {
+            // Polling compares snapshots, so renames show up as removals and additions
+            WatcherKind::PollWatcher => &[
+                WatchEventKind::Added,
+                WatchEventKind::Removed,
+                WatchEventKind::Changed,
+            ],
+            _ => &[
+                WatchEventKind::Added,
+                WatchEventKind::Removed,
+                WatchEventKind::Changed,
+                WatchEventKind::Renamed,
+            ],
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for WatcherInfo {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let event_kinds = TableBuilder::new(lua)?
+            .with_sequential_values(
+                self.event_kinds()
+                    .iter()
+                    .map(|kind| kind.name())
+                    .collect::<Vec<_>>(),
+            )?
+            .build_readonly()?;
+        TableBuilder::new(lua)?
+            .with_value("backend", self.backend())?
+            .with_value("nativeRecursion", self.native_recursion())?
+            .with_value("reportsOrigin", self.origins)?
+            .with_value("eventKinds", event_kinds)?
+            .build_readonly()?
+            .into_lua(lua)
+    }
+}
@@ -0,0 +1,347 @@
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSpawnExt;
+
+mod backend;
+mod coalesce;
+mod cost;
+mod defaults;
+mod event;
+mod feed;
+mod filter;
+mod handle;
+mod history;
+mod info;
+mod options;
+mod origin;
+mod pattern;
+mod prewatch;
+mod quiet;
+mod recursion;
+mod root;
+mod subscription;
+
+pub use self::cost::{watch_cost, WatchCost, WatchCostOptions};
+pub use self::defaults::{WatchBackend, WatchDefaults};
+pub use self::event::create_event_kinds;
+pub use self::feed::{FsWatchFeed, FsWatchFeedEvent};
+pub use self::handle::FsWatcher;
+pub use self::info::WatcherInfo;
+pub use self::options::WatchOptions;
+pub use self::pattern::WatchPattern;
+pub use self::prewatch::{FsPrewatch, FsPrewatchOptions};
+pub use self::quiet::{await_quiet, QuietOptions};
+
+use self::backend::WatchSource;
+use self::coalesce::{CoalescedEvent, RenameCoalescer};
+use self::event::WatchEventKind;
+use self::filter::WatchFilter;
+use self::history::{RecordedEvent, WatchHistory};
+use self::origin::WatchOrigins;
+use self::recursion::{watch_root, WatchRecursion};
+use self::root::WatchRoot;
+use crate::clock::SharedClock;
+use crate::config::FsConfig;
+use crate::save::SaveTags;
+
+use self::subscription::{SubscriptionOptions, WatchSubscriptions};
+
+/**
+    How often to check if a removed watch root has been created again.
+*/
+const REWATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/**
+    How often to check if the device containing the watch root has been removed.
+*/
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn watch<'lua>(
+    lua: &'lua Lua,
+    root_path: String,
+    options: WatchOptions,
+    handlers: LuaTable<'lua>,
+) -> LuaResult<FsWatcher> {
+    let given_root = PathBuf::from(root_path);
+    let canonical_root = resolve_root(&given_root)?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(options.channel_capacity);
+    let mut watcher = options.create_watcher(tx.clone()).into_lua_err()?;
+    let emulate_recursion =
+        watch_root(&mut *watcher, &canonical_root, options.recursive_mode()).into_lua_err()?;
+
+    let source = WatchSource {
+        watcher,
+        tx,
+        rx,
+        callback: None,
+        emulate_recursion,
+    };
+    run_watcher(lua, given_root, canonical_root, options, handlers, source)
+}
+
+/**
+    Watches the given root, forwarding every event to the feed that the
+    embedder registered using [`FsConfig::with_watch_feed`], instead of to Lua handlers.
+
+    [`FsConfig::with_watch_feed`]: crate::FsConfig::with_watch_feed
+*/
+pub fn watch_serve(lua: &Lua, root_path: String, options: WatchOptions) -> LuaResult<FsWatcher> {
+    let feed = FsConfig::get(lua).watch_feed.ok_or_else(|| {
+        LuaError::runtime("No watch feed was provided by the program running this script")
+    })?;
+    let handlers = feed.create_handlers(lua, &root_path)?;
+    watch(lua, root_path, options, handlers)
+}
+
+/**
+    Takes a root that the embedder registered using [`FsConfig::with_prewatch`],
+    delivering all of the events it queued up to the given handlers first.
+
+    [`FsConfig::with_prewatch`]: crate::FsConfig::with_prewatch
+*/
+pub fn prewatched<'lua>(
+    lua: &'lua Lua,
+    name: String,
+    options: WatchOptions,
+    handlers: LuaTable<'lua>,
+) -> LuaResult<FsWatcher> {
+    let config = FsConfig::get(lua);
+    let prewatch = config.prewatches.get(&name).ok_or_else(|| {
+        LuaError::RuntimeError(format!("No prewatched root named '{name}' exists"))
+    })?;
+    let (given_root, canonical_root, options, source) = prewatch.take(&name, options)?;
+    run_watcher(lua, given_root, canonical_root, options, handlers, source)
+}
+
+/**
+    Delivers the events of an already watching source to the given handlers,
+    in the background, until the returned handle is used to stop it.
+*/
+#[allow(clippy::too_many_lines)]
+fn run_watcher<'lua>(
+    lua: &'lua Lua,
+    given_root: PathBuf,
+    canonical_root: PathBuf,
+    options: WatchOptions,
+    handlers: LuaTable<'lua>,
+    source: WatchSource,
+) -> LuaResult<FsWatcher> {
+    // NOTE: Emulated recursion watches every directory non-recursively, including the root
+    let recursion = WatchRecursion::new(canonical_root.clone(), source.emulate_recursion);
+    let recursive_mode = recursion.recursive_mode(options.recursive_mode());
+    let settle_delay = options.settle_delay;
+    let defer = options.defer;
+    let history = WatchHistory::new(options.history_size);
+    let own_saves = options.ignore_own_saves.then(|| SaveTags::get(lua));
+    let clock = FsConfig::get(lua).clock;
+    let mut coalescer = RenameCoalescer::new(options.coalesce_renames);
+
+    let exclude_self = options.exclude_self.then(std::process::id);
+    let mut origins = WatchOrigins::new(
+        canonical_root.clone(),
+        options.recursive,
+        options.report_origin || options.exclude_self,
+    );
+    if options.exclude_self && !origins.is_tracking() {
+        return Err(LuaError::runtime(
+            "Invalid watch options - excludeSelf is not supported, since \
+            the processes that cause events are not known on this system",
+        ));
+    }
+
+    let filter = WatchFilter::new(&options, given_root, canonical_root.clone())?;
+    let matchers = filter.matchers();
+    let mut root = WatchRoot::new(canonical_root, recursive_mode, options.rewatch_root);
+
+    let info = WatcherInfo::new(options.watcher_kind()).with_origins(origins.is_tracking());
+    let WatchSource {
+        watcher,
+        tx,
+        mut rx,
+        callback,
+        ..
+    } = source;
+    let recreate_watcher = move || options.create_watcher_with(tx.clone(), callback.clone());
+
+    let lua_inner = strong_lua(lua);
+    // NOTE: The handlers given to fs.watch are just the first subscription,
+    // one that can not be unsubscribed since its handle is never returned
+    let subscriptions = WatchSubscriptions::default();
+    let _ = subscriptions.subscribe(lua, handlers, SubscriptionOptions::default())?;
+    let handle_subscriptions = subscriptions.clone();
+
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    let (rescan_tx, mut rescan_rx) = tokio::sync::mpsc::unbounded_channel();
+    let handle_history = history.clone();
+    lua.spawn_local(async move {
+        // NOTE: The native watcher is owned by this task, so that
+        // it gets dropped and stops watching once the loop ends
+        let mut watcher = watcher;
+        let mut recursion = recursion;
+        recursion.rewatch_all(&mut *watcher);
+
+        // While settling, every received event pushes the deadline further
+        // back, so that delivery only starts once the watched tree is quiet
+        let mut settle_deadline = settle_delay.map(|delay| clock.now() + delay);
+
+        let mut next_device_check = clock.now() + DEVICE_CHECK_INTERVAL;
+        let mut shutdown_closed = false;
+
+        let deliver = |event: RecordedEvent| {
+            history.record(&event);
+            subscriptions.deliver(&lua_inner, &event, defer)
+        };
+        let root_event = |kind, received_at| {
+            RecordedEvent::new(kind, vec![filter.root_path()], received_at)
+        };
+        let deliver_all = |ready: Vec<CoalescedEvent>| {
+            for ready in ready {
+                let Some(kind) = WatchEventKind::from_notify(ready.event.kind) else {
+                    continue;
+                };
+                let filtered_paths = filter.filter_paths(&ready.event);
+                if filtered_paths.is_empty() {
+                    continue;
+                }
+                let event = RecordedEvent::new(kind, filtered_paths, ready.received_at)
+                    .with_chain(filter.report_chain(&ready.chain))
+                    .with_pid(ready.event.attrs.process_id());
+                if !deliver(event) {
+                    return false;
+                }
+            }
+            true
+        };
+
+        loop {
+            let (res, received_at) = tokio::select! {
+                res = rx.recv() => match res {
+                    Some(res) => res,
+                    None => break,
+                },
+                res = shutdown_rx.changed(), if !shutdown_closed => {
+                    // NOTE: We will only get a RecvError here if the watcher handle is dropped,
+                    // this means lua has garbage collected it and the user does not want
+                    // to manually stop the watcher using the handle. Run forever, without
+                    // waiting for a shutdown again, since it would error again right away.
+                    if res.is_ok() {
+                        break;
+                    }
+                    shutdown_closed = true;
+                    continue;
+                }
+                Some(()) = rescan_rx.recv() => {
+                    if root.rescan(&mut watcher, &recreate_watcher).is_ok() {
+                        recursion.rewatch_all(&mut *watcher);
+                        origins.rewatch_all();
+                    }
+                    continue;
+                }
+                () = clock.sleep(REWATCH_INTERVAL), if root.awaiting_recreation() => {
+                    let kind = WatchEventKind::RootRecreated;
+                    if root.try_rewatch(&mut *watcher) {
+                        recursion.rewatch_all(&mut *watcher);
+                        origins.rewatch_all();
+                        if !deliver(root_event(kind, SystemTime::now())) {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                () = sleep_until_deadline(&clock, coalescer.deadline()), if coalescer.deadline().is_some() => {
+                    if !deliver_all(coalescer.flush_expired(clock.now())) {
+                        break;
+                    }
+                    continue;
+                }
+                () = clock.sleep_until(next_device_check) => {
+                    next_device_check = clock.now() + DEVICE_CHECK_INTERVAL;
+                    // Every queued origin holds an open file, so they are read even without events
+                    origins.drain();
+                    if root.detect_device_removal() {
+                        // NOTE: Nothing on the device can be watched anymore, and native
+                        // backends go quiet without telling us, so this is the last event
+                        let _ = deliver(root_event(WatchEventKind::DeviceRemoved, SystemTime::now()));
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            // NOTE: Backend errors are not fatal for the watcher as a whole,
+            // they usually concern a single path that could not be read
+            let Ok(mut event) = res else {
+                continue;
+            };
+            recursion.update(&mut *watcher, &event);
+            origins.update(&event);
+            if let Some(tags) = &own_saves {
+                event.paths.retain(|path| !tags.is_tagged(path));
+            }
+            if let Some(own_pid) = exclude_self {
+                event.paths.retain(|path| origins.origin(event.kind, path) != Some(own_pid));
+            }
+            if let Some(pid) = event.paths.iter().find_map(|path| origins.origin(event.kind, path)) {
+                event.attrs.set_process_id(pid);
+            }
+
+            if let (Some(deadline), Some(delay)) = (settle_deadline, settle_delay) {
+                let now = clock.now();
+                if now < deadline {
+                    settle_deadline = Some(now + delay);
+                    continue;
+                }
+                settle_deadline = None;
+            }
+
+            if !deliver_all(coalescer.push(event, received_at, clock.now())) {
+                break;
+            }
+
+            // NOTE: Removing the root is reported after removing its contents,
+            // so it is delivered last, once all of the other events have been
+            if root.detect_removal() && !deliver(root_event(WatchEventKind::RootRemoved, received_at)) {
+                break;
+            }
+        }
+    });
+
+    Ok(FsWatcher::new(
+        info,
+        handle_history,
+        handle_subscriptions,
+        matchers,
+        shutdown_tx,
+        rescan_tx,
+    ))
+}
+
+fn strong_lua(lua: &Lua) -> Rc<Lua> {
+    lua.app_data_ref::<Weak<Lua>>()
+        .expect("Missing weak lua ref")
+        .upgrade()
+        .expect("Lua was dropped unexpectedly")
+}
+
+async fn sleep_until_deadline(clock: &SharedClock, deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        clock.sleep_until(deadline).await;
+    }
+}
+
+/**
+    Resolves the given watch root, since native backends report paths relative
+    to the resolved root, so we watch that for consistent results instead.
+*/
+fn resolve_root(given_root: &Path) -> LuaResult<PathBuf> {
+    std::fs::canonicalize(given_root).map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to resolve watch root '{}'\n{e}",
+            given_root.display()
+        ))
+    })
+}
@@ -0,0 +1,401 @@
+use std::{
+    default::Default,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use mlua::prelude::*;
+use notify::{Config, Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher, WatcherKind};
+
+use crate::config::FsConfig;
+use crate::normalize::PathNormalization;
+
+use super::backend::{ActiveWatcher, EventSender};
+use super::defaults::{WatchBackend, WatchDefaults};
+use super::event::WatchEventKind;
+use super::pattern::WatchPattern;
+use super::prewatch::PrewatchCallback;
+
+/**
+    How paths given to watch handlers should be reported.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WatchPathReporting {
+    /// Paths are relative to the root path exactly as it was given.
+    AsGiven,
+    /// Paths are fully resolved, with any symlinks in the root path followed.
+    #[default]
+    Canonical,
+}
+
+impl WatchPathReporting {
+    /**
+        Converts a path reported by the native watcher, which is always
+        located within `canonical_root`, into the path that should be
+        matched against and passed to handlers.
+    */
+    pub fn report(self, path: &Path, given_root: &Path, canonical_root: &Path) -> PathBuf {
+        match self {
+            Self::Canonical => path.to_path_buf(),
+            Self::AsGiven => match path.strip_prefix(canonical_root) {
+                Ok(rel) if rel.as_os_str().is_empty() => given_root.to_path_buf(),
+                Ok(rel) => given_root.join(rel),
+                Err(_) => path.to_path_buf(),
+            },
+        }
+    }
+}
+
+impl FromStr for WatchPathReporting {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "asgiven" => Ok(Self::AsGiven),
+            "canonical" => Ok(Self::Canonical),
+            _ => Err("Invalid path reporting mode - expected 'asGiven' or 'canonical'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for WatchPathReporting {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "WatchPathReporting",
+                message: Some("Path reporting mode must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+/**
+    How directory events should be matched against the watch pattern.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WatchDirectoryMatching {
+    /// Directory events are always delivered, even if they do not match the pattern.
+    Always,
+    /// Directory events are only delivered if they match the pattern.
+    #[default]
+    Glob,
+    /// Directory events are never delivered.
+    Never,
+}
+
+impl FromStr for WatchDirectoryMatching {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_ref() {
+            "always" => Ok(Self::Always),
+            "glob" => Ok(Self::Glob),
+            "never" => Ok(Self::Never),
+            _ => Err("Invalid directory matching mode - expected 'always', 'glob' or 'never'"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for WatchDirectoryMatching {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => s.to_str()?.parse().map_err(LuaError::runtime),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "WatchDirectoryMatching",
+                message: Some("Directory matching mode must be a string".to_string()),
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct WatchOptions {
+    /// The glob patterns defining which files to watch.
+    pub pattern: WatchPattern,
+    /// The glob patterns defining which files and directories to never watch.
+    pub ignore: Option<WatchPattern>,
+    /// Whether to watch changes recursively.
+    pub recursive: bool,
+    /// Whether to watch files.
+    pub watch_files: bool,
+    /// Whether to watch directories.
+    pub watch_diretories: bool,
+    /// The interval to poll for changes at, for backends that poll.
+    pub interval: Duration,
+    /// How long the watcher must go without receiving any events before
+    /// it starts delivering them, events received before then are discarded.
+    pub settle_delay: Option<Duration>,
+    /// How paths passed to handlers, and matched against the pattern, are reported.
+    pub report_paths: WatchPathReporting,
+    /// How directory events are matched against the pattern.
+    pub match_directories: WatchDirectoryMatching,
+    /// The unicode normalization form that paths are converted into before being matched.
+    pub normalize: Option<PathNormalization>,
+    /// Whether handlers should be deferred to the end of the current
+    /// resumption cycle, instead of being resumed immediately.
+    pub defer: bool,
+    /// Whether the watch should be re-established once the
+    /// watched root has been removed and then created again.
+    pub rewatch_root: bool,
+    /// The number of delivered events to keep around for inspection.
+    pub history_size: usize,
+    /// How many native events may be queued up before the backend has to wait.
+    pub channel_capacity: usize,
+    /// The backend used to receive events.
+    pub backend: WatchBackend,
+    /// Whether events for files saved by this script using `fs.saveAtomic` should be ignored.
+    pub ignore_own_saves: bool,
+    /// Whether poll watchers should compare the contents of files, instead of
+    /// only their modification times, to find out if they have changed.
+    pub compare_contents: bool,
+    /// Whether files that are written to a temporary path and then renamed over
+    /// another file should be reported as a single change of the destination.
+    pub coalesce_renames: bool,
+    /// Whether events should report the process that caused them, where supported.
+    pub report_origin: bool,
+    /// Whether events caused by the current process should be ignored, where supported.
+    pub exclude_self: bool,
+}
+
+impl WatchOptions {
+    /**
+        Gets the default watch options, using the watch defaults set by the embedder.
+    */
+    pub fn defaults(lua: &Lua) -> Self {
+        Self::from_defaults(&FsConfig::get(lua).watch_defaults)
+    }
+
+    pub fn from_defaults(defaults: &WatchDefaults) -> Self {
+        Self {
+            pattern: WatchPattern::any(),
+            ignore: None,
+            recursive: false,
+            watch_files: true,
+            watch_diretories: true,
+            interval: defaults.interval,
+            settle_delay: defaults.settle_delay,
+            report_paths: WatchPathReporting::default(),
+            match_directories: WatchDirectoryMatching::default(),
+            normalize: None,
+            defer: true,
+            rewatch_root: true,
+            history_size: 64,
+            channel_capacity: defaults.channel_capacity,
+            backend: defaults.backend,
+            ignore_own_saves: false,
+            compare_contents: false,
+            coalesce_renames: false,
+            report_origin: false,
+            exclude_self: false,
+        }
+    }
+
+    pub fn recursive_mode(&self) -> RecursiveMode {
+        if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        }
+    }
+
+    pub fn create_watcher(&self, tx: EventSender) -> notify::Result<ActiveWatcher> {
+        self.create_watcher_with(tx, None)
+    }
+
+    /**
+        Creates a watcher like [`WatchOptions::create_watcher`], which also
+        calls the given callback for every event, before sending it on.
+    */
+    pub fn create_watcher_with(
+        &self,
+        tx: EventSender,
+        callback: Option<PrewatchCallback>,
+    ) -> notify::Result<ActiveWatcher> {
+        // NOTE: Sending only fails once the receiving end has been dropped,
+        // meaning the watcher was stopped, and any late events can be ignored.
+        // None of the backends give us the time an event happened, so events
+        // are timestamped here, as soon as the backend hands them over to us
+        let handler = move |res: notify::Result<Event>| {
+            if let (Some(callback), Ok(event)) = (&callback, &res) {
+                if let Some(kind) = WatchEventKind::from_notify(event.kind) {
+                    callback(kind.name(), &event.paths);
+                }
+            }
+            let _ = tx.blocking_send((res, SystemTime::now()));
+        };
+        let config = Config::default()
+            .with_poll_interval(self.interval)
+            .with_compare_contents(self.compare_contents);
+        Ok(match self.backend {
+            WatchBackend::Native => {
+                ActiveWatcher::Native(RecommendedWatcher::new(handler, config)?)
+            }
+            WatchBackend::Poll => ActiveWatcher::Poll(PollWatcher::new(handler, config)?),
+        })
+    }
+
+    /**
+        The kind of watcher that [`WatchOptions::create_watcher`] creates.
+    */
+    pub fn watcher_kind(&self) -> WatcherKind {
+        match self.backend {
+            WatchBackend::Native => RecommendedWatcher::kind(),
+            WatchBackend::Poll => PollWatcher::kind(),
+        }
+    }
+}
+
+/**
+    All of the keys that are accepted in the table form of watch options.
+*/
+const WATCH_OPTION_KEYS: &[&str] = &[
+    "pattern",
+    "ignore",
+    "recursive",
+    "watchFiles",
+    "watchDirectories",
+    "watchDirs",
+    "interval",
+    "intervalMs",
+    "settleDelay",
+    "reportPaths",
+    "matchDirectories",
+    "normalize",
+    "defer",
+    "rewatchRoot",
+    "historySize",
+    "ignoreOwnSaves",
+    "compareContents",
+    "coalesceRenames",
+    "reportOrigin",
+    "excludeSelf",
+];
+
+impl WatchOptions {
+    fn from_table(t: &LuaTable, defaults: Self) -> LuaResult<Self> {
+        for pair in t.clone().pairs::<LuaValue, LuaValue>() {
+            let (key, _) = pair?;
+            let known = match &key {
+                LuaValue::String(s) => WATCH_OPTION_KEYS.contains(&s.to_str()?),
+                _ => false,
+            };
+            if !known {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid watch options - unknown option '{}'\nValid options are: {}",
+                    match &key {
+                        LuaValue::String(s) => s.to_string_lossy().to_string(),
+                        other => other.type_name().to_string(),
+                    },
+                    WATCH_OPTION_KEYS.join(", ")
+                )));
+            }
+        }
+
+        let watch_diretories = match t.get::<_, Option<bool>>("watchDirectories")? {
+            Some(b) => Some(b),
+            None => t.get::<_, Option<bool>>("watchDirs")?,
+        };
+
+        Ok(Self {
+            pattern: match t.get::<_, LuaValue>("pattern")? {
+                LuaValue::Nil => defaults.pattern,
+                value => WatchPattern::from_lua_value(value, "watch")?,
+            },
+            ignore: match t.get::<_, LuaValue>("ignore")? {
+                LuaValue::Nil => defaults.ignore,
+                value => Some(WatchPattern::from_lua_value(value, "ignore")?),
+            },
+            recursive: t
+                .get::<_, Option<bool>>("recursive")?
+                .unwrap_or(defaults.recursive),
+            watch_files: t
+                .get::<_, Option<bool>>("watchFiles")?
+                .unwrap_or(defaults.watch_files),
+            watch_diretories: watch_diretories.unwrap_or(defaults.watch_diretories),
+            interval: parse_interval(t.get("interval")?, t.get("intervalMs")?)?
+                .unwrap_or(defaults.interval),
+            settle_delay: parse_millis("settle delay", t.get("settleDelay")?)?
+                .or(defaults.settle_delay),
+            report_paths: t.get("reportPaths")?,
+            match_directories: t.get("matchDirectories")?,
+            normalize: t.get("normalize")?,
+            defer: t.get::<_, Option<bool>>("defer")?.unwrap_or(defaults.defer),
+            rewatch_root: t
+                .get::<_, Option<bool>>("rewatchRoot")?
+                .unwrap_or(defaults.rewatch_root),
+            history_size: t
+                .get::<_, Option<usize>>("historySize")?
+                .unwrap_or(defaults.history_size),
+            channel_capacity: defaults.channel_capacity,
+            backend: defaults.backend,
+            ignore_own_saves: t
+                .get::<_, Option<bool>>("ignoreOwnSaves")?
+                .unwrap_or(defaults.ignore_own_saves),
+            compare_contents: t
+                .get::<_, Option<bool>>("compareContents")?
+                .unwrap_or(defaults.compare_contents),
+            coalesce_renames: t
+                .get::<_, Option<bool>>("coalesceRenames")?
+                .unwrap_or(defaults.coalesce_renames),
+            report_origin: t
+                .get::<_, Option<bool>>("reportOrigin")?
+                .unwrap_or(defaults.report_origin),
+            exclude_self: t
+                .get::<_, Option<bool>>("excludeSelf")?
+                .unwrap_or(defaults.exclude_self),
+        })
+    }
+}
+
+impl FromLua<'_> for WatchOptions {
+    fn from_lua(value: LuaValue<'_>, lua: &'_ mlua::Lua) -> LuaResult<Self> {
+        let defaults = Self::defaults(lua);
+        match value {
+            value @ LuaValue::String(_) => Ok(Self {
+                pattern: WatchPattern::from_lua_value(value, "watch")?,
+                ..defaults
+            }),
+            LuaValue::Table(t) => Self::from_table(&t, defaults),
+            other => Err(LuaError::FromLuaConversionError {
+                from: other.type_name(),
+                to: "WatchOptions",
+                message: Some("Argument must be of type string or table".to_string()),
+            }),
+        }
+    }
+}
+
+fn parse_interval(secs: Option<f64>, millis: Option<f64>) -> LuaResult<Option<Duration>> {
+    let interval = match (secs, millis) {
+        (Some(_), Some(_)) => {
+            return Err(LuaError::runtime(
+                "Invalid watch options - only one of 'interval' and 'intervalMs' may be given",
+            ))
+        }
+        (Some(secs), None) => parse_millis("interval", Some(secs * 1000.0))?,
+        (None, millis) => parse_millis("interval", millis)?,
+    };
+    if interval.is_some_and(|i| i.is_zero()) {
+        return Err(LuaError::runtime(
+            "Invalid interval - expected a positive number, got 0",
+        ));
+    }
+    Ok(interval)
+}
+
+pub(super) fn parse_millis(name: &str, millis: Option<f64>) -> LuaResult<Option<Duration>> {
+    match millis {
+        None => Ok(None),
+        Some(ms) if ms.is_finite() && ms >= 0.0 => Ok(Some(Duration::from_secs_f64(ms / 1000.0))),
+        Some(ms) => Err(LuaError::RuntimeError(format!(
+            "Invalid {name} - expected a positive number of milliseconds, got {ms}"
+        ))),
+    }
+}
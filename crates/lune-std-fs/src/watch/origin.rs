@@ -0,0 +1,336 @@
+use std::path::{Path, PathBuf};
+
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind};
+
+/**
+    Tracks which processes wrote to files in a watched tree, so that events
+    can report the process they came from, and scripts can ignore their own.
+
+    Origins are only known on Linux when the `fanotify` feature is enabled, and using
+    fanotify is permitted, which usually needs the `CAP_SYS_ADMIN` capability.
+    Everywhere else, tracking does nothing and the origin of every path is unknown.
+*/
+#[derive(Debug)]
+pub struct WatchOrigins(Option<imp::OriginTracker>);
+
+impl WatchOrigins {
+    pub fn new(root: PathBuf, recursive: bool, enabled: bool) -> Self {
+        Self(
+            enabled
+                .then(|| imp::OriginTracker::new(root, recursive))
+                .flatten(),
+        )
+    }
+
+    /**
+        Whether origins are being tracked, meaning that they will be known for files that
+        are written to, but not always for creating them, and never for directories,
+        removals or renames.
+    */
+    pub fn is_tracking(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /**
+        Marks every directory of the tree again, such as after the root was
+        watched again, or the backend was replaced, and all previous marks are gone.
+    */
+    pub fn rewatch_all(&mut self) {
+        if let Some(tracker) = &mut self.0 {
+            tracker.rewatch_all();
+        }
+    }
+
+    /**
+        Reads the origins of everything that happened in the tree since the last
+        update, also marking or unmarking the directories that changed in the event.
+    */
+    pub fn update(&mut self, event: &Event) {
+        if let Some(tracker) = &mut self.0 {
+            tracker.update(Some(event));
+        }
+    }
+
+    /**
+        Reads the origins of everything that happened in the tree since the last update,
+        which should be done regularly, since each of them holds on to an open file.
+    */
+    pub fn drain(&mut self) {
+        if let Some(tracker) = &mut self.0 {
+            tracker.update(None);
+        }
+    }
+
+    /**
+        Gets the process that most recently wrote to the given path, if known, and if the
+        event was caused by writing to it. Other events, such as removing a path that
+        was written to just before, may have been caused by any process.
+    */
+    pub fn origin(&self, kind: EventKind, path: &Path) -> Option<u32> {
+        let written = matches!(
+            kind,
+            EventKind::Create(_)
+                | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+                | EventKind::Access(AccessKind::Close(AccessMode::Write))
+        );
+        self.0.as_ref().filter(|_| written)?.origin(path)
+    }
+}
+
+#[cfg(all(feature = "fanotify", target_os = "linux"))]
+mod imp {
+    use std::collections::HashMap;
+    use std::ffi::CString;
+    use std::fs;
+    use std::io::{Error as IoError, ErrorKind};
+    use std::mem::size_of;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    use notify::{Config, Event, EventHandler, RecursiveMode, Watcher, WatcherKind};
+
+    use super::super::recursion::EmulatedRecursion;
+
+    /**
+        How long the origin of a path is remembered for after it was last written to.
+    */
+    const ORIGIN_LIFETIME: Duration = Duration::from_secs(5);
+
+    /**
+        The events that origins are tracked for, which are only those writing to files, since
+        files that are opened just to be read should not be reported as written by the reader.
+        Creating a file is only known to come from a process once it writes to it, and fanotify
+        can not report processes for removals or renames, since they have no open file.
+    */
+    const ORIGIN_MASK: u64 = libc::FAN_MODIFY | libc::FAN_CLOSE_WRITE | libc::FAN_EVENT_ON_CHILD;
+
+    #[derive(Debug)]
+    pub struct OriginTracker {
+        group: FanotifyGroup,
+        root: PathBuf,
+        recursion: Option<EmulatedRecursion>,
+        origins: HashMap<PathBuf, (u32, Instant)>,
+    }
+
+    impl OriginTracker {
+        /**
+            Creates a fanotify group for the given root, returning `None`
+            if fanotify is not supported, or not permitted, on this system.
+        */
+        pub fn new(root: PathBuf, recursive: bool) -> Option<Self> {
+            let mut tracker = Self {
+                group: FanotifyGroup::init().ok()?,
+                recursion: recursive.then(|| EmulatedRecursion::new(root.clone())),
+                root,
+                origins: HashMap::new(),
+            };
+            tracker.rewatch_all();
+            Some(tracker)
+        }
+
+        pub fn rewatch_all(&mut self) {
+            // NOTE: The root may not exist while waiting for it to be created
+            // again, it is then marked once the watch itself is re-established
+            let _ = self.group.watch(&self.root, RecursiveMode::NonRecursive);
+            if let Some(recursion) = &mut self.recursion {
+                recursion.rewatch_all(&mut self.group);
+            }
+        }
+
+        pub fn update(&mut self, event: Option<&Event>) {
+            if let (Some(recursion), Some(event)) = (&mut self.recursion, event) {
+                recursion.update(&mut self.group, event);
+            }
+            let now = Instant::now();
+            self.origins
+                .retain(|_, (_, at)| now.duration_since(*at) < ORIGIN_LIFETIME);
+            for (path, pid) in self.group.read_events() {
+                self.origins.insert(path, (pid, now));
+            }
+        }
+
+        pub fn origin(&self, path: &Path) -> Option<u32> {
+            self.origins.get(path).map(|(pid, _)| *pid)
+        }
+    }
+
+    /**
+        A fanotify group that is notified about files written to in marked directories.
+
+        Implements [`Watcher`] so that marks can be kept up to date for
+        recursive watches using [`EmulatedRecursion`], like native watches.
+    */
+    #[derive(Debug)]
+    struct FanotifyGroup {
+        fd: OwnedFd,
+    }
+
+    impl FanotifyGroup {
+        fn init() -> Result<Self, IoError> {
+            let flags = libc::FAN_CLASS_NOTIF | libc::FAN_CLOEXEC | libc::FAN_NONBLOCK;
+            let event_flags = libc::O_RDONLY | libc::O_CLOEXEC | libc::O_LARGEFILE;
+            // SAFETY: Initializing a group has no preconditions, and the returned descriptor is owned by us
+            let fd = unsafe { libc::fanotify_init(flags, event_flags as libc::c_uint) };
+            if fd < 0 {
+                return Err(IoError::last_os_error());
+            }
+            // SAFETY: The descriptor was just created, and nothing else owns it
+            Ok(Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            })
+        }
+
+        fn mark(&self, path: &Path, flags: libc::c_uint) -> Result<(), IoError> {
+            let path = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: The path is a valid null-terminated string for the duration of the call
+            let res = unsafe {
+                libc::fanotify_mark(
+                    self.fd.as_raw_fd(),
+                    flags,
+                    ORIGIN_MASK,
+                    libc::AT_FDCWD,
+                    path.as_ptr(),
+                )
+            };
+            if res == 0 {
+                Ok(())
+            } else {
+                Err(IoError::last_os_error())
+            }
+        }
+
+        /**
+            Reads all of the queued events, without waiting for more, returning
+            the path of the file and the process for each one of them.
+        */
+        fn read_events(&self) -> Vec<(PathBuf, u32)> {
+            let meta_len = size_of::<libc::fanotify_event_metadata>();
+            let mut events = Vec::new();
+            let mut buf = vec![0u8; 4096];
+            loop {
+                // SAFETY: The buffer is valid for writes of its entire length
+                let read =
+                    unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                let Ok(read) = usize::try_from(read) else {
+                    // NOTE: The group is non-blocking, so this is usually
+                    // just telling us that there are no more events to read
+                    break;
+                };
+                if read == 0 {
+                    break;
+                }
+                let mut offset = 0;
+                while offset + meta_len <= read {
+                    // SAFETY: The kernel wrote a full event metadata struct at this offset,
+                    // which may not be aligned, since events can have variable lengths
+                    let meta = unsafe {
+                        buf.as_ptr()
+                            .add(offset)
+                            .cast::<libc::fanotify_event_metadata>()
+                            .read_unaligned()
+                    };
+                    if (meta.event_len as usize) < meta_len {
+                        break;
+                    }
+                    offset += meta.event_len as usize;
+                    // NOTE: Queue overflows have no file, and every other event must
+                    // have its file closed, which happens once it is dropped here
+                    if meta.fd < 0 {
+                        continue;
+                    }
+                    // SAFETY: The kernel opened this descriptor for us, and nothing else owns it
+                    let file = unsafe { OwnedFd::from_raw_fd(meta.fd) };
+                    if meta.vers != libc::FANOTIFY_METADATA_VERSION {
+                        continue;
+                    }
+                    let link = format!("/proc/self/fd/{}", file.as_raw_fd());
+                    if let (Ok(path), Ok(pid)) = (fs::read_link(link), u32::try_from(meta.pid)) {
+                        events.push((path, pid));
+                    }
+                }
+            }
+            events
+        }
+    }
+
+    impl Watcher for FanotifyGroup {
+        fn new<F: EventHandler>(_: F, _: Config) -> notify::Result<Self> {
+            Self::init().map_err(notify::Error::io)
+        }
+
+        fn watch(&mut self, path: &Path, _: RecursiveMode) -> notify::Result<()> {
+            self.mark(path, libc::FAN_MARK_ADD | libc::FAN_MARK_ONLYDIR)
+                .map_err(notify::Error::io)
+        }
+
+        fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+            // NOTE: Marks of removed directories are dropped by the kernel along with them
+            match self.mark(path, libc::FAN_MARK_REMOVE) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(notify::Error::io(e)),
+                _ => Ok(()),
+            }
+        }
+
+        fn kind() -> WatcherKind {
+            WatcherKind::NullWatcher
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        #[ignore = "using fanotify needs the CAP_SYS_ADMIN capability"]
+        fn tracks_own_writes() {
+            let root = std::env::temp_dir().join(format!("lune-fs-origin-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("nested")).unwrap();
+            let root = fs::canonicalize(&root).unwrap();
+
+            let mut tracker =
+                OriginTracker::new(root.clone(), true).expect("fanotify should be permitted");
+            fs::write(root.join("file.txt"), "origin").unwrap();
+            fs::write(root.join("nested/file.txt"), "origin").unwrap();
+            tracker.update(None);
+
+            let own = Some(std::process::id());
+            assert_eq!(tracker.origin(&root.join("file.txt")), own);
+            assert_eq!(tracker.origin(&root.join("nested/file.txt")), own);
+            assert_eq!(tracker.origin(&root.join("missing.txt")), None);
+
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+}
+
+#[cfg(not(all(feature = "fanotify", target_os = "linux")))]
+mod imp {
+    use std::path::{Path, PathBuf};
+
+    use notify::Event;
+
+    #[derive(Debug)]
+    pub enum OriginTracker {}
+
+    impl OriginTracker {
+        pub fn new(_: PathBuf, _: bool) -> Option<Self> {
+            None
+        }
+
+        pub fn rewatch_all(&mut self) {
+            match *self {}
+        }
+
+        pub fn update(&mut self, _: Option<&Event>) {
+            match *self {}
+        }
+
+        pub fn origin(&self, _: &Path) -> Option<u32> {
+            match *self {}
+        }
+    }
+}
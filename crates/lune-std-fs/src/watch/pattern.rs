@@ -0,0 +1,125 @@
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use mlua::prelude::*;
+
+/**
+    A set of glob patterns that paths are matched against, given as either a
+    single pattern or a list of patterns, where patterns starting with `!` exclude
+    paths instead, the same way as most npm-style tools handle lists of globs.
+
+    A path matches if it matches any of the including patterns and none of the
+    excluding patterns. A list with only excluding patterns includes everything else.
+*/
+#[derive(Debug, Clone)]
+pub struct WatchPattern {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl WatchPattern {
+    /**
+        A pattern that matches every path.
+    */
+    pub fn any() -> Self {
+        Self {
+            include: None,
+            exclude: None,
+        }
+    }
+
+    /**
+        Parses a single pattern string or a list of pattern strings,
+        naming the pattern using `what` in any error messages.
+
+        All of the patterns are validated eagerly, so that mistakes are
+        reported at the call site instead of silently matching nothing.
+    */
+    pub fn from_lua_value(value: LuaValue, what: &str) -> LuaResult<Self> {
+        let patterns = match value {
+            LuaValue::String(s) => vec![s.to_str()?.to_string()],
+            LuaValue::Table(t) => t
+                .sequence_values::<String>()
+                .collect::<LuaResult<Vec<_>>>()?,
+            other => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid {what} pattern - expected string or list of strings, got {}",
+                    other.type_name()
+                )))
+            }
+        };
+        if patterns.is_empty() {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid {what} pattern - expected at least one pattern"
+            )));
+        }
+
+        let (excluding, including): (Vec<_>, Vec<_>) = patterns
+            .iter()
+            .partition(|pattern| pattern.starts_with('!'));
+        let build = |patterns: &[&String]| -> LuaResult<Option<GlobSet>> {
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                let glob =
+                    Glob::new(pattern.strip_prefix('!').unwrap_or(pattern)).map_err(|e| {
+                        LuaError::RuntimeError(format!(
+                            "Invalid {what} pattern '{pattern}'\n{}",
+                            e.kind()
+                        ))
+                    })?;
+                builder.add(glob);
+            }
+            Ok(Some(builder.build().into_lua_err()?))
+        };
+
+        Ok(Self {
+            include: build(&including)?,
+            exclude: build(&excluding)?,
+        })
+    }
+
+    pub fn is_match(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        self.include.as_ref().is_none_or(|set| set.is_match(path))
+            && !self.exclude.as_ref().is_some_and(|set| set.is_match(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(lua: &Lua, patterns: &[&str]) -> LuaResult<WatchPattern> {
+        let value = LuaValue::Table(lua.create_sequence_from(patterns.iter().copied())?);
+        WatchPattern::from_lua_value(value, "test")
+    }
+
+    #[test]
+    fn braces_and_negations() {
+        let lua = Lua::new();
+        let pattern = pattern(&lua, &["{src,tests}/**/*.luau", "!**/*.spec.luau"]).unwrap();
+        assert!(pattern.is_match("src/lib/init.luau"));
+        assert!(pattern.is_match("tests/fs/files.luau"));
+        assert!(!pattern.is_match("tests/fs/files.spec.luau"));
+        assert!(!pattern.is_match("docs/index.luau"));
+    }
+
+    #[test]
+    fn only_negations_include_everything_else() {
+        let lua = Lua::new();
+        let pattern = pattern(&lua, &["!**/*.tmp"]).unwrap();
+        assert!(pattern.is_match("src/main.luau"));
+        assert!(!pattern.is_match("src/main.luau.tmp"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let lua = Lua::new();
+        assert!(pattern(&lua, &[]).is_err());
+        assert!(pattern(&lua, &["**", "!**/*.{json"]).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+use std::io::{Error as IoError, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use mlua::prelude::*;
+
+use super::backend::WatchSource;
+use super::defaults::WatchDefaults;
+use super::options::WatchOptions;
+use super::recursion::watch_root;
+
+/**
+    A callback for events received by a prewatched root, called
+    with the name of the event kind and the paths of the event.
+*/
+pub(crate) type PrewatchCallback = Arc<dyn Fn(&str, &[PathBuf]) + Send + Sync>;
+
+/**
+    The default number of events that a prewatched root
+    queues up before the backend has to wait for the script.
+*/
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/**
+    Options for a root that is watched by the embedder before any script runs.
+
+    Started using [`FsPrewatchOptions::start`], and handed to
+    scripts by registering it using [`FsConfig::with_prewatch`].
+
+    [`FsConfig::with_prewatch`]: crate::FsConfig::with_prewatch
+*/
+#[derive(Clone)]
+pub struct FsPrewatchOptions {
+    recursive: bool,
+    defaults: WatchDefaults,
+    queue_capacity: usize,
+    callback: Option<PrewatchCallback>,
+}
+
+impl FsPrewatchOptions {
+    /**
+        Creates new prewatch options with all the default values.
+    */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+        Sets whether changes should be watched recursively.
+
+        Defaults to `false`, just like for `fs.watch`.
+    */
+    #[must_use]
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /**
+        Sets the backend and poll interval used by the watcher.
+
+        Defaults to [`WatchDefaults::default`], not to the defaults in the
+        configuration that the watcher is later registered in.
+    */
+    #[must_use]
+    pub fn with_watch_defaults(mut self, defaults: WatchDefaults) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /**
+        Sets how many events are queued up until the script takes the watcher.
+
+        Once the queue is full, the backend waits for the script instead of dropping
+        events, and native backends keep buffering events in the kernel meanwhile.
+
+        Defaults to `1024`.
+
+        # Panics
+
+        Panics if `capacity` is zero.
+    */
+    #[must_use]
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "Queue capacity must be greater than zero");
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /**
+        Sets a callback that is called for every event the watcher receives, with
+        the name of the event kind, such as `"changed"`, and the paths of the event.
+
+        The callback is called from the thread of the backend, as soon as events arrive,
+        both before and after the script has taken the watcher. Paths are reported
+        as the backend gives them, without any of the filtering done for scripts.
+    */
+    #[must_use]
+    pub fn with_callback(
+        mut self,
+        callback: impl Fn(&str, &[PathBuf]) + Send + Sync + 'static,
+    ) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /**
+        Starts watching the given root right away, queueing up
+        events until a script takes the watcher using `fs.prewatched`.
+
+        # Errors
+
+        Errors if the root could not be resolved, or if the backend failed to watch it.
+    */
+    pub fn start(self, root: impl Into<PathBuf>) -> IoResult<FsPrewatch> {
+        let given_root = root.into();
+        let canonical_root = given_root.canonicalize()?;
+
+        let mut options = WatchOptions::from_defaults(&self.defaults);
+        options.recursive = self.recursive;
+        options.channel_capacity = self.queue_capacity;
+
+        let callback = self.callback.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(self.queue_capacity);
+        let mut watcher = options
+            .create_watcher_with(tx.clone(), callback.clone())
+            .map_err(IoError::other)?;
+        let emulate_recursion =
+            watch_root(&mut *watcher, &canonical_root, options.recursive_mode())
+                .map_err(IoError::other)?;
+
+        Ok(FsPrewatch {
+            given_root,
+            canonical_root,
+            options: self,
+            source: Arc::new(Mutex::new(Some(WatchSource {
+                watcher,
+                tx,
+                rx,
+                callback,
+                emulate_recursion,
+            }))),
+        })
+    }
+}
+
+impl Default for FsPrewatchOptions {
+    fn default() -> Self {
+        Self {
+            recursive: false,
+            defaults: WatchDefaults::default(),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            callback: None,
+        }
+    }
+}
+
+impl fmt::Debug for FsPrewatchOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FsPrewatchOptions")
+            .field("recursive", &self.recursive)
+            .field("defaults", &self.defaults)
+            .field("queue_capacity", &self.queue_capacity)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/**
+    A root that the embedder started watching before any script ran.
+
+    Events are queued up from the moment the watch was started, and delivered to the
+    handlers of the script that takes the watcher, so that none are missed while the
+    script is starting up, such as when a host reloads a script that watches its sources.
+*/
+#[derive(Debug, Clone)]
+pub struct FsPrewatch {
+    given_root: PathBuf,
+    canonical_root: PathBuf,
+    options: FsPrewatchOptions,
+    source: Arc<Mutex<Option<WatchSource>>>,
+}
+
+impl FsPrewatch {
+    /**
+        The root that is being watched, as it was given when starting the watch.
+    */
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.given_root
+    }
+
+    /**
+        Hands the running watcher over to a script, along with all of its queued events.
+
+        Options that decide what the backend watches are kept from when the watch was
+        started, while options such as patterns and filters are taken from the script.
+    */
+    pub(crate) fn take(
+        &self,
+        name: &str,
+        mut options: WatchOptions,
+    ) -> LuaResult<(PathBuf, PathBuf, WatchOptions, WatchSource)> {
+        let source = self
+            .source
+            .lock()
+            .expect("Prewatch lock was poisoned")
+            .take()
+            .ok_or_else(|| {
+                LuaError::RuntimeError(format!(
+                    "The prewatched root '{name}' has already been taken"
+                ))
+            })?;
+        let started = WatchOptions::from_defaults(&self.options.defaults);
+        options.recursive = self.options.recursive;
+        options.interval = started.interval;
+        options.backend = started.backend;
+        options.compare_contents = started.compare_contents;
+        options.channel_capacity = self.options.queue_capacity;
+        Ok((
+            self.given_root.clone(),
+            self.canonical_root.clone(),
+            options,
+            source,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn fails_to_start_for_missing_roots() {
+        let root = std::env::temp_dir().join("lune-fs-prewatch-missing");
+        assert!(FsPrewatchOptions::new().start(root).is_err());
+    }
+
+    #[test]
+    fn queues_events_and_calls_back_before_being_taken() {
+        let root = std::env::temp_dir().join(format!("lune-fs-prewatch-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let (called_tx, called_rx) = mpsc::channel();
+        let called_tx = Mutex::new(called_tx);
+        let prewatch = FsPrewatchOptions::new()
+            .with_callback(move |kind, _| {
+                let _ = called_tx.lock().unwrap().send(kind.to_string());
+            })
+            .start(&root)
+            .unwrap();
+        std::fs::write(root.join("file.txt"), "contents").unwrap();
+
+        let kind = called_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let mut source = prewatch.source.lock().unwrap().take().unwrap();
+        let (res, _) = source.rx.blocking_recv().unwrap();
+        assert!(res.is_ok());
+        assert!(!kind.is_empty());
+
+        drop(source);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
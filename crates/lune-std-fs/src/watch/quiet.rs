@@ -0,0 +1,121 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+use super::event::WatchEventKind;
+use super::handle::FsWatcher;
+use super::options::{parse_millis, WatchOptions};
+use super::watch;
+use crate::codes::TIMED_OUT;
+use crate::config::FsConfig;
+
+/**
+    Options for waiting until a watched path has gone quiet, on top of the usual watch options.
+*/
+#[derive(Debug)]
+pub struct QuietOptions {
+    quiet_for: Duration,
+    timeout: Option<Duration>,
+    watch: WatchOptions,
+}
+
+impl<'lua> FromLua<'lua> for QuietOptions {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        let t = match value {
+            LuaValue::Nil => lua.create_table()?,
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "QuietOptions",
+                    message: Some(format!(
+                        "Invalid quiet options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        let quiet_for = parse_millis("quiet duration", t.get("quietFor")?)?
+            .unwrap_or(Duration::from_millis(500));
+        let timeout = parse_millis("timeout", t.get("timeout")?)?;
+
+        // NOTE: Everything else is a watch option, and waiting
+        // for a whole tree to go quiet is what callers expect
+        let watch = lua.create_table()?;
+        for pair in t.pairs::<LuaValue, LuaValue>() {
+            let (key, value) = pair?;
+            if let LuaValue::String(s) = &key {
+                if matches!(s.to_str()?, "quietFor" | "timeout") {
+                    continue;
+                }
+            }
+            watch.set(key, value)?;
+        }
+        if watch.get::<_, LuaValue>("recursive")?.is_nil() {
+            watch.set("recursive", true)?;
+        }
+
+        Ok(Self {
+            quiet_for,
+            timeout,
+            watch: WatchOptions::from_lua(LuaValue::Table(watch), lua)?,
+        })
+    }
+}
+
+struct StopOnDrop(FsWatcher);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.stop();
+    }
+}
+
+/**
+    Watches the given path until no matching events have been delivered
+    for the quiet duration, such as once another program has finished
+    writing all of its output, and then stops watching it.
+
+    Returns an error if the path does not go quiet before the timeout.
+*/
+pub async fn await_quiet(lua: &Lua, path: String, options: QuietOptions) -> LuaResult<()> {
+    let clock = FsConfig::get(lua).clock;
+    let last_event = Rc::new(Cell::new(clock.now()));
+
+    let handlers = WatchEventKind::ALL
+        .iter()
+        .try_fold(TableBuilder::new(lua)?, |builder, kind| {
+            let last_event = Rc::clone(&last_event);
+            let clock = clock.clone();
+            builder.with_function(kind.name(), move |_, _: LuaMultiValue| {
+                last_event.set(clock.now());
+                Ok(())
+            })
+        })?
+        .build()?;
+    // NOTE: The watcher is also stopped if the waiting thread is cancelled
+    let _watcher = StopOnDrop(watch(lua, path.clone(), options.watch, handlers)?);
+
+    let deadline = options.timeout.map(|timeout| clock.now() + timeout);
+    loop {
+        let quiet_at = last_event.get() + options.quiet_for;
+        let wake_at = deadline.map_or(quiet_at, |deadline| quiet_at.min(deadline));
+        clock.sleep_until(wake_at).await;
+
+        let now = clock.now();
+        if now >= last_event.get() + options.quiet_for {
+            return Ok(());
+        }
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(LuaError::RuntimeError(format!(
+                "{TIMED_OUT}: The path '{path}' did not go quiet for {}ms within {}ms",
+                options.quiet_for.as_millis(),
+                options.timeout.unwrap_or_default().as_millis(),
+            )));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+/**
+    Watches the given root, falling back to watching only the root itself if the
+    backend does not support recursive watches, returning `true` if it fell back.
+
+    Recursion then has to be emulated using [`EmulatedRecursion`], since
+    events in subdirectories would otherwise be silently missed.
+*/
+pub fn watch_root(
+    watcher: &mut dyn Watcher,
+    root: &Path,
+    recursive_mode: RecursiveMode,
+) -> notify::Result<bool> {
+    match watcher.watch(root, recursive_mode) {
+        Err(e) if recursive_mode == RecursiveMode::Recursive && is_unsupported(&e) => {
+            watcher.watch(root, RecursiveMode::NonRecursive)?;
+            Ok(true)
+        }
+        res => res.map(|()| false),
+    }
+}
+
+fn is_unsupported(err: &notify::Error) -> bool {
+    matches!(&err.kind, notify::ErrorKind::Io(e) if e.kind() == ErrorKind::Unsupported)
+}
+
+/**
+    Emulated recursion for a watcher, if its backend needed it, otherwise doing nothing.
+*/
+#[derive(Debug)]
+pub struct WatchRecursion(Option<EmulatedRecursion>);
+
+impl WatchRecursion {
+    pub fn new(root: PathBuf, emulate: bool) -> Self {
+        Self(emulate.then(|| EmulatedRecursion::new(root)))
+    }
+
+    /**
+        The mode that the root itself is watched with, which is
+        never recursive while recursion is being emulated.
+    */
+    pub fn recursive_mode(&self, requested: RecursiveMode) -> RecursiveMode {
+        match self.0 {
+            Some(_) => RecursiveMode::NonRecursive,
+            None => requested,
+        }
+    }
+
+    pub fn rewatch_all(&mut self, watcher: &mut dyn Watcher) {
+        if let Some(recursion) = &mut self.0 {
+            recursion.rewatch_all(watcher);
+        }
+    }
+
+    pub fn update(&mut self, watcher: &mut dyn Watcher, event: &Event) {
+        if let Some(recursion) = &mut self.0 {
+            recursion.update(watcher, event);
+        }
+    }
+}
+
+/**
+    Emulates a recursive watch for backends that only support non-recursive watches,
+    by watching every subdirectory of the root, and adding and removing watches
+    as directories are created and removed underneath it.
+
+    Directories are watched as they are found, so events for anything created inside of
+    a new directory before its watch was added are missed, same as with native emulation.
+*/
+#[derive(Debug)]
+pub struct EmulatedRecursion {
+    root: PathBuf,
+    dirs: HashSet<PathBuf>,
+}
+
+impl EmulatedRecursion {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            dirs: HashSet::new(),
+        }
+    }
+
+    /**
+        Watches every subdirectory of the root again, such as after the root was
+        watched again, or the backend was replaced, and all previous watches are gone.
+    */
+    pub fn rewatch_all(&mut self, watcher: &mut dyn Watcher) {
+        self.dirs.clear();
+        let root = self.root.clone();
+        self.watch_tree(watcher, &root);
+    }
+
+    /**
+        Adds or removes watches for the directories that were created or removed in the event.
+    */
+    pub fn update(&mut self, watcher: &mut dyn Watcher, event: &Event) {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_)
+                | EventKind::Remove(_)
+                | EventKind::Modify(ModifyKind::Name(_) | ModifyKind::Any)
+                | EventKind::Any
+                | EventKind::Other
+        ) {
+            return;
+        }
+        for path in &event.paths {
+            if path == &self.root || !path.starts_with(&self.root) {
+                continue;
+            }
+            let is_dir = fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir());
+            if is_dir && !self.dirs.contains(path) {
+                if watcher.watch(path, RecursiveMode::NonRecursive).is_ok() {
+                    self.dirs.insert(path.clone());
+                    self.watch_tree(watcher, path);
+                }
+            } else if !is_dir && self.dirs.contains(path) {
+                self.unwatch_tree(watcher, path);
+            }
+        }
+    }
+
+    fn watch_tree(&mut self, watcher: &mut dyn Watcher, dir: &Path) {
+        let mut queue = vec![dir.to_path_buf()];
+        while let Some(dir) = queue.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            // NOTE: Entry file types do not follow symlinks, so linked
+            // directories are never watched, and can not cause cycles
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !entry.file_type().is_ok_and(|kind| kind.is_dir()) || self.dirs.contains(&path) {
+                    continue;
+                }
+                if watcher.watch(&path, RecursiveMode::NonRecursive).is_ok() {
+                    self.dirs.insert(path.clone());
+                    queue.push(path);
+                }
+            }
+        }
+    }
+
+    fn unwatch_tree(&mut self, watcher: &mut dyn Watcher, dir: &Path) {
+        self.dirs.retain(|path| {
+            if path.starts_with(dir) {
+                // NOTE: Backends usually drop watches of removed directories by
+                // themselves, so errors from removing them here are expected
+                let _ = watcher.unwatch(path);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use notify::event::{CreateKind, RemoveKind};
+    use notify::{Config, EventHandler, WatcherKind};
+
+    /**
+        A backend that only supports non-recursive watches, recording what it watches.
+    */
+    #[derive(Default)]
+    struct NonRecursiveWatcher {
+        watched: HashSet<PathBuf>,
+    }
+
+    impl Watcher for NonRecursiveWatcher {
+        fn new<F: EventHandler>(_: F, _: Config) -> notify::Result<Self> {
+            Ok(Self::default())
+        }
+
+        fn watch(&mut self, path: &Path, mode: RecursiveMode) -> notify::Result<()> {
+            if mode == RecursiveMode::Recursive {
+                return Err(notify::Error::io(ErrorKind::Unsupported.into()));
+            }
+            self.watched.insert(path.to_path_buf());
+            Ok(())
+        }
+
+        fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+            self.watched.remove(path);
+            Ok(())
+        }
+
+        fn kind() -> WatcherKind {
+            WatcherKind::NullWatcher
+        }
+    }
+
+    #[test]
+    fn emulates_recursion_for_new_and_removed_dirs() {
+        let root = std::env::temp_dir().join(format!("lune-fs-recursion-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+
+        let mut watcher = NonRecursiveWatcher::default();
+        assert!(watch_root(&mut watcher, &root, RecursiveMode::Recursive).unwrap());
+        let mut recursion = EmulatedRecursion::new(root.clone());
+        recursion.rewatch_all(&mut watcher);
+        assert!(watcher.watched.contains(&root));
+        assert!(watcher.watched.contains(&root.join("a/b")));
+
+        fs::create_dir_all(root.join("c/d")).unwrap();
+        let created = Event::new(EventKind::Create(CreateKind::Folder)).add_path(root.join("c"));
+        recursion.update(&mut watcher, &created);
+        assert!(watcher.watched.contains(&root.join("c/d")));
+
+        fs::remove_dir_all(root.join("a")).unwrap();
+        let removed = Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(root.join("a"));
+        recursion.update(&mut watcher, &removed);
+        assert!(!watcher.watched.contains(&root.join("a")));
+        assert!(!watcher.watched.contains(&root.join("a/b")));
+        assert!(watcher.watched.contains(&root.join("c")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
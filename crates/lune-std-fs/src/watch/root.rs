@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::PathBuf;
+
+use notify::{RecursiveMode, Watcher};
+
+use super::backend::ActiveWatcher;
+use crate::device::is_device_removed;
+use crate::mount::device;
+
+/**
+    Tracks whether the watched root still exists.
+
+    Native backends stop delivering events once the watched root itself
+    is removed, even if a new directory is created at the same path later,
+    so the watch has to be re-established manually when that happens.
+*/
+#[derive(Debug)]
+pub struct WatchRoot {
+    path: PathBuf,
+    recursive_mode: RecursiveMode,
+    rewatch: bool,
+    removed: bool,
+    // The device that the root was on when it was last watched, if the platform has device ids
+    device: Option<u64>,
+}
+
+impl WatchRoot {
+    pub fn new(path: PathBuf, recursive_mode: RecursiveMode, rewatch: bool) -> Self {
+        let device = current_device(&path);
+        Self {
+            path,
+            recursive_mode,
+            rewatch,
+            removed: false,
+            device,
+        }
+    }
+
+    /**
+        Whether the root has been removed and should be checked for until it reappears.
+    */
+    pub fn awaiting_recreation(&self) -> bool {
+        self.removed && self.rewatch
+    }
+
+    /**
+        Checks if the root was removed since the last check,
+        returning `true` only the first time it is found missing.
+    */
+    pub fn detect_removal(&mut self) -> bool {
+        if self.removed || self.path.is_dir() {
+            return false;
+        }
+        self.removed = true;
+        true
+    }
+
+    /**
+        Re-establishes the watch if the root has been created again,
+        returning `true` if the watch was re-established.
+    */
+    pub fn try_rewatch(&mut self, watcher: &mut dyn Watcher) -> bool {
+        if !self.awaiting_recreation() || !self.path.is_dir() {
+            return false;
+        }
+        // NOTE: The previous watch may or may not have been cleaned up by
+        // the backend already, so errors from removing it are expected
+        let _ = watcher.unwatch(&self.path);
+        if watcher.watch(&self.path, self.recursive_mode).is_err() {
+            return false;
+        }
+        self.removed = false;
+        self.device = current_device(&self.path);
+        true
+    }
+
+    /**
+        Checks if the device containing the root was removed, such as an ejected
+        USB drive, either because the root now lives on a different device, or
+        because accessing it fails with an error that only removed devices cause.
+    */
+    pub fn detect_device_removal(&self) -> bool {
+        match fs::metadata(&self.path) {
+            Ok(meta) => self.device.is_some() && device(&meta) != self.device,
+            Err(e) => is_device_removed(&e),
+        }
+    }
+
+    /**
+        Makes the watcher look for changes to the root that it may have missed,
+        unless the root has been removed, in which case there is nothing to look at.
+    */
+    pub fn rescan(
+        &self,
+        watcher: &mut ActiveWatcher,
+        recreate: impl FnOnce() -> notify::Result<ActiveWatcher>,
+    ) -> notify::Result<()> {
+        if self.removed {
+            return Ok(());
+        }
+        watcher.rescan(&self.path, self.recursive_mode, recreate)
+    }
+}
+
+fn current_device(path: &std::path::Path) -> Option<u64> {
+    fs::metadata(path).ok().and_then(|meta| device(&meta))
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSchedulerExt;
+
+use super::event::WatchEventKind;
+use super::history::RecordedEvent;
+use super::pattern::WatchPattern;
+
+/**
+    Options for filtering the events delivered to a single subscription,
+    on top of the filtering that the watcher itself already does.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionOptions {
+    pattern: Option<WatchPattern>,
+    kinds: Option<Vec<WatchEventKind>>,
+}
+
+impl<'lua> FromLua<'lua> for SubscriptionOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let t = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "SubscriptionOptions",
+                    message: Some(format!(
+                        "Invalid subscription options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        let pattern = match t.get::<_, LuaValue>("pattern")? {
+            LuaValue::Nil => None,
+            value => Some(WatchPattern::from_lua_value(value, "subscription")?),
+        };
+        let kinds = match t.get::<_, Option<Vec<String>>>("kinds")? {
+            Some(kinds) => Some(
+                kinds
+                    .iter()
+                    .map(|kind| kind.parse().map_err(LuaError::RuntimeError))
+                    .collect::<LuaResult<_>>()?,
+            ),
+            None => None,
+        };
+        Ok(Self { pattern, kinds })
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    handlers_key: LuaRegistryKey,
+    options: SubscriptionOptions,
+}
+
+impl Subscription {
+    /**
+        Returns the paths of an event that should be delivered to this subscription.
+
+        Events for the watched root itself are not matched against the
+        pattern, since the root is usually not something the pattern matches.
+    */
+    fn matching_paths(&self, kind: WatchEventKind, paths: &[String]) -> Vec<String> {
+        if self
+            .options
+            .kinds
+            .as_ref()
+            .is_some_and(|k| !k.contains(&kind))
+        {
+            return Vec::new();
+        }
+        match &self.options.pattern {
+            Some(pattern) if !kind.is_root() => paths
+                .iter()
+                .filter(|path| pattern.is_match(path))
+                .cloned()
+                .collect(),
+            _ => paths.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SubscriptionsInner {
+    next_id: u64,
+    entries: BTreeMap<u64, Subscription>,
+}
+
+/**
+    All of the sets of handlers that a watcher delivers events to,
+    shared between the watcher task, its handle, and its subscriptions.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct WatchSubscriptions {
+    inner: Arc<Mutex<SubscriptionsInner>>,
+}
+
+impl WatchSubscriptions {
+    pub fn subscribe(
+        &self,
+        lua: &Lua,
+        handlers: LuaTable,
+        options: SubscriptionOptions,
+    ) -> LuaResult<FsWatchSubscription> {
+        let handlers_key = lua.create_registry_value(handlers)?;
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Watch subscriptions lock was poisoned");
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.insert(
+            id,
+            Subscription {
+                handlers_key,
+                options,
+            },
+        );
+        Ok(FsWatchSubscription {
+            id,
+            subscriptions: self.clone(),
+        })
+    }
+
+    fn unsubscribe(&self, id: u64) -> bool {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Watch subscriptions lock was poisoned");
+        inner.entries.remove(&id).is_some()
+    }
+
+    /**
+        Schedules the handler for the given kind of event in every subscription that has one.
+
+        Returns `false` if the handlers are no longer available and the watcher should stop.
+    */
+    pub fn deliver(&self, lua: &Lua, event: &RecordedEvent, defer: bool) -> bool {
+        let kind = event.kind();
+        let inner = self
+            .inner
+            .lock()
+            .expect("Watch subscriptions lock was poisoned");
+        for subscription in inner.entries.values() {
+            let paths = subscription.matching_paths(kind, event.paths());
+            if paths.is_empty() {
+                continue;
+            }
+            let Ok(handlers) = lua.registry_value::<LuaTable>(&subscription.handlers_key) else {
+                return false;
+            };
+            if let Ok(handler) = handlers.get::<_, LuaFunction>(kind.name()) {
+                let args = (paths.clone(), event.with_paths(paths));
+                let _ = if defer {
+                    lua.push_thread_back(handler, args)
+                } else {
+                    lua.push_thread_front(handler, args)
+                };
+            }
+        }
+        true
+    }
+}
+
+/**
+    A handle to a set of handlers subscribed to a watcher.
+*/
+#[derive(Debug)]
+pub struct FsWatchSubscription {
+    id: u64,
+    subscriptions: WatchSubscriptions,
+}
+
+impl LuaUserData for FsWatchSubscription {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("unsubscribe", |_, this, ()| {
+            if this.subscriptions.unsubscribe(this.id) {
+                Ok(())
+            } else {
+                Err(LuaError::runtime("Subscription already unsubscribed"))
+            }
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "FsWatchSubscription");
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/**
+    Finds an executable by name, searching the directories in `PATH`.
+
+    Names that contain a path separator are not searched for, and are only
+    checked to be executable, same as how shells resolve commands.
+*/
+pub fn which(name: &str) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+    if Path::new(name).components().count() > 1 {
+        return find_executable(Path::new(name));
+    }
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .find_map(|dir| find_executable(&dir.join(name)))
+}
+
+#[cfg(unix)]
+fn find_executable(path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let meta = std::fs::metadata(path).ok()?;
+    (meta.is_file() && meta.permissions().mode() & 0o111 != 0).then(|| path.to_path_buf())
+}
+
+#[cfg(windows)]
+fn find_executable(path: &Path) -> Option<PathBuf> {
+    let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    let extensions = extensions
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .collect::<Vec<_>>();
+
+    // Names that already have an executable extension are used as-is,
+    // all other names are tried with each extension in PATHEXT, in order
+    let has_extension = path.extension().is_some_and(|ext| {
+        extensions.iter().any(|known| {
+            known
+                .trim_start_matches('.')
+                .eq_ignore_ascii_case(&ext.to_string_lossy())
+        })
+    });
+    if has_extension {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    extensions.iter().find_map(|ext| {
+        let mut candidate = std::ffi::OsString::from(path.as_os_str());
+        candidate.push(ext);
+        let candidate = PathBuf::from(candidate);
+        candidate.is_file().then_some(candidate)
+    })
+}
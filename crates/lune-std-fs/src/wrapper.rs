@@ -0,0 +1,28 @@
+use mlua::prelude::*;
+
+use lune_utils::TableBuilder;
+
+/**
+    Loads a function implemented in Luau, which wraps a callback given by the script.
+
+    Wrappers are implemented in Luau so that the callback may yield freely, and so that errors
+    propagate unchanged after the wrapper has cleaned up after it. The `source` must return the
+    function, and may use `pcall`, `error`, `pack` and `unpack` in addition to everything in `env`.
+*/
+pub fn create_luau_wrapper<'lua>(
+    lua: &'lua Lua,
+    name: &str,
+    source: &str,
+    env: TableBuilder<'lua>,
+) -> LuaResult<LuaFunction<'lua>> {
+    let table = lua.globals().get::<_, LuaTable>("table")?;
+
+    let env = env
+        .with_value("pcall", lua.globals().get::<_, LuaFunction>("pcall")?)?
+        .with_value("error", lua.globals().get::<_, LuaFunction>("error")?)?
+        .with_value("pack", table.get::<_, LuaFunction>("pack")?)?
+        .with_value("unpack", table.get::<_, LuaFunction>("unpack")?)?
+        .build_readonly()?;
+
+    lua.load(source).set_name(name).set_environment(env).eval()
+}
@@ -177,20 +177,32 @@ async fn spawn_command(
     let stderr = options.stdio.stderr;
     let stdin = options.stdio.stdin.take();
 
+    let stdin_stdio = match (&options.stdio.stdin_file, &stdin) {
+        (Some(file), _) => file.to_stdio()?,
+        (None, Some(_)) => Stdio::piped(),
+        (None, None) => Stdio::null(),
+    };
+    let stdout_stdio = match &options.stdio.stdout_file {
+        Some(file) => file.to_stdio()?,
+        None => stdout.as_stdio(),
+    };
+    let stderr_stdio = match &options.stdio.stderr_file {
+        Some(file) => file.to_stdio()?,
+        None => stderr.as_stdio(),
+    };
+
     let mut child = options
         .into_command(program, args)
-        .stdin(if stdin.is_some() {
-            Stdio::piped()
-        } else {
-            Stdio::null()
-        })
-        .stdout(stdout.as_stdio())
-        .stderr(stderr.as_stdio())
+        .stdin(stdin_stdio)
+        .stdout(stdout_stdio)
+        .stderr(stderr_stdio)
         .spawn()?;
 
     if let Some(stdin) = stdin {
-        let mut child_stdin = child.stdin.take().unwrap();
-        child_stdin.write_all(&stdin).await.into_lua_err()?;
+        // NOTE: There is no pipe to write to when stdin was given as a file
+        if let Some(mut child_stdin) = child.stdin.take() {
+            child_stdin.write_all(&stdin).await.into_lua_err()?;
+        }
     }
 
     wait_for_child(child, stdout, stderr).await
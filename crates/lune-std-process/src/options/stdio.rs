@@ -1,5 +1,7 @@
 use mlua::prelude::*;
 
+use lune_utils::stdio::StdioHandle;
+
 use super::kind::ProcessSpawnOptionsStdioKind;
 
 #[derive(Debug, Clone, Default)]
@@ -7,6 +9,13 @@ pub struct ProcessSpawnOptionsStdio {
     pub stdout: ProcessSpawnOptionsStdioKind,
     pub stderr: ProcessSpawnOptionsStdioKind,
     pub stdin: Option<Vec<u8>>,
+    /*
+        Files given using `file:toStdio()`, which the child process reads
+        from or writes to directly, instead of going through the stdio kind
+    */
+    pub stdout_file: Option<StdioHandle>,
+    pub stderr_file: Option<StdioHandle>,
+    pub stdin_file: Option<StdioHandle>,
 }
 
 impl From<ProcessSpawnOptionsStdioKind> for ProcessSpawnOptionsStdio {
@@ -29,16 +38,28 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptionsStdio {
             LuaValue::Table(t) => {
                 let mut this = Self::default();
 
-                if let Some(stdin) = t.get("stdin")? {
-                    this.stdin = stdin;
+                match t.get("stdin")? {
+                    LuaValue::Nil => {}
+                    LuaValue::UserData(ud) => this.stdin_file = Some(stdio_handle(&ud)?),
+                    value => this.stdin = FromLua::from_lua(value, lua)?,
                 }
 
-                if let Some(stdout) = t.get("stdout")? {
-                    this.stdout = stdout;
+                match t.get("stdout")? {
+                    LuaValue::Nil => {}
+                    LuaValue::UserData(ud) => {
+                        this.stdout = ProcessSpawnOptionsStdioKind::None;
+                        this.stdout_file = Some(stdio_handle(&ud)?);
+                    }
+                    value => this.stdout = FromLua::from_lua(value, lua)?,
                 }
 
-                if let Some(stderr) = t.get("stderr")? {
-                    this.stderr = stderr;
+                match t.get("stderr")? {
+                    LuaValue::Nil => {}
+                    LuaValue::UserData(ud) => {
+                        this.stderr = ProcessSpawnOptionsStdioKind::None;
+                        this.stderr_file = Some(stdio_handle(&ud)?);
+                    }
+                    value => this.stderr = FromLua::from_lua(value, lua)?,
                 }
 
                 Ok(this)
@@ -54,3 +75,14 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptionsStdio {
         }
     }
 }
+
+fn stdio_handle(ud: &LuaAnyUserData) -> LuaResult<StdioHandle> {
+    ud.borrow::<StdioHandle>()
+        .map(|handle| handle.clone())
+        .map_err(|_| {
+            LuaError::RuntimeError(
+                "Invalid spawn options stdio - expected a handle created using file:toStdio()"
+                    .to_string(),
+            )
+        })
+}
@@ -5,6 +5,7 @@ mod version_string;
 
 pub mod fmt;
 pub mod path;
+pub mod stdio;
 
 pub use self::table_builder::TableBuilder;
 pub use self::version_string::get_version_string;
@@ -0,0 +1,54 @@
+use std::{fs::File, io, process::Stdio, sync::Arc};
+
+use mlua::prelude::*;
+
+/**
+    An open file that child processes can use as their stdin, stdout, or stderr.
+
+    Created by standard libraries that open files, such as using `file:toStdio()`,
+    and accepted by standard libraries that spawn processes, so that neither of
+    them has to know about the other, and output never has to pass through Lua.
+
+    The file is shared with the handle it was created from, including its
+    position, and every process given the handle gets its own duplicate of it.
+*/
+#[derive(Debug, Clone)]
+pub struct StdioHandle {
+    file: Arc<File>,
+}
+
+impl StdioHandle {
+    /**
+        Creates a new handle for the given file.
+    */
+    #[must_use]
+    pub fn new(file: File) -> Self {
+        Self {
+            file: Arc::new(file),
+        }
+    }
+
+    /**
+        Duplicates the file, to be used as stdio of a single child process.
+
+        # Errors
+
+        Errors if the file could not be duplicated, such as when
+        the process is out of file descriptors.
+    */
+    pub fn to_stdio(&self) -> io::Result<Stdio> {
+        Ok(Stdio::from(self.file.try_clone()?))
+    }
+}
+
+impl From<File> for StdioHandle {
+    fn from(file: File) -> Self {
+        Self::new(file)
+    }
+}
+
+impl LuaUserData for StdioHandle {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "StdioHandle");
+    }
+}
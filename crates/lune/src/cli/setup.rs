@@ -15,6 +15,8 @@ use tokio::fs;
 use serde_json::Value as JsonValue;
 
 pub(crate) static TYPEDEFS_DIR: Dir<'_> = include_dir!("types");
+// NOTE: The fs definitions live in their own crate, which also embeds and checks them
+pub(crate) static FS_TYPEDEFS_DIR: Dir<'_> = include_dir!("crates/lune-std-fs/types");
 
 pub(crate) static SETTING_NAME_MODE: &str = "luau-lsp.require.mode";
 pub(crate) static SETTING_NAME_ALIASES: &str = "luau-lsp.require.directoryAliases";
@@ -25,7 +27,7 @@ pub struct SetupCommand {}
 
 impl SetupCommand {
     pub async fn run(self) -> Result<ExitCode> {
-        generate_typedef_files_from_definitions(&TYPEDEFS_DIR)
+        generate_typedef_files_from_definitions(&[&TYPEDEFS_DIR, &FS_TYPEDEFS_DIR])
             .await
             .expect("Failed to generate typedef files");
 
@@ -142,8 +144,11 @@ fn add_values_to_vscode_settings_json(value: JsonValue) -> JsonValue {
     settings_json
 }
 
-async fn generate_typedef_files_from_definitions(dir: &Dir<'_>) -> Result<String> {
-    let contents = read_typedefs_dir_contents(dir);
+async fn generate_typedef_files_from_definitions(dirs: &[&Dir<'_>]) -> Result<String> {
+    let contents = dirs
+        .iter()
+        .flat_map(|dir| read_typedefs_dir_contents(dir))
+        .collect();
     write_typedef_files(contents).await
 }
 
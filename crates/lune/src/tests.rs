@@ -102,10 +102,16 @@ create_tests! {
 #[cfg(feature = "std-fs")]
 create_tests! {
     fs_files: "fs/files",
+    fs_cas: "fs/cas",
     fs_copy: "fs/copy",
+    fs_csv: "fs/csv",
     fs_dirs: "fs/dirs",
     fs_metadata: "fs/metadata",
+    fs_handles: "fs/handles",
+    fs_index: "fs/index",
     fs_move: "fs/move",
+    fs_streams: "fs/streams",
+    fs_watch: "fs/watch",
 }
 
 #[cfg(feature = "std-luau")]